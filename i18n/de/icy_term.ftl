@@ -1,4 +1,11 @@
 connect-to = Verbinde zu { $address }...
+terminal-login-script-failed = Login-Skript: { $error }
+terminal-script-failed = Skript: { $error }
+terminal-trigger-matched = Auslöser: { $text }
+terminal-capture-started = Mitschnitt nach { $file }
+terminal-capture-stopped = Mitschnitt gespeichert in { $file }
+terminal-capture-failed = Mitschnitt fehlgeschlagen: { $error }
+terminal-server-info = { $name } · SysOp { $operator } · { $location }
 
 title-connected = iCY TERM { $version } - Verbunden zu { $name } seit { $time }
 title-offline   = iCY TERM { $version } - Keine Verbindung
@@ -6,6 +13,36 @@ title-offline   = iCY TERM { $version } - Keine Verbindung
 settings-heading = Einstellungen
 settings-scaling = Skalierung
 settings-monitor-type = Monitor
+settings-text-upload = Text-Upload
+settings-text-upload-char-delay = Zeichenverzögerung:
+settings-text-upload-line-delay = Zeilenverzögerung:
+settings-text-upload-prompt = Auf Prompt warten:
+settings-text-upload-echo = Auf Echo warten
+settings-downloads = Downloads
+settings-download-dir = Download-Verzeichnis:
+settings-download-dir-default = Download-Verzeichnis des Systems
+settings-collision-policy = Vorhandene Dateien:
+settings-transfer-log = Übertragungsprotokoll (DSZLOG):
+settings-download-hook = Nach Download ausführen:
+settings-download-hook-hint = Ein Befehl, der für jede heruntergeladene Datei ausgeführt wird, %f wird durch den Pfad ersetzt. Die Ausgabe erscheint im Übertragungsfenster.
+settings-triggers = Auslöser
+settings-identities = Identitäten
+settings-iemsi = IEMSI-Vorgaben
+settings-passwords = Passwörter
+settings-passwords-plain = Als Klartext gespeichert.
+settings-passwords-encrypt = Mit einem Master-Passwort verschlüsseln…
+settings-passwords-unlocked = Verschlüsselt, für diese Sitzung entsperrt.
+settings-passwords-locked = Verschlüsselt und gesperrt.
+settings-passwords-lock = Sperren
+settings-zmodem = ZModem
+settings-zmodem-window = ZModem-Fenster:
+settings-zmodem-window-hint = Bytes, die vor dem Warten auf eine Bestätigung gesendet werden, 0 sendet ohne Unterbrechung. Ein Fenster hilft bei Verbindungen, die kein Vollduplex-Streaming können.
+settings-zmodem-escaping = Maskierung:
+settings-zmodem-resume = Empfänger dürfen Uploads fortsetzen
+settings-zmodem-resume-hint = Sendet ZCRESUM, manche Empfänger hängen dann an jede Datei mit gleichem Namen an.
+zmodem-escaping-minimal = Minimal (nur CAN, wenn die Gegenseite icy_term ist)
+zmodem-escaping-standard = Standard
+zmodem-escaping-control = Alle Steuerzeichen
 
 phonebook-connect-to = Verbinde zu…
 phonebook-new_bbs = Neue BBS
@@ -20,9 +57,23 @@ phonebook-screen_mode = Bildschirmmodus:
 phonebook-terminal_type = Terminaltyp:
 phonebook-music-option = Ansimusik:
 phonebook-autologin = Autologin String:
+phonebook-login-script = Login-Skript:
+phonebook-login-script-hint = expect "(?i)name:" / send "$user\r" - ersetzt den Autologin-String
+phonebook-login-variables = Skript-Variablen:
+phonebook-login-variable-add = Variable hinzufügen
+phonebook-script-file = Skript:
+phonebook-script-file-hint = Ein Rhai-Skript, das während der Verbindung läuft
+phonebook-triggers = Auslöser
+phonebook-identities = Identitäten
+phonebook-identities-hint = Identitäten werden in den Einstellungen angelegt
+phonebook-identity-default = Standard
+phonebook-unlock = Entsperren…
 phonebook-comment = Kommentar:
 phonebook-delete = Ausgewählte BBS löschen
 phonebook-add = Neue BBS
+phonebook-import = Aus einem anderen Terminal importieren…
+phonebook-export = Für andere exportieren…
+phonebook-directory = BBS-Verzeichnis öffnen
 phonebook-settings = { settings-heading }
 phonebook-connect-to-address = <Verbinde zu Addresse>
 phonebook-connect-button = Verbinden
@@ -34,6 +85,8 @@ phonebook-created-at-date-format=Erstellt am %d.%m.%Y um %H:%M
 phonebook-clear-filter = Filter löschen
 phonebook-starred-items = Favoriten filtern
 phonebook-no-entries = Keine passenden Einträge
+phonebook-download-dir = Download-Verzeichnis:
+phonebook-download-dir-default = Einstellungen verwenden
 
 terminal-upload = Upload
 terminal-download = Download
@@ -42,16 +95,21 @@ terminal-autologin = Username/Passwort senden
 terminal-phonebook = Telefonbuch
 terminal-menu-copy = Kopieren
 terminal-menu-paste = Einfügen
+terminal-recent-downloads = Letzte Downloads
 
 protocol-select-download = Download-Protokoll wählen
 protocol-select-upload = Upload-Protokoll wählen
 protocol-zmodem-description = Der Standard
 protocol-zmodem8k-description = 8k Zmodem
+protocol-directzap-description = 8k Zmodem für saubere 8-Bit-Verbindungen
 protocol-xmodem-description = Veraltetes Protokoll
 protocol-xmodem1k-description = XModem Variante
 protocol-xmodem1kG-description = Schnelle XModem Variante
 protocol-ymodem-description = Solide, aber ZModem ist besser
 protocol-ymodemg-description = Schnelle YModem Variante
+protocol-kermit-description = Langsam, kommt aber durch jede Leitung
+protocol-punter-description = Für C64 Mailboxen
+protocol-text-description = Sendet Textdateien zeilenweise
 
 transfer-upload = { terminal-upload }
 transfer-download = { terminal-download }
@@ -64,7 +122,97 @@ transfer-timeleft = Restzeit:
 transfer-file = Datei:
 transfer-rate = Transferrate:
 transfer-bps = { $bps } pro Sekunde
+transfer-batch = Datei { $files } - { $bytes }
+transfer-errors = { $total } ({ $file } in dieser Datei)
+transfer-rate-details = Durchschnitt { $average }/s, Spitze { $peak }/s
+transfer-finished-files = Fertige Dateien
+transfer-file-errors = { $errors } Fehler
+transfer-file-exists = '{ $file }' existiert bereits.
+transfer-queue = Warteschlange
+transfer-queue-waiting = Der Upload startet, sobald die Box bereit zum Empfangen ist.
+transfer-queue-protocol = Ändern…
+transfer-queue-start = Jetzt starten
+transfer-queue-total = { $files } Dateien, { $bytes }
+
+downloads-heading = { terminal-recent-downloads }
+downloads-empty = Noch nichts heruntergeladen.
+downloads-open-folder = Ordner öffnen
+downloads-delete = Löschen
+downloads-delete-question = '{ $file }' löschen?
+downloads-unsupported = Der Inhalt dieses Archivtyps kann nicht angezeigt werden.
+downloads-name = Name
+downloads-size = Größe
+
+collision-policy-rename = Umbenennen
+collision-policy-overwrite = Überschreiben
+collision-policy-skip = Überspringen
+collision-policy-ask = Nachfragen
 
 menu-item-discuss=Forum
 menu-item-report-bug=Fehler melden
-menu-item-settings={ settings-heading }
\ No newline at end of file
+menu-item-settings={ settings-heading }
+menu-item-run-script=Skript ausführen…
+menu-item-start-capture=Mitschnitt starten
+menu-item-stop-capture=Mitschnitt beenden
+
+triggers-pattern = Muster
+triggers-send = Antwort
+triggers-regex = Regex
+triggers-highlight = Hervorheben
+triggers-sound = Ton
+triggers-notify = Benachrichtigen
+triggers-capture = Mitschnitt
+triggers-none = Keine Auslöser
+triggers-add = Auslöser hinzufügen
+
+identities-name = Name
+identities-handle = Benutzername
+identities-first-name = Vorname
+identities-last-name = Nachname
+identities-location = Ort
+identities-fields = Felder
+identities-none = Keine Identitäten
+identities-add = Identität hinzufügen
+identities-new = Neue Identität
+identities-name-taken = Jede Identität braucht einen eigenen Namen
+
+identity-dialog-heading = Anrufen als
+identity-dialog-call = Anrufen
+
+export-heading = Telefonbuch exportieren
+export-select-all = Alle
+export-select-none = Keine
+export-format-syncterm = SyncTERM-Liste
+export-format-bundle = icy_term-Paket
+export-format-csv = CSV
+export-strip-credentials = Benutzernamen, Passwörter, IEMSI Profile und Login Skripte mit getipptem Text weglassen
+export-button = Exportieren…
+
+directory-heading = BBS-Verzeichnis
+directory-open = Liste öffnen…
+directory-filter-all = Alle
+directory-software = Software
+directory-terminal = Terminal
+directory-country = Land
+directory-empty = Eine BBS-Liste im CSV- oder XML-Format öffnen, z.B. vom Telnet BBS Guide.
+directory-name = Name
+directory-add = Zum Telefonbuch hinzufügen
+directory-already-added = { $name } ist bereits im Telefonbuch
+directory-added = { $name } wurde zum Telefonbuch hinzugefügt
+
+iemsi-alias = Alias:
+iemsi-location = Ort:
+iemsi-data-phone = Datentelefon:
+iemsi-voice-phone = Telefon:
+iemsi-requests = Anfragen:
+
+chat-sysop = SysOp
+chat-you = Du
+
+unlock-heading = Passwörter entsperren
+unlock-create-heading = Master-Passwort festlegen
+unlock-master-password = Master-Passwort:
+unlock-confirm-password = Wiederholen:
+unlock-create-hint = Alle Passwörter im Telefonbuch werden verschlüsselt. Ohne das Master-Passwort lassen sie sich nicht wiederherstellen.
+unlock-empty-password = Das Master-Passwort darf nicht leer sein.
+unlock-passwords-differ = Die Passwörter stimmen nicht überein.
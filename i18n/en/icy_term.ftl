@@ -1,4 +1,11 @@
 connect-to = Connect to { $address }...
+terminal-login-script-failed = Login script: { $error }
+terminal-script-failed = Script: { $error }
+terminal-trigger-matched = Trigger: { $text }
+terminal-capture-started = Capturing to { $file }
+terminal-capture-stopped = Capture saved to { $file }
+terminal-capture-failed = Capture failed: { $error }
+terminal-server-info = { $name } · SysOp { $operator } · { $location }
 
 title-connected = iCY TERM { $version } - Connected { $time } to { $name }
 title-offline   = iCY TERM { $version } - Offline
@@ -6,6 +13,36 @@ title-offline   = iCY TERM { $version } - Offline
 settings-heading = Settings
 settings-scaling = Scaling
 settings-monitor-type = Monitor
+settings-text-upload = Text upload
+settings-text-upload-char-delay = Character delay:
+settings-text-upload-line-delay = Line delay:
+settings-text-upload-prompt = Wait for prompt:
+settings-text-upload-echo = Wait for echo
+settings-downloads = Downloads
+settings-download-dir = Download directory:
+settings-download-dir-default = System download directory
+settings-collision-policy = Existing files:
+settings-transfer-log = Transfer log (DSZLOG):
+settings-download-hook = Run after download:
+settings-download-hook-hint = A command that runs on every downloaded file, %f is replaced by its path. The output shows up in the transfer window.
+settings-triggers = Triggers
+settings-identities = Identities
+settings-iemsi = IEMSI defaults
+settings-passwords = Passwords
+settings-passwords-plain = Stored as plain text.
+settings-passwords-encrypt = Encrypt with a master password…
+settings-passwords-unlocked = Encrypted, unlocked for this session.
+settings-passwords-locked = Encrypted and locked.
+settings-passwords-lock = Lock
+settings-zmodem = ZModem
+settings-zmodem-window = ZModem window:
+settings-zmodem-window-hint = Bytes sent before waiting for an acknowledge, 0 streams nonstop. Set a window for links that can't stream at full duplex.
+settings-zmodem-escaping = Escaping:
+settings-zmodem-resume = Let receivers resume uploads
+settings-zmodem-resume-hint = Sends ZCRESUM, some receivers then append to any file with the same name.
+zmodem-escaping-minimal = Minimal (CAN only, if the other side is icy_term)
+zmodem-escaping-standard = Standard
+zmodem-escaping-control = All control characters
 
 phonebook-connect-to = Quick connect to…
 phonebook-new_bbs = New BBS
@@ -20,9 +57,23 @@ phonebook-screen_mode = Screen Mode:
 phonebook-terminal_type = Terminal type:
 phonebook-music-option = Ansi music:
 phonebook-autologin = Autologin String:
+phonebook-login-script = Login script:
+phonebook-login-script-hint = expect "(?i)name:" / send "$user\r" - replaces the autologin string
+phonebook-login-variables = Script variables:
+phonebook-login-variable-add = Add variable
+phonebook-script-file = Script:
+phonebook-script-file-hint = A Rhai script that runs while connected
+phonebook-triggers = Triggers
+phonebook-identities = Identities
+phonebook-identities-hint = Identities are defined in the settings
+phonebook-identity-default = Default
+phonebook-unlock = Unlock…
 phonebook-comment = Comment:
 phonebook-delete = Delete selected BBS
 phonebook-add = Add new BBS
+phonebook-import = Import from another terminal…
+phonebook-export = Export for other users…
+phonebook-directory = Open the BBS directory
 phonebook-settings = { settings-heading }
 phonebook-connect-to-address = <Connect to Address>
 phonebook-connect-button = Connect
@@ -35,6 +86,8 @@ phonebook-date-format=%m/%d/%Y at %H:%M
 phonebook-clear-filter = Clear filter
 phonebook-starred-items = Filter starred items
 phonebook-no-entries = No matching entries
+phonebook-download-dir = Download directory:
+phonebook-download-dir-default = Use settings
 
 terminal-upload = Upload
 terminal-download = Download
@@ -44,16 +97,21 @@ terminal-phonebook = Dialing directory
 terminal-menu = Menu
 terminal-menu-copy = Copy
 terminal-menu-paste = Paste
+terminal-recent-downloads = Recent downloads
 
 protocol-select-download = Select download protocol
 protocol-select-upload = Select upload protocol
 protocol-zmodem-description = The standard protocol
 protocol-zmodem8k-description = 8k Zmodem
+protocol-directzap-description = 8k Zmodem for clean 8 bit links
 protocol-xmodem-description = Outdated protocol
 protocol-xmodem1k-description = Rarely used anymore
 protocol-xmodem1kG-description = Does that even exist?
 protocol-ymodem-description = Ok but Zmodem is better
 protocol-ymodemg-description = A fast Ymodem variant
+protocol-kermit-description = Slow but gets through any line
+protocol-punter-description = For C64 boards
+protocol-text-description = Sends text files line by line
 
 transfer-upload = { terminal-upload }
 transfer-download = { terminal-download }
@@ -66,7 +124,97 @@ transfer-timeleft = Time left:
 transfer-file = File:
 transfer-rate = transfer rate:
 transfer-bps = { $bps } per second
+transfer-batch = File { $files } - { $bytes }
+transfer-errors = { $total } ({ $file } in this file)
+transfer-rate-details = average { $average }/s, peak { $peak }/s
+transfer-finished-files = Finished files
+transfer-file-errors = { $errors } errors
+transfer-file-exists = '{ $file }' already exists.
+transfer-queue = Upload queue
+transfer-queue-waiting = Uploads start as soon as the board is ready to receive.
+transfer-queue-protocol = Change…
+transfer-queue-start = Start now
+transfer-queue-total = { $files } files, { $bytes }
+
+downloads-heading = { terminal-recent-downloads }
+downloads-empty = Nothing downloaded yet.
+downloads-open-folder = Open folder
+downloads-delete = Delete
+downloads-delete-question = Delete '{ $file }'?
+downloads-unsupported = The contents of this archive type can't be shown.
+downloads-name = Name
+downloads-size = Size
+
+collision-policy-rename = Rename
+collision-policy-overwrite = Overwrite
+collision-policy-skip = Skip
+collision-policy-ask = Ask
 
 menu-item-discuss=Forum
 menu-item-report-bug=Report a bug
-menu-item-settings={ settings-heading }
\ No newline at end of file
+menu-item-settings={ settings-heading }
+menu-item-run-script=Run script…
+menu-item-start-capture=Start capture
+menu-item-stop-capture=Stop capture
+
+triggers-pattern = Pattern
+triggers-send = Response
+triggers-regex = Regex
+triggers-highlight = Highlight
+triggers-sound = Sound
+triggers-notify = Notify
+triggers-capture = Capture
+triggers-none = No triggers
+triggers-add = Add trigger
+
+identities-name = Name
+identities-handle = Handle
+identities-first-name = First name
+identities-last-name = Last name
+identities-location = Location
+identities-fields = Fields
+identities-none = No identities
+identities-add = Add identity
+identities-new = New identity
+identities-name-taken = Every identity needs a name of its own
+
+identity-dialog-heading = Call as
+identity-dialog-call = Call
+
+export-heading = Export phonebook
+export-select-all = All
+export-select-none = None
+export-format-syncterm = SyncTERM list
+export-format-bundle = icy_term bundle
+export-format-csv = CSV
+export-strip-credentials = Leave out user names, passwords, IEMSI profiles and login scripts with typed text
+export-button = Export…
+
+directory-heading = BBS directory
+directory-open = Open listing…
+directory-filter-all = All
+directory-software = Software
+directory-terminal = Terminal
+directory-country = Country
+directory-empty = Open a BBS listing in CSV or XML format, like the Telnet BBS Guide.
+directory-name = Name
+directory-add = Add to phonebook
+directory-already-added = { $name } is already in the phonebook
+directory-added = Added { $name } to the phonebook
+
+iemsi-alias = Alias:
+iemsi-location = Location:
+iemsi-data-phone = Data phone:
+iemsi-voice-phone = Voice phone:
+iemsi-requests = Requests:
+
+chat-sysop = SysOp
+chat-you = You
+
+unlock-heading = Unlock passwords
+unlock-create-heading = Set a master password
+unlock-master-password = Master password:
+unlock-confirm-password = Repeat:
+unlock-create-hint = All passwords of the phonebook get encrypted. There is no way to recover them without the master password.
+unlock-empty-password = The master password can't be empty.
+unlock-passwords-differ = The passwords don't match.
@@ -1,3 +1,8 @@
+use crate::credentials::{parse_vault, Vault};
+use crate::identities::{identities_to_toml, parse_identity, Identity};
+use crate::iemsi_mod::{parse_iemsi_profile, IEmsiProfile};
+use crate::protocol::{TransferType, XYModemVariant};
+use crate::triggers::{parse_trigger, triggers_to_toml, Trigger};
 use crate::ui::screen_modes::ScreenMode;
 use crate::ui::AdressCategory;
 use crate::TerminalResult;
@@ -10,6 +15,7 @@ use std::fs::File;
 use std::io::Write;
 use std::path::Path;
 use std::{
+    collections::HashSet,
     fmt::Display,
     fs::{self},
     path::PathBuf,
@@ -68,6 +74,16 @@ impl Protocol {
     pub const ALL: [Protocol; 2] = [Protocol::Telnet, Protocol::Raw /*, Protocol::Ssh*/];
 }
 
+/// A message a board shows before an X/Y-Modem transfer. These protocols have no
+/// header the terminal could recognize, so the board's own wording is the hint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransferPrompt {
+    pub pattern: String,
+    pub protocol: XYModemVariant,
+    /// The board sends, otherwise it waits for the upload with 'C', NAK or 'G'.
+    pub download: bool,
+}
+
 #[derive(Debug, Clone)]
 pub struct AddressBook {
     pub addresses: Vec<Address>,
@@ -94,11 +110,22 @@ pub struct Address {
 
     pub user_name: String,
     pub password: String,
+    /// Names of the attached identities, see `identities`.
+    pub identities: Vec<String>,
+    pub default_identity: String,
     pub comment: String,
     pub terminal_type: Terminal,
 
     pub address: String,
     pub auto_login: String,
+    /// Replaces `auto_login` if set, see `login_script`.
+    pub login_script: String,
+    /// Custom variables for the login script.
+    pub login_variables: Vec<(String, String)>,
+    /// A Rhai script loaded when connecting, see `scripting`.
+    pub script_file: String,
+    /// Sent in the IEMSI login, empty fields come from the options.
+    pub iemsi: IEmsiProfile,
     pub protocol: Protocol,
 
     pub ice_mode: bool,
@@ -107,6 +134,13 @@ pub struct Address {
     pub font_name: Option<String>,
     pub screen_mode: ScreenMode,
 
+    /// Overrides the download directory of the options if set.
+    pub download_dir: String,
+    pub transfer_prompts: Vec<TransferPrompt>,
+    pub triggers: Vec<Trigger>,
+    /// The protocol last used for uploads to this board.
+    pub upload_protocol: Option<TransferType>,
+
     pub created: chrono::DateTime<Utc>,
     pub updated: chrono::DateTime<Utc>,
     pub overall_duration: chrono::Duration,
@@ -181,11 +215,21 @@ impl Address {
             system_name,
             user_name: String::new(),
             password: String::new(),
+            identities: Vec::new(),
+            default_identity: String::new(),
             comment: String::new(),
             terminal_type: Terminal::Ansi,
             font_name: None,
             screen_mode: ScreenMode::Vga(80, 25),
+            download_dir: String::new(),
+            transfer_prompts: Vec::new(),
+            triggers: Vec::new(),
+            upload_protocol: None,
             auto_login: String::new(),
+            login_script: String::new(),
+            login_variables: Vec::new(),
+            script_file: String::new(),
+            iemsi: IEmsiProfile::default(),
             address: String::new(),
             protocol: Protocol::Telnet,
             ansi_music: MusicOption::Off,
@@ -252,6 +296,34 @@ impl Address {
         }
         res
     }
+
+    /// The vault of the sealed passwords, see `credentials`.
+    pub fn read_vault() -> Option<Vault> {
+        let phonebook = Address::get_phonebook_file()?;
+        let input_text = fs::read_to_string(phonebook).ok()?;
+        let value = input_text.parse::<Value>().ok()?;
+        parse_vault(value.get("vault")?)
+    }
+
+    /// The identities shared by all addresses, see `identities`.
+    pub fn read_identities() -> Vec<Identity> {
+        let Some(phonebook) = Address::get_phonebook_file() else {
+            return Vec::new();
+        };
+        let Ok(input_text) = fs::read_to_string(phonebook) else {
+            return Vec::new();
+        };
+        match input_text.parse::<Value>() {
+            Ok(value) => {
+                if let Some(Value::Array(values)) = value.get("identities") {
+                    values.iter().filter_map(parse_identity).collect()
+                } else {
+                    Vec::new()
+                }
+            }
+            Err(_) => Vec::new(),
+        }
+    }
 }
 
 pub static mut READ_ADDRESSES: bool = false;
@@ -271,10 +343,22 @@ pub fn start_read_book() -> Vec<Address> {
     res
 }
 
-pub fn store_phone_book(addresses: &[Address]) -> TerminalResult<()> {
+pub fn store_phone_book(
+    addresses: &[Address],
+    identities: &[Identity],
+    vault: Option<&Vault>,
+) -> TerminalResult<()> {
     if let Some(file_name) = Address::get_phonebook_file() {
         let mut file = File::create(file_name)?;
         file.write_all(b"version = \"1.0\"\n")?;
+        if let Some(vault) = vault {
+            file.write_all(format!("vault = {}\n", vault.to_toml()).as_bytes())?;
+        }
+        if !identities.is_empty() {
+            file.write_all(
+                format!("identities = {}\n", identities_to_toml(identities)).as_bytes(),
+            )?;
+        }
 
         for addr in addresses.iter().skip(1) {
             store_address(&mut file, addr)?;
@@ -283,6 +367,22 @@ pub fn store_phone_book(addresses: &[Address]) -> TerminalResult<()> {
     Ok(())
 }
 
+/// The addresses alone, without the vault and the identities of the phonebook.
+pub fn write_bundle(out: &mut impl Write, addresses: &[Address]) -> TerminalResult<()> {
+    out.write_all(b"version = \"1.0\"\n")?;
+    for addr in addresses {
+        store_address(out, addr)?;
+    }
+    Ok(())
+}
+
+pub fn read_bundle(text: &str) -> TerminalResult<Vec<Address>> {
+    let value = text.parse::<Value>()?;
+    let mut result = Vec::new();
+    parse_addresses(&mut result, &value);
+    Ok(result)
+}
+
 fn watch<P: AsRef<Path>>(path: P) -> notify::Result<()> {
     let (tx, rx) = std::sync::mpsc::channel();
 
@@ -341,15 +441,56 @@ fn parse_address(value: &Value) -> Address {
         if let Some(Value::String(value)) = table.get("password") {
             result.password = value.clone();
         }
+        if let Some(Value::Array(values)) = table.get("identities") {
+            for value in values {
+                if let Value::String(value) = value {
+                    result.identities.push(value.clone());
+                }
+            }
+        }
+        if let Some(Value::String(value)) = table.get("default_identity") {
+            result.default_identity = value.clone();
+        }
         if let Some(Value::String(value)) = table.get("comment") {
             result.comment = value.clone();
         }
         if let Some(Value::String(value)) = table.get("auto_login") {
             result.auto_login = value.clone();
         }
+        if let Some(Value::String(value)) = table.get("login_script") {
+            result.login_script = value.clone();
+        }
+        if let Some(Value::Table(values)) = table.get("login_variables") {
+            for (name, value) in values {
+                if let Value::String(value) = value {
+                    result.login_variables.push((name.clone(), value.clone()));
+                }
+            }
+        }
+        if let Some(Value::String(value)) = table.get("script_file") {
+            result.script_file = value.clone();
+        }
+        if let Some(value) = table.get("iemsi") {
+            result.iemsi = parse_iemsi_profile(value);
+        }
+        if let Some(Value::String(value)) = table.get("font_name") {
+            result.font_name = Some(value.clone());
+        }
+        if let Some(Value::String(value)) = table.get("download_dir") {
+            result.download_dir = value.clone();
+        }
         if let Some(Value::Boolean(value)) = table.get("is_favored") {
             result.is_favored = *value;
         }
+        if let Some(Value::String(value)) = table.get("upload_protocol") {
+            result.upload_protocol = TransferType::from_name(value);
+        }
+        if let Some(Value::Array(values)) = table.get("transfer_prompts") {
+            result.transfer_prompts = values.iter().filter_map(parse_transfer_prompt).collect();
+        }
+        if let Some(Value::Array(values)) = table.get("triggers") {
+            result.triggers = values.iter().filter_map(parse_trigger).collect();
+        }
 
         if let Some(Value::Integer(value)) = table.get("number_of_calls") {
             if *value >= 0 {
@@ -410,7 +551,36 @@ fn parse_address(value: &Value) -> Address {
     result
 }
 
-fn escape(value: &str) -> String {
+fn parse_transfer_prompt(value: &Value) -> Option<TransferPrompt> {
+    let Value::Table(table) = value else {
+        return None;
+    };
+    let Some(Value::String(pattern)) = table.get("pattern") else {
+        return None;
+    };
+    if pattern.is_empty() {
+        return None;
+    }
+    let protocol = match table.get("protocol") {
+        Some(Value::String(protocol)) => match protocol.to_lowercase().as_str() {
+            "xmodem" => XYModemVariant::XModem,
+            "xmodem1k" => XYModemVariant::XModem1k,
+            "xmodem1kg" => XYModemVariant::XModem1kG,
+            "ymodem" => XYModemVariant::YModem,
+            "ymodemg" => XYModemVariant::YModemG,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let download = matches!(table.get("download"), Some(Value::Boolean(true)));
+    Some(TransferPrompt {
+        pattern: pattern.clone(),
+        protocol,
+        download,
+    })
+}
+
+pub fn escape(value: &str) -> String {
     value
         .replace('\\', "\\\\")
         .replace('"', "\\\"")
@@ -419,7 +589,7 @@ fn escape(value: &str) -> String {
         .replace('\r', "\\r")
 }
 
-fn store_address(file: &mut File, addr: &Address) -> TerminalResult<()> {
+fn store_address(file: &mut impl Write, addr: &Address) -> TerminalResult<()> {
     file.write_all(b"\n[[addresses]]\n")?;
     file.write_all(format!("system_name = \"{}\"\n", escape(&addr.system_name)).as_bytes())?;
     if addr.is_favored {
@@ -433,17 +603,83 @@ fn store_address(file: &mut File, addr: &Address) -> TerminalResult<()> {
     if !addr.password.is_empty() {
         file.write_all(format!("password = \"{}\"\n", escape(&addr.password)).as_bytes())?;
     }
+    if !addr.identities.is_empty() {
+        let names: Vec<String> = addr
+            .identities
+            .iter()
+            .map(|name| format!("\"{}\"", escape(name)))
+            .collect();
+        file.write_all(format!("identities = [{}]\n", names.join(", ")).as_bytes())?;
+    }
+    if !addr.default_identity.is_empty() {
+        file.write_all(
+            format!(
+                "default_identity = \"{}\"\n",
+                escape(&addr.default_identity)
+            )
+            .as_bytes(),
+        )?;
+    }
     if !addr.auto_login.is_empty() {
         file.write_all(format!("auto_login = \"{}\"\n", escape(&addr.auto_login)).as_bytes())?;
     }
+    if !addr.login_script.is_empty() {
+        file.write_all(format!("login_script = \"{}\"\n", escape(&addr.login_script)).as_bytes())?;
+    }
+    if !addr.login_variables.is_empty() {
+        // TOML doesn't allow a key twice, the first one wins.
+        let mut names = HashSet::new();
+        let variables: Vec<String> = addr
+            .login_variables
+            .iter()
+            .filter(|(name, _)| !name.is_empty() && names.insert(name))
+            .map(|(name, value)| format!("\"{}\" = \"{}\"", escape(name), escape(value)))
+            .collect();
+        file.write_all(format!("login_variables = {{ {} }}\n", variables.join(", ")).as_bytes())?;
+    }
+    if !addr.script_file.is_empty() {
+        file.write_all(format!("script_file = \"{}\"\n", escape(&addr.script_file)).as_bytes())?;
+    }
+    if !addr.iemsi.is_empty() {
+        file.write_all(format!("iemsi = {}\n", addr.iemsi.to_toml()).as_bytes())?;
+    }
     file.write_all(format!("terminal_type = \"{:?}\"\n", addr.terminal_type).as_bytes())?;
     if addr.ansi_music != MusicOption::Off {
         file.write_all(format!("ansi_music = \"{:?}\"\n", addr.ansi_music).as_bytes())?;
     }
     file.write_all(format!("screen_mode = \"{:?}\"\n", addr.screen_mode).as_bytes())?;
+    if let Some(font_name) = &addr.font_name {
+        file.write_all(format!("font_name = \"{}\"\n", escape(font_name)).as_bytes())?;
+    }
     if !addr.comment.is_empty() {
         file.write_all(format!("comment = \"{}\"\n", escape(&addr.comment)).as_bytes())?;
     }
+    if !addr.download_dir.is_empty() {
+        file.write_all(format!("download_dir = \"{}\"\n", escape(&addr.download_dir)).as_bytes())?;
+    }
+    if let Some(protocol) = &addr.upload_protocol {
+        if !matches!(protocol, TransferType::Text(_)) {
+            file.write_all(format!("upload_protocol = \"{protocol:?}\"\n").as_bytes())?;
+        }
+    }
+    if !addr.transfer_prompts.is_empty() {
+        let prompts: Vec<String> = addr
+            .transfer_prompts
+            .iter()
+            .map(|p| {
+                format!(
+                    "{{ pattern = \"{}\", protocol = \"{:?}\", download = {} }}",
+                    escape(&p.pattern),
+                    p.protocol,
+                    p.download
+                )
+            })
+            .collect();
+        file.write_all(format!("transfer_prompts = [{}]\n", prompts.join(", ")).as_bytes())?;
+    }
+    if !addr.triggers.is_empty() {
+        file.write_all(format!("triggers = {}\n", triggers_to_toml(&addr.triggers)).as_bytes())?;
+    }
     file.write_all(format!("number_of_calls = {}\n", addr.number_of_calls).as_bytes())?;
 
     if let Some(last_call) = addr.last_call {
@@ -1,4 +1,10 @@
-use crate::protocol::TransferType;
+use crate::{
+    address_mod::TransferPrompt,
+    protocol::{TransferType, XYModemVariant},
+};
+
+const SOH: u8 = 0x01;
+const NAK: u8 = 0x15;
 
 pub struct PatternRecognizer {
     pattern: Vec<u8>,
@@ -47,9 +53,43 @@ fn to_upper(ch: u8) -> u8 {
     }
 }
 
+/// Kermit packets start with SOH, the length, the sequence number and the type,
+/// all printable. A send-init is the first packet ('S' with sequence 0).
+#[derive(Default)]
+struct KermitRecognizer {
+    pos: usize,
+}
+
+impl KermitRecognizer {
+    fn push_ch(&mut self, ch: u8) -> bool {
+        self.pos = match (self.pos, ch) {
+            (_, SOH) => 1,
+            (1, b'#'..=b'~') => 2,
+            (2, b' ') => 3,
+            (3, b'S') => {
+                self.pos = 0;
+                return true;
+            }
+            _ => 0,
+        };
+        false
+    }
+}
+
+/// Bytes after an upload prompt the board may need before it asks for the data.
+const MAX_PROMPT_DISTANCE: usize = 256;
+
 pub struct AutoFileTransfer {
     zmodem_dl: PatternRecognizer,
     zmodem_ul: PatternRecognizer,
+    kermit: KermitRecognizer,
+    punter: PatternRecognizer,
+    prompts: Vec<(PatternRecognizer, TransferPrompt)>,
+    /// The upload prompt that was seen and the bytes since.
+    awaiting_upload: Option<(XYModemVariant, usize)>,
+    last_ch: u8,
+    /// A 'C' or 'G' that doesn't belong to a word, the second one in a row starts the upload.
+    got_request: Option<u8>,
 }
 
 impl AutoFileTransfer {
@@ -57,28 +97,139 @@ impl AutoFileTransfer {
         Self {
             zmodem_dl: PatternRecognizer::from(b"\x18B00000000000000", true),
             zmodem_ul: PatternRecognizer::from(b"\x18B0100000023be50", true),
+            kermit: KermitRecognizer::default(),
+            // Punter receivers repeat "GOO" until the sender answers, a single one is too common in text.
+            punter: PatternRecognizer::from(b"GOOGOO", false),
+            prompts: Vec::new(),
+            awaiting_upload: None,
+            last_ch: 0,
+            got_request: None,
         }
     }
 
+    /// The prompts of the board that's called.
+    pub fn set_prompts(&mut self, prompts: &[TransferPrompt]) {
+        self.prompts = prompts
+            .iter()
+            .map(|p| {
+                (
+                    PatternRecognizer::from(p.pattern.as_bytes(), true),
+                    p.clone(),
+                )
+            })
+            .collect();
+        self.reset();
+    }
+
     pub fn reset(&mut self) {
         self.zmodem_dl.reset();
         self.zmodem_ul.reset();
+        self.kermit = KermitRecognizer::default();
+        self.punter.reset();
+        for (recognizer, _) in &mut self.prompts {
+            recognizer.reset();
+        }
+        self.awaiting_upload = None;
+        self.last_ch = 0;
+        self.got_request = None;
     }
 
+    /// The transfer the remote side started and whether it's a download.
     pub fn try_transfer(&mut self, ch: u8) -> Option<(TransferType, bool)> {
+        let last_ch = std::mem::replace(&mut self.last_ch, ch);
         if self.zmodem_dl.push_ch(ch) {
             return Some((TransferType::ZModem, true));
         }
         if self.zmodem_ul.push_ch(ch) {
             return Some((TransferType::ZModem, false));
         }
+        if self.kermit.push_ch(ch) {
+            return Some((TransferType::Kermit, true));
+        }
+        if self.punter.push_ch(ch) {
+            return Some((TransferType::Punter, false));
+        }
+
+        if let Some((variant, distance)) = self.awaiting_upload {
+            if let Some(transfer_type) = self.get_upload_type(variant, last_ch, ch) {
+                self.awaiting_upload = None;
+                return Some((transfer_type, false));
+            }
+            self.awaiting_upload = if distance < MAX_PROMPT_DISTANCE {
+                Some((variant, distance + 1))
+            } else {
+                None
+            };
+        }
+        for (recognizer, prompt) in &mut self.prompts {
+            if recognizer.push_ch(ch) {
+                if prompt.download {
+                    return Some((get_transfer_type(prompt.protocol), true));
+                }
+                self.awaiting_upload = Some((prompt.protocol, 0));
+                self.got_request = None;
+            }
+        }
         None
     }
+
+    /// The receiver asks for the data with NAK (checksum), 'C' (CRC) or 'G' (streaming)
+    /// and repeats it until the sender starts. Text like "Ctrl-X" doesn't count.
+    fn get_upload_type(
+        &mut self,
+        variant: XYModemVariant,
+        last_ch: u8,
+        ch: u8,
+    ) -> Option<TransferType> {
+        let ready = ch == NAK || (matches!(ch, b'C' | b'G') && self.got_request == Some(ch));
+        self.got_request = if matches!(ch, b'C' | b'G')
+            && (self.got_request == Some(ch) || !last_ch.is_ascii_alphanumeric())
+        {
+            Some(ch)
+        } else {
+            None
+        };
+        if !ready {
+            return None;
+        }
+        Some(if ch == b'G' {
+            match variant {
+                XYModemVariant::YModem | XYModemVariant::YModemG => TransferType::YModemG,
+                _ => TransferType::XModem1kG,
+            }
+        } else {
+            get_transfer_type(variant)
+        })
+    }
+}
+
+fn get_transfer_type(variant: XYModemVariant) -> TransferType {
+    match variant {
+        XYModemVariant::XModem => TransferType::XModem,
+        XYModemVariant::XModem1k => TransferType::XModem1k,
+        XYModemVariant::XModem1kG => TransferType::XModem1kG,
+        XYModemVariant::YModem => TransferType::YModem,
+        XYModemVariant::YModemG => TransferType::YModemG,
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::auto_file_transfer::PatternRecognizer;
+    use crate::{
+        address_mod::TransferPrompt,
+        auto_file_transfer::{AutoFileTransfer, PatternRecognizer},
+        protocol::{TransferType, XYModemVariant},
+    };
+
+    fn feed(auto: &mut AutoFileTransfer, data: &[u8]) -> Option<(TransferType, bool)> {
+        let mut result = None;
+        for b in data {
+            if let Some(transfer) = auto.try_transfer(*b) {
+                result = Some(transfer);
+            }
+        }
+        result
+    }
 
     #[test]
     fn test_pattern_recognizer() {
@@ -141,4 +292,94 @@ mod tests {
         }
         assert!(!result);
     }
+
+    #[test]
+    fn test_detect_zmodem() {
+        let mut auto = AutoFileTransfer::new();
+        let result = feed(&mut auto, b"**\x18B00000000000000\r\n");
+        assert_eq!(Some((TransferType::ZModem, true)), result);
+    }
+
+    #[test]
+    fn test_detect_kermit_send_init() {
+        let mut auto = AutoFileTransfer::new();
+        assert!(feed(&mut auto, b"Kermit Server ready\r\n").is_none());
+        let result = feed(&mut auto, b"\x01) S~* @-#Y3~^>J)0___F\r");
+        assert_eq!(Some((TransferType::Kermit, true)), result);
+    }
+
+    /// The GOOs come from a receiver, the board waits for an upload.
+    #[test]
+    fn test_detect_punter() {
+        let mut auto = AutoFileTransfer::new();
+        assert!(feed(&mut auto, b"GOODBYE GOO").is_none());
+        let result = feed(&mut auto, b"GOOGOO");
+        assert_eq!(Some((TransferType::Punter, false)), result);
+    }
+
+    fn prompts() -> Vec<TransferPrompt> {
+        vec![
+            TransferPrompt {
+                pattern: "Begin your YModem upload".to_string(),
+                protocol: XYModemVariant::YModem,
+                download: false,
+            },
+            TransferPrompt {
+                pattern: "Start your XModem download".to_string(),
+                protocol: XYModemVariant::XModem1k,
+                download: true,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_detect_upload_prompt() {
+        let mut auto = AutoFileTransfer::new();
+        auto.set_prompts(&prompts());
+        // a 'C' without the prompt means nothing
+        assert!(feed(&mut auto, b"CC\r\n").is_none());
+        assert!(feed(&mut auto, b"begin your ymodem upload now.\r\n").is_none());
+        assert!(feed(&mut auto, b"C").is_none());
+        let result = feed(&mut auto, b"C");
+        assert_eq!(Some((TransferType::YModem, false)), result);
+
+        auto.reset();
+        let result = feed(&mut auto, b"Begin your YModem upload\r\nGG");
+        assert_eq!(Some((TransferType::YModemG, false)), result);
+
+        auto.reset();
+        let result = feed(&mut auto, b"Begin your YModem upload\r\n\x15");
+        assert_eq!(Some((TransferType::YModem, false)), result);
+    }
+
+    /// Only a 'C' on its own asks for the data, not the one of a word.
+    #[test]
+    fn test_upload_prompt_ignores_text() {
+        let mut auto = AutoFileTransfer::new();
+        auto.set_prompts(&prompts());
+        let result = feed(
+            &mut auto,
+            b"Begin your YModem upload, Ctrl-X to abort. ACCESS GRANTED. CG",
+        );
+        assert!(result.is_none());
+        let result = feed(&mut auto, b" CCC");
+        assert_eq!(Some((TransferType::YModem, false)), result);
+    }
+
+    #[test]
+    fn test_detect_download_prompt() {
+        let mut auto = AutoFileTransfer::new();
+        auto.set_prompts(&prompts());
+        let result = feed(&mut auto, b"Start your XModem download");
+        assert_eq!(Some((TransferType::XModem1k, true)), result);
+    }
+
+    #[test]
+    fn test_upload_prompt_expires() {
+        let mut auto = AutoFileTransfer::new();
+        auto.set_prompts(&prompts());
+        assert!(feed(&mut auto, b"Begin your YModem upload").is_none());
+        assert!(feed(&mut auto, &[b'.'; 300]).is_none());
+        assert!(feed(&mut auto, b"CC").is_none());
+    }
 }
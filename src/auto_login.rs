@@ -1,199 +1,98 @@
 use crate::{
-    address_mod::Address, auto_file_transfer::PatternRecognizer, com::Connection, iemsi_mod::IEmsi,
+    address_mod::Address,
+    com::Connection,
+    credentials::Credentials,
+    iemsi_mod::IEmsi,
+    login_script::{
+        get_variables, parse_script, translate_legacy, ScriptAction, ScriptError, ScriptRunner,
+    },
     TerminalResult,
 };
-use std::{
-    io::{self, ErrorKind},
-    time::{Duration, SystemTime},
-};
+use std::time::Instant;
 
 pub struct AutoLogin {
     pub logged_in: bool,
     pub disabled: bool,
     pub iemsi: Option<IEmsi>,
-    last_char_recv: SystemTime,
-    first_char_recv: Option<SystemTime>,
-    continue_time: SystemTime,
-
-    login_expr: Vec<u8>,
-    cur_expr_idx: usize,
-    got_name: bool,
-    name_recognizer: PatternRecognizer,
-    login_recognizer: PatternRecognizer,
+    /// Opened from the credential store when calling.
+    pub credentials: Credentials,
+    script: Option<ScriptRunner>,
 }
 
-impl AutoLogin {
-    pub fn new(login_expr: &str) -> Self {
+impl Default for AutoLogin {
+    fn default() -> Self {
         Self {
             logged_in: false,
             disabled: false,
             iemsi: Some(IEmsi::new()),
-            first_char_recv: None,
-            last_char_recv: SystemTime::now(),
-            continue_time: SystemTime::now(),
-            login_expr: login_expr.as_bytes().to_vec(),
-            cur_expr_idx: 0,
-            got_name: false,
-            name_recognizer: PatternRecognizer::from(b"NAME", true),
-            login_recognizer: PatternRecognizer::from(b"LOGIN:", true),
+            credentials: Credentials::default(),
+            script: None,
         }
     }
+}
 
-    pub fn run_command(&mut self, con: &mut Connection, adr: &Address) -> TerminalResult<bool> {
-        let ch = *self.login_expr.get(self.cur_expr_idx + 1).unwrap();
-        match ch {
-            b'D' => {
-                // Delay for x seconds. !D4= Delay for 4 seconds
-                let ch = self.login_expr[self.cur_expr_idx + 2];
-                self.continue_time =
-                    self.last_char_recv + Duration::from_secs(u64::from(ch - b'0'));
-                self.cur_expr_idx += 3;
-            }
-            b'E' => {
-                // wait until data came in
-                match self.first_char_recv {
-                    Some(_) => {
-                        if SystemTime::now()
-                            .duration_since(self.last_char_recv)
-                            .unwrap()
-                            .as_millis()
-                            < 500
-                        {
-                            return Ok(true);
-                        }
-                    }
-                    _ => return Ok(true),
-                }
-                self.cur_expr_idx += 2;
-                return Ok(true);
-            }
-            b'W' => {
-                // Wait for one of the name questions defined arrives
-                if self.got_name {
-                    self.cur_expr_idx += 2;
-                }
-                return Ok(false);
-            }
-            b'N' => {
-                // Send full user name of active user
-                self.cur_expr_idx += 2;
-                con.send((adr.user_name.clone() + "\r").as_bytes().to_vec())?;
-            }
-            b'F' => {
-                // Send first name of active user
-                self.cur_expr_idx += 2;
-                con.send((adr.user_name.clone() + "first\r").as_bytes().to_vec())?;
-                // TODO
-            }
-            b'L' => {
-                // Send last name of active user
-                self.cur_expr_idx += 2;
-                con.send((adr.user_name.clone() + "last\r").as_bytes().to_vec())?;
-                // TODO
-            }
-            b'P' => {
-                // Send password from active user
-                self.cur_expr_idx += 2;
-                con.send((adr.password.clone() + "\r").as_bytes().to_vec())?;
-                self.logged_in = true;
-            }
-            b'I' => {
-                // Disable IEMSI in this session
-                self.cur_expr_idx += 2;
-                if let Some(iemsi) = &mut self.iemsi {
-                    iemsi.aborted = true;
-                }
-            }
-            ch => {
-                con.send(vec![ch])?;
-                self.cur_expr_idx += 1;
-            }
-        }
-
-        Ok(true)
+impl AutoLogin {
+    /// Uses the login script of the address, or the translated autologin string.
+    pub fn new(adr: &Address, credentials: Credentials) -> Result<Self, ScriptError> {
+        let script = if !adr.login_script.trim().is_empty() {
+            Some(parse_script(&adr.login_script)?)
+        } else if !adr.auto_login.is_empty()
+            && !credentials.user_name.is_empty()
+            && !credentials.password.is_empty()
+        {
+            Some(parse_script(&translate_legacy(&adr.auto_login))?)
+        } else {
+            None
+        };
+        Ok(Self {
+            script: script.map(ScriptRunner::new),
+            credentials,
+            ..Default::default()
+        })
     }
 
-    pub fn try_login(&mut self, con: &mut Connection, adr: &Address, ch: u8) -> TerminalResult<()> {
-        if self.logged_in || self.disabled {
+    pub fn try_login(&mut self, con: &mut Connection, ch: u8) -> TerminalResult<()> {
+        if self.disabled {
             return Ok(());
         }
-        if adr.user_name.is_empty() || adr.password.is_empty() {
-            self.logged_in = true;
+        if let Some(script) = &mut self.script {
+            script.push_data(Instant::now(), ch);
+        }
+        if self.logged_in {
             return Ok(());
         }
-
-        if self.first_char_recv.is_none() && (ch.is_ascii_uppercase() || ch.is_ascii_lowercase()) {
-            self.first_char_recv = Some(SystemTime::now());
+        if self.credentials.user_name.is_empty() || self.credentials.password.is_empty() {
+            self.logged_in = true;
+            return Ok(());
         }
 
-        self.last_char_recv = SystemTime::now();
-        self.got_name |= self.name_recognizer.push_ch(ch) | self.login_recognizer.push_ch(ch);
-
         if let Some(iemsi) = &mut self.iemsi {
-            self.logged_in |= iemsi.try_login(con, adr, ch)?;
+            self.logged_in |= iemsi.try_login(con, &self.credentials, ch)?;
         }
         Ok(())
     }
 
+    /// Fails if the script aborts, it doesn't run after that.
     pub fn run_autologin(&mut self, con: &mut Connection, adr: &Address) -> TerminalResult<()> {
-        if self.logged_in && self.cur_expr_idx >= self.login_expr.len() || self.disabled {
+        if self.disabled {
             return Ok(());
         }
-        if adr.user_name.is_empty() || adr.password.is_empty() {
-            self.logged_in = true;
-            return Ok(());
-        }
-
-        if adr.auto_login.is_empty() {
+        let Some(script) = &mut self.script else {
             return Ok(());
-        }
-
-        if SystemTime::now() < self.continue_time {
+        };
+        if script.is_finished() {
+            self.script = None;
             return Ok(());
         }
-        if self.cur_expr_idx < self.login_expr.len() {
-            match self.login_expr.get(self.cur_expr_idx).unwrap() {
-                b'!' => {
-                    self.run_command(con, adr)?;
-                }
-                b'\\' => {
-                    while self.cur_expr_idx < self.login_expr.len()
-                        && self.login_expr[self.cur_expr_idx] == b'\\'
-                    {
-                        self.cur_expr_idx += 1; // escape
-                        let ch = self.login_expr.get(self.cur_expr_idx).unwrap();
-                        match ch {
-                            b'e' => {
-                                con.send(vec![0x1B])?;
-                            }
-                            b'n' => {
-                                con.send(vec![b'\n'])?;
-                            }
-                            b'r' => {
-                                con.send(vec![b'\r'])?;
-                            }
-                            b't' => {
-                                con.send(vec![b'\t'])?;
-                            }
-                            ch => {
-                                self.cur_expr_idx += 1; // escape
-                                return Err(Box::new(io::Error::new(
-                                    ErrorKind::InvalidData,
-                                    format!(
-                                        "invalid escape sequence in autologin string: {:?}",
-                                        char::from_u32(u32::from(*ch))
-                                    ),
-                                )));
-                            }
-                        }
-                        self.cur_expr_idx += 1; // escape
+        for action in script.run(Instant::now(), &get_variables(adr, &self.credentials))? {
+            match action {
+                ScriptAction::Send(data) => con.send(data)?,
+                ScriptAction::DisableIemsi => {
+                    if let Some(iemsi) = &mut self.iemsi {
+                        iemsi.aborted = true;
                     }
-                    self.last_char_recv = SystemTime::now();
-                }
-                ch => {
-                    con.send(vec![*ch])?;
-                    self.cur_expr_idx += 1;
                 }
+                ScriptAction::LoggedIn => self.logged_in = true,
             }
         }
         Ok(())
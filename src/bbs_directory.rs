@@ -0,0 +1,347 @@
+//! Offline BBS listings, like the CSV and XML files of the Telnet BBS Guide.
+//! The columns or elements are found by their names, so similar lists load as well.
+use std::{collections::HashMap, fs, path::Path};
+
+use quick_xml::{
+    events::{BytesStart, Event},
+    Reader,
+};
+
+use crate::{
+    address_mod::{Address, Terminal},
+    phonebook_io::{join_port, split_port},
+    ui::screen_modes::ScreenMode,
+    TerminalResult,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirectoryEntry {
+    pub name: String,
+    pub address: String,
+    pub software: String,
+    pub country: String,
+    pub sysop: String,
+    pub comment: String,
+    pub terminal_type: Terminal,
+    pub screen_mode: ScreenMode,
+}
+
+impl DirectoryEntry {
+    pub fn to_address(&self) -> Address {
+        let mut adr = Address::new(self.name.clone());
+        adr.address = self.address.clone();
+        adr.terminal_type = self.terminal_type;
+        adr.screen_mode = self.screen_mode;
+        adr.comment = self.comment.clone();
+        adr
+    }
+}
+
+/// Empty fields match everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DirectoryFilter {
+    pub text: String,
+    pub software: String,
+    pub country: String,
+    pub terminal_type: Option<Terminal>,
+}
+
+impl DirectoryFilter {
+    pub fn matches(&self, entry: &DirectoryEntry) -> bool {
+        let text = self.text.to_lowercase();
+        (text.is_empty()
+            || entry.name.to_lowercase().contains(&text)
+            || entry.address.to_lowercase().contains(&text)
+            || entry.comment.to_lowercase().contains(&text))
+            && (self.software.is_empty() || entry.software == self.software)
+            && (self.country.is_empty() || entry.country == self.country)
+            && self
+                .terminal_type
+                .map_or(true, |t| t == entry.terminal_type)
+    }
+}
+
+/// The distinct non empty values of a field, sorted for the filter lists.
+pub fn get_values(
+    entries: &[DirectoryEntry],
+    field: fn(&DirectoryEntry) -> &String,
+) -> Vec<String> {
+    let mut values: Vec<String> = entries
+        .iter()
+        .map(field)
+        .filter(|v| !v.is_empty())
+        .cloned()
+        .collect();
+    values.sort_by_key(|v| v.to_lowercase());
+    values.dedup();
+    values
+}
+
+const PETSCII_HINTS: [&str; 7] = [
+    "petscii",
+    "c64",
+    "c128",
+    "commodore",
+    "image bbs",
+    "c*base",
+    "color 64",
+];
+const ATASCII_HINTS: [&str; 4] = ["atascii", "atari", "bbs express", "carina"];
+const VIEWDATA_HINTS: [&str; 4] = ["viewdata", "videotex", "prestel", "teletext"];
+
+/// The listing's terminal column, or the software if it's only for one computer.
+fn guess_terminal(terminal: &str, software: &str) -> (Terminal, ScreenMode) {
+    let terminal = terminal.to_lowercase();
+    let software = software.to_lowercase();
+    let has_hint = |hints: &[&str]| {
+        hints
+            .iter()
+            .any(|h| terminal.contains(h) || software.contains(h))
+    };
+    if has_hint(&PETSCII_HINTS) {
+        (Terminal::PETscii, ScreenMode::Vic)
+    } else if has_hint(&ATASCII_HINTS) {
+        (Terminal::ATAscii, ScreenMode::Antic)
+    } else if has_hint(&VIEWDATA_HINTS) {
+        (Terminal::ViewData, ScreenMode::Videotex)
+    } else if terminal.contains("avatar") {
+        (Terminal::Avatar, ScreenMode::Vga(80, 25))
+    } else {
+        (Terminal::Ansi, ScreenMode::Vga(80, 25))
+    }
+}
+
+/// Lower case letters and digits, "BBS Name" and "bbs_name" are the same column.
+fn normalize_key(key: &str) -> String {
+    key.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+fn create_entry(fields: &HashMap<String, String>) -> Option<DirectoryEntry> {
+    let get = |keys: &[&str]| {
+        keys.iter()
+            .find_map(|k| fields.get(*k).filter(|v| !v.is_empty()))
+            .map(|v| v.trim().to_string())
+            .unwrap_or_default()
+    };
+    let address = get(&[
+        "address",
+        "telnet",
+        "telnetaddress",
+        "host",
+        "hostname",
+        "url",
+    ]);
+    if address.is_empty() {
+        return None;
+    }
+    let address = address
+        .split_once("://")
+        .map_or(address.as_str(), |(_, a)| a)
+        .trim_end_matches('/');
+    let (host, port) = split_port(address);
+    let port = get(&["port", "telnetport"]).parse().ok().or(port);
+    let software = get(&["software", "bbssoftware"]);
+    let (terminal_type, screen_mode) = guess_terminal(
+        &get(&["terminal", "terminaltype", "emulation", "term"]),
+        &software,
+    );
+    let name = get(&["name", "bbsname", "bbs", "system", "title"]);
+    Some(DirectoryEntry {
+        name: if name.is_empty() {
+            host.to_string()
+        } else {
+            name
+        },
+        address: join_port(host, port, 23),
+        software,
+        country: get(&["country", "location"]),
+        sysop: get(&["sysop"]),
+        comment: get(&["comment", "description", "notes"]),
+        terminal_type,
+        screen_mode,
+    })
+}
+
+/// Splits into records of fields, quoted fields may have commas, quotes and line breaks.
+fn read_csv(text: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => record.push(std::mem::take(&mut field)),
+            '\r' if !in_quotes => {}
+            '\n' if !in_quotes => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            ch => field.push(ch),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records
+}
+
+pub fn parse_csv(text: &str) -> Vec<DirectoryEntry> {
+    let mut records = read_csv(text.trim_start_matches('\u{FEFF}')).into_iter();
+    let Some(header) = records.next() else {
+        return Vec::new();
+    };
+    let header: Vec<String> = header.iter().map(|h| normalize_key(h)).collect();
+    records
+        .filter_map(|record| {
+            let fields = header.iter().cloned().zip(record).collect();
+            create_entry(&fields)
+        })
+        .collect()
+}
+
+fn get_attributes(e: &BytesStart) -> TerminalResult<HashMap<String, String>> {
+    let mut fields = HashMap::new();
+    for attribute in e.attributes().flatten() {
+        fields.insert(
+            normalize_key(&String::from_utf8_lossy(attribute.key.as_ref())),
+            attribute.unescape_value()?.to_string(),
+        );
+    }
+    Ok(fields)
+}
+
+/// An element with elements that only have text, or with attributes only, is an entry.
+pub fn parse_xml(text: &str) -> TerminalResult<Vec<DirectoryEntry>> {
+    let mut reader = Reader::from_str(text);
+    reader.trim_text(true);
+    let mut result = Vec::new();
+    // The fields of the open elements, if they have child elements, and the text of the
+    // innermost one.
+    let mut stack: Vec<(HashMap<String, String>, bool)> = Vec::new();
+    let mut text = String::new();
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => {
+                if let Some((_, has_children)) = stack.last_mut() {
+                    *has_children = true;
+                }
+                stack.push((get_attributes(&e)?, false));
+                text.clear();
+            }
+            Event::Empty(e) => result.extend(create_entry(&get_attributes(&e)?)),
+            Event::Text(t) => text.push_str(&t.unescape()?),
+            Event::CData(t) => text.push_str(&String::from_utf8_lossy(&t.into_inner())),
+            Event::End(e) => {
+                let Some((fields, has_children)) = stack.pop() else {
+                    continue;
+                };
+                if has_children {
+                    result.extend(create_entry(&fields));
+                } else if let Some((parent, _)) = stack.last_mut() {
+                    let key = normalize_key(&String::from_utf8_lossy(e.name().as_ref()));
+                    parent.insert(key, std::mem::take(&mut text));
+                }
+                text.clear();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(result)
+}
+
+pub fn load_directory(path: &Path) -> TerminalResult<Vec<DirectoryEntry>> {
+    // the lists come from all over the world, not every one is valid UTF-8
+    let data = fs::read(path)?;
+    let text = String::from_utf8_lossy(&data);
+    let is_xml = path
+        .extension()
+        .map_or(false, |e| e.eq_ignore_ascii_case("xml"))
+        || text.trim_start().starts_with('<');
+    if is_xml {
+        parse_xml(&text)
+    } else {
+        Ok(parse_csv(&text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        address_mod::Terminal,
+        bbs_directory::{get_values, parse_csv, parse_xml, DirectoryFilter},
+        ui::screen_modes::ScreenMode,
+    };
+
+    #[test]
+    fn test_parse_csv() {
+        let entries = parse_csv(
+            "BBS Name,Telnet Address,Port,Software,Country,Terminal\r\n\
+             Dark Realms,dark.example.com,23,Mystic,USA,ANSI\r\n\
+             \"Commodore, Inc.\",c64.example.com,6400,Image BBS,Germany,\r\n\
+             No Address,,23,Synchronet,USA,ANSI\r\n",
+        );
+        assert_eq!(2, entries.len());
+        assert_eq!("Dark Realms", entries[0].name);
+        assert_eq!("dark.example.com", entries[0].address);
+        assert_eq!(Terminal::Ansi, entries[0].terminal_type);
+        assert_eq!("Commodore, Inc.", entries[1].name);
+        assert_eq!("c64.example.com:6400", entries[1].address);
+        assert_eq!(Terminal::PETscii, entries[1].terminal_type);
+        assert_eq!(ScreenMode::Vic, entries[1].screen_mode);
+
+        let filter = DirectoryFilter {
+            country: "Germany".to_string(),
+            ..Default::default()
+        };
+        assert!(!filter.matches(&entries[0]));
+        assert!(filter.matches(&entries[1]));
+        assert_eq!(vec!["Germany", "USA"], get_values(&entries, |e| &e.country));
+    }
+
+    #[test]
+    fn test_parse_xml() {
+        let entries = parse_xml(
+            r#"<?xml version="1.0"?>
+            <bbslist>
+              <bbs>
+                <name>Atari Haven</name>
+                <telnet>telnet://atari.example.com:2323</telnet>
+                <software>BBS Express Pro</software>
+                <country>Canada</country>
+              </bbs>
+              <bbs>
+                <name><![CDATA[Tele & Text]]></name>
+                <address>viewdata.example.com</address>
+                <terminal>Viewdata</terminal>
+              </bbs>
+              <bbs name="Attribute BBS" host="attr.example.com" />
+            </bbslist>"#,
+        )
+        .unwrap();
+        assert_eq!(3, entries.len());
+        assert_eq!("Atari Haven", entries[0].name);
+        assert_eq!("atari.example.com:2323", entries[0].address);
+        assert_eq!(Terminal::ATAscii, entries[0].terminal_type);
+        assert_eq!(ScreenMode::Antic, entries[0].screen_mode);
+        assert_eq!("Tele & Text", entries[1].name);
+        assert_eq!(Terminal::ViewData, entries[1].terminal_type);
+        assert_eq!("attr.example.com", entries[2].address);
+
+        let filter = DirectoryFilter {
+            terminal_type: Some(Terminal::ATAscii),
+            ..Default::default()
+        };
+        assert_eq!(1, entries.iter().filter(|e| filter.matches(e)).count());
+    }
+}
@@ -0,0 +1,86 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::Local;
+
+/// Writes the received data as it is to a file, ANSI and all.
+#[derive(Default)]
+pub struct Capture {
+    file: Option<(PathBuf, BufWriter<File>)>,
+}
+
+/// `board` made safe for a file name.
+fn get_capture_name(board: &str) -> String {
+    let board: String = board
+        .chars()
+        .map(|ch| if ch.is_alphanumeric() { ch } else { '_' })
+        .collect();
+    let board = board.trim_matches('_');
+    let time = Local::now().format("%Y%m%d-%H%M%S");
+    if board.is_empty() {
+        format!("capture-{time}.ans")
+    } else {
+        format!("{board}-{time}.ans")
+    }
+}
+
+impl Capture {
+    pub fn is_capturing(&self) -> bool {
+        self.file.is_some()
+    }
+
+    /// Returns the new capture file, a running capture goes on.
+    pub fn start(&mut self, dir: &Path, board: &str) -> io::Result<PathBuf> {
+        if let Some((path, _)) = &self.file {
+            return Ok(path.clone());
+        }
+        fs::create_dir_all(dir)?;
+        let path = dir.join(get_capture_name(board));
+        let file = File::create(&path)?;
+        self.file = Some((path.clone(), BufWriter::new(file)));
+        Ok(path)
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        if let Some((_, file)) = &mut self.file {
+            file.write_all(data)?;
+        }
+        Ok(())
+    }
+
+    pub fn stop(&mut self) -> io::Result<Option<PathBuf>> {
+        match self.file.take() {
+            Some((path, mut file)) => {
+                file.flush()?;
+                Ok(Some(path))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::capture::Capture;
+
+    #[test]
+    fn test_capture() {
+        let dir = std::env::temp_dir().join("icy_term_test_capture");
+        let mut capture = Capture::default();
+        let path = capture.start(&dir, "Some/BBS").unwrap();
+        assert!(path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("Some_BBS-"));
+        assert_eq!(path, capture.start(&dir, "Other").unwrap());
+        capture.write(b"\x1B[1mHello").unwrap();
+        assert_eq!(Some(path.clone()), capture.stop().unwrap());
+        assert!(!capture.is_capturing());
+        assert_eq!(b"\x1B[1mHello".to_vec(), std::fs::read(&path).unwrap());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -35,6 +35,11 @@ pub trait Com: Sync + Send {
     async fn read_exact(&mut self, len: usize) -> TermComResult<Vec<u8>>;
     fn set_terminal_type(&mut self, terminal: Terminal);
 
+    /// Sends a break signal, does nothing on connections that don't have one.
+    async fn send_break(&mut self) -> TermComResult<()> {
+        Ok(())
+    }
+
     fn disconnect(&mut self) -> TermComResult<()>;
 }
 
@@ -589,6 +589,20 @@ impl Com for ComTelnetImpl {
         }
     }
 
+    async fn send_break(&mut self) -> TermComResult<()> {
+        if self.use_raw_transfer {
+            return Ok(());
+        }
+        if let Some(stream) = self.tcp_stream.as_mut() {
+            stream
+                .write_all(&[telnet_cmd::Iac, telnet_cmd::Break])
+                .await?;
+            Ok(())
+        } else {
+            Err(Box::new(ConnectionError::ConnectionLost))
+        }
+    }
+
     fn disconnect(&mut self) -> TermComResult<()> {
         // self.tcp_stream.shutdown(std::net::Shutdown::Both)
         Ok(())
@@ -1,4 +1,6 @@
 use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -6,14 +8,102 @@ use async_trait::async_trait;
 use eframe::epaint::mutex::Mutex;
 
 use crate::address_mod::Address;
+use crate::rng::Rng;
 
 use super::{Com, TermComResult};
 
+const XON: u8 = 0x11;
+const XOFF: u8 = 0x13;
+const CAN: u8 = 0x18;
+
+/// Line faults a `TestCom` applies to the bytes it sends. Rates are the chance per byte.
+#[derive(Debug, Clone, Default)]
+pub struct Impairments {
+    pub seed: u64,
+    pub bit_flip_rate: f64,
+    pub drop_rate: f64,
+    pub duplicate_rate: f64,
+    /// Inserts XON/XOFF, like a modem doing software flow control.
+    pub flow_control_rate: f64,
+    /// Inserts a burst of 1 - 5 CAN bytes.
+    pub can_burst_rate: f64,
+    pub latency: Duration,
+    /// The connection drops after that many bytes were sent.
+    pub truncate_after: Option<usize>,
+}
+
+impl Impairments {
+    pub fn with_seed(&self, seed: u64) -> Self {
+        Self {
+            seed,
+            ..self.clone()
+        }
+    }
+}
+
+fn hit(rng: &mut Rng, rate: f64) -> bool {
+    rate > 0.0 && f64::from(rng.next()) / f64::from(0x7fff_ffff) < rate
+}
+
 pub struct TestCom {
     name: String,
     write_buf: Arc<Mutex<std::collections::VecDeque<u8>>>,
     read_buf: Arc<Mutex<std::collections::VecDeque<u8>>>,
     pub cmd_table: HashMap<u8, String>,
+    /// Positions in the written stream that get garbled, simulates a noisy line.
+    corrupt_at: Vec<usize>,
+    bytes_written: usize,
+    impairments: Impairments,
+    rng: Rng,
+    /// Shared by both ends, set once the connection dropped.
+    closed: Arc<AtomicBool>,
+    /// Prints everything that gets read and written.
+    verbose: bool,
+}
+
+impl TestCom {
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    fn connection_lost() -> Box<Error> {
+        Box::new(Error::new(ErrorKind::ConnectionAborted, "connection lost"))
+    }
+
+    /// Waits until `len` bytes are available, fails if the connection dropped before.
+    async fn wait_for_data(&self, len: usize) -> TermComResult<()> {
+        while self.read_buf.lock().len() < len {
+            if self.is_closed() {
+                return Err(TestCom::connection_lost());
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        Ok(())
+    }
+
+    fn impair(&mut self, b: u8, out: &mut Vec<u8>) {
+        let imp = &self.impairments;
+        let rng = &mut self.rng;
+        if hit(rng, imp.flow_control_rate) {
+            out.push(if rng.gen_range(0..2) == 0 { XON } else { XOFF });
+        }
+        if hit(rng, imp.can_burst_rate) {
+            let len = rng.gen_range(1..=5) as usize;
+            out.resize(out.len() + len, CAN);
+        }
+        if hit(rng, imp.drop_rate) {
+            return;
+        }
+        let b = if hit(rng, imp.bit_flip_rate) {
+            b ^ (1 << rng.gen_range(0..8))
+        } else {
+            b
+        };
+        out.push(b);
+        if hit(rng, imp.duplicate_rate) {
+            out.push(b);
+        }
+    }
 }
 
 pub fn indent_receiver() {
@@ -33,11 +123,20 @@ impl Com for TestCom {
     }
 
     async fn read_data(&mut self) -> TermComResult<Vec<u8>> {
+        let result: Vec<u8> = self.read_buf.lock().drain(0..).collect();
+        if result.is_empty() {
+            if self.is_closed() {
+                return Err(TestCom::connection_lost());
+            }
+            return Ok(result);
+        }
+        if !self.verbose {
+            return Ok(result);
+        }
+
         if self.name == "receiver" {
             indent_receiver();
         }
-        let result: Vec<u8> = self.read_buf.lock().drain(0..).collect();
-
         if result.len() == 1 {
             if let Some(cmd) = self.cmd_table.get(&result[0]) {
                 println!("{} {}({} 0x{})", self.name, cmd, result[0], result[0]);
@@ -52,35 +151,62 @@ impl Com for TestCom {
     }
 
     async fn send<'a>(&mut self, buf: &'a [u8]) -> TermComResult<usize> {
-        if self.name == "receiver" {
-            indent_receiver();
+        if self.verbose {
+            if self.name == "receiver" {
+                indent_receiver();
+            }
+            if buf.len() == 1 {
+                if let Some(cmd) = self.cmd_table.get(&buf[0]) {
+                    println!("{} {}({} 0x{})", self.name, cmd, buf[0], buf[0]);
+                } else {
+                    println!("{} writes {} 0x{:X}", self.name, buf[0], buf[0]);
+                }
+            } else {
+                println!("{} writes {:?} #{}", self.name, buf, buf.len());
+            }
         }
-        if buf.len() == 1 {
-            if let Some(cmd) = self.cmd_table.get(&buf[0]) {
-                println!("{} {}({} 0x{})", self.name, cmd, buf[0], buf[0]);
+        if self.is_closed() {
+            return Err(TestCom::connection_lost());
+        }
+        if !self.impairments.latency.is_zero() {
+            tokio::time::sleep(self.impairments.latency).await;
+        }
+        let mut out = Vec::with_capacity(buf.len());
+        for b in buf {
+            if let Some(truncate_after) = self.impairments.truncate_after {
+                if self.bytes_written >= truncate_after {
+                    self.closed.store(true, Ordering::SeqCst);
+                    break;
+                }
+            }
+            if self.corrupt_at.contains(&self.bytes_written) {
+                out.push(*b ^ 0x01);
             } else {
-                println!("{} writes {} 0x{:X}", self.name, buf[0], buf[0]);
+                self.impair(*b, &mut out);
             }
-        } else {
-            println!("{} writes {:?} #{}", self.name, buf, buf.len());
+            self.bytes_written += 1;
+        }
+        self.write_buf.lock().extend(out);
+        if self.is_closed() {
+            return Err(TestCom::connection_lost());
         }
-        self.write_buf.lock().extend(buf.iter());
         Ok(buf.len())
     }
 
     async fn read_u8(&mut self) -> TermComResult<u8> {
-        if self.name == "receiver" {
+        if self.verbose && self.name == "receiver" {
             indent_receiver();
         }
-        while self.read_buf.lock().is_empty() {
-            tokio::time::sleep(Duration::from_millis(10)).await;
-        }
+        self.wait_for_data(1).await?;
 
-        if let Some(b) = self.read_buf.lock().pop_front() {
-            if let Some(cmd) = self.cmd_table.get(&b) {
-                println!("{} {}({} 0x{})", self.name, cmd, b, b);
-            } else {
-                println!("{} reads {} 0x{:X}", self.name, b, b);
+        let b = self.read_buf.lock().pop_front();
+        if let Some(b) = b {
+            if self.verbose {
+                if let Some(cmd) = self.cmd_table.get(&b) {
+                    println!("{} {}({} 0x{})", self.name, cmd, b, b);
+                } else {
+                    println!("{} reads {} 0x{:X}", self.name, b, b);
+                }
             }
             Ok(b)
         } else {
@@ -92,6 +218,7 @@ impl Com for TestCom {
     }
 
     async fn read_exact(&mut self, len: usize) -> TermComResult<Vec<u8>> {
+        self.wait_for_data(len).await?;
         let result: Vec<u8> = self.read_buf.lock().drain(0..len).collect();
         Ok(result)
     }
@@ -111,27 +238,59 @@ pub struct TestChannel {
 #[cfg(test)]
 impl TestChannel {
     pub fn new() -> Self {
+        TestChannel::new_lossy(Vec::new())
+    }
+
+    /// The bytes the sender writes at the given positions arrive garbled.
+    pub fn new_lossy(corrupt_at: Vec<usize>) -> Self {
+        TestChannel::create(corrupt_at, &Impairments::default(), true)
+    }
+
+    /// Both directions suffer from the same kind of faults, each with its own random sequence.
+    pub fn new_impaired(impairments: &Impairments) -> Self {
+        TestChannel::create(Vec::new(), impairments, true)
+    }
+
+    /// Doesn't print the traffic, for large transfers.
+    pub fn new_silent() -> Self {
+        TestChannel::create(Vec::new(), &Impairments::default(), false)
+    }
+
+    fn create(corrupt_at: Vec<usize>, impairments: &Impairments, verbose: bool) -> Self {
         let b1 = Arc::new(Mutex::new(std::collections::VecDeque::new()));
         let b2 = Arc::new(Mutex::new(std::collections::VecDeque::new()));
+        let closed = Arc::new(AtomicBool::new(false));
         Self {
             sender: Box::new(TestCom {
                 name: "sender".to_string(),
                 read_buf: b1.clone(),
                 write_buf: b2.clone(),
                 cmd_table: HashMap::new(),
+                corrupt_at,
+                bytes_written: 0,
+                impairments: impairments.clone(),
+                rng: Rng::from_seed(impairments.seed),
+                closed: closed.clone(),
+                verbose,
             }),
             receiver: Box::new(TestCom {
                 name: "receiver".to_string(),
                 read_buf: b2,
                 write_buf: b1,
                 cmd_table: HashMap::new(),
+                corrupt_at: Vec::new(),
+                bytes_written: 0,
+                impairments: impairments.clone(),
+                rng: Rng::from_seed(impairments.seed ^ 0xFFFF),
+                closed,
+                verbose,
             }),
         }
     }
 }
 
 mod communication_tests {
-    use crate::com::TestChannel;
+    use crate::com::{Impairments, TestChannel};
 
     #[tokio::test]
     async fn test_simple() {
@@ -147,4 +306,35 @@ mod communication_tests {
         let _ = test.sender.send(&[42]).await;
         assert_eq!(42, test.receiver.read_u8().await.unwrap());
     }
+
+    #[tokio::test]
+    async fn test_impairments_are_reproducible() {
+        let impairments = Impairments {
+            seed: 42,
+            bit_flip_rate: 0.05,
+            drop_rate: 0.05,
+            duplicate_rate: 0.05,
+            ..Default::default()
+        };
+        let data: Vec<u8> = (0..=255).collect();
+        let mut first = TestChannel::new_impaired(&impairments);
+        let mut second = TestChannel::new_impaired(&impairments);
+        first.sender.send(&data).await.unwrap();
+        second.sender.send(&data).await.unwrap();
+        let received = first.receiver.read_data().await.unwrap();
+        assert_ne!(data, received);
+        assert_eq!(received, second.receiver.read_data().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_truncated_connection() {
+        let mut test = TestChannel::new_impaired(&Impairments {
+            truncate_after: Some(4),
+            ..Default::default()
+        });
+        assert!(test.sender.send(b"Hello").await.is_err());
+        assert_eq!(b"Hell".to_vec(), test.receiver.read_exact(4).await.unwrap());
+        assert!(test.receiver.read_u8().await.is_err());
+        assert!(test.receiver.send(b"?").await.is_err());
+    }
 }
@@ -0,0 +1,319 @@
+//! Passwords of the phonebook sealed with a master password. The vault (salt and a check
+//! value) is stored in the phonebook, so a synced phonebook opens with the same master
+//! password everywhere. Sealed passwords are only opened in memory.
+use std::{error::Error, fmt};
+
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use toml::Value;
+
+use crate::{
+    address_mod::{escape, Address},
+    identities::Identity,
+};
+
+pub const SEALED_PREFIX: &str = "sealed:";
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+/// Sealed into `Vault::check` to tell a wrong master password.
+const CHECK_TEXT: &str = "icy_term";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CredentialError {
+    Locked,
+    WrongPassword,
+    Corrupt,
+}
+
+impl fmt::Display for CredentialError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CredentialError::Locked => write!(f, "the passwords are locked"),
+            CredentialError::WrongPassword => write!(f, "wrong master password"),
+            CredentialError::Corrupt => write!(f, "a sealed password is damaged"),
+        }
+    }
+}
+
+impl Error for CredentialError {
+    fn description(&self) -> &str {
+        "use std::display"
+    }
+}
+
+pub fn is_sealed(value: &str) -> bool {
+    value.starts_with(SEALED_PREFIX)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vault {
+    pub salt: String,
+    pub check: String,
+}
+
+impl Vault {
+    pub fn to_toml(&self) -> String {
+        format!(
+            "{{ salt = \"{}\", check = \"{}\" }}",
+            escape(&self.salt),
+            escape(&self.check)
+        )
+    }
+}
+
+pub fn parse_vault(value: &Value) -> Option<Vault> {
+    let Some(Value::String(salt)) = value.get("salt") else {
+        return None;
+    };
+    let Some(Value::String(check)) = value.get("check") else {
+        return None;
+    };
+    Some(Vault {
+        salt: salt.clone(),
+        check: check.clone(),
+    })
+}
+
+/// What the login of one call uses, opened from the identity or the address.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Credentials {
+    pub user_name: String,
+    pub password: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub location: String,
+    pub fields: Vec<(String, String)>,
+}
+
+/// The password never shows up in a log or panic message.
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("user_name", &self.user_name)
+            .field("password", &"<redacted>")
+            .field("first_name", &self.first_name)
+            .field("last_name", &self.last_name)
+            .field("location", &self.location)
+            .field("fields", &self.fields)
+            .finish()
+    }
+}
+
+#[derive(Default)]
+pub struct CredentialStore {
+    pub vault: Option<Vault>,
+    cipher: Option<ChaCha20Poly1305>,
+}
+
+fn derive_cipher(master_password: &str, salt: &str) -> Result<ChaCha20Poly1305, CredentialError> {
+    let salt = STANDARD
+        .decode(salt)
+        .map_err(|_| CredentialError::Corrupt)?;
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(master_password.as_bytes(), &salt, &mut key)
+        .map_err(|_| CredentialError::Corrupt)?;
+    Ok(ChaCha20Poly1305::new(&key))
+}
+
+fn seal_with(cipher: &ChaCha20Poly1305, plain: &str) -> String {
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let mut data = nonce.to_vec();
+    data.extend(cipher.encrypt(&nonce, plain.as_bytes()).unwrap_or_default());
+    format!("{SEALED_PREFIX}{}", STANDARD.encode(data))
+}
+
+fn open_with(cipher: &ChaCha20Poly1305, sealed: &str) -> Result<String, CredentialError> {
+    let data = STANDARD
+        .decode(&sealed[SEALED_PREFIX.len()..])
+        .map_err(|_| CredentialError::Corrupt)?;
+    if data.len() < NONCE_LENGTH {
+        return Err(CredentialError::Corrupt);
+    }
+    let plain = cipher
+        .decrypt(
+            Nonce::from_slice(&data[..NONCE_LENGTH]),
+            &data[NONCE_LENGTH..],
+        )
+        .map_err(|_| CredentialError::WrongPassword)?;
+    String::from_utf8(plain).map_err(|_| CredentialError::Corrupt)
+}
+
+impl CredentialStore {
+    pub fn new(vault: Option<Vault>) -> Self {
+        Self {
+            vault,
+            cipher: None,
+        }
+    }
+
+    /// A new vault, unlocked. The passwords still need `seal_all`.
+    pub fn create(master_password: &str) -> Self {
+        let mut salt = [0; SALT_LENGTH];
+        OsRng.fill_bytes(&mut salt);
+        let salt = STANDARD.encode(salt);
+        let cipher = derive_cipher(master_password, &salt).expect("valid salt");
+        Self {
+            vault: Some(Vault {
+                check: seal_with(&cipher, CHECK_TEXT),
+                salt,
+            }),
+            cipher: Some(cipher),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.vault.is_some()
+    }
+
+    pub fn is_unlocked(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    pub fn unlock(&mut self, master_password: &str) -> Result<(), CredentialError> {
+        let Some(vault) = &self.vault else {
+            return Ok(());
+        };
+        let cipher = derive_cipher(master_password, &vault.salt)?;
+        if !is_sealed(&vault.check) || open_with(&cipher, &vault.check)? != CHECK_TEXT {
+            return Err(CredentialError::WrongPassword);
+        }
+        self.cipher = Some(cipher);
+        Ok(())
+    }
+
+    pub fn lock(&mut self) {
+        self.cipher = None;
+    }
+
+    /// Plain text stays as it is without a vault.
+    pub fn seal(&self, plain: &str) -> Result<String, CredentialError> {
+        if self.vault.is_none() || plain.is_empty() {
+            return Ok(plain.to_string());
+        }
+        match &self.cipher {
+            Some(cipher) => Ok(seal_with(cipher, plain)),
+            None => Err(CredentialError::Locked),
+        }
+    }
+
+    /// Plain text, from before the vault was set up, is returned as it is.
+    pub fn open(&self, value: &str) -> Result<String, CredentialError> {
+        if !is_sealed(value) {
+            return Ok(value.to_string());
+        }
+        match &self.cipher {
+            Some(cipher) => open_with(cipher, value),
+            None => Err(CredentialError::Locked),
+        }
+    }
+
+    /// The login of the address is used without an identity.
+    pub fn get_credentials(
+        &self,
+        adr: &Address,
+        identity: Option<&Identity>,
+    ) -> Result<Credentials, CredentialError> {
+        let Some(identity) = identity else {
+            return Ok(Credentials {
+                user_name: adr.user_name.clone(),
+                password: self.open(&adr.password)?,
+                ..Default::default()
+            });
+        };
+        Ok(Credentials {
+            user_name: identity.handle.clone(),
+            password: self.open(&identity.password)?,
+            first_name: identity.first_name.clone(),
+            last_name: identity.last_name.clone(),
+            location: identity.location.clone(),
+            fields: identity.fields.clone(),
+        })
+    }
+
+    /// Seals the plain text passwords, returns how many there were.
+    pub fn seal_all(
+        &self,
+        addresses: &mut [Address],
+        identities: &mut [Identity],
+    ) -> Result<usize, CredentialError> {
+        let mut count = 0;
+        let passwords = addresses
+            .iter_mut()
+            .map(|adr| &mut adr.password)
+            .chain(identities.iter_mut().map(|identity| &mut identity.password));
+        for password in passwords {
+            if !password.is_empty() && !is_sealed(password) {
+                *password = self.seal(password)?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        address_mod::Address,
+        credentials::{is_sealed, CredentialError, CredentialStore, Credentials},
+        identities::Identity,
+    };
+
+    #[test]
+    fn test_seal_and_open() {
+        let store = CredentialStore::create("master");
+        let mut addresses = vec![Address::new(String::new()), Address::new(String::new())];
+        addresses[0].password = "secret".to_string();
+        let mut identities = vec![Identity {
+            handle: "Sue".to_string(),
+            password: "hidden".to_string(),
+            ..Default::default()
+        }];
+        assert_eq!(Ok(2), store.seal_all(&mut addresses, &mut identities));
+        assert!(is_sealed(&identities[0].password));
+        assert!(is_sealed(&addresses[0].password));
+        assert!(addresses[1].password.is_empty());
+
+        let mut other = CredentialStore::new(store.vault.clone());
+        assert_eq!(
+            Err(CredentialError::Locked),
+            other
+                .get_credentials(&addresses[0], None)
+                .map(|c| c.password)
+        );
+        assert_eq!(Err(CredentialError::WrongPassword), other.unlock("wrong"));
+        other.unlock("master").unwrap();
+        assert_eq!(
+            "secret",
+            other.get_credentials(&addresses[0], None).unwrap().password
+        );
+        let credentials = other
+            .get_credentials(&addresses[0], Some(&identities[0]))
+            .unwrap();
+        assert_eq!(
+            ("Sue", "hidden"),
+            (
+                credentials.user_name.as_str(),
+                credentials.password.as_str()
+            )
+        );
+        assert_eq!("plain", other.open("plain").unwrap());
+    }
+
+    #[test]
+    fn test_debug_hides_password() {
+        let credentials = Credentials {
+            user_name: "Sue".to_string(),
+            password: "secret".to_string(),
+            ..Default::default()
+        };
+        let debug = format!("{credentials:?}");
+        assert!(debug.contains("Sue"));
+        assert!(!debug.contains("secret"));
+    }
+}
@@ -0,0 +1,173 @@
+//! Named identities, stored in the phonebook so everyone sharing it can log in with
+//! their own account. Addresses attach identities by name, the password is sealed
+//! like the address passwords, see `credentials`.
+use toml::Value;
+
+use crate::address_mod::{escape, Address};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Identity {
+    pub name: String,
+    /// The user name on the boards.
+    pub handle: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub password: String,
+    pub location: String,
+    /// Available to the login script as variables.
+    pub fields: Vec<(String, String)>,
+}
+
+impl Identity {
+    pub fn to_toml(&self) -> String {
+        let mut values = vec![format!("name = \"{}\"", escape(&self.name))];
+        for (key, value) in [
+            ("handle", &self.handle),
+            ("first_name", &self.first_name),
+            ("last_name", &self.last_name),
+            ("password", &self.password),
+            ("location", &self.location),
+        ] {
+            if !value.is_empty() {
+                values.push(format!("{key} = \"{}\"", escape(value)));
+            }
+        }
+        if !self.fields.is_empty() {
+            let fields: Vec<String> = self
+                .fields
+                .iter()
+                .map(|(name, value)| format!("\"{}\" = \"{}\"", escape(name), escape(value)))
+                .collect();
+            values.push(format!("fields = {{ {} }}", fields.join(", ")));
+        }
+        format!("{{ {} }}", values.join(", "))
+    }
+}
+
+pub fn identities_to_toml(identities: &[Identity]) -> String {
+    let identities: Vec<String> = identities.iter().map(Identity::to_toml).collect();
+    format!("[{}]", identities.join(", "))
+}
+
+pub fn parse_identity(value: &Value) -> Option<Identity> {
+    let Some(Value::String(name)) = value.get("name") else {
+        return None;
+    };
+    let get = |key: &str| {
+        if let Some(Value::String(value)) = value.get(key) {
+            value.clone()
+        } else {
+            String::new()
+        }
+    };
+    let mut fields = Vec::new();
+    if let Some(Value::Table(values)) = value.get("fields") {
+        for (name, value) in values {
+            if let Value::String(value) = value {
+                fields.push((name.clone(), value.clone()));
+            }
+        }
+    }
+    Some(Identity {
+        name: name.clone(),
+        handle: get("handle"),
+        first_name: get("first_name"),
+        last_name: get("last_name"),
+        password: get("password"),
+        location: get("location"),
+        fields,
+    })
+}
+
+/// Names have to be unique, the addresses refer to the identities by name.
+pub fn is_free_name(identities: &[Identity], index: usize, name: &str) -> bool {
+    !name.is_empty()
+        && !identities
+            .iter()
+            .enumerate()
+            .any(|(i, identity)| i != index && identity.name == name)
+}
+
+/// `name`, or `name 2`, `name 3`… if it's taken.
+pub fn get_free_name(identities: &[Identity], name: &str) -> String {
+    let mut result = name.to_string();
+    let mut n = 2;
+    while identities.iter().any(|identity| identity.name == result) {
+        result = format!("{name} {n}");
+        n += 1;
+    }
+    result
+}
+
+/// The identities the address can be called with, the default one first.
+pub fn get_identities<'a>(identities: &'a [Identity], adr: &Address) -> Vec<&'a Identity> {
+    let mut result: Vec<&Identity> = adr
+        .identities
+        .iter()
+        .filter_map(|name| identities.iter().find(|i| i.name == *name))
+        .collect();
+    if let Some(i) = result.iter().position(|i| i.name == adr.default_identity) {
+        let default = result.remove(i);
+        result.insert(0, default);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        address_mod::Address,
+        identities::{get_free_name, get_identities, is_free_name, parse_identity, Identity},
+    };
+
+    #[test]
+    fn test_identities() {
+        let identity = Identity {
+            name: "Work \"Tom\"".to_string(),
+            handle: "Tom".to_string(),
+            first_name: "Thomas".to_string(),
+            last_name: "Miller".to_string(),
+            fields: vec![("zip".to_string(), "12345".to_string())],
+            ..Default::default()
+        };
+        let value = format!("identity = {}", identity.to_toml())
+            .parse::<toml::Value>()
+            .unwrap();
+        assert_eq!(Some(identity.clone()), parse_identity(&value["identity"]));
+
+        let other = Identity {
+            name: "Sue".to_string(),
+            ..Default::default()
+        };
+        let identities = vec![identity, other];
+        let mut adr = Address::new(String::new());
+        adr.identities = vec![
+            "Work \"Tom\"".to_string(),
+            "Sue".to_string(),
+            "gone".to_string(),
+        ];
+        adr.default_identity = "Sue".to_string();
+        let names: Vec<&str> = get_identities(&identities, &adr)
+            .iter()
+            .map(|i| i.name.as_str())
+            .collect();
+        assert_eq!(vec!["Sue", "Work \"Tom\""], names);
+    }
+
+    #[test]
+    fn test_free_names() {
+        let identities: Vec<Identity> = ["Sue", "New", "New 2"]
+            .iter()
+            .map(|name| Identity {
+                name: name.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        assert!(is_free_name(&identities, 0, "Sue"));
+        assert!(is_free_name(&identities, 0, "Tom"));
+        assert!(!is_free_name(&identities, 0, "New"));
+        assert!(!is_free_name(&identities, 0, ""));
+        assert_eq!("New 3", get_free_name(&identities, "New"));
+        assert_eq!("Tom", get_free_name(&identities, "Tom"));
+    }
+}
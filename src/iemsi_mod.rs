@@ -2,13 +2,22 @@
 
 // IEMSI autologin implementation http://ftsc.org/docs/fsc-0056.001
 use std::{
-    fmt,
+    fmt, fs,
     io::{self, ErrorKind},
+    path::PathBuf,
 };
 
 use icy_engine::{get_crc16, get_crc32, update_crc32};
-
-use crate::{address_mod::Address, com::Connection, TerminalResult, VERSION};
+use toml::Value;
+
+use crate::{
+    address_mod::{escape, Terminal},
+    com::Connection,
+    credentials::Credentials,
+    protocol::TransferType,
+    ui::screen_modes::ScreenMode,
+    TerminalResult, VERSION,
+};
 
 /// EMSI Inquiry is transmitted by the calling system to identify it as
 /// EMSI capable. If an `EMSI_REQ` sequence is received in response, it is
@@ -277,14 +286,29 @@ impl EmsiICI {
             password: String::new(),
             birthdate: String::new(),
             crtdef: "ANSI,24,80,0".to_string(),
-            protocols: "ZAP,ZMO,KER".to_string(),
-            capabilities: "CHT,TAB,ASCII8".to_string(),
+            protocols: get_supported_protocols(),
+            capabilities: "CHT,MNU,TAB,ASCII8".to_string(),
             requests: "HOT,MORE,FSED,NEWS,CLR".to_string(),
             software: format!("-Icy-Term-,{VERSION},iced"),
             xlattabl: String::new(),
         }
     }
 
+    /// Takes the fields the profile has set.
+    pub fn apply_profile(&mut self, profile: &IEmsiProfile) {
+        for (field, value) in [
+            (&mut self.alias, &profile.alias),
+            (&mut self.location, &profile.location),
+            (&mut self.data_telephone, &profile.data_phone),
+            (&mut self.voice_telephone, &profile.voice_phone),
+            (&mut self.requests, &profile.requests),
+        ] {
+            if !value.is_empty() {
+                *field = value.clone();
+            }
+        }
+    }
+
     pub fn encode(&self) -> TerminalResult<Vec<u8>> {
         // **EMSI_ICI<len><data><crc32><CR>
         let data = encode_emsi(&[
@@ -321,6 +345,100 @@ impl EmsiICI {
     }
 }
 
+/// The protocols of `TransferType` that have an IEMSI code, fastest first.
+pub fn get_supported_protocols() -> String {
+    let protocols: Vec<&str> = [
+        TransferType::ZedZap,
+        TransferType::ZModem,
+        TransferType::DirectZap,
+    ]
+    .iter()
+    .filter_map(TransferType::get_iemsi_code)
+    .collect();
+    protocols.join(",")
+}
+
+/// `<emulation>,<rows>,<columns>,<nuls>` for the ICI packet.
+pub fn get_crtdef(terminal: Terminal, screen_mode: ScreenMode) -> String {
+    let emulation = match terminal {
+        Terminal::Ansi => "ANSI",
+        Terminal::Avatar => "AVT0",
+        Terminal::Ascii | Terminal::PETscii | Terminal::ATAscii | Terminal::ViewData => "TTY",
+    };
+    let size = screen_mode.get_window_size();
+    format!("{emulation},{},{},0", size.height, size.width)
+}
+
+/// What the user tells the server about themselves in the ICI packet.
+/// Empty fields fall back to the defaults of the options, then to the ones of `EmsiICI`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct IEmsiProfile {
+    pub alias: String,
+    pub location: String,
+    pub data_phone: String,
+    pub voice_phone: String,
+    /// Comma separated request codes, see `EmsiICI::requests`.
+    pub requests: String,
+}
+
+impl IEmsiProfile {
+    fn fields(&self) -> [(&'static str, &String); 5] {
+        [
+            ("alias", &self.alias),
+            ("location", &self.location),
+            ("data_phone", &self.data_phone),
+            ("voice_phone", &self.voice_phone),
+            ("requests", &self.requests),
+        ]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields().iter().all(|(_, value)| value.is_empty())
+    }
+
+    /// Fills the empty fields from `defaults`.
+    pub fn or(&self, defaults: &IEmsiProfile) -> IEmsiProfile {
+        let pick = |value: &String, default: &String| {
+            if value.is_empty() {
+                default.clone()
+            } else {
+                value.clone()
+            }
+        };
+        IEmsiProfile {
+            alias: pick(&self.alias, &defaults.alias),
+            location: pick(&self.location, &defaults.location),
+            data_phone: pick(&self.data_phone, &defaults.data_phone),
+            voice_phone: pick(&self.voice_phone, &defaults.voice_phone),
+            requests: pick(&self.requests, &defaults.requests),
+        }
+    }
+
+    pub fn to_toml(&self) -> String {
+        let fields: Vec<String> = self
+            .fields()
+            .iter()
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(name, value)| format!("{name} = \"{}\"", escape(value)))
+            .collect();
+        format!("{{ {} }}", fields.join(", "))
+    }
+}
+
+pub fn parse_iemsi_profile(value: &Value) -> IEmsiProfile {
+    let get = |key| match value.get(key) {
+        Some(Value::String(value)) => value.clone(),
+        _ => String::new(),
+    };
+    IEmsiProfile {
+        alias: get("alias"),
+        location: get("location"),
+        data_phone: get("data_phone"),
+        voice_phone: get("voice_phone"),
+        requests: get("requests"),
+    }
+}
+
 pub fn get_crc32string(block: &[u8]) -> String {
     let crc = get_crc32(block);
     format!("{:08X}", !crc)
@@ -368,21 +486,186 @@ pub struct EmsiISI {
 /// the Server needs to display a previously displayed image.
 /// This will be further described in future revisions of this document.
 /// SPOILER: There will me no future revisions :)
-pub fn _encode_ism(data: &[u8]) -> Vec<u8> {
-    let mut block = Vec::new();
-    block.extend_from_slice(format!("EMSI_ISM{:X}", data.len()).as_bytes());
-    block.extend_from_slice(data);
-    let crc = get_crc16(&block);
-
+///
+/// So recalling is up to us: an ISM packet that only holds the id of a cached
+/// image (see `IsmCache`) displays that image again.
+pub fn encode_ism(data: &[u8]) -> Vec<u8> {
+    // **EMSI_ISM<len><data><crc32><CR>
     let mut result = Vec::new();
-    result.extend_from_slice(b"**");
-    result.extend_from_slice(&block);
-    result.push((crc >> 8) as u8);
-    result.push(u8::try_from(crc & 0xFF).unwrap());
+    result.extend_from_slice(b"**EMSI_ISM");
+    result.extend_from_slice(get_length_string(data.len()).as_bytes());
+    result.extend_from_slice(data);
+    result.extend_from_slice(get_crc32string(&result[2..]).as_bytes());
     result.push(b'\r');
     result
 }
 
+/// Sequences the server may send at any time, not only while logging in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmsiEvent {
+    /// `EMSI_CHT`, answer with `EMSI_ACK` and start the chat.
+    StartChat,
+    /// `EMSI_TCH`, answer with `EMSI_ACK` and end the chat.
+    EndChat,
+    /// An ISM packet with a new image.
+    Image(Vec<u8>),
+    /// An ISM packet with the id of a cached image.
+    RecallImage(String),
+}
+
+#[derive(Default)]
+enum EventState {
+    #[default]
+    Header,
+    IsmLength,
+    IsmData,
+    IsmCrc,
+}
+
+/// Finds `EMSI_CHT`, `EMSI_TCH` and `EMSI_ISM` in the received data.
+#[derive(Default)]
+pub struct EmsiEvents {
+    state: EventState,
+    buf: Vec<u8>,
+    len: usize,
+    data: Vec<u8>,
+}
+
+const EMSI_PREFIX: &[u8; 7] = b"**EMSI_";
+
+impl EmsiEvents {
+    pub fn push_char(&mut self, ch: u8) -> Option<EmsiEvent> {
+        match self.state {
+            EventState::Header => {
+                self.buf.push(ch);
+                let n = self.buf.len();
+                if n <= EMSI_PREFIX.len() {
+                    if self.buf[n - 1] != EMSI_PREFIX[n - 1] {
+                        self.restart(ch);
+                    }
+                    return None;
+                }
+                if n == 10 && &self.buf[7..] == b"ISM" {
+                    self.buf.clear();
+                    self.len = 0;
+                    self.state = EventState::IsmLength;
+                    return None;
+                }
+                for (seq, event) in [
+                    (EMSI_CHT, EmsiEvent::StartChat),
+                    (EMSI_TCH, EmsiEvent::EndChat),
+                ] {
+                    if seq.starts_with(&self.buf) {
+                        if n == seq.len() {
+                            self.buf.clear();
+                            return Some(event);
+                        }
+                        return None;
+                    }
+                }
+                self.restart(ch);
+            }
+            EventState::IsmLength => {
+                if !ch.is_ascii_hexdigit() {
+                    self.reset();
+                    return None;
+                }
+                self.len = self.len * 16 + get_value(ch);
+                self.buf.push(ch);
+                if self.buf.len() == 4 {
+                    self.data.clear();
+                    self.state = if self.len == 0 {
+                        EventState::IsmCrc
+                    } else {
+                        EventState::IsmData
+                    };
+                    self.buf.clear();
+                }
+            }
+            EventState::IsmData => {
+                self.data.push(ch);
+                if self.data.len() == self.len {
+                    self.state = EventState::IsmCrc;
+                }
+            }
+            EventState::IsmCrc => {
+                if self.buf.len() < 8 {
+                    self.buf.push(ch);
+                    return None;
+                }
+                let mut block =
+                    format!("EMSI_ISM{}", get_length_string(self.data.len())).into_bytes();
+                block.extend_from_slice(&self.data);
+                let crc = get_crc32string(&block);
+                let event = if ch == b'\r' && self.buf.eq_ignore_ascii_case(crc.as_bytes()) {
+                    let data = std::mem::take(&mut self.data);
+                    match std::str::from_utf8(&data) {
+                        Ok(id) if is_ism_id(id) => Some(EmsiEvent::RecallImage(id.to_string())),
+                        _ => Some(EmsiEvent::Image(data)),
+                    }
+                } else {
+                    None
+                };
+                self.reset();
+                return event;
+            }
+        }
+        None
+    }
+
+    fn reset(&mut self) {
+        self.state = EventState::Header;
+        self.buf.clear();
+        self.data.clear();
+    }
+
+    /// A mismatch may be the start of the next sequence.
+    fn restart(&mut self, ch: u8) {
+        let after_star = self.buf.len() >= 2 && self.buf[self.buf.len() - 2] == b'*';
+        self.reset();
+        if ch == b'*' {
+            if after_star {
+                self.buf.push(b'*');
+            }
+            self.buf.push(b'*');
+        }
+    }
+}
+
+fn is_ism_id(id: &str) -> bool {
+    id.len() == 8 && id.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Images of ISM packets, stored by the CRC32 of their data.
+pub struct IsmCache {
+    dir: PathBuf,
+}
+
+impl IsmCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    pub fn get_id(data: &[u8]) -> String {
+        get_crc32string(data)
+    }
+
+    /// Returns the id to recall the image with.
+    pub fn store(&self, data: &[u8]) -> io::Result<String> {
+        let id = IsmCache::get_id(data);
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.dir.join(format!("{id}.ans")), data)?;
+        Ok(id)
+    }
+
+    pub fn load(&self, id: &str) -> Option<Vec<u8>> {
+        if !is_ism_id(id) {
+            return None;
+        }
+        fs::read(self.dir.join(format!("{}.ans", id.to_ascii_uppercase()))).ok()
+    }
+}
+
 pub struct IEmsi {
     irq_requested: bool,
     nak_requested: bool,
@@ -401,6 +684,11 @@ pub struct IEmsi {
 
     pub aborted: bool,
     logged_in: bool,
+
+    /// Merged with the options, see `IEmsiProfile`.
+    pub profile: IEmsiProfile,
+    /// Replaces the default `crtdef` of the ICI packet if set.
+    pub crtdef: String,
 }
 
 // **EMSI_ISI<len><data><crc32><CR>
@@ -425,9 +713,25 @@ impl IEmsi {
             isi_data: Vec::new(),
             aborted: false,
             logged_in: false,
+            profile: IEmsiProfile::default(),
+            crtdef: String::new(),
         }
     }
 
+    fn create_ici(&self, credentials: &Credentials) -> EmsiICI {
+        let mut data = EmsiICI::new();
+        data.name = credentials.user_name.clone();
+        data.password = credentials.password.clone();
+        data.apply_profile(&self.profile);
+        if !credentials.location.is_empty() {
+            data.location = credentials.location.clone();
+        }
+        if !self.crtdef.is_empty() {
+            data.crtdef = self.crtdef.clone();
+        }
+        data
+    }
+
     pub fn parse_char(&mut self, ch: u8) -> TerminalResult<()> {
         if self.stars_read >= 2 {
             if self.isi_seq > 7 {
@@ -535,19 +839,23 @@ impl IEmsi {
     pub fn try_login(
         &mut self,
         con: &mut Connection,
-        adr: &Address,
+        credentials: &Credentials,
         ch: u8,
     ) -> TerminalResult<bool> {
         if self.aborted {
             return Ok(false);
         }
-        if let Some(data) = self.advance_char(adr, ch)? {
+        if let Some(data) = self.advance_char(credentials, ch)? {
             con.send(data)?;
         }
         Ok(self.logged_in)
     }
 
-    pub fn advance_char(&mut self, adr: &Address, ch: u8) -> TerminalResult<Option<Vec<u8>>> {
+    pub fn advance_char(
+        &mut self,
+        credentials: &Credentials,
+        ch: u8,
+    ) -> TerminalResult<Option<Vec<u8>>> {
         if self.aborted {
             return Ok(None);
         }
@@ -555,10 +863,7 @@ impl IEmsi {
         if self.irq_requested {
             self.irq_requested = false;
             // self.log_file.push("Starting IEMSI negotiation…".to_string());
-            let mut data = EmsiICI::new();
-            data.name = adr.user_name.clone();
-            data.password = adr.password.clone();
-            return Ok(Some(data.encode()?));
+            return Ok(Some(self.create_ici(credentials).encode()?));
         } else if let Some(_isi) = &self.isi {
             // self.log_file.push("Receiving valid IEMSI server info…".to_string());
             // self.log_file.push(format!("Name:{} Location:{} Operator:{} Notice:{} System:{}", isi.name, isi.location, isi.operator, isi.notice, isi.id));
@@ -575,11 +880,8 @@ impl IEmsi {
             self.nak_requested = false;
             if self.retries < 2 {
                 // self.log_file.push("IEMSI retry…".to_string());
-                let mut data = EmsiICI::new();
-                data.name = adr.user_name.clone();
-                data.password = adr.password.clone();
                 self.retries += 1;
-                return Ok(Some(data.encode()?));
+                return Ok(Some(self.create_ici(credentials).encode()?));
             }
             // self.log_file.push("IEMSI aborted…".to_string());
             self.aborted = true;
@@ -813,17 +1115,90 @@ mod tests {
     #[test]
     fn test_auto_logon() {
         let mut state = IEmsi::new();
-        let mut adr = Address::new(String::new());
-        adr.user_name = "foo".to_string();
-        adr.password = "bar".to_string();
+        let credentials = Credentials {
+            user_name: "foo".to_string(),
+            password: "bar".to_string(),
+            ..Default::default()
+        };
 
         let mut back_data = Vec::new();
         for b in EMSI_IRQ {
-            if let Some(data) = state.advance_char(&adr, *b).unwrap() {
+            if let Some(data) = state.advance_char(&credentials, *b).unwrap() {
                 back_data = data;
             }
         }
-        let data = format!("EMSI_ICI0093{{foo}}{{}}{{.........}}{{-Unpublished-}}{{-Unpublished-}}{{bar}}{{}}{{ANSI,24,80,0}}{{ZAP,ZMO,KER}}{{CHT,TAB,ASCII8}}{{HOT,MORE,FSED,NEWS,CLR}}{{-Icy-Term-,{VERSION},iced}}{{}}").as_bytes().to_vec();
-        assert_eq!(format!("**EMSI_ICI0093{{foo}}{{}}{{.........}}{{-Unpublished-}}{{-Unpublished-}}{{bar}}{{}}{{ANSI,24,80,0}}{{ZAP,ZMO,KER}}{{CHT,TAB,ASCII8}}{{HOT,MORE,FSED,NEWS,CLR}}{{-Icy-Term-,{},iced}}{{}}{}\r**EMSI_ACKA490\r**EMSI_ACKA490\r", VERSION, get_crc32string(&data)), String::from_utf8(back_data).unwrap());
+        let data = format!("EMSI_ICI0097{{foo}}{{}}{{.........}}{{-Unpublished-}}{{-Unpublished-}}{{bar}}{{}}{{ANSI,24,80,0}}{{ZAP,ZMO,DZA}}{{CHT,MNU,TAB,ASCII8}}{{HOT,MORE,FSED,NEWS,CLR}}{{-Icy-Term-,{VERSION},iced}}{{}}").as_bytes().to_vec();
+        assert_eq!(format!("**EMSI_ICI0097{{foo}}{{}}{{.........}}{{-Unpublished-}}{{-Unpublished-}}{{bar}}{{}}{{ANSI,24,80,0}}{{ZAP,ZMO,DZA}}{{CHT,MNU,TAB,ASCII8}}{{HOT,MORE,FSED,NEWS,CLR}}{{-Icy-Term-,{},iced}}{{}}{}\r**EMSI_ACKA490\r**EMSI_ACKA490\r", VERSION, get_crc32string(&data)), String::from_utf8(back_data).unwrap());
+    }
+
+    #[test]
+    fn test_crtdef() {
+        assert_eq!(
+            "ANSI,50,80,0",
+            get_crtdef(Terminal::Ansi, ScreenMode::Vga(80, 50))
+        );
+        assert_eq!(
+            "AVT0,25,80,0",
+            get_crtdef(Terminal::Avatar, ScreenMode::Default)
+        );
+        assert_eq!(
+            "TTY,25,40,0",
+            get_crtdef(Terminal::PETscii, ScreenMode::Vic)
+        );
+    }
+
+    #[test]
+    fn test_iemsi_profile() {
+        let defaults = IEmsiProfile {
+            location: "Stockholm, Sweden".to_string(),
+            requests: "NEWS,MAIL".to_string(),
+            ..Default::default()
+        };
+        let profile = IEmsiProfile {
+            alias: "Foo \"Bar\"".to_string(),
+            requests: "HOT".to_string(),
+            ..Default::default()
+        }
+        .or(&defaults);
+        assert_eq!("Stockholm, Sweden", profile.location);
+        assert_eq!("HOT", profile.requests);
+
+        let value = format!("iemsi = {}", profile.to_toml())
+            .parse::<Value>()
+            .unwrap();
+        assert_eq!(profile, parse_iemsi_profile(&value["iemsi"]));
+
+        let mut ici = EmsiICI::new();
+        ici.apply_profile(&profile);
+        assert_eq!("Foo \"Bar\"", ici.alias);
+        assert_eq!("-Unpublished-", ici.data_telephone);
+        assert_eq!("HOT", ici.requests);
+    }
+
+    #[test]
+    fn test_emsi_events() {
+        let mut events = EmsiEvents::default();
+        let mut data = b"Chat? **EMSI_CHTF5D4\rHi ***EMSI_TCH3C60\r".to_vec();
+        data.extend(encode_ism(b"\x1B[2J{Menu}"));
+        data.extend(encode_ism(b"0BADF00D"));
+        data.extend(b"**EMSI_ISM0002ab00000000\r");
+        let found: Vec<_> = data.iter().filter_map(|ch| events.push_char(*ch)).collect();
+        assert_eq!(
+            vec![
+                EmsiEvent::StartChat,
+                EmsiEvent::EndChat,
+                EmsiEvent::Image(b"\x1B[2J{Menu}".to_vec()),
+                EmsiEvent::RecallImage("0BADF00D".to_string()),
+            ],
+            found
+        );
+    }
+
+    #[test]
+    fn test_ism_cache() {
+        let cache = IsmCache::new(std::env::temp_dir().join("icy_term_test_ism"));
+        let id = cache.store(b"\x1B[1mMenu").unwrap();
+        assert_eq!(Some(b"\x1B[1mMenu".to_vec()), cache.load(&id));
+        assert_eq!(None, cache.load("../../etc"));
     }
 }
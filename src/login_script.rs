@@ -0,0 +1,925 @@
+//! Login scripts, one command per line, `#` starts a comment:
+//!
+//! ```text
+//! timeout 20
+//! expect "(?i)ansi" goto ansi "(?i)name:" goto name else goto fail
+//! label ansi
+//! send "y\r"
+//! label name
+//! expect "(?i)name:"
+//! send "$user\r"
+//! expect "(?i)password:" timeout 10
+//! send "${password}\r"
+//! logged_in
+//! repeat 3
+//!   expect "(?i)press.*enter" timeout 5 else goto done
+//!   send "\r"
+//! end
+//! label done
+//! ```
+//!
+//! Text received since the last match is what `expect` looks at. An expect that times out
+//! without an `else goto` aborts the script.
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use regex::Regex;
+
+use crate::{address_mod::Address, credentials::Credentials};
+
+/// Timeout of `expect` until a `timeout` command sets another one.
+pub const DEFAULT_EXPECT_TIMEOUT: Duration = Duration::from_secs(30);
+/// Silence `wait_quiet` waits for.
+const QUIET_TIME: Duration = Duration::from_millis(500);
+/// Only the tail of the received text is kept for matching.
+const MAX_BUFFER_SIZE: usize = 4096;
+/// Commands run in one go without waiting - more means the script loops forever.
+const MAX_STEPS: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptError {
+    Syntax(usize, String),
+    UnknownCommand(usize, String),
+    InvalidRegex(usize, String),
+    UnknownLabel(usize, String),
+    DuplicateLabel(usize, String),
+    UnmatchedEnd(usize),
+    MissingEnd(usize),
+    UnknownVariable(String),
+    Timeout(String),
+    Aborted(String),
+    EndlessLoop,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptError::Syntax(line, msg) => write!(f, "line {line}: {msg}"),
+            ScriptError::UnknownCommand(line, cmd) => {
+                write!(f, "line {line}: unknown command '{cmd}'")
+            }
+            ScriptError::InvalidRegex(line, msg) => write!(f, "line {line}: {msg}"),
+            ScriptError::UnknownLabel(line, label) => {
+                write!(f, "line {line}: unknown label '{label}'")
+            }
+            ScriptError::DuplicateLabel(line, label) => {
+                write!(f, "line {line}: label '{label}' is already defined")
+            }
+            ScriptError::UnmatchedEnd(line) => write!(f, "line {line}: 'end' without 'repeat'"),
+            ScriptError::MissingEnd(line) => write!(f, "line {line}: 'repeat' without 'end'"),
+            ScriptError::UnknownVariable(name) => write!(f, "unknown variable '${name}'"),
+            ScriptError::Timeout(pattern) => write!(f, "timeout waiting for {pattern}"),
+            ScriptError::Aborted(msg) => {
+                if msg.is_empty() {
+                    write!(f, "login script aborted")
+                } else {
+                    write!(f, "login script aborted: {msg}")
+                }
+            }
+            ScriptError::EndlessLoop => write!(f, "login script loops without waiting"),
+        }
+    }
+}
+
+impl Error for ScriptError {
+    fn description(&self) -> &str {
+        "use std::display"
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SendPart {
+    Bytes(Vec<u8>),
+    Variable(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Alternative {
+    pub regex: Regex,
+    pub goto: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Command {
+    Send(Vec<SendPart>),
+    Expect {
+        alternatives: Vec<Alternative>,
+        timeout: Option<Duration>,
+        on_timeout: Option<usize>,
+    },
+    Goto(usize),
+    SetTimeout(Duration),
+    Wait(Duration),
+    WaitQuiet,
+    Repeat {
+        count: u32,
+        end: usize,
+    },
+    End {
+        start: usize,
+    },
+    Abort(String),
+    DisableIemsi,
+    LoggedIn,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Script {
+    pub commands: Vec<Command>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    /// The text between the quotes, escapes are left for the command to handle.
+    Quoted(String),
+}
+
+fn tokenize(line_number: usize, line: &str) -> Result<Vec<Token>, ScriptError> {
+    let mut result = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '#' => break,
+            '"' => {
+                let mut text = String::new();
+                let mut closed = false;
+                while let Some(ch) = chars.next() {
+                    match ch {
+                        '"' => {
+                            closed = true;
+                            break;
+                        }
+                        '\\' => {
+                            text.push(ch);
+                            if let Some(ch) = chars.next() {
+                                text.push(ch);
+                            }
+                        }
+                        _ => text.push(ch),
+                    }
+                }
+                if !closed {
+                    return Err(ScriptError::Syntax(
+                        line_number,
+                        "missing closing quote".to_string(),
+                    ));
+                }
+                result.push(Token::Quoted(text));
+            }
+            ch if ch.is_whitespace() => {}
+            ch => {
+                let mut word = ch.to_string();
+                while let Some(ch) = chars.peek() {
+                    if ch.is_whitespace() || *ch == '"' || *ch == '#' {
+                        break;
+                    }
+                    word.push(*ch);
+                    chars.next();
+                }
+                result.push(Token::Word(word));
+            }
+        }
+    }
+    Ok(result)
+}
+
+fn is_variable_char(ch: char) -> bool {
+    ch.is_ascii_alphanumeric() || ch == '_'
+}
+
+/// Splits a `send` string into bytes and variables, `line_number` is for the errors.
+pub fn parse_send(line_number: usize, text: &str) -> Result<Vec<SendPart>, ScriptError> {
+    let mut result = Vec::new();
+    let mut bytes = Vec::new();
+    let mut chars = text.chars().peekable();
+    let syntax = |msg: &str| ScriptError::Syntax(line_number, msg.to_string());
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => match chars.next() {
+                Some('r') => bytes.push(b'\r'),
+                Some('n') => bytes.push(b'\n'),
+                Some('t') => bytes.push(b'\t'),
+                Some('e') => bytes.push(0x1B),
+                Some('x') => {
+                    let hex: String = chars.by_ref().take(2).collect();
+                    let b = u8::from_str_radix(&hex, 16)
+                        .map_err(|_| syntax("\\x needs two hex digits"))?;
+                    bytes.push(b);
+                }
+                Some(ch @ ('\\' | '"' | '$')) => bytes.push(ch as u8),
+                Some(ch) => {
+                    return Err(syntax(&format!("invalid escape sequence '\\{ch}'")));
+                }
+                None => return Err(syntax("incomplete escape sequence")),
+            },
+            '$' => {
+                let mut name = String::new();
+                if chars.peek() == Some(&'{') {
+                    chars.next();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(ch) => name.push(ch),
+                            None => return Err(syntax("missing '}'")),
+                        }
+                    }
+                } else {
+                    while let Some(ch) = chars.peek() {
+                        if !is_variable_char(*ch) {
+                            break;
+                        }
+                        name.push(*ch);
+                        chars.next();
+                    }
+                }
+                if name.is_empty() {
+                    return Err(syntax("'$' without variable name"));
+                }
+                if !bytes.is_empty() {
+                    result.push(SendPart::Bytes(std::mem::take(&mut bytes)));
+                }
+                result.push(SendPart::Variable(name));
+            }
+            ch => {
+                let mut buf = [0; 4];
+                bytes.extend_from_slice(ch.encode_utf8(&mut buf).as_bytes());
+            }
+        }
+    }
+    if !bytes.is_empty() {
+        result.push(SendPart::Bytes(bytes));
+    }
+    Ok(result)
+}
+
+fn parse_seconds(line_number: usize, token: Option<&Token>) -> Result<Duration, ScriptError> {
+    let Some(Token::Word(word)) = token else {
+        return Err(ScriptError::Syntax(
+            line_number,
+            "expected a number of seconds".to_string(),
+        ));
+    };
+    match word.parse::<f64>() {
+        Ok(secs) if secs >= 0.0 && secs.is_finite() => Ok(Duration::from_secs_f64(secs)),
+        _ => Err(ScriptError::Syntax(
+            line_number,
+            format!("'{word}' is not a number of seconds"),
+        )),
+    }
+}
+
+/// A command before the labels are resolved.
+enum Unresolved {
+    Command(Command),
+    Goto(String),
+    Expect {
+        alternatives: Vec<(Regex, Option<String>)>,
+        timeout: Option<Duration>,
+        on_timeout: Option<String>,
+    },
+}
+
+fn parse_expect(line_number: usize, tokens: &[Token]) -> Result<Unresolved, ScriptError> {
+    let syntax = |msg: &str| ScriptError::Syntax(line_number, msg.to_string());
+    let mut alternatives: Vec<(Regex, Option<String>)> = Vec::new();
+    let mut timeout = None;
+    let mut on_timeout = None;
+    let mut tokens = tokens.iter().peekable();
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Quoted(pattern) => {
+                let regex = Regex::new(pattern)
+                    .map_err(|err| ScriptError::InvalidRegex(line_number, err.to_string()))?;
+                alternatives.push((regex, None));
+            }
+            Token::Word(word) => match word.as_str() {
+                "goto" => {
+                    let Some(Token::Word(label)) = tokens.next() else {
+                        return Err(syntax("'goto' needs a label"));
+                    };
+                    match alternatives.last_mut() {
+                        Some((_, goto)) if goto.is_none() => *goto = Some(label.clone()),
+                        _ => return Err(syntax("'goto' needs to follow a pattern")),
+                    }
+                }
+                "timeout" => timeout = Some(parse_seconds(line_number, tokens.next())?),
+                "else" => {
+                    if tokens.next() != Some(&Token::Word("goto".to_string())) {
+                        return Err(syntax("'else' needs to be followed by 'goto'"));
+                    }
+                    let Some(Token::Word(label)) = tokens.next() else {
+                        return Err(syntax("'goto' needs a label"));
+                    };
+                    on_timeout = Some(label.clone());
+                }
+                _ => return Err(syntax(&format!("unexpected '{word}'"))),
+            },
+        }
+    }
+    if alternatives.is_empty() {
+        return Err(syntax("'expect' needs a pattern"));
+    }
+    Ok(Unresolved::Expect {
+        alternatives,
+        timeout,
+        on_timeout,
+    })
+}
+
+pub fn parse_script(text: &str) -> Result<Script, ScriptError> {
+    let mut commands = Vec::new();
+    let mut lines = Vec::new();
+    let mut labels = HashMap::new();
+    let mut open_repeats = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let line_number = i + 1;
+        let tokens = tokenize(line_number, line)?;
+        let Some(Token::Word(command)) = tokens.first() else {
+            if tokens.is_empty() {
+                continue;
+            }
+            return Err(ScriptError::Syntax(
+                line_number,
+                "line needs to start with a command".to_string(),
+            ));
+        };
+        let args = &tokens[1..];
+        let syntax = |msg: &str| ScriptError::Syntax(line_number, msg.to_string());
+        let command = match command.as_str() {
+            "label" => {
+                let [Token::Word(label)] = args else {
+                    return Err(syntax("'label' needs a name"));
+                };
+                if labels.insert(label.clone(), commands.len()).is_some() {
+                    return Err(ScriptError::DuplicateLabel(line_number, label.clone()));
+                }
+                continue;
+            }
+            "goto" => {
+                let [Token::Word(label)] = args else {
+                    return Err(syntax("'goto' needs a label"));
+                };
+                Unresolved::Goto(label.clone())
+            }
+            "send" => {
+                let [Token::Quoted(text)] = args else {
+                    return Err(syntax("'send' needs a quoted string"));
+                };
+                Unresolved::Command(Command::Send(parse_send(line_number, text)?))
+            }
+            "expect" => parse_expect(line_number, args)?,
+            "timeout" => Unresolved::Command(Command::SetTimeout(parse_seconds(
+                line_number,
+                args.first(),
+            )?)),
+            "wait" => Unresolved::Command(Command::Wait(parse_seconds(line_number, args.first())?)),
+            "wait_quiet" => Unresolved::Command(Command::WaitQuiet),
+            "repeat" => {
+                let count = match args {
+                    [Token::Word(count)] => count
+                        .parse::<u32>()
+                        .map_err(|_| syntax("'repeat' needs a count"))?,
+                    _ => return Err(syntax("'repeat' needs a count")),
+                };
+                open_repeats.push((commands.len(), line_number));
+                Unresolved::Command(Command::Repeat { count, end: 0 })
+            }
+            "end" => {
+                let Some((start, _)) = open_repeats.pop() else {
+                    return Err(ScriptError::UnmatchedEnd(line_number));
+                };
+                let end = commands.len();
+                if let Unresolved::Command(Command::Repeat { end: e, .. }) = &mut commands[start] {
+                    *e = end;
+                }
+                Unresolved::Command(Command::End { start })
+            }
+            "abort" => {
+                let msg = match args {
+                    [] => String::new(),
+                    [Token::Quoted(msg)] => msg.clone(),
+                    _ => return Err(syntax("'abort' takes an optional quoted message")),
+                };
+                Unresolved::Command(Command::Abort(msg))
+            }
+            "disable_iemsi" => Unresolved::Command(Command::DisableIemsi),
+            "logged_in" => Unresolved::Command(Command::LoggedIn),
+            cmd => return Err(ScriptError::UnknownCommand(line_number, cmd.to_string())),
+        };
+        commands.push(command);
+        lines.push(line_number);
+    }
+    if let Some((_, line_number)) = open_repeats.pop() {
+        return Err(ScriptError::MissingEnd(line_number));
+    }
+
+    let mut result = Script::default();
+    for (command, line_number) in commands.into_iter().zip(lines) {
+        let resolve = |label: &String| {
+            labels
+                .get(label)
+                .copied()
+                .ok_or_else(|| ScriptError::UnknownLabel(line_number, label.clone()))
+        };
+        let command = match command {
+            Unresolved::Command(command) => command,
+            Unresolved::Goto(label) => Command::Goto(resolve(&label)?),
+            Unresolved::Expect {
+                alternatives,
+                timeout,
+                on_timeout,
+            } => Command::Expect {
+                alternatives: alternatives
+                    .into_iter()
+                    .map(|(regex, goto)| -> Result<Alternative, ScriptError> {
+                        Ok(Alternative {
+                            regex,
+                            goto: goto.as_ref().map(resolve).transpose()?,
+                        })
+                    })
+                    .collect::<Result<_, _>>()?,
+                timeout,
+                on_timeout: on_timeout.as_ref().map(resolve).transpose()?,
+            },
+        };
+        result.commands.push(command);
+    }
+    Ok(result)
+}
+
+fn quote_send(text: &str) -> String {
+    let mut result = String::new();
+    for ch in text.chars() {
+        match ch {
+            '\r' => result.push_str("\\r"),
+            '\n' => result.push_str("\\n"),
+            '\t' => result.push_str("\\t"),
+            '\x1B' => result.push_str("\\e"),
+            '\\' | '"' | '$' => {
+                result.push('\\');
+                result.push(ch);
+            }
+            _ => result.push(ch),
+        }
+    }
+    result
+}
+
+fn flush_send(lines: &mut Vec<String>, text: &mut String) {
+    if !text.is_empty() {
+        lines.push(format!("send \"{}\"", quote_send(text)));
+        text.clear();
+    }
+}
+
+/// Turns an old autologin string like `!D4!W!N!P` into a script.
+pub fn translate_legacy(login_expr: &str) -> String {
+    let mut lines = Vec::new();
+    let mut text = String::new();
+    let expr: Vec<char> = login_expr.chars().collect();
+    let mut i = 0;
+    while i < expr.len() {
+        match (expr[i], expr.get(i + 1)) {
+            ('!', Some(ch)) => {
+                let command = match ch {
+                    'D' if expr.get(i + 2).map_or(false, char::is_ascii_digit) => {
+                        i += 1;
+                        format!("wait {}", expr[i + 1])
+                    }
+                    'E' => "wait_quiet".to_string(),
+                    'W' => "expect \"(?i)name|login:\" timeout 0".to_string(),
+                    'N' => "send \"$user\\r\"".to_string(),
+                    'F' => "send \"$first\\r\"".to_string(),
+                    'L' => "send \"$last\\r\"".to_string(),
+                    'P' => "send \"$password\\r\"\nlogged_in".to_string(),
+                    'I' => "disable_iemsi".to_string(),
+                    _ => {
+                        text.push('!');
+                        i += 1;
+                        continue;
+                    }
+                };
+                flush_send(&mut lines, &mut text);
+                lines.push(command);
+                i += 2;
+            }
+            ('\\', Some(ch)) => {
+                match ch {
+                    'e' => text.push('\x1B'),
+                    'n' => text.push('\n'),
+                    'r' => text.push('\r'),
+                    't' => text.push('\t'),
+                    ch => text.push(*ch),
+                }
+                i += 2;
+            }
+            (ch, _) => {
+                text.push(ch);
+                i += 1;
+            }
+        }
+    }
+    flush_send(&mut lines, &mut text);
+    lines.join("\n")
+}
+
+/// True if the script sends typed text, that may be a password. A script that
+/// doesn't parse counts as well.
+pub fn has_literal_sends(text: &str) -> bool {
+    let Ok(script) = parse_script(text) else {
+        return true;
+    };
+    script.commands.iter().any(|command| {
+        let Command::Send(parts) = command else {
+            return false;
+        };
+        parts.iter().any(|part| {
+            matches!(part, SendPart::Bytes(bytes) if bytes.iter().any(u8::is_ascii_alphanumeric))
+        })
+    })
+}
+
+/// `user`, `password`, `first`, `last` and `location` of the credentials, the custom
+/// variables of the address and the fields of the identity.
+pub fn get_variables(adr: &Address, credentials: &Credentials) -> HashMap<String, String> {
+    let mut result = HashMap::new();
+    let (first, last) = if credentials.first_name.is_empty() && credentials.last_name.is_empty() {
+        let mut names = credentials.user_name.split_whitespace();
+        let first = names.next().unwrap_or_default().to_string();
+        (first, names.collect::<Vec<_>>().join(" "))
+    } else {
+        (
+            credentials.first_name.clone(),
+            credentials.last_name.clone(),
+        )
+    };
+    result.insert("user".to_string(), credentials.user_name.clone());
+    result.insert("password".to_string(), credentials.password.clone());
+    result.insert("first".to_string(), first);
+    result.insert("last".to_string(), last);
+    result.insert("location".to_string(), credentials.location.clone());
+    for (name, value) in adr.login_variables.iter().chain(credentials.fields.iter()) {
+        result.insert(name.clone(), value.clone());
+    }
+    result
+}
+
+pub fn expand_send(
+    parts: &[SendPart],
+    variables: &HashMap<String, String>,
+) -> Result<Vec<u8>, ScriptError> {
+    let mut data = Vec::new();
+    for part in parts {
+        match part {
+            SendPart::Bytes(bytes) => data.extend_from_slice(bytes),
+            SendPart::Variable(name) => {
+                let Some(value) = variables.get(name) else {
+                    return Err(ScriptError::UnknownVariable(name.clone()));
+                };
+                data.extend_from_slice(value.as_bytes());
+            }
+        }
+    }
+    Ok(data)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptAction {
+    Send(Vec<u8>),
+    DisableIemsi,
+    LoggedIn,
+}
+
+/// Runs a script on the received data, it doesn't do any IO itself.
+pub struct ScriptRunner {
+    script: Script,
+    pc: usize,
+    received: String,
+    expect_timeout: Duration,
+    /// When the current wait/expect started.
+    started: Option<Instant>,
+    last_data: Option<Instant>,
+    loop_counts: HashMap<usize, u32>,
+}
+
+impl ScriptRunner {
+    pub fn new(script: Script) -> Self {
+        Self {
+            script,
+            pc: 0,
+            received: String::new(),
+            expect_timeout: DEFAULT_EXPECT_TIMEOUT,
+            started: None,
+            last_data: None,
+            loop_counts: HashMap::new(),
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.pc >= self.script.commands.len()
+    }
+
+    pub fn push_data(&mut self, now: Instant, ch: u8) {
+        self.last_data = Some(now);
+        self.received.push(char::from(ch));
+        if self.received.len() > MAX_BUFFER_SIZE * 2 {
+            let cut = self
+                .received
+                .char_indices()
+                .map(|(i, _)| i)
+                .find(|i| self.received.len() - *i <= MAX_BUFFER_SIZE)
+                .unwrap_or(0);
+            self.received.drain(..cut);
+        }
+    }
+
+    fn jump(&mut self, pc: usize) {
+        self.pc = pc;
+        self.started = None;
+    }
+
+    /// Runs until the script needs to wait for data or time. On an error the script is finished.
+    pub fn run(
+        &mut self,
+        now: Instant,
+        variables: &HashMap<String, String>,
+    ) -> Result<Vec<ScriptAction>, ScriptError> {
+        let res = self.run_commands(now, variables);
+        if res.is_err() {
+            self.pc = self.script.commands.len();
+        }
+        res
+    }
+
+    fn run_commands(
+        &mut self,
+        now: Instant,
+        variables: &HashMap<String, String>,
+    ) -> Result<Vec<ScriptAction>, ScriptError> {
+        let mut actions = Vec::new();
+        for _ in 0..MAX_STEPS {
+            let Some(command) = self.script.commands.get(self.pc).cloned() else {
+                return Ok(actions);
+            };
+            match command {
+                Command::Send(parts) => {
+                    actions.push(ScriptAction::Send(expand_send(&parts, variables)?));
+                    self.jump(self.pc + 1);
+                }
+                Command::Expect {
+                    alternatives,
+                    timeout,
+                    on_timeout,
+                } => {
+                    let started = *self.started.get_or_insert(now);
+                    let found = alternatives.iter().find_map(|alt| {
+                        alt.regex.find(&self.received).map(|m| (m.end(), alt.goto))
+                    });
+                    if let Some((end, goto)) = found {
+                        self.received.drain(..end);
+                        self.jump(goto.unwrap_or(self.pc + 1));
+                        continue;
+                    }
+                    let timeout = timeout.unwrap_or(self.expect_timeout);
+                    if timeout.is_zero() || now.saturating_duration_since(started) < timeout {
+                        return Ok(actions);
+                    }
+                    match on_timeout {
+                        Some(pc) => self.jump(pc),
+                        None => {
+                            let patterns: Vec<String> = alternatives
+                                .iter()
+                                .map(|alt| format!("\"{}\"", alt.regex.as_str()))
+                                .collect();
+                            return Err(ScriptError::Timeout(patterns.join(", ")));
+                        }
+                    }
+                }
+                Command::Goto(pc) => self.jump(pc),
+                Command::SetTimeout(timeout) => {
+                    self.expect_timeout = timeout;
+                    self.jump(self.pc + 1);
+                }
+                Command::Wait(duration) => {
+                    let started = *self.started.get_or_insert(now);
+                    if now.saturating_duration_since(started) < duration {
+                        return Ok(actions);
+                    }
+                    self.jump(self.pc + 1);
+                }
+                Command::WaitQuiet => match self.last_data {
+                    Some(last_data) if now.saturating_duration_since(last_data) >= QUIET_TIME => {
+                        self.jump(self.pc + 1);
+                    }
+                    _ => return Ok(actions),
+                },
+                Command::Repeat { count, end } => {
+                    if count == 0 {
+                        self.jump(end + 1);
+                    } else {
+                        self.loop_counts.insert(self.pc, count);
+                        self.jump(self.pc + 1);
+                    }
+                }
+                Command::End { start } => {
+                    let count = self.loop_counts.entry(start).or_insert(1);
+                    *count = count.saturating_sub(1);
+                    if *count > 0 {
+                        self.jump(start + 1);
+                    } else {
+                        self.jump(self.pc + 1);
+                    }
+                }
+                Command::Abort(msg) => return Err(ScriptError::Aborted(msg)),
+                Command::DisableIemsi => {
+                    actions.push(ScriptAction::DisableIemsi);
+                    self.jump(self.pc + 1);
+                }
+                Command::LoggedIn => {
+                    actions.push(ScriptAction::LoggedIn);
+                    self.jump(self.pc + 1);
+                }
+            }
+        }
+        Err(ScriptError::EndlessLoop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        address_mod::Address,
+        credentials::Credentials,
+        login_script::{
+            get_variables, has_literal_sends, parse_script, translate_legacy, ScriptAction,
+            ScriptError, ScriptRunner,
+        },
+    };
+
+    fn variables() -> HashMap<String, String> {
+        let mut result = HashMap::new();
+        result.insert("user".to_string(), "Mike".to_string());
+        result.insert("password".to_string(), "secret".to_string());
+        result
+    }
+
+    fn receive(runner: &mut ScriptRunner, now: Instant, text: &str) {
+        for b in text.bytes() {
+            runner.push_data(now, b);
+        }
+    }
+
+    fn send(text: &str) -> ScriptAction {
+        ScriptAction::Send(text.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_expect_send() {
+        let script =
+            parse_script("expect \"(?i)name:\"\nsend \"$user\\r\"\nexpect \"word:\"\nsend \"${password}\\r\"\nlogged_in")
+                .unwrap();
+        let mut runner = ScriptRunner::new(script);
+        let now = Instant::now();
+        assert_eq!(Ok(vec![]), runner.run(now, &variables()));
+        receive(&mut runner, now, "Welcome\r\nNAME: ");
+        assert_eq!(Ok(vec![send("Mike\r")]), runner.run(now, &variables()));
+        receive(&mut runner, now, "Password: ");
+        assert_eq!(
+            Ok(vec![send("secret\r"), ScriptAction::LoggedIn]),
+            runner.run(now, &variables())
+        );
+        assert!(runner.is_finished());
+    }
+
+    #[test]
+    fn test_alternatives_and_timeout() {
+        let script = parse_script(
+            "expect \"ANSI\" goto ansi \"NAME\" goto name timeout 5 else goto fail
+label ansi
+send \"Y\"
+label name
+send \"$user\"
+goto done
+label fail
+abort \"no prompt\"
+label done",
+        )
+        .unwrap();
+
+        let now = Instant::now();
+        let mut runner = ScriptRunner::new(script.clone());
+        receive(&mut runner, now, "Your NAME");
+        assert_eq!(Ok(vec![send("Mike")]), runner.run(now, &variables()));
+
+        let mut runner = ScriptRunner::new(script);
+        assert_eq!(Ok(vec![]), runner.run(now, &variables()));
+        assert_eq!(
+            Err(ScriptError::Aborted("no prompt".to_string())),
+            runner.run(now + Duration::from_secs(5), &variables())
+        );
+        assert!(runner.is_finished());
+    }
+
+    #[test]
+    fn test_repeat() {
+        let script = parse_script("repeat 3\n  expect \"more\"\n  send \"\\r\"\nend").unwrap();
+        let now = Instant::now();
+        let mut runner = ScriptRunner::new(script);
+        receive(&mut runner, now, "more more more more");
+        assert_eq!(
+            Ok(vec![send("\r"), send("\r"), send("\r")]),
+            runner.run(now, &variables())
+        );
+        assert!(runner.is_finished());
+    }
+
+    #[test]
+    fn test_endless_loop() {
+        let script = parse_script("label a\ngoto a").unwrap();
+        let mut runner = ScriptRunner::new(script);
+        assert_eq!(
+            Err(ScriptError::EndlessLoop),
+            runner.run(Instant::now(), &variables())
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!(
+            parse_script("goto nowhere"),
+            Err(ScriptError::UnknownLabel(1, _))
+        ));
+        assert!(matches!(
+            parse_script("# login\nfoo"),
+            Err(ScriptError::UnknownCommand(2, _))
+        ));
+        assert!(matches!(
+            parse_script("expect \"(\""),
+            Err(ScriptError::InvalidRegex(1, _))
+        ));
+        assert!(matches!(
+            parse_script("repeat 2\nsend \"x\""),
+            Err(ScriptError::MissingEnd(1))
+        ));
+        assert!(matches!(
+            parse_script("send \"foo"),
+            Err(ScriptError::Syntax(1, _))
+        ));
+    }
+
+    #[test]
+    fn test_translate_legacy() {
+        assert_eq!(
+            "wait 4\nexpect \"(?i)name|login:\" timeout 0\nsend \"$user\\r\"\nsend \"$password\\r\"\nlogged_in",
+            translate_legacy("!D4!W!N!P")
+        );
+        assert_eq!(
+            "disable_iemsi\nsend \"\\e[0m\\r\"\nwait_quiet\nsend \"$first\\r\"\nsend \"$last\\r\"",
+            translate_legacy("!I\\e[0m\\r!E!F!L")
+        );
+        assert!(parse_script(&translate_legacy("!I\\e[0m\\r!E!F!L!W!N!P!D9")).is_ok());
+    }
+
+    #[test]
+    fn test_has_literal_sends() {
+        assert!(!has_literal_sends(""));
+        assert!(!has_literal_sends(&translate_legacy("!I!E!W!N!P")));
+        assert!(has_literal_sends("expect \"word:\"\nsend \"s3cret\\r\""));
+        assert!(has_literal_sends("send \"$user"));
+    }
+
+    #[test]
+    fn test_get_variables() {
+        let mut adr = Address::new(String::new());
+        adr.login_variables
+            .push(("zip".to_string(), "00000".to_string()));
+        let mut credentials = Credentials {
+            user_name: "Jean Luc Picard".to_string(),
+            ..Default::default()
+        };
+        let variables = get_variables(&adr, &credentials);
+        assert_eq!("Jean", variables["first"]);
+        assert_eq!("Luc Picard", variables["last"]);
+
+        credentials.first_name = "Jean-Luc".to_string();
+        credentials.last_name = "Picard".to_string();
+        credentials
+            .fields
+            .push(("zip".to_string(), "12345".to_string()));
+        let variables = get_variables(&adr, &credentials);
+        assert_eq!("Jean-Luc", variables["first"]);
+        assert_eq!("Picard", variables["last"]);
+        assert_eq!("12345", variables["zip"]);
+    }
+}
@@ -29,10 +29,20 @@ mod com;
 
 mod auto_file_transfer;
 mod auto_login;
+mod bbs_directory;
+mod capture;
+mod credentials;
+mod identities;
 mod iemsi_mod;
+mod login_script;
+mod phonebook_io;
 mod protocol;
+mod recent_downloads;
 mod rng;
+mod scripting;
 mod sound;
+mod transfer_queue;
+mod triggers;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
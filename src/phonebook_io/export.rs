@@ -0,0 +1,228 @@
+//! Writes addresses for other users, as SyncTERM list, icy_term bundle or CSV.
+use icy_engine::ansi::MusicOption;
+
+use crate::{
+    address_mod::{write_bundle, Address, Protocol, Terminal},
+    iemsi_mod::IEmsiProfile,
+    login_script::{has_literal_sends, translate_legacy},
+    ui::screen_modes::ScreenMode,
+    TerminalResult,
+};
+
+use super::split_port;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    SyncTerm,
+    Bundle,
+    Csv,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 3] = [
+        ExportFormat::SyncTerm,
+        ExportFormat::Bundle,
+        ExportFormat::Csv,
+    ];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::SyncTerm => "lst",
+            ExportFormat::Bundle => "toml",
+            ExportFormat::Csv => "csv",
+        }
+    }
+
+    /// The passwords have to be opened, sealed ones can't be read by others.
+    pub fn export(self, addresses: &[Address]) -> TerminalResult<String> {
+        match self {
+            ExportFormat::SyncTerm => Ok(export_syncterm(addresses)),
+            ExportFormat::Bundle => {
+                let mut data = Vec::new();
+                write_bundle(&mut data, addresses)?;
+                Ok(String::from_utf8(data)?)
+            }
+            ExportFormat::Csv => Ok(export_csv(addresses)),
+        }
+    }
+}
+
+/// Removes what logs in and the IEMSI profile. Login scripts stay if they only send variables.
+pub fn strip_credentials(adr: &mut Address) {
+    adr.user_name.clear();
+    adr.password.clear();
+    adr.identities.clear();
+    adr.default_identity.clear();
+    adr.iemsi = IEmsiProfile::default();
+    if has_literal_sends(&adr.login_script) {
+        adr.login_script.clear();
+    }
+    if has_literal_sends(&translate_legacy(&adr.auto_login)) {
+        adr.auto_login.clear();
+    }
+}
+
+fn export_syncterm(addresses: &[Address]) -> String {
+    let mut result = String::new();
+    for adr in addresses {
+        let (connection_type, default_port) = match adr.protocol {
+            Protocol::Telnet => ("Telnet", 23),
+            Protocol::Raw => ("Raw", 23),
+            Protocol::Ssh => ("SSH", 22),
+        };
+        let (host, port) = split_port(&adr.address);
+        let emulation = match adr.terminal_type {
+            Terminal::PETscii => "PETSCII",
+            Terminal::ATAscii => "ATASCII",
+            Terminal::ViewData => "Prestel",
+            Terminal::Ansi | Terminal::Avatar | Terminal::Ascii => "ANSI-BBS",
+        };
+        let screen_mode = match adr.screen_mode {
+            ScreenMode::Vga(w, h) => format!("{w}x{h}"),
+            ScreenMode::Vic => "C64".to_string(),
+            ScreenMode::Antic => "Atari".to_string(),
+            ScreenMode::Videotex => "Prestel".to_string(),
+            ScreenMode::Default => "Current".to_string(),
+        };
+        let music = match adr.ansi_music {
+            MusicOption::Off => "ESC [ | only",
+            MusicOption::Banana => "BANSI Style",
+            MusicOption::Conflicting | MusicOption::Both => "All ANSI Music enabled",
+        };
+        // Brackets would end the section name.
+        let name = adr.system_name.replace(['[', ']'], "");
+        result.push_str(&format!("[{name}]\n"));
+        result.push_str(&format!("\tAddress={host}\n"));
+        result.push_str(&format!("\tPort={}\n", port.unwrap_or(default_port)));
+        result.push_str(&format!("\tConnectionType={connection_type}\n"));
+        result.push_str(&format!("\tEmulation={emulation}\n"));
+        result.push_str(&format!("\tScreenMode={screen_mode}\n"));
+        result.push_str(&format!("\tMusic={music}\n"));
+        for (key, value) in [
+            ("Font", adr.font_name.as_deref().unwrap_or_default()),
+            ("UserName", adr.user_name.as_str()),
+            ("Password", adr.password.as_str()),
+            ("Comment", adr.comment.as_str()),
+        ] {
+            if !value.is_empty() {
+                result.push_str(&format!("\t{key}={}\n", value.replace('\n', " ")));
+            }
+        }
+        result.push_str(&format!("\tCalls={}\n", adr.number_of_calls));
+        result.push_str(&format!("\tAdded={}\n\n", adr.created.timestamp()));
+    }
+    result
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn export_csv(addresses: &[Address]) -> String {
+    let mut result =
+        "system_name,address,protocol,terminal_type,screen_mode,user_name,password,comment\n"
+            .to_string();
+    for adr in addresses {
+        let fields = [
+            adr.system_name.clone(),
+            adr.address.clone(),
+            format!("{:?}", adr.protocol),
+            format!("{:?}", adr.terminal_type),
+            format!("{:?}", adr.screen_mode),
+            adr.user_name.clone(),
+            adr.password.clone(),
+            adr.comment.clone(),
+        ];
+        let fields: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        result.push_str(&fields.join(","));
+        result.push('\n');
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        address_mod::{read_bundle, Address, Protocol, Terminal},
+        phonebook_io::{
+            export::{strip_credentials, ExportFormat},
+            PhonebookFormat,
+        },
+        ui::screen_modes::ScreenMode,
+    };
+
+    fn addresses() -> Vec<Address> {
+        let mut adr = Address::new("Test BBS".to_string());
+        adr.address = "bbs.example.com:2323".to_string();
+        adr.terminal_type = Terminal::PETscii;
+        adr.screen_mode = ScreenMode::Vic;
+        adr.user_name = "me".to_string();
+        adr.password = "secret".to_string();
+        adr.comment = "Quotes \", commas, and more".to_string();
+        let mut ssh = Address::new("SSH BBS".to_string());
+        ssh.address = "ssh.example.com".to_string();
+        ssh.protocol = Protocol::Ssh;
+        vec![adr, ssh]
+    }
+
+    #[test]
+    fn test_export_syncterm() {
+        let text = ExportFormat::SyncTerm.export(&addresses()).unwrap();
+        let imported = PhonebookFormat::SyncTerm.import(text.as_bytes()).unwrap();
+        assert_eq!(2, imported.len());
+        for (adr, imported) in addresses().iter().zip(&imported) {
+            assert_eq!(adr.system_name, imported.system_name);
+            assert_eq!(adr.address, imported.address);
+            assert_eq!(adr.protocol, imported.protocol);
+            assert_eq!(adr.terminal_type, imported.terminal_type);
+            assert_eq!(adr.screen_mode, imported.screen_mode);
+            assert_eq!(adr.password, imported.password);
+        }
+    }
+
+    #[test]
+    fn test_export_bundle() {
+        let mut addresses = addresses();
+        strip_credentials(&mut addresses[0]);
+        let text = ExportFormat::Bundle.export(&addresses).unwrap();
+        assert!(!text.contains("secret"));
+        let imported = read_bundle(&text).unwrap();
+        assert_eq!(2, imported.len());
+        assert_eq!(addresses[0].comment, imported[0].comment);
+        assert_eq!(addresses[1].address, imported[1].address);
+        assert!(imported[0].user_name.is_empty());
+    }
+
+    #[test]
+    fn test_strip_credentials() {
+        let mut adr = addresses().remove(0);
+        adr.iemsi.alias = "Mike".to_string();
+        adr.auto_login = "!W!N!P".to_string();
+        adr.login_script = "expect \"word:\"\nsend \"secret\\r\"".to_string();
+        strip_credentials(&mut adr);
+        assert!(adr.iemsi.is_empty());
+        assert!(adr.login_script.is_empty());
+        assert_eq!("!W!N!P", adr.auto_login);
+
+        adr.login_script = "expect \"word:\"\nsend \"$password\\r\"".to_string();
+        adr.auto_login = "!Wsecret\\r".to_string();
+        strip_credentials(&mut adr);
+        assert!(!adr.login_script.is_empty());
+        assert!(adr.auto_login.is_empty());
+    }
+
+    #[test]
+    fn test_export_csv() {
+        let text = ExportFormat::Csv.export(&addresses()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(3, lines.len());
+        assert_eq!(
+            "Test BBS,bbs.example.com:2323,Telnet,PETscii,Vic,me,secret,\"Quotes \"\", commas, and more\"",
+            lines[1]
+        );
+    }
+}
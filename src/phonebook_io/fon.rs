@@ -0,0 +1,123 @@
+//! The `.FON` dialing directories of Telix and Qmodem. Only entries with a host as the
+//! number are imported, phone numbers can't be called.
+//!
+//! Telix: a 64 byte header starting with `TELIX_ID` and the entry count (u16), then
+//! entries of 184 bytes with zero padded strings: name (25), number (17), line settings,
+//! script (12), last call (6), calls (u16), terminal (0 TTY, 1 ANSI-BBS, 2 VT102,
+//! 3 VT52, 4 AVATAR) and the password (14) at 71.
+//!
+//! Qmodem: entries of 100 bytes with Pascal strings: name (30), number (25), baud (u16),
+//! script (12), password (15) and the emulation (0 TTY, 1 ANSI, 2 VT100, 3 AVATAR).
+use crate::address_mod::{Address, Terminal};
+
+use super::{is_host, join_port, read_fixed, read_pascal, split_port, ImportError};
+
+const TELIX_ID: [u8; 4] = [0x2E, 0x2B, 0x29, 0xD0];
+const TELIX_HEADER_SIZE: usize = 64;
+const TELIX_ENTRY_SIZE: usize = 184;
+const QMODEM_ENTRY_SIZE: usize = 100;
+
+fn create_address(name: String, number: &str, password: String) -> Address {
+    let number = number.strip_prefix("telnet://").unwrap_or(number);
+    let (host, port) = split_port(number);
+    let mut adr = Address::new(name);
+    adr.address = join_port(host, port, 23);
+    adr.password = password;
+    adr
+}
+
+fn import_telix(data: &[u8]) -> Result<Vec<Address>, ImportError> {
+    if data.len() < TELIX_HEADER_SIZE {
+        return Err(ImportError::InvalidFile("header is too short".to_string()));
+    }
+    let count = u16::from_le_bytes([data[4], data[5]]) as usize;
+    let mut result = Vec::new();
+    for entry in data[TELIX_HEADER_SIZE..]
+        .chunks_exact(TELIX_ENTRY_SIZE)
+        .take(count)
+    {
+        let number = read_fixed(&entry[25..42]);
+        if !is_host(&number) {
+            continue;
+        }
+        let mut adr = create_address(
+            read_fixed(&entry[0..25]),
+            &number,
+            read_fixed(&entry[71..85]),
+        );
+        adr.number_of_calls = u16::from_le_bytes([entry[65], entry[66]]) as usize;
+        adr.terminal_type = match entry[67] {
+            0 => Terminal::Ascii,
+            4 => Terminal::Avatar,
+            _ => Terminal::Ansi,
+        };
+        result.push(adr);
+    }
+    Ok(result)
+}
+
+fn import_qmodem(data: &[u8]) -> Result<Vec<Address>, ImportError> {
+    if data.len() % QMODEM_ENTRY_SIZE != 0 {
+        return Err(ImportError::InvalidFile(format!(
+            "size {} isn't a multiple of {QMODEM_ENTRY_SIZE}",
+            data.len()
+        )));
+    }
+    let mut result = Vec::new();
+    for entry in data.chunks_exact(QMODEM_ENTRY_SIZE) {
+        let number = read_pascal(&entry[31..], 25);
+        if !is_host(&number) {
+            continue;
+        }
+        let mut adr = create_address(
+            read_pascal(entry, 30),
+            &number,
+            read_pascal(&entry[72..], 15),
+        );
+        adr.terminal_type = match entry[88] {
+            0 => Terminal::Ascii,
+            3 => Terminal::Avatar,
+            _ => Terminal::Ansi,
+        };
+        result.push(adr);
+    }
+    Ok(result)
+}
+
+pub fn import(data: &[u8]) -> Result<Vec<Address>, ImportError> {
+    if data.starts_with(&TELIX_ID) {
+        import_telix(data)
+    } else {
+        import_qmodem(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::address_mod::Terminal;
+
+    #[test]
+    fn test_import_telix() {
+        let addresses = super::import(include_bytes!("fixtures/telix.fon")).unwrap();
+        assert_eq!(2, addresses.len());
+        assert_eq!("Telix Test BBS", addresses[0].system_name);
+        assert_eq!("bbs.test.org:2323", addresses[0].address);
+        assert_eq!("letmein", addresses[0].password);
+        assert_eq!(7, addresses[0].number_of_calls);
+        assert_eq!(Terminal::Ansi, addresses[0].terminal_type);
+        assert_eq!("avatar.bbs.org", addresses[1].address);
+        assert_eq!(Terminal::Avatar, addresses[1].terminal_type);
+    }
+
+    #[test]
+    fn test_import_qmodem() {
+        let addresses = super::import(include_bytes!("fixtures/qmodem.fon")).unwrap();
+        assert_eq!(2, addresses.len());
+        assert_eq!("Qmodem Test BBS", addresses[0].system_name);
+        assert_eq!("qbbs.example.com", addresses[0].address);
+        assert_eq!("secret", addresses[0].password);
+        assert_eq!(Terminal::Ansi, addresses[0].terminal_type);
+        assert_eq!("tty.example.com:6023", addresses[1].address);
+        assert_eq!(Terminal::Ascii, addresses[1].terminal_type);
+    }
+}
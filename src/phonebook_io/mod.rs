@@ -0,0 +1,215 @@
+//! Reads the dialing directories of other terminals into addresses, and writes them
+//! for others, see `export`.
+use std::{error::Error, fmt, fs, path::Path};
+
+use crate::{
+    address_mod::{read_bundle, Address, Protocol},
+    TerminalResult,
+};
+
+pub mod export;
+mod fon;
+mod mtelnet;
+mod netrunner;
+mod syncterm;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportError {
+    UnknownFormat,
+    InvalidFile(String),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::UnknownFormat => write!(f, "unknown phonebook format"),
+            ImportError::InvalidFile(reason) => write!(f, "invalid phonebook: {reason}"),
+        }
+    }
+}
+
+impl Error for ImportError {
+    fn description(&self) -> &str {
+        "use std::display"
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhonebookFormat {
+    SyncTerm,
+    NetRunner,
+    MTelnet,
+    /// Qmodem and Telix dialing directories.
+    Fon,
+    /// Exported from icy_term.
+    Bundle,
+}
+
+impl PhonebookFormat {
+    /// By the file name, the `.FON` variants are told apart by their content.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match extension.as_str() {
+            "lst" => Some(PhonebookFormat::SyncTerm),
+            "phn" => Some(PhonebookFormat::NetRunner),
+            "dir" => Some(PhonebookFormat::MTelnet),
+            "fon" => Some(PhonebookFormat::Fon),
+            "toml" => Some(PhonebookFormat::Bundle),
+            _ if name.contains("syncterm") => Some(PhonebookFormat::SyncTerm),
+            _ if name.contains("netrunner") => Some(PhonebookFormat::NetRunner),
+            _ => None,
+        }
+    }
+
+    pub fn import(self, data: &[u8]) -> Result<Vec<Address>, ImportError> {
+        match self {
+            PhonebookFormat::SyncTerm => Ok(syncterm::import(&String::from_utf8_lossy(data))),
+            PhonebookFormat::NetRunner => Ok(netrunner::import(&String::from_utf8_lossy(data))),
+            PhonebookFormat::MTelnet => mtelnet::import(data),
+            PhonebookFormat::Fon => fon::import(data),
+            PhonebookFormat::Bundle => read_bundle(&String::from_utf8_lossy(data))
+                .map_err(|err| ImportError::InvalidFile(err.to_string())),
+        }
+    }
+}
+
+pub fn import_phonebook(path: &Path) -> TerminalResult<Vec<Address>> {
+    let Some(format) = PhonebookFormat::from_path(path) else {
+        return Err(Box::new(ImportError::UnknownFormat));
+    };
+    let data = fs::read(path)?;
+    Ok(format.import(&data)?)
+}
+
+/// Host and port in lower case, with the default port, to find the same system twice.
+pub fn get_host_key(adr: &Address) -> String {
+    let address = adr.address.trim().to_lowercase();
+    let address = address
+        .split_once("://")
+        .map_or(address.as_str(), |(_, address)| address);
+    let (host, port) = split_port(address.trim_end_matches('/'));
+    let default_port = match adr.protocol {
+        Protocol::Ssh => 22,
+        Protocol::Telnet | Protocol::Raw => 23,
+    };
+    format!(
+        "{}:{}",
+        host.trim_end_matches('.'),
+        port.unwrap_or(default_port)
+    )
+}
+
+/// Adds the imported addresses that aren't in the phonebook yet, returns how many.
+pub fn merge_addresses(addresses: &mut Vec<Address>, imported: Vec<Address>) -> usize {
+    let mut count = 0;
+    for adr in imported {
+        let key = get_host_key(&adr);
+        if !addresses.iter().any(|a| get_host_key(a) == key) {
+            addresses.push(adr);
+            count += 1;
+        }
+    }
+    count
+}
+
+/// Splits off the port, IPv6 addresses are in brackets.
+pub fn split_port(address: &str) -> (&str, Option<u16>) {
+    if let Some((host, port)) = address.rsplit_once(':') {
+        if !host.contains(':') || host.ends_with(']') {
+            if let Ok(port) = port.parse() {
+                return (host, Some(port));
+            }
+        }
+    }
+    (address, None)
+}
+
+/// Leaves the port out if it's the default one of the protocol.
+pub fn join_port(host: &str, port: Option<u16>, default_port: u16) -> String {
+    match port {
+        Some(port) if port != default_port && port != 0 => format!("{host}:{port}"),
+        _ => host.to_string(),
+    }
+}
+
+/// Dialing directories of modem terminals have phone numbers, only hosts can be called.
+fn is_host(number: &str) -> bool {
+    number.chars().any(|c| c.is_ascii_alphabetic() || c == '.')
+}
+
+/// A string of fixed length, padded with zeros or spaces.
+fn read_fixed(data: &[u8]) -> String {
+    let end = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).trim().to_string()
+}
+
+/// A Pascal string of `len` characters, `len + 1` bytes with the length byte.
+fn read_pascal(data: &[u8], len: usize) -> String {
+    let used = (data[0] as usize).min(len);
+    String::from_utf8_lossy(&data[1..=used]).trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{
+        address_mod::Address,
+        phonebook_io::{join_port, merge_addresses, split_port, PhonebookFormat},
+    };
+
+    #[test]
+    fn test_from_path() {
+        assert_eq!(
+            Some(PhonebookFormat::SyncTerm),
+            PhonebookFormat::from_path(Path::new("/home/me/.syncterm/syncterm.lst"))
+        );
+        assert_eq!(
+            Some(PhonebookFormat::Fon),
+            PhonebookFormat::from_path(Path::new("C:\\TELIX\\TELIX.FON"))
+        );
+        assert_eq!(None, PhonebookFormat::from_path(Path::new("notes.txt")));
+    }
+
+    #[test]
+    fn test_ports() {
+        assert_eq!(
+            ("bbs.example.com", Some(2323)),
+            split_port("bbs.example.com:2323")
+        );
+        assert_eq!(("[::1]", Some(23)), split_port("[::1]:23"));
+        assert_eq!(("::1", None), split_port("::1"));
+        assert_eq!(
+            "bbs.example.com",
+            join_port("bbs.example.com", Some(23), 23)
+        );
+        assert_eq!(
+            "bbs.example.com:22",
+            join_port("bbs.example.com", Some(22), 23)
+        );
+    }
+
+    #[test]
+    fn test_merge_addresses() {
+        let create = |address: &str| {
+            let mut adr = Address::new(String::new());
+            adr.address = address.to_string();
+            adr
+        };
+        let mut addresses = vec![create("bbs.example.com"), create("other.example.com:2323")];
+        let imported = vec![
+            create("BBS.Example.com:23"),
+            create("telnet://other.example.com.:2323/"),
+            create("other.example.com"),
+            create("new.example.com"),
+            create("new.example.com:23"),
+        ];
+        assert_eq!(2, merge_addresses(&mut addresses, imported));
+        assert_eq!("other.example.com", addresses[2].address);
+        assert_eq!("new.example.com", addresses[3].address);
+    }
+}
@@ -0,0 +1,65 @@
+//! mTelnet's `mtelnet.dir`, records of Pascal strings:
+//! name (40), address (60), port (u16), user (30), password (30) and the emulation
+//! (0 ANSI, 1 AVATAR, 2 TTY).
+use crate::address_mod::{Address, Terminal};
+
+use super::{join_port, read_pascal, split_port, ImportError};
+
+const RECORD_SIZE: usize = 41 + 61 + 2 + 31 + 31 + 1;
+
+pub fn import(data: &[u8]) -> Result<Vec<Address>, ImportError> {
+    if data.len() % RECORD_SIZE != 0 {
+        return Err(ImportError::InvalidFile(format!(
+            "size {} isn't a multiple of {RECORD_SIZE}",
+            data.len()
+        )));
+    }
+    let mut result = Vec::new();
+    for record in data.chunks(RECORD_SIZE) {
+        let address = read_pascal(&record[41..], 60);
+        if address.is_empty() {
+            continue;
+        }
+        let mut adr = Address::new(read_pascal(record, 40));
+        let (host, port) = split_port(&address);
+        let port = match u16::from_le_bytes([record[102], record[103]]) {
+            0 => port,
+            port => Some(port),
+        };
+        adr.address = join_port(host, port, 23);
+        adr.user_name = read_pascal(&record[104..], 30);
+        adr.password = read_pascal(&record[135..], 30);
+        adr.terminal_type = match record[166] {
+            1 => Terminal::Avatar,
+            2 => Terminal::Ascii,
+            _ => Terminal::Ansi,
+        };
+        result.push(adr);
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::address_mod::Terminal;
+
+    #[test]
+    fn test_import_mtelnet() {
+        let addresses = super::import(include_bytes!("fixtures/mtelnet.dir")).unwrap();
+        assert_eq!(2, addresses.len());
+        assert_eq!("Particles! BBS", addresses[0].system_name);
+        assert_eq!("particlesbbs.dyndns.org:6400", addresses[0].address);
+        assert_eq!(
+            ("guest", "guest"),
+            (
+                addresses[0].user_name.as_str(),
+                addresses[0].password.as_str()
+            )
+        );
+        assert_eq!(Terminal::Ansi, addresses[0].terminal_type);
+        assert_eq!("avatar.example.com", addresses[1].address);
+        assert_eq!(Terminal::Avatar, addresses[1].terminal_type);
+
+        assert!(super::import(&[0; 10]).is_err());
+    }
+}
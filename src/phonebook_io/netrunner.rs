@@ -0,0 +1,54 @@
+//! NetRunner's phonebook, a text file with a system per line:
+//! `name|address|port|user|password|emulation`, the fields after the address are optional.
+//! The emulation is `ansi`, `avatar` or `tty`, lines starting with `;` are comments.
+use crate::address_mod::{Address, Terminal};
+
+use super::{join_port, split_port};
+
+pub fn import(text: &str) -> Vec<Address> {
+    let mut result = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('|').map(str::trim).collect();
+        if fields.len() < 2 || fields[1].is_empty() {
+            continue;
+        }
+        let field = |i: usize| fields.get(i).copied().unwrap_or_default();
+        let mut adr = Address::new(field(0).to_string());
+        let (host, port) = split_port(field(1));
+        let port = field(2).parse().ok().or(port);
+        adr.address = join_port(host, port, 23);
+        adr.user_name = field(3).to_string();
+        adr.password = field(4).to_string();
+        adr.terminal_type = match field(5).to_lowercase().as_str() {
+            "avatar" => Terminal::Avatar,
+            "tty" | "ascii" => Terminal::Ascii,
+            _ => Terminal::Ansi,
+        };
+        result.push(adr);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::address_mod::Terminal;
+
+    #[test]
+    fn test_import_netrunner() {
+        let addresses = super::import(include_str!("fixtures/netrunner.phn"));
+        assert_eq!(3, addresses.len());
+        assert_eq!("Mystic BBS", addresses[0].system_name);
+        assert_eq!("mysticbbs.com", addresses[0].address);
+        assert_eq!("g00r00", addresses[0].user_name);
+        assert_eq!("pw", addresses[0].password);
+        assert_eq!("avatar.example.com:2323", addresses[1].address);
+        assert_eq!(Terminal::Avatar, addresses[1].terminal_type);
+        assert_eq!("plain.example.com:8023", addresses[2].address);
+        assert_eq!(Terminal::Ascii, addresses[2].terminal_type);
+        assert!(addresses[2].user_name.is_empty());
+    }
+}
@@ -0,0 +1,187 @@
+//! SyncTERM's `syncterm.lst`, an INI file with a section per system.
+use chrono::{TimeZone, Utc};
+use icy_engine::ansi::MusicOption;
+
+use crate::{
+    address_mod::{Address, Protocol, Terminal},
+    ui::screen_modes::ScreenMode,
+};
+
+use super::join_port;
+
+/// In the order of SyncTERM's enum, the values are stored as name or index.
+const CONNECTION_TYPES: [&str; 13] = [
+    "Unknown",
+    "RLogin",
+    "RLoginReversed",
+    "Telnet",
+    "Raw",
+    "SSH",
+    "SSHNA",
+    "Modem",
+    "Serial",
+    "NoRTS",
+    "Shell",
+    "MBBSGhost",
+    "TelnetS",
+];
+const MUSIC_NAMES: [&str; 3] = ["ESC [ | only", "BANSI Style", "All ANSI Music enabled"];
+
+fn get_enum(value: &str, names: &[&str]) -> Option<usize> {
+    let normalized = |s: &str| s.replace([' ', '(', ')', '-'], "").to_lowercase();
+    let value = normalized(value);
+    names
+        .iter()
+        .position(|name| normalized(name) == value)
+        .or_else(|| value.parse().ok())
+}
+
+fn get_screen_mode(value: &str) -> (ScreenMode, Option<Terminal>) {
+    let lower = value.to_lowercase();
+    if lower.starts_with("c64") || lower.starts_with("c128 (40") {
+        return (ScreenMode::Vic, Some(Terminal::PETscii));
+    }
+    if lower.starts_with("atari") && !lower.starts_with("atari st") {
+        return (ScreenMode::Antic, Some(Terminal::ATAscii));
+    }
+    if lower.starts_with("prestel") || lower.starts_with("bbc") {
+        return (ScreenMode::Videotex, Some(Terminal::ViewData));
+    }
+    // "80x25", "VGA 80x50", "Atari ST 80x25" …
+    for word in lower.split_whitespace() {
+        if let Some((w, h)) = word.split_once('x') {
+            if let (Ok(w), Ok(h)) = (w.parse(), h.parse()) {
+                return (ScreenMode::Vga(w, h), None);
+            }
+        }
+    }
+    (ScreenMode::Vga(80, 25), None)
+}
+
+fn get_terminal(value: &str) -> Option<Terminal> {
+    match value.to_lowercase().as_str() {
+        "ansi-bbs" | "ansi" => Some(Terminal::Ansi),
+        "petscii" => Some(Terminal::PETscii),
+        "atascii" => Some(Terminal::ATAscii),
+        "prestel" | "bbc" => Some(Terminal::ViewData),
+        _ => None,
+    }
+}
+
+fn parse_section(name: &str, values: &[(String, String)]) -> Option<Address> {
+    let get = |key: &str| {
+        values
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    };
+    let host = get("Address")?;
+    let connection_type = match get("ConnectionType") {
+        Some(connection_type) => get_enum(connection_type, &CONNECTION_TYPES)?,
+        None => 3,
+    };
+    let (protocol, default_port) = match connection_type {
+        3 | 12 => (Protocol::Telnet, 23),
+        4 => (Protocol::Raw, 23),
+        5 | 6 => (Protocol::Ssh, 22),
+        // RLogin, modem, serial and shell connections can't be made.
+        _ => return None,
+    };
+
+    let mut adr = Address::new(name.to_string());
+    adr.protocol = protocol;
+    let port = get("Port").and_then(|p| p.parse().ok());
+    adr.address = join_port(host, port, default_port);
+    let (screen_mode, mode_terminal) = get_screen_mode(get("ScreenMode").unwrap_or_default());
+    adr.screen_mode = screen_mode;
+    adr.terminal_type = get("Emulation")
+        .and_then(get_terminal)
+        .or(mode_terminal)
+        .unwrap_or(Terminal::Ansi);
+    // SyncTERM font names don't map to our fonts, the screen mode picks the font.
+    adr.ansi_music = match get("Music").and_then(|m| get_enum(m, &MUSIC_NAMES)) {
+        Some(1) => MusicOption::Banana,
+        Some(2) => MusicOption::Both,
+        _ => MusicOption::Off,
+    };
+    adr.user_name = get("UserName").unwrap_or_default().to_string();
+    adr.password = get("Password").unwrap_or_default().to_string();
+    adr.comment = get("Comment").unwrap_or_default().to_string();
+    if let Some(calls) = get("Calls").and_then(|c| c.parse().ok()) {
+        adr.number_of_calls = calls;
+    }
+    if let Some(time) = get("LastConnected").and_then(|t| t.parse().ok()) {
+        adr.last_call = Utc.timestamp_opt(time, 0).single().filter(|_| time > 0);
+    }
+    if let Some(time) = get("Added").and_then(|t| t.parse().ok()) {
+        if let Some(created) = Utc.timestamp_opt(time, 0).single() {
+            adr.created = created;
+        }
+    }
+    Some(adr)
+}
+
+pub fn import(text: &str) -> Vec<Address> {
+    let mut result = Vec::new();
+    let mut section: Option<(String, Vec<(String, String)>)> = None;
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') && line.ends_with(']') {
+            if let Some((name, values)) = section.take() {
+                result.extend(parse_section(&name, &values));
+            }
+            section = Some((line[1..line.len() - 1].to_string(), Vec::new()));
+        } else if let (Some((_, values)), Some((key, value))) = (&mut section, line.split_once('='))
+        {
+            values.push((key.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    if let Some((name, values)) = section {
+        result.extend(parse_section(&name, &values));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use icy_engine::ansi::MusicOption;
+
+    use crate::{
+        address_mod::{Protocol, Terminal},
+        ui::screen_modes::ScreenMode,
+    };
+
+    #[test]
+    fn test_import_syncterm() {
+        let addresses = super::import(include_str!("fixtures/syncterm.lst"));
+        assert_eq!(3, addresses.len());
+
+        let adr = &addresses[0];
+        assert_eq!("Vertrauen", adr.system_name);
+        assert_eq!("vert.synchro.net", adr.address);
+        assert_eq!(Protocol::Telnet, adr.protocol);
+        assert_eq!(Terminal::Ansi, adr.terminal_type);
+        assert_eq!(ScreenMode::Vga(80, 50), adr.screen_mode);
+        assert_eq!(None, adr.font_name);
+        assert_eq!(MusicOption::Both, adr.ansi_music);
+        assert_eq!(
+            ("sysop", "secret"),
+            (adr.user_name.as_str(), adr.password.as_str())
+        );
+        assert_eq!(12, adr.number_of_calls);
+
+        let adr = &addresses[1];
+        assert_eq!("Retro C64", adr.system_name);
+        assert_eq!("c64.example.com:6400", adr.address);
+        assert_eq!(Terminal::PETscii, adr.terminal_type);
+        assert_eq!(ScreenMode::Vic, adr.screen_mode);
+
+        let adr = &addresses[2];
+        assert_eq!(Protocol::Ssh, adr.protocol);
+        assert_eq!("ssh.example.com:2222", adr.address);
+        assert_eq!(MusicOption::Banana, adr.ansi_music);
+    }
+}
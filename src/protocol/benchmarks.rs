@@ -0,0 +1,167 @@
+//! Throughput of the protocols on a local line, run with
+//! `cargo test --release benchmarks -- --ignored --nocapture`
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    com::{Com, TermComResult, TestChannel},
+    protocol::{
+        append_zdle_encoded, decode_zdle_escape, read_zdle_bytes, ByteReader, FileDescriptor,
+        Protocol, TransferDriver, TransferState, XYModemVariant, XYmodem, ZdleEscaping, Zmodem,
+        ZDLE,
+    },
+};
+
+const TRANSFER_SIZE: usize = 4 * 1024 * 1024;
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn create_test_data() -> Vec<u8> {
+    (0..TRANSFER_SIZE).map(|i| (i * 7 % 251) as u8).collect()
+}
+
+fn mb_per_sec(bytes: usize, elapsed: Duration) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0) / elapsed.as_secs_f64()
+}
+
+/// How frames were read before: one `Com::read_u8` call per byte.
+async fn read_zdle_bytes_from_com(com: &mut Box<dyn Com>, length: usize) -> TermComResult<Vec<u8>> {
+    let mut data = Vec::with_capacity(length);
+    while data.len() < length {
+        let c = com.read_u8().await?;
+        if c == ZDLE {
+            let c2 = com.read_u8().await?;
+            data.push(decode_zdle_escape(c2).unwrap());
+        } else {
+            data.push(c);
+        }
+    }
+    Ok(data)
+}
+
+#[tokio::test]
+#[ignore]
+async fn bench_zdle_decoding() {
+    let data = create_test_data();
+    let mut encoded = Vec::new();
+    append_zdle_encoded(&mut encoded, &data);
+
+    let mut com = TestChannel::new_silent();
+    com.sender.send(&encoded).await.unwrap();
+    let start = Instant::now();
+    let decoded = read_zdle_bytes_from_com(&mut com.receiver, data.len())
+        .await
+        .unwrap();
+    let per_byte = start.elapsed();
+    assert_eq!(data, decoded);
+
+    com.sender.send(&encoded).await.unwrap();
+    let start = Instant::now();
+    let received = com.receiver.read_data().await.unwrap();
+    let decoded = read_zdle_bytes(
+        &mut ByteReader::new(&received),
+        data.len(),
+        ZdleEscaping::Standard,
+    )
+    .unwrap()
+    .unwrap();
+    let from_slice = start.elapsed();
+    assert_eq!(data, decoded);
+
+    println!(
+        "zdle decoding: read_u8 {:.1} MB/s, slice {:.1} MB/s",
+        mb_per_sec(data.len(), per_byte),
+        mb_per_sec(data.len(), from_slice)
+    );
+}
+
+async fn run(
+    mut protocol: Box<dyn Protocol>,
+    mut com: Box<dyn Com>,
+    state: Arc<Mutex<TransferState>>,
+    files: Option<Vec<FileDescriptor>>,
+) -> TermComResult<()> {
+    let mut driver = TransferDriver::new(TIMEOUT);
+    if let Some(files) = files {
+        driver
+            .initiate_send(&mut protocol, &mut com, files, state.clone())
+            .await?;
+    } else {
+        driver
+            .initiate_recv(&mut protocol, &mut com, state.clone())
+            .await?;
+    }
+    driver.run(&mut protocol, &mut com, state).await
+}
+
+async fn bench_transfer(name: &str, create: fn() -> Box<dyn Protocol>) {
+    let dir: PathBuf = std::env::temp_dir().join(format!("icy_term_bench_{name}"));
+    if dir.exists() {
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    fs::create_dir_all(&dir).unwrap();
+    let data = create_test_data();
+    let com = TestChannel::new_silent();
+
+    let files = vec![FileDescriptor::create_test(
+        "foo.bar".to_string(),
+        data.clone(),
+    )];
+    let mut recv_state = TransferState::new();
+    recv_state.download_settings.download_dir = Some(dir.clone());
+
+    let start = Instant::now();
+    let sender = tokio::spawn(run(
+        create(),
+        com.sender,
+        Arc::new(Mutex::new(TransferState::new())),
+        Some(files),
+    ));
+    let receiver = tokio::spawn(run(
+        create(),
+        com.receiver,
+        Arc::new(Mutex::new(recv_state)),
+        None,
+    ));
+    sender.await.unwrap().unwrap();
+    receiver.await.unwrap().unwrap();
+    let elapsed = start.elapsed();
+
+    assert_eq!(data, fs::read(dir.join("foo.bar")).unwrap());
+    println!(
+        "{name}: {} bytes in {elapsed:?}, {:.1} MB/s",
+        data.len(),
+        mb_per_sec(data.len(), elapsed)
+    );
+}
+
+/// The block delay only exists for slow BBS software, it would dominate the measurement.
+#[tokio::test(flavor = "multi_thread")]
+#[ignore]
+async fn bench_zmodem_transfers() {
+    bench_transfer("zmodem", || {
+        Box::new(Zmodem::new(1024).with_block_delay(Duration::ZERO))
+    })
+    .await;
+    bench_transfer("zedzap", || {
+        Box::new(Zmodem::new(8 * 1024).with_block_delay(Duration::ZERO))
+    })
+    .await;
+    bench_transfer("directzap", || {
+        Box::new(Zmodem::new_direct_zap().with_block_delay(Duration::ZERO))
+    })
+    .await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore]
+async fn bench_xymodem_transfers() {
+    bench_transfer("ymodem", || Box::new(XYmodem::new(XYModemVariant::YModem))).await;
+    bench_transfer("ymodem_g", || {
+        Box::new(XYmodem::new(XYModemVariant::YModemG))
+    })
+    .await;
+}
@@ -0,0 +1,351 @@
+use std::{
+    io::{self, ErrorKind},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::com::{Com, TermComResult};
+
+use super::{FileDescriptor, Protocol, TransferState};
+
+/// The other side is gone if nothing was sent or received for that long.
+pub const INACTIVITY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long the driver waits for data before the protocol gets to check its timers.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// What a protocol wants next after an update.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    /// There's more to do with the data at hand, call `update` again right away.
+    Continue,
+    /// Waiting for the other side, call `update` again when data arrived.
+    NeedInput,
+    /// Waiting for something else - the user or a delay. Doesn't time out.
+    Wait(Duration),
+    Finished,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Output {
+    Data(Vec<u8>),
+    /// A break signal, see `Com::send_break`.
+    Break,
+    /// Nothing gets sent for that long.
+    Pause(Duration),
+}
+
+/// Everything a protocol wants to send, the driver writes it in order.
+#[derive(Debug, Default)]
+pub struct OutputBuffer {
+    output: Vec<Output>,
+}
+
+impl OutputBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn send(&mut self, data: &[u8]) {
+        if let Some(Output::Data(last)) = self.output.last_mut() {
+            last.extend_from_slice(data);
+        } else {
+            self.output.push(Output::Data(data.to_vec()));
+        }
+    }
+
+    pub fn send_break(&mut self) {
+        self.output.push(Output::Break);
+    }
+
+    pub fn pause(&mut self, duration: Duration) {
+        self.output.push(Output::Pause(duration));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.output.is_empty()
+    }
+
+    pub fn take(&mut self) -> Vec<Output> {
+        std::mem::take(&mut self.output)
+    }
+
+    /// All data sent so far, breaks and pauses are left out.
+    #[cfg(test)]
+    pub fn take_data(&mut self) -> Vec<u8> {
+        self.take()
+            .into_iter()
+            .filter_map(|o| match o {
+                Output::Data(data) => Some(data),
+                _ => None,
+            })
+            .flatten()
+            .collect()
+    }
+}
+
+/// Received bytes a protocol didn't use yet.
+#[derive(Debug, Default)]
+pub struct InputBuffer {
+    data: Vec<u8>,
+    pos: usize,
+}
+
+impl InputBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn extend(&mut self, data: &[u8]) {
+        if self.pos > 0 && self.pos >= self.data.len() / 2 {
+            self.data.drain(..self.pos);
+            self.pos = 0;
+        }
+        self.data.extend_from_slice(data);
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data[self.pos..]
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn consume(&mut self, len: usize) {
+        self.pos = (self.pos + len).min(self.data.len());
+        if self.pos == self.data.len() {
+            self.clear();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.data.clear();
+        self.pos = 0;
+    }
+
+    pub fn read_u8(&mut self) -> Option<u8> {
+        let b = *self.as_slice().first()?;
+        self.consume(1);
+        Some(b)
+    }
+
+    /// Takes `len` bytes, nothing if less arrived so far.
+    pub fn read_exact(&mut self, len: usize) -> Option<Vec<u8>> {
+        if self.len() < len {
+            return None;
+        }
+        let result = self.as_slice()[..len].to_vec();
+        self.consume(len);
+        Some(result)
+    }
+}
+
+/// Parses received bytes without taking them from the input. Parsers return `None`
+/// when the data isn't complete yet and run again once more arrived.
+pub struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    pub fn read_u8(&mut self) -> Option<u8> {
+        let b = *self.data.get(self.pos)?;
+        self.pos += 1;
+        Some(b)
+    }
+
+    /// The bytes read so far.
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.data[self.pos..]
+    }
+
+    pub fn skip(&mut self, len: usize) {
+        self.pos = (self.pos + len).min(self.data.len());
+    }
+}
+
+/// Runs a protocol on a connection. The protocol only turns received bytes into
+/// bytes to send, reading and writing happens here.
+pub struct TransferDriver {
+    input: InputBuffer,
+    output: OutputBuffer,
+    timeout: Duration,
+    last_activity: Instant,
+    needs_input: bool,
+}
+
+impl TransferDriver {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            input: InputBuffer::new(),
+            output: OutputBuffer::new(),
+            timeout,
+            last_activity: Instant::now(),
+            needs_input: false,
+        }
+    }
+
+    pub async fn initiate_send(
+        &mut self,
+        protocol: &mut Box<dyn Protocol>,
+        com: &mut Box<dyn Com>,
+        files: Vec<FileDescriptor>,
+        transfer_state: Arc<Mutex<TransferState>>,
+    ) -> TermComResult<()> {
+        let result = protocol.initiate_send(&mut self.output, files, transfer_state);
+        self.flush(com).await?;
+        result
+    }
+
+    pub async fn initiate_recv(
+        &mut self,
+        protocol: &mut Box<dyn Protocol>,
+        com: &mut Box<dyn Com>,
+        transfer_state: Arc<Mutex<TransferState>>,
+    ) -> TermComResult<()> {
+        let result = protocol.initiate_recv(&mut self.output, transfer_state);
+        self.flush(com).await?;
+        result
+    }
+
+    /// Feeds the protocol with what arrived and sends what it produced.
+    /// Returns false once the transfer ended.
+    pub async fn step(
+        &mut self,
+        protocol: &mut Box<dyn Protocol>,
+        com: &mut Box<dyn Com>,
+        transfer_state: Arc<Mutex<TransferState>>,
+    ) -> TermComResult<bool> {
+        if self.needs_input {
+            self.read_input(com).await?;
+        }
+        let step = protocol.update(&mut self.input, &mut self.output, transfer_state);
+        self.flush(com).await?;
+        match step? {
+            Step::Continue => self.needs_input = false,
+            Step::NeedInput => self.needs_input = true,
+            Step::Wait(duration) => {
+                self.needs_input = false;
+                tokio::time::sleep(duration).await;
+                self.last_activity = Instant::now();
+            }
+            Step::Finished => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Runs the transfer until it ends.
+    pub async fn run(
+        &mut self,
+        protocol: &mut Box<dyn Protocol>,
+        com: &mut Box<dyn Com>,
+        transfer_state: Arc<Mutex<TransferState>>,
+    ) -> TermComResult<()> {
+        while self.step(protocol, com, transfer_state.clone()).await? {}
+        Ok(())
+    }
+
+    pub async fn cancel(
+        &mut self,
+        protocol: &mut Box<dyn Protocol>,
+        com: &mut Box<dyn Com>,
+    ) -> TermComResult<()> {
+        let result = protocol.cancel(&mut self.output);
+        self.flush(com).await?;
+        result
+    }
+
+    async fn read_input(&mut self, com: &mut Box<dyn Com>) -> TermComResult<()> {
+        let data = match tokio::time::timeout(POLL_INTERVAL, com.read_data()).await {
+            Ok(data) => data?,
+            Err(_) => Vec::new(),
+        };
+        if data.is_empty() {
+            if self.last_activity.elapsed() > self.timeout {
+                return Err(Box::new(io::Error::new(
+                    ErrorKind::TimedOut,
+                    "no response from the remote side",
+                )));
+            }
+            // connections that don't wait for data return right away
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        } else {
+            self.input.extend(&data);
+            self.last_activity = Instant::now();
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self, com: &mut Box<dyn Com>) -> TermComResult<()> {
+        for output in self.output.take() {
+            match output {
+                Output::Data(data) => {
+                    com.send(&data).await?;
+                    self.last_activity = Instant::now();
+                }
+                Output::Break => com.send_break().await?,
+                Output::Pause(duration) => tokio::time::sleep(duration).await,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ByteReader, InputBuffer, Output, OutputBuffer};
+
+    #[test]
+    fn test_input_buffer() {
+        let mut input = InputBuffer::new();
+        input.extend(b"Hello");
+        assert_eq!(Some(b'H'), input.read_u8());
+        assert_eq!(None, input.read_exact(5));
+        input.extend(b" World");
+        assert_eq!(Some(b"ello ".to_vec()), input.read_exact(5));
+        assert_eq!(b"World", input.as_slice());
+        input.consume(5);
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn test_byte_reader_does_not_consume() {
+        let input = b"ab";
+        let mut reader = ByteReader::new(input);
+        assert_eq!(Some(b'a'), reader.read_u8());
+        assert_eq!(Some(b'b'), reader.read_u8());
+        assert_eq!(None, reader.read_u8());
+        assert_eq!(2, reader.pos());
+    }
+
+    #[test]
+    fn test_output_buffer_merges_data() {
+        let mut output = OutputBuffer::new();
+        output.send(b"ab");
+        output.send(b"c");
+        output.send_break();
+        output.send(b"d");
+        assert_eq!(
+            vec![
+                Output::Data(b"abc".to_vec()),
+                Output::Break,
+                Output::Data(b"d".to_vec())
+            ],
+            output.take()
+        );
+        assert!(output.is_empty());
+    }
+}
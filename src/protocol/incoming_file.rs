@@ -0,0 +1,369 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use directories::UserDirs;
+
+use super::{get_file_crc32, zfile_flag};
+
+const WRITE_BUFFER_SIZE: usize = 64 * 1024;
+
+/// What happens when a downloaded file already exists in the download directory.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CollisionPolicy {
+    /// Keep the existing file and store the download as `name.1`, `name.2`…
+    #[default]
+    Rename,
+    Overwrite,
+    Skip,
+    /// Let the user decide for every file.
+    Ask,
+}
+
+impl CollisionPolicy {
+    pub const ALL: [CollisionPolicy; 4] = [
+        CollisionPolicy::Rename,
+        CollisionPolicy::Overwrite,
+        CollisionPolicy::Skip,
+        CollisionPolicy::Ask,
+    ];
+
+    /// The ZModem ZF1 file management option with the same meaning. Renaming is
+    /// the receiver's default when the sender doesn't request anything.
+    pub fn get_management_option(self) -> u8 {
+        match self {
+            CollisionPolicy::Rename | CollisionPolicy::Ask => 0,
+            CollisionPolicy::Overwrite => zfile_flag::ZMCLOB,
+            CollisionPolicy::Skip => zfile_flag::ZMPROT,
+        }
+    }
+
+    pub fn from_management_option(f1: u8) -> Option<Self> {
+        match f1 & zfile_flag::ZMMASK {
+            zfile_flag::ZMCLOB => Some(CollisionPolicy::Overwrite),
+            zfile_flag::ZMPROT => Some(CollisionPolicy::Skip),
+            _ => None,
+        }
+    }
+}
+
+/// Where and how received files are stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadSettings {
+    pub download_dir: Option<PathBuf>,
+    pub collision_policy: CollisionPolicy,
+    /// The name for protocols that don't transfer one.
+    pub file_name: Option<String>,
+}
+
+impl Default for DownloadSettings {
+    fn default() -> Self {
+        Self {
+            download_dir: get_default_download_dir(),
+            collision_policy: CollisionPolicy::default(),
+            file_name: None,
+        }
+    }
+}
+
+pub fn get_default_download_dir() -> Option<PathBuf> {
+    UserDirs::new().and_then(|user_dirs| user_dirs.download_dir().map(Path::to_path_buf))
+}
+
+/// Pending question to the user, see `CollisionPolicy::Ask`.
+#[derive(Debug, Clone)]
+pub struct FileCollision {
+    pub file_name: String,
+    pub answer: Option<CollisionPolicy>,
+}
+
+/// Makes a file name sent by the remote side safe to use in the download directory.
+/// Path components, drive letters, control and reserved characters are removed.
+pub fn sanitize_file_name(file_name: &str) -> String {
+    let file_name = file_name
+        .rsplit(|c| c == '/' || c == '\\')
+        .next()
+        .unwrap_or_default();
+    let mut result: String = file_name
+        .chars()
+        .map(|c| {
+            if c.is_control() || matches!(c, '<' | '>' | ':' | '"' | '|' | '?' | '*') {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+
+    // Windows ignores trailing dots and spaces, "." and ".." are no file names at all.
+    while result.ends_with('.') || result.ends_with(' ') {
+        result.pop();
+    }
+    // a leading dot hides the file or could replace a dotfile like .bashrc
+    let result = result
+        .trim_start_matches(|c: char| c == '.' || c.is_whitespace())
+        .to_string();
+    if result.is_empty() {
+        return "download".to_string();
+    }
+
+    let stem = result
+        .split('.')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_uppercase();
+    let is_reserved = matches!(stem.as_str(), "CON" | "PRN" | "AUX" | "NUL")
+        || (stem.len() == 4
+            && (stem.starts_with("COM") || stem.starts_with("LPT"))
+            && stem.as_bytes()[3].is_ascii_digit());
+    if is_reserved {
+        format!("_{result}")
+    } else {
+        result
+    }
+}
+
+/// A file that is currently received. The data is written to `<name>.part`
+/// in the download directory and moved to its final name once the transfer
+/// is complete. An interrupted transfer leaves the partial file behind so it
+/// can be resumed later.
+pub struct IncomingFile {
+    path: PathBuf,
+    /// Set when an existing download is continued in place (crash recovery).
+    is_target: bool,
+    writer: BufWriter<File>,
+    len: usize,
+}
+
+impl IncomingFile {
+    pub fn get_partial_path(dir: &Path, file_name: &str) -> PathBuf {
+        dir.join(format!("{}.part", sanitize_file_name(file_name)))
+    }
+
+    /// Creates a new partial file or continues an existing one.
+    pub fn open(dir: &Path, file_name: &str) -> io::Result<Self> {
+        IncomingFile::open_path(IncomingFile::get_partial_path(dir, file_name), false)
+    }
+
+    /// Continues writing to an already existing file.
+    pub fn append_to(path: PathBuf) -> io::Result<Self> {
+        IncomingFile::open_path(path, true)
+    }
+
+    fn open_path(path: PathBuf, is_target: bool) -> io::Result<Self> {
+        let mut file = OpenOptions::new().write(true).create(true).open(&path)?;
+        let len = file.seek(SeekFrom::End(0))? as usize;
+        Ok(Self {
+            path,
+            is_target,
+            writer: BufWriter::with_capacity(WRITE_BUFFER_SIZE, file),
+            len,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_target(&self) -> bool {
+        self.is_target
+    }
+
+    /// CRC-32 of the data written so far, to check that it's the start of the file that's sent.
+    pub fn get_crc32(&mut self) -> io::Result<u32> {
+        self.writer.flush()?;
+        get_file_crc32(&mut File::open(&self.path)?, self.len)
+    }
+
+    pub fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        self.writer.write_all(data)?;
+        self.len += data.len();
+        Ok(())
+    }
+
+    /// Cuts the file, used when the sender restarts at an earlier position.
+    pub fn set_len(&mut self, len: usize) -> io::Result<()> {
+        self.writer.flush()?;
+        let file = self.writer.get_mut();
+        file.set_len(len as u64)?;
+        file.seek(SeekFrom::Start(len as u64))?;
+        self.len = len;
+        Ok(())
+    }
+
+    /// Keeps the data received so far for a later resume.
+    pub fn suspend(mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        let is_empty = self.len == 0 && !self.is_target;
+        let path = self.path.clone();
+        drop(self);
+        if is_empty {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Removes the data received so far.
+    pub fn discard(self) -> io::Result<()> {
+        let IncomingFile { path, writer, .. } = self;
+        drop(writer);
+        fs::remove_file(path)
+    }
+
+    /// Moves the received data to `dir/file_name`. An existing file is replaced
+    /// if `overwrite` is set, otherwise the download gets a new name.
+    /// Returns the path of the stored file.
+    pub fn finish(self, dir: &Path, file_name: &str, overwrite: bool) -> io::Result<PathBuf> {
+        let IncomingFile {
+            path,
+            is_target,
+            writer,
+            ..
+        } = self;
+        writer
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)?;
+        if is_target {
+            return Ok(path);
+        }
+
+        let file_name = sanitize_file_name(file_name);
+        let mut file_name_path = dir.join(&file_name);
+        let mut i = 1;
+        while !overwrite && file_name_path.exists() {
+            file_name_path = dir.join(format!("{file_name}.{i}"));
+            i += 1;
+        }
+        fs::rename(&path, &file_name_path)?;
+        Ok(file_name_path)
+    }
+}
+
+#[cfg(windows)]
+const LOCAL_NEWLINE: &[u8] = b"\r\n";
+#[cfg(not(windows))]
+const LOCAL_NEWLINE: &[u8] = b"\n";
+
+/// Rewrites CR LF, CR and LF line endings of a received text file to the local convention.
+pub fn convert_to_local_newlines(path: &Path) -> io::Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut writer = BufWriter::with_capacity(WRITE_BUFFER_SIZE, File::create(&tmp_path)?);
+        let mut last_was_cr = false;
+        loop {
+            let buf = reader.fill_buf()?;
+            if buf.is_empty() {
+                break;
+            }
+            for &b in buf {
+                match b {
+                    b'\r' => writer.write_all(LOCAL_NEWLINE)?,
+                    b'\n' if last_was_cr => {}
+                    b'\n' => writer.write_all(LOCAL_NEWLINE)?,
+                    _ => writer.write_all(&[b])?,
+                }
+                last_was_cr = b == b'\r';
+            }
+            let len = buf.len();
+            reader.consume(len);
+        }
+        writer.flush()?;
+    }
+    fs::rename(tmp_path, path)
+}
+
+/// Applies modification time and mode sent by the remote side, 0 means unknown.
+pub fn set_file_metadata(path: &Path, mtime: u64, mode: u32) -> io::Result<()> {
+    if mtime > 0 {
+        let file = OpenOptions::new().write(true).open(path)?;
+        file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(mtime))?;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if mode & 0o777 != 0 {
+            // Read/write permissions only: downloads never become executable or setuid
+            // and the owner always keeps access.
+            let mode = (mode & 0o666) | 0o600;
+            fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{convert_to_local_newlines, sanitize_file_name, IncomingFile, LOCAL_NEWLINE};
+
+    #[test]
+    fn test_incoming_file_resume_and_finish() {
+        let dir = std::env::temp_dir().join("icy_term_incoming_file");
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("foo.bar"), b"old").unwrap();
+
+        let mut incoming = IncomingFile::open(&dir, "foo.bar").unwrap();
+        incoming.write_all(b"Hello").unwrap();
+        incoming.suspend().unwrap();
+        assert_eq!(
+            b"Hello".to_vec(),
+            fs::read(dir.join("foo.bar.part")).unwrap()
+        );
+
+        let mut incoming = IncomingFile::open(&dir, "foo.bar").unwrap();
+        assert_eq!(5, incoming.len());
+        incoming.set_len(4).unwrap();
+        incoming.write_all(b"o World").unwrap();
+        let path = incoming.finish(&dir, "foo.bar", false).unwrap();
+
+        assert_eq!(dir.join("foo.bar.1"), path);
+        assert_eq!(b"Hello World".to_vec(), fs::read(path).unwrap());
+        assert_eq!(b"old".to_vec(), fs::read(dir.join("foo.bar")).unwrap());
+        assert!(!dir.join("foo.bar.part").exists());
+    }
+
+    #[test]
+    fn test_sanitize_file_name() {
+        assert_eq!("passwd", sanitize_file_name("../../etc/passwd"));
+        assert_eq!("evil.exe", sanitize_file_name("C:\\Windows\\evil.exe"));
+        assert_eq!("a_b_c", sanitize_file_name("a:b\x07c"));
+        assert_eq!("download", sanitize_file_name(".."));
+        assert_eq!("download", sanitize_file_name(""));
+        assert_eq!("_con.txt", sanitize_file_name("con.txt"));
+        assert_eq!("_COM1", sanitize_file_name("COM1"));
+        assert_eq!("COMMAND.COM", sanitize_file_name("COMMAND.COM"));
+        assert_eq!("file.zip", sanitize_file_name("file.zip. "));
+        assert_eq!("bashrc", sanitize_file_name(".bashrc"));
+        assert_eq!("profile", sanitize_file_name("~/. .profile"));
+        assert_eq!("download", sanitize_file_name("..."));
+    }
+
+    #[test]
+    fn test_convert_to_local_newlines() {
+        let dir = std::env::temp_dir().join("icy_term_newlines");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("text.txt");
+        fs::write(&path, b"a\r\nb\rc\nd").unwrap();
+        convert_to_local_newlines(&path).unwrap();
+
+        let nl = std::str::from_utf8(LOCAL_NEWLINE).unwrap();
+        let expected = format!("a{nl}b{nl}c{nl}d");
+        assert_eq!(expected.as_bytes(), fs::read(&path).unwrap().as_slice());
+    }
+}
@@ -0,0 +1,27 @@
+pub const SOH: u8 = 0x01;
+pub const CR: u8 = 0x0D;
+
+/// The control prefix for what we send.
+pub const QCTL: u8 = b'#';
+/// The repeat count prefix, only used if both sides offer it.
+pub const REPT: u8 = b'~';
+/// The longest packet without the long packet extension.
+pub const MAX_PACKET_LEN: usize = 94;
+/// The most bytes a repeat count covers.
+pub const MAX_REPEAT: usize = 94;
+/// What the other side should wait for a packet before it sends again.
+pub const TIMEOUT_SECS: u8 = 10;
+/// The same packet failing that often in a row ends the transfer.
+pub const MAX_RETRIES: usize = 10;
+
+pub mod packet_type {
+    pub const SEND_INIT: u8 = b'S';
+    pub const ACK: u8 = b'Y';
+    pub const NAK: u8 = b'N';
+    pub const FILE_HEADER: u8 = b'F';
+    pub const ATTRIBUTES: u8 = b'A';
+    pub const DATA: u8 = b'D';
+    pub const EOF: u8 = b'Z';
+    pub const BREAK: u8 = b'B';
+    pub const ERROR: u8 = b'E';
+}
@@ -0,0 +1,35 @@
+use std::error::Error;
+
+#[derive(Debug, Clone)]
+pub enum TransmissionError {
+    /// The other side sent an error packet.
+    Remote(String),
+    TooManyRetries,
+    UnexpectedPacket(u8),
+}
+
+impl std::fmt::Display for TransmissionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransmissionError::Remote(msg) => write!(f, "remote kermit error: {msg}"),
+            TransmissionError::TooManyRetries => write!(f, "too many retries"),
+            TransmissionError::UnexpectedPacket(packet_type) => {
+                write!(f, "unexpected kermit packet '{}'", char::from(*packet_type))
+            }
+        }
+    }
+}
+
+impl Error for TransmissionError {
+    fn description(&self) -> &str {
+        "use std::display"
+    }
+
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+
+    fn cause(&self) -> Option<&dyn Error> {
+        self.source()
+    }
+}
@@ -0,0 +1,430 @@
+//
+// Kermit protocol specification: Frank da Cruz, "Kermit Protocol Manual", sixth edition.
+// Only the basic protocol - no long packets, no sliding windows, no server commands.
+
+use std::sync::{Arc, Mutex};
+
+use crate::com::TermComResult;
+
+mod constants;
+mod error_mod;
+mod rk;
+mod sk;
+mod tests;
+
+use self::constants::{packet_type, CR, MAX_PACKET_LEN, MAX_REPEAT, QCTL, REPT, SOH, TIMEOUT_SECS};
+
+use super::{FileDescriptor, InputBuffer, OutputBuffer, Protocol, Step, TransferState};
+
+pub fn tochar(b: u8) -> u8 {
+    b + 32
+}
+
+pub fn unchar(b: u8) -> u8 {
+    b.wrapping_sub(32)
+}
+
+/// Turns a control character into a printable one and back.
+pub fn ctl(b: u8) -> u8 {
+    b ^ 64
+}
+
+/// Everything after the SOH is printable, anything else means the packet got cut off.
+fn is_packet_char(b: u8) -> bool {
+    (32..127).contains(&(b & 0x7F))
+}
+
+/// Characters that may be used as 8th bit prefix.
+fn is_prefix_char(b: u8) -> bool {
+    matches!(b, 33..=62 | 96..=126)
+}
+
+/// CRC-16/KERMIT, the CCITT polynomial bit reversed.
+pub fn get_crc16_kermit(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for b in data {
+        crc ^= u16::from(*b);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0x8408
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockCheck {
+    /// Type 1, 6 bits. The send-init and its ACK always use it.
+    Checksum,
+    /// Type 3.
+    Crc16,
+}
+
+impl BlockCheck {
+    pub fn len(self) -> usize {
+        match self {
+            BlockCheck::Checksum => 1,
+            BlockCheck::Crc16 => 3,
+        }
+    }
+
+    /// Over the packet from the LEN field to the last data byte.
+    pub fn get(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            BlockCheck::Checksum => {
+                let s: u32 = data.iter().map(|b| u32::from(*b)).sum();
+                vec![tochar(((s + ((s & 0xC0) >> 6)) & 0x3F) as u8)]
+            }
+            BlockCheck::Crc16 => {
+                let crc = get_crc16_kermit(data);
+                vec![
+                    tochar(((crc >> 12) & 0x0F) as u8),
+                    tochar(((crc >> 6) & 0x3F) as u8),
+                    tochar((crc & 0x3F) as u8),
+                ]
+            }
+        }
+    }
+
+    fn get_name(self) -> &'static str {
+        match self {
+            BlockCheck::Checksum => "Checksum",
+            BlockCheck::Crc16 => "Crc",
+        }
+    }
+}
+
+/// The send-init fields we offer: MAXL, TIME, NPAD, PADC, EOL, QCTL, QBIN, CHKT and REPT.
+/// 8th bit prefixing is done if the other side asks for it, the line is 8 bit clean.
+pub fn get_init_data() -> Vec<u8> {
+    vec![
+        tochar(MAX_PACKET_LEN as u8),
+        tochar(TIMEOUT_SECS),
+        tochar(0),
+        ctl(0),
+        tochar(CR),
+        QCTL,
+        b'Y',
+        b'3',
+        REPT,
+    ]
+}
+
+/// What both sides agreed on in the send-init exchange.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Params {
+    /// The longest packet the other side takes.
+    pub max_len: usize,
+    pub eol: u8,
+    /// The control prefix of the other side, ours is always `QCTL`.
+    pub remote_qctl: u8,
+    pub qbin: Option<u8>,
+    pub rept: Option<u8>,
+    pub check: BlockCheck,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            max_len: 80,
+            eol: CR,
+            remote_qctl: QCTL,
+            qbin: None,
+            rept: None,
+            check: BlockCheck::Checksum,
+        }
+    }
+}
+
+impl Params {
+    /// Takes the send-init fields of the other side, blank or missing ones are defaults.
+    pub fn negotiate(remote: &[u8]) -> Self {
+        let field = |i: usize| remote.get(i).copied().filter(|b| *b != b' ');
+        let mut params = Params::default();
+        if let Some(max_len) = field(0).map(unchar) {
+            params.max_len = usize::from(max_len).clamp(20, MAX_PACKET_LEN);
+        }
+        if let Some(eol) = field(4).map(unchar).filter(|b| *b < 32) {
+            params.eol = eol;
+        }
+        if let Some(qctl) = field(5).filter(|b| is_packet_char(*b)) {
+            params.remote_qctl = qctl;
+        }
+        params.qbin = field(6).filter(|b| is_prefix_char(*b) && *b != params.remote_qctl);
+        if field(7) == Some(b'3') {
+            params.check = BlockCheck::Crc16;
+        }
+        params.rept = field(8).filter(|b| *b == REPT);
+        params
+    }
+
+    /// The data of a packet of the longest length the other side takes.
+    pub fn get_max_data_len(&self) -> usize {
+        self.max_len - 2 - self.check.len()
+    }
+
+    fn encode_byte(&self, b: u8) -> Vec<u8> {
+        let mut unit = Vec::with_capacity(3);
+        let mut b = b;
+        if let Some(qbin) = self.qbin {
+            if b & 0x80 != 0 {
+                unit.push(qbin);
+                b &= 0x7F;
+            }
+        }
+        let a = b & 0x7F;
+        if a < 32 || a == 127 {
+            unit.push(QCTL);
+            unit.push(ctl(b));
+        } else if a == QCTL || Some(a) == self.qbin || Some(a) == self.rept {
+            unit.push(QCTL);
+            unit.push(b);
+        } else {
+            unit.push(b);
+        }
+        unit
+    }
+
+    /// Encodes as much of `data` as fits into `max_len` bytes.
+    /// Returns the encoded bytes and how many bytes of `data` they hold.
+    pub fn encode(&self, data: &[u8], max_len: usize) -> (Vec<u8>, usize) {
+        let mut result = Vec::new();
+        let mut i = 0;
+        while i < data.len() {
+            let b = data[i];
+            let mut unit = self.encode_byte(b);
+            let mut count = 1;
+            if let Some(rept) = self.rept {
+                let run = data[i..]
+                    .iter()
+                    .take(MAX_REPEAT)
+                    .take_while(|x| **x == b)
+                    .count();
+                if run > 2 {
+                    unit.splice(0..0, [rept, tochar(run as u8)]);
+                    count = run;
+                }
+            }
+            if result.len() + unit.len() > max_len {
+                break;
+            }
+            result.extend(unit);
+            i += count;
+        }
+        (result, i)
+    }
+
+    /// Undoes the prefixing of the other side, a cut off prefix at the end is dropped.
+    pub fn decode(&self, data: &[u8]) -> Vec<u8> {
+        let mut result = Vec::new();
+        let mut iter = data.iter().copied();
+        while let Some(mut b) = iter.next() {
+            let mut count = 1;
+            if Some(b) == self.rept {
+                let (Some(n), Some(next)) = (iter.next(), iter.next()) else {
+                    break;
+                };
+                count = usize::from(unchar(n));
+                b = next;
+            }
+            let mut bit8 = 0;
+            if Some(b) == self.qbin {
+                let Some(next) = iter.next() else {
+                    break;
+                };
+                bit8 = 0x80;
+                b = next;
+            }
+            if b == self.remote_qctl {
+                let Some(next) = iter.next() else {
+                    break;
+                };
+                b = next;
+                if (63..=95).contains(&(b & 0x7F)) {
+                    b = ctl(b);
+                }
+            }
+            result.extend(std::iter::repeat(b | bit8).take(count));
+        }
+        result
+    }
+
+    fn get_check_and_size(&self) -> String {
+        format!("{}/{}", self.check.get_name(), self.max_len)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Packet {
+    pub seq: u8,
+    pub packet_type: u8,
+    pub data: Vec<u8>,
+}
+
+impl Packet {
+    pub fn new(seq: u8, packet_type: u8, data: Vec<u8>) -> Self {
+        Self {
+            seq,
+            packet_type,
+            data,
+        }
+    }
+
+    pub fn encode(&self, check: BlockCheck, eol: u8) -> Vec<u8> {
+        let len = 2 + self.data.len() + check.len();
+        let mut result = vec![SOH, tochar(len as u8), tochar(self.seq), self.packet_type];
+        result.extend_from_slice(&self.data);
+        result.extend(check.get(&result[1..]));
+        result.push(eol);
+        result
+    }
+
+    /// Takes a packet from the SOH to the block check, `None` if the check fails.
+    fn parse(raw: &[u8], check: BlockCheck) -> Option<Self> {
+        if raw.len() < 4 + check.len() {
+            return None;
+        }
+        let (body, chk) = raw[1..].split_at(raw.len() - 1 - check.len());
+        if check.get(body) != chk {
+            return None;
+        }
+        Some(Self::new(unchar(body[1]), body[2], body[3..].to_vec()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Received {
+    Packet(Packet),
+    /// Cut off or failed the block check.
+    Garbled,
+}
+
+/// Skips to the next packet and takes it from the input, `None` until one is complete.
+pub fn read_packet(input: &mut InputBuffer, check: BlockCheck) -> Option<Received> {
+    let Some(start) = input.as_slice().iter().position(|b| *b == SOH) else {
+        input.clear();
+        return None;
+    };
+    input.consume(start);
+
+    let data = input.as_slice();
+    let len = *data.get(1)?;
+    if !is_packet_char(len) || usize::from(unchar(len & 0x7F)) < 3 {
+        input.consume(1);
+        return Some(Received::Garbled);
+    }
+    let end = 2 + usize::from(unchar(len));
+    if let Some(i) = data[1..end.min(data.len())]
+        .iter()
+        .position(|b| !is_packet_char(*b))
+    {
+        input.consume(1 + i);
+        return Some(Received::Garbled);
+    }
+    if data.len() < end {
+        return None;
+    }
+    let raw = &data[..end];
+    let packet = Packet::parse(raw, check).or_else(|| {
+        // the send-init and its ACK are always checked with type 1
+        let is_init =
+            raw[3] == packet_type::SEND_INIT || (raw[3] == packet_type::ACK && raw[2] == tochar(0));
+        if is_init && check != BlockCheck::Checksum {
+            Packet::parse(raw, BlockCheck::Checksum)
+        } else {
+            None
+        }
+    });
+    input.consume(end);
+    Some(packet.map_or(Received::Garbled, Received::Packet))
+}
+
+pub struct Kermit {
+    sk: Option<sk::Sk>,
+    rk: Option<rk::Rk>,
+}
+
+impl Kermit {
+    pub fn new() -> Self {
+        Self { sk: None, rk: None }
+    }
+}
+
+impl Protocol for Kermit {
+    fn update(
+        &mut self,
+        input: &mut InputBuffer,
+        output: &mut OutputBuffer,
+        transfer_state: Arc<Mutex<TransferState>>,
+    ) -> TermComResult<Step> {
+        if let Some(rk) = &mut self.rk {
+            let step = rk.update(input, output, &transfer_state)?;
+            transfer_state.lock().unwrap().is_finished = rk.is_finished();
+            if rk.is_finished() {
+                return Ok(Step::Finished);
+            }
+            Ok(step)
+        } else if let Some(sk) = &mut self.sk {
+            let step = sk.update(input, output, &transfer_state)?;
+            transfer_state.lock().unwrap().is_finished = sk.is_finished();
+            if sk.is_finished() {
+                return Ok(Step::Finished);
+            }
+            Ok(step)
+        } else {
+            Ok(Step::Finished)
+        }
+    }
+
+    fn initiate_send(
+        &mut self,
+        output: &mut OutputBuffer,
+        files: Vec<FileDescriptor>,
+        transfer_state: Arc<Mutex<TransferState>>,
+    ) -> TermComResult<()> {
+        let mut sk = sk::Sk::new();
+        sk.send(files, output);
+        self.sk = Some(sk);
+        transfer_state.lock().unwrap().protocol_name = "Kermit".to_string();
+        Ok(())
+    }
+
+    fn initiate_recv(
+        &mut self,
+        output: &mut OutputBuffer,
+        transfer_state: Arc<Mutex<TransferState>>,
+    ) -> TermComResult<()> {
+        let settings = {
+            let mut transfer_state = transfer_state.lock().unwrap();
+            transfer_state.protocol_name = "Kermit".to_string();
+            transfer_state.download_settings.clone()
+        };
+        let mut rk = rk::Rk::new();
+        rk.download_dir = settings.download_dir;
+        rk.collision_policy = settings.collision_policy;
+        rk.recv(output);
+        self.rk = Some(rk);
+        Ok(())
+    }
+
+    fn get_received_files(&mut self) -> Vec<FileDescriptor> {
+        if let Some(rk) = &mut self.rk {
+            std::mem::take(&mut rk.files)
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn cancel(&mut self, output: &mut OutputBuffer) -> TermComResult<()> {
+        if let Some(rk) = &mut self.rk {
+            return rk.cancel(output);
+        }
+        if let Some(sk) = &mut self.sk {
+            sk.cancel(output);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,274 @@
+use std::{
+    io::{self, ErrorKind},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use super::{
+    constants::{packet_type, MAX_RETRIES},
+    error_mod::TransmissionError,
+    get_init_data, read_packet, unchar, Packet, Params, Received,
+};
+use crate::{
+    com::TermComResult,
+    protocol::{
+        get_default_download_dir, sanitize_file_name, CollisionPolicy, FileDescriptor,
+        IncomingFile, InputBuffer, OutputBuffer, Step, TransferState,
+    },
+};
+
+/// The packet that's expected next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RecvState {
+    None,
+    Init,
+    FileHeader,
+    Data,
+}
+
+pub struct Rk {
+    pub files: Vec<FileDescriptor>,
+    pub download_dir: Option<PathBuf>,
+    pub collision_policy: CollisionPolicy,
+    incoming: Option<IncomingFile>,
+
+    params: Params,
+    /// The sequence number of the next packet.
+    seq: u8,
+    /// The answer to the last packet, sent again if the sender repeats it.
+    last_ack: Vec<u8>,
+    recv_state: RecvState,
+    retries: usize,
+    errors: usize,
+}
+
+impl Rk {
+    pub fn new() -> Self {
+        Self {
+            files: Vec::new(),
+            download_dir: get_default_download_dir(),
+            collision_policy: CollisionPolicy::default(),
+            incoming: None,
+            params: Params::default(),
+            seq: 0,
+            last_ack: Vec::new(),
+            recv_state: RecvState::None,
+            retries: 0,
+            errors: 0,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.recv_state == RecvState::None
+    }
+
+    /// A NAK for the send-init, senders that already sent it send it again.
+    pub fn recv(&mut self, output: &mut OutputBuffer) {
+        self.seq = 0;
+        self.recv_state = RecvState::Init;
+        output.send(&self.create_packet(packet_type::NAK, Vec::new()));
+    }
+
+    pub fn update(
+        &mut self,
+        input: &mut InputBuffer,
+        output: &mut OutputBuffer,
+        state: &Arc<Mutex<TransferState>>,
+    ) -> TermComResult<Step> {
+        if let Ok(transfer_state) = &mut state.lock() {
+            transfer_state.current_state = match self.recv_state {
+                RecvState::None => "Finishing…",
+                RecvState::Init => "Start receiving…",
+                _ => "Receiving data…",
+            };
+            let transfer_info = &mut transfer_state.recieve_state;
+            if let Some(f) = self.files.last() {
+                transfer_info.file_name = f.file_name.clone();
+                transfer_info.file_size = f.size;
+            }
+            transfer_info.bytes_transfered = self.incoming.as_ref().map_or(0, IncomingFile::len);
+            transfer_info.errors = self.errors;
+            transfer_info.check_size = self.params.get_check_and_size();
+            transfer_info.update_statistics();
+        }
+
+        if self.recv_state == RecvState::None {
+            return Ok(Step::Continue);
+        }
+        let Some(received) = read_packet(input, self.params.check) else {
+            return Ok(Step::NeedInput);
+        };
+        let packet = match received {
+            Received::Packet(packet) => packet,
+            Received::Garbled => {
+                self.nak(output)?;
+                return Ok(Step::Continue);
+            }
+        };
+        if packet.packet_type == packet_type::ERROR {
+            self.recv_state = RecvState::None;
+            if let Some(incoming) = self.incoming.take() {
+                incoming.suspend()?;
+            }
+            let msg = String::from_utf8_lossy(&self.params.decode(&packet.data)).to_string();
+            return Err(Box::new(TransmissionError::Remote(msg)));
+        }
+        if packet.seq != self.seq {
+            if packet.seq == (self.seq + 63) % 64 && !self.last_ack.is_empty() {
+                // the sender missed the ACK
+                self.retry(output)?;
+                output.send(&self.last_ack);
+            } else {
+                self.nak(output)?;
+            }
+            return Ok(Step::Continue);
+        }
+        self.retries = 0;
+
+        match (self.recv_state, packet.packet_type) {
+            (RecvState::Init, packet_type::SEND_INIT) => {
+                let params = Params::negotiate(&packet.data);
+                // the ACK still has the type 1 check
+                self.ack(output, get_init_data());
+                self.params = params;
+                self.recv_state = RecvState::FileHeader;
+            }
+            (RecvState::FileHeader, packet_type::FILE_HEADER) => {
+                let name = String::from_utf8_lossy(&self.params.decode(&packet.data)).to_string();
+                let mut fd = FileDescriptor::new();
+                fd.file_name = sanitize_file_name(&name);
+                self.files.push(fd);
+                self.open_incoming_file()?;
+                self.ack(output, Vec::new());
+                self.recv_state = RecvState::Data;
+            }
+            (RecvState::FileHeader, packet_type::BREAK) => {
+                self.ack(output, Vec::new());
+                self.recv_state = RecvState::None;
+            }
+            (RecvState::Data, packet_type::ATTRIBUTES) => {
+                if let Some(size) = get_file_size(&self.params.decode(&packet.data)) {
+                    if let Some(fd) = self.files.last_mut() {
+                        fd.size = size;
+                    }
+                }
+                self.ack(output, Vec::new());
+            }
+            (RecvState::Data, packet_type::DATA) => {
+                let data = self.params.decode(&packet.data);
+                if let Some(incoming) = &mut self.incoming {
+                    incoming.write_all(&data)?;
+                }
+                self.ack(output, Vec::new());
+            }
+            (RecvState::Data, packet_type::EOF) => {
+                // "D" discards the file
+                if packet.data.first() == Some(&b'D') {
+                    if let Some(incoming) = self.incoming.take() {
+                        incoming.discard()?;
+                    }
+                    self.files.pop();
+                } else {
+                    self.save_file()?;
+                }
+                self.ack(output, Vec::new());
+                self.recv_state = RecvState::FileHeader;
+            }
+            (_, other) => {
+                self.cancel(output)?;
+                return Err(Box::new(TransmissionError::UnexpectedPacket(other)));
+            }
+        }
+        Ok(Step::Continue)
+    }
+
+    fn open_incoming_file(&mut self) -> TermComResult<()> {
+        let Some(dir) = &self.download_dir else {
+            return Err(Box::new(io::Error::new(
+                ErrorKind::NotFound,
+                "no download directory",
+            )));
+        };
+        let file_name = self.files.last().map_or("", |fd| fd.file_name.as_str());
+        let mut incoming = IncomingFile::open(dir, file_name)?;
+        // kermit doesn't resume, remove data of an earlier attempt
+        incoming.set_len(0)?;
+        self.incoming = Some(incoming);
+        Ok(())
+    }
+
+    fn save_file(&mut self) -> TermComResult<()> {
+        let (Some(dir), Some(incoming), Some(fd)) = (
+            &self.download_dir,
+            self.incoming.take(),
+            self.files.last_mut(),
+        ) else {
+            return Ok(());
+        };
+        // Kermit can't wait for the user, asking falls back to renaming.
+        let exists = dir.join(sanitize_file_name(&fd.file_name)).exists();
+        if exists && self.collision_policy == CollisionPolicy::Skip {
+            incoming.discard()?;
+            self.files.pop();
+            return Ok(());
+        }
+        let overwrite = self.collision_policy == CollisionPolicy::Overwrite;
+        let path = incoming.finish(dir, &fd.file_name, overwrite)?;
+        fd.path_name = path.to_string_lossy().to_string();
+        fd.set_path(path);
+        Ok(())
+    }
+
+    fn create_packet(&self, packet_type: u8, data: Vec<u8>) -> Vec<u8> {
+        Packet::new(self.seq, packet_type, data).encode(self.params.check, self.params.eol)
+    }
+
+    fn ack(&mut self, output: &mut OutputBuffer, data: Vec<u8>) {
+        self.last_ack = self.create_packet(packet_type::ACK, data);
+        output.send(&self.last_ack);
+        self.seq = (self.seq + 1) % 64;
+    }
+
+    /// Asks for the expected packet again.
+    fn nak(&mut self, output: &mut OutputBuffer) -> TermComResult<()> {
+        self.retry(output)?;
+        output.send(&self.create_packet(packet_type::NAK, Vec::new()));
+        Ok(())
+    }
+
+    fn retry(&mut self, output: &mut OutputBuffer) -> TermComResult<()> {
+        self.errors += 1;
+        self.retries += 1;
+        if self.retries > MAX_RETRIES {
+            self.cancel(output)?;
+            return Err(Box::new(TransmissionError::TooManyRetries));
+        }
+        Ok(())
+    }
+
+    pub fn cancel(&mut self, output: &mut OutputBuffer) -> TermComResult<()> {
+        if self.recv_state != RecvState::None {
+            output.send(&self.create_packet(packet_type::ERROR, b"canceled".to_vec()));
+        }
+        self.recv_state = RecvState::None;
+        if let Some(incoming) = self.incoming.take() {
+            incoming.suspend()?;
+        }
+        Ok(())
+    }
+}
+
+/// The size attribute ('1') of an attribute packet.
+fn get_file_size(data: &[u8]) -> Option<usize> {
+    let mut i = 0;
+    while i + 1 < data.len() {
+        let tag = data[i];
+        let len = usize::from(unchar(data[i + 1]));
+        let value = data.get(i + 2..i + 2 + len)?;
+        if tag == b'1' {
+            return std::str::from_utf8(value).ok()?.parse().ok();
+        }
+        i += 2 + len;
+    }
+    None
+}
@@ -0,0 +1,277 @@
+use std::{
+    io::Read,
+    sync::{Arc, Mutex},
+};
+
+use super::{
+    constants::{packet_type, MAX_REPEAT, MAX_RETRIES},
+    error_mod::TransmissionError,
+    get_init_data, read_packet, tochar, Packet, Params, Received,
+};
+use crate::{
+    com::TermComResult,
+    protocol::{FileDescriptor, InputBuffer, OutputBuffer, ReadSeek, Step, TransferState},
+};
+
+/// The packet that waits for its ACK.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SendState {
+    None,
+    Init,
+    FileHeader,
+    Attributes,
+    Data,
+    EndOfFile,
+    Break,
+}
+
+pub struct Sk {
+    pub files: Vec<FileDescriptor>,
+    cur_file: usize,
+    reader: Option<Box<dyn ReadSeek>>,
+    /// Read from the file but not sent yet.
+    pending: Vec<u8>,
+    bytes_send: usize,
+    /// The receiver asked to skip the rest of the batch.
+    skip_batch: bool,
+
+    params: Params,
+    seq: u8,
+    /// Sent again on a NAK.
+    packet: Vec<u8>,
+    send_state: SendState,
+    retries: usize,
+    errors: usize,
+}
+
+impl Sk {
+    pub fn new() -> Self {
+        Self {
+            files: Vec::new(),
+            cur_file: 0,
+            reader: None,
+            pending: Vec::new(),
+            bytes_send: 0,
+            skip_batch: false,
+            params: Params::default(),
+            seq: 0,
+            packet: Vec::new(),
+            send_state: SendState::None,
+            retries: 0,
+            errors: 0,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.send_state == SendState::None
+    }
+
+    pub fn send(&mut self, files: Vec<FileDescriptor>, output: &mut OutputBuffer) {
+        self.files = files;
+        self.cur_file = 0;
+        self.seq = 0;
+        self.send_packet(output, packet_type::SEND_INIT, get_init_data());
+        self.send_state = SendState::Init;
+    }
+
+    pub fn update(
+        &mut self,
+        input: &mut InputBuffer,
+        output: &mut OutputBuffer,
+        state: &Arc<Mutex<TransferState>>,
+    ) -> TermComResult<Step> {
+        if let Ok(transfer_state) = &mut state.lock() {
+            transfer_state.current_state = match self.send_state {
+                SendState::None | SendState::Break => "Finishing…",
+                SendState::Init => "Initiate send…",
+                _ => "Sending data…",
+            };
+            let transfer_info = &mut transfer_state.send_state;
+            if let Some(f) = self.files.get(self.cur_file) {
+                transfer_info.file_name = f.file_name.clone();
+                transfer_info.file_size = f.size;
+            }
+            transfer_info.bytes_transfered = self.bytes_send;
+            transfer_info.total_files = self.files.len();
+            transfer_info.batch_files_transfered = self.cur_file;
+            transfer_info.total_bytes = self.files.iter().map(|f| f.size).sum();
+            transfer_info.batch_bytes_transfered = self
+                .files
+                .iter()
+                .take(self.cur_file)
+                .map(|f| f.size)
+                .sum::<usize>()
+                + self
+                    .files
+                    .get(self.cur_file)
+                    .map_or(0, |f| self.bytes_send.min(f.size));
+            transfer_info.errors = self.errors;
+            transfer_info.check_size = self.params.get_check_and_size();
+            transfer_info.update_statistics();
+        }
+
+        if self.send_state == SendState::None {
+            return Ok(Step::Continue);
+        }
+        let Some(received) = read_packet(input, self.params.check) else {
+            return Ok(Step::NeedInput);
+        };
+        match received {
+            // A NAK for the next packet means the receiver got the current one.
+            Received::Packet(packet)
+                if (packet.packet_type == packet_type::ACK && packet.seq == self.seq)
+                    || (packet.packet_type == packet_type::NAK
+                        && packet.seq == (self.seq + 1) % 64) =>
+            {
+                if packet.packet_type == packet_type::NAK {
+                    self.next_packet(&[], output)?;
+                } else {
+                    self.next_packet(&packet.data, output)?;
+                }
+            }
+            Received::Packet(packet) if packet.packet_type == packet_type::NAK => {
+                if packet.seq == self.seq {
+                    self.resend(output)?;
+                }
+            }
+            Received::Packet(packet) if packet.packet_type == packet_type::ERROR => {
+                self.send_state = SendState::None;
+                let msg = String::from_utf8_lossy(&self.params.decode(&packet.data)).to_string();
+                return Err(Box::new(TransmissionError::Remote(msg)));
+            }
+            // ACKs of earlier packets the receiver got twice
+            Received::Packet(_) => {}
+            Received::Garbled => self.resend(output)?,
+        }
+        Ok(Step::Continue)
+    }
+
+    /// The current packet went through, `ack_data` is what the receiver answered.
+    fn next_packet(&mut self, ack_data: &[u8], output: &mut OutputBuffer) -> TermComResult<()> {
+        self.seq = (self.seq + 1) % 64;
+        match self.send_state {
+            SendState::None => {}
+            SendState::Init => {
+                self.params = Params::negotiate(ack_data);
+                self.send_next_file(output);
+            }
+            SendState::FileHeader => {
+                self.reader = Some(self.files[self.cur_file].open_reader()?);
+                self.pending.clear();
+                self.bytes_send = 0;
+                // the file size in bytes
+                let size = self.files[self.cur_file].size.to_string();
+                let mut attributes = vec![b'1', tochar(size.len() as u8)];
+                attributes.extend_from_slice(size.as_bytes());
+                let (data, _) = self
+                    .params
+                    .encode(&attributes, self.params.get_max_data_len());
+                self.send_packet(output, packet_type::ATTRIBUTES, data);
+                self.send_state = SendState::Attributes;
+            }
+            SendState::Attributes => {
+                // 'N' refuses the file
+                if ack_data.first() == Some(&b'N') {
+                    self.skip_file(output);
+                } else {
+                    self.send_data(output)?;
+                }
+            }
+            SendState::Data => {
+                // 'X' skips the file, 'Z' the whole batch
+                match ack_data.first() {
+                    Some(b'X') => self.skip_file(output),
+                    Some(b'Z') => {
+                        self.skip_batch = true;
+                        self.skip_file(output);
+                    }
+                    _ => self.send_data(output)?,
+                }
+            }
+            SendState::EndOfFile => {
+                self.cur_file += 1;
+                self.send_next_file(output);
+            }
+            SendState::Break => {
+                self.send_state = SendState::None;
+            }
+        }
+        Ok(())
+    }
+
+    fn send_next_file(&mut self, output: &mut OutputBuffer) {
+        if self.skip_batch || self.cur_file >= self.files.len() {
+            self.send_packet(output, packet_type::BREAK, Vec::new());
+            self.send_state = SendState::Break;
+            return;
+        }
+        let name = self.files[self.cur_file].file_name.clone();
+        let (data, _) = self
+            .params
+            .encode(name.as_bytes(), self.params.get_max_data_len());
+        self.send_packet(output, packet_type::FILE_HEADER, data);
+        self.send_state = SendState::FileHeader;
+    }
+
+    fn send_data(&mut self, output: &mut OutputBuffer) -> TermComResult<()> {
+        let max_len = self.params.get_max_data_len();
+        // repeat counts may pack that much into one packet
+        let wanted = max_len * MAX_REPEAT;
+        if self.pending.len() < wanted {
+            if let Some(reader) = &mut self.reader {
+                reader
+                    .by_ref()
+                    .take((wanted - self.pending.len()) as u64)
+                    .read_to_end(&mut self.pending)?;
+            }
+        }
+        if self.pending.is_empty() {
+            self.reader = None;
+            self.send_packet(output, packet_type::EOF, Vec::new());
+            self.send_state = SendState::EndOfFile;
+            return Ok(());
+        }
+        let (data, used) = self.params.encode(&self.pending, max_len);
+        self.pending.drain(..used);
+        self.bytes_send += used;
+        self.send_packet(output, packet_type::DATA, data);
+        self.send_state = SendState::Data;
+        Ok(())
+    }
+
+    /// Ends the file with a discard.
+    fn skip_file(&mut self, output: &mut OutputBuffer) {
+        self.reader = None;
+        self.pending.clear();
+        self.send_packet(output, packet_type::EOF, b"D".to_vec());
+        self.send_state = SendState::EndOfFile;
+    }
+
+    fn send_packet(&mut self, output: &mut OutputBuffer, packet_type: u8, data: Vec<u8>) {
+        self.packet =
+            Packet::new(self.seq, packet_type, data).encode(self.params.check, self.params.eol);
+        self.retries = 0;
+        output.send(&self.packet);
+    }
+
+    fn resend(&mut self, output: &mut OutputBuffer) -> TermComResult<()> {
+        self.errors += 1;
+        self.retries += 1;
+        if self.retries > MAX_RETRIES {
+            self.cancel(output);
+            return Err(Box::new(TransmissionError::TooManyRetries));
+        }
+        output.send(&self.packet);
+        Ok(())
+    }
+
+    pub fn cancel(&mut self, output: &mut OutputBuffer) {
+        if self.send_state == SendState::None {
+            return;
+        }
+        self.send_state = SendState::None;
+        self.reader = None;
+        let packet = Packet::new(self.seq, packet_type::ERROR, b"canceled".to_vec());
+        output.send(&packet.encode(self.params.check, self.params.eol));
+    }
+}
@@ -0,0 +1,225 @@
+#[cfg(test)]
+mod kermit_tests {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+        sync::{Arc, Mutex},
+    };
+
+    use crate::protocol::{
+        kermit::{
+            constants::packet_type, get_crc16_kermit, get_init_data, read_packet, rk::Rk, sk::Sk,
+            BlockCheck, Packet, Params, Received,
+        },
+        FileDescriptor, InputBuffer, OutputBuffer, Step, TransferState,
+    };
+
+    fn create_download_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("icy_term_{name}"));
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn create_test_data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    /// Passes the packets between both sides until neither has anything to do.
+    fn run(sk: &mut Sk, rk: &mut Rk, mut to_receiver: Vec<u8>, mut to_sender: Vec<u8>) {
+        let state = Arc::new(Mutex::new(TransferState::new()));
+        let mut send_input = InputBuffer::new();
+        let mut recv_input = InputBuffer::new();
+        let mut output = OutputBuffer::new();
+        for _ in 0..10_000 {
+            if to_receiver.is_empty() && to_sender.is_empty() {
+                break;
+            }
+            recv_input.extend(&std::mem::take(&mut to_receiver));
+            while !rk.is_finished() {
+                if rk.update(&mut recv_input, &mut output, &state).unwrap() == Step::NeedInput {
+                    break;
+                }
+            }
+            to_sender.extend(output.take_data());
+
+            send_input.extend(&std::mem::take(&mut to_sender));
+            while !sk.is_finished() {
+                if sk.update(&mut send_input, &mut output, &state).unwrap() == Step::NeedInput {
+                    break;
+                }
+            }
+            to_receiver.extend(output.take_data());
+        }
+    }
+
+    fn transfer(dir: &Path, files: Vec<FileDescriptor>) -> Rk {
+        let mut output = OutputBuffer::new();
+        let mut sk = Sk::new();
+        sk.send(files, &mut output);
+        let to_receiver = output.take_data();
+
+        let mut rk = Rk::new();
+        rk.download_dir = Some(dir.to_path_buf());
+        rk.recv(&mut output);
+        let to_sender = output.take_data();
+
+        run(&mut sk, &mut rk, to_receiver, to_sender);
+        assert!(sk.is_finished());
+        assert!(rk.is_finished());
+        rk
+    }
+
+    #[test]
+    fn test_crc16_kermit() {
+        assert_eq!(0x2189, get_crc16_kermit(b"123456789"));
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let data: Vec<u8> = (0..=255).chain([0; 50]).chain(*b"##~~~~&&").collect();
+        let mut params = Params::negotiate(&get_init_data());
+        for qbin in [None, Some(b'&')] {
+            for rept in [None, Some(b'~')] {
+                params.qbin = qbin;
+                params.rept = rept;
+                let (encoded, used) = params.encode(&data, 10_000);
+                assert_eq!(data.len(), used);
+                assert!(encoded.iter().all(|b| (32..127).contains(&(b & 0x7F))));
+                if qbin.is_some() {
+                    assert!(encoded.iter().all(|b| *b < 0x80));
+                }
+                assert_eq!(data, params.decode(&encoded));
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_stops_at_max_len() {
+        let params = Params::default();
+        let (encoded, used) = params.encode(b"ab\x01cd", 3);
+        assert_eq!(b"ab".to_vec(), encoded);
+        assert_eq!(2, used);
+    }
+
+    #[test]
+    fn test_negotiate() {
+        // C-Kermit's send-init: 94 bytes, CR, '#', 8th bit prefix on request, type 3, '~'
+        let params = Params::negotiate(b"~* @-#Y3~^>J)0___F");
+        assert_eq!(94, params.max_len);
+        assert_eq!(0x0D, params.eol);
+        assert_eq!(None, params.qbin);
+        assert_eq!(Some(b'~'), params.rept);
+        assert_eq!(BlockCheck::Crc16, params.check);
+
+        let params = Params::negotiate(b"H* @-#&1");
+        assert_eq!(40, params.max_len);
+        assert_eq!(Some(b'&'), params.qbin);
+        assert_eq!(None, params.rept);
+        assert_eq!(BlockCheck::Checksum, params.check);
+    }
+
+    #[test]
+    fn test_read_packet() {
+        let packet = Packet::new(5, packet_type::DATA, b"Hello".to_vec());
+        for check in [BlockCheck::Checksum, BlockCheck::Crc16] {
+            let mut input = InputBuffer::new();
+            input.extend(b"noise");
+            input.extend(&packet.encode(check, 0x0D));
+            assert_eq!(
+                Some(Received::Packet(packet.clone())),
+                read_packet(&mut input, check)
+            );
+        }
+
+        // cut off by the end of line
+        let raw = packet.encode(BlockCheck::Crc16, 0x0D);
+        let mut input = InputBuffer::new();
+        input.extend(&raw[..5]);
+        assert_eq!(None, read_packet(&mut input, BlockCheck::Crc16));
+        input.extend(b"\r");
+        assert_eq!(
+            Some(Received::Garbled),
+            read_packet(&mut input, BlockCheck::Crc16)
+        );
+
+        let mut corrupted = raw.clone();
+        corrupted[6] ^= 1;
+        let mut input = InputBuffer::new();
+        input.extend(&corrupted);
+        assert_eq!(
+            Some(Received::Garbled),
+            read_packet(&mut input, BlockCheck::Crc16)
+        );
+    }
+
+    #[test]
+    fn test_kermit_batch() {
+        let dir = create_download_dir("kermit_batch");
+        let files = vec![
+            FileDescriptor::create_test("foo.bar".to_string(), create_test_data(10_000)),
+            FileDescriptor::create_test("empty".to_string(), Vec::new()),
+            FileDescriptor::create_test("zeros".to_string(), vec![0; 5000]),
+        ];
+        let rk = transfer(&dir, files.clone());
+        assert_eq!(3, rk.files.len());
+        for (sent, received) in files.iter().zip(rk.files.iter()) {
+            assert_eq!(sent.file_name, received.file_name);
+            assert_eq!(sent.size, received.size);
+            assert_eq!(
+                sent.get_data(),
+                fs::read(dir.join(&sent.file_name)).unwrap()
+            );
+        }
+    }
+
+    /// The receiver asks for the send-init although it's already underway, the sender
+    /// sends it twice and the receiver has to answer both.
+    #[test]
+    fn test_kermit_repeated_send_init() {
+        let dir = create_download_dir("kermit_repeated_init");
+        let data = create_test_data(1000);
+        let mut output = OutputBuffer::new();
+        let mut sk = Sk::new();
+        sk.send(
+            vec![FileDescriptor::create_test(
+                "foo.bar".to_string(),
+                data.clone(),
+            )],
+            &mut output,
+        );
+        let send_init = output.take_data();
+        let mut to_receiver = send_init.clone();
+        to_receiver.extend(send_init);
+
+        let mut rk = Rk::new();
+        rk.download_dir = Some(dir.clone());
+        rk.recv(&mut output);
+        let to_sender = output.take_data();
+        run(&mut sk, &mut rk, to_receiver, to_sender);
+        assert!(sk.is_finished());
+        assert!(rk.is_finished());
+        assert_eq!(data, fs::read(dir.join("foo.bar")).unwrap());
+    }
+
+    #[test]
+    fn test_kermit_remote_error() {
+        let state = Arc::new(Mutex::new(TransferState::new()));
+        let mut output = OutputBuffer::new();
+        let mut sk = Sk::new();
+        sk.send(
+            vec![FileDescriptor::create_test("foo.bar".to_string(), vec![1])],
+            &mut output,
+        );
+        let mut input = InputBuffer::new();
+        input.extend(
+            &Packet::new(0, packet_type::ERROR, b"disk full".to_vec())
+                .encode(BlockCheck::Checksum, 0x0D),
+        );
+        let err = sk.update(&mut input, &mut output, &state).unwrap_err();
+        assert!(err.to_string().contains("disk full"));
+        assert!(sk.is_finished());
+    }
+}
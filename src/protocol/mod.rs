@@ -1,18 +1,50 @@
 #![allow(dead_code)]
 
-use std::fs;
+use std::fs::{self, File};
+use std::io::{BufReader, Cursor, Read, Seek};
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 
 pub mod xymodem;
-use async_trait::async_trait;
 pub use xymodem::*;
 
 pub mod zmodem;
-use crate::com::{Com, TermComResult};
+use crate::com::TermComResult;
 pub use zmodem::*;
 
+pub mod kermit;
+pub use kermit::Kermit;
+
+pub mod punter;
+pub use punter::Punter;
+
+pub mod driver;
+pub use driver::*;
+
+pub mod text_upload;
+pub use text_upload::*;
+
+pub mod incoming_file;
+pub use incoming_file::*;
+
+pub mod transfer_log;
+pub use transfer_log::*;
+
+pub mod transfer_rate;
+pub use transfer_rate::*;
+
+#[cfg(test)]
+mod tests;
+
+#[cfg(test)]
+mod benchmarks;
+
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+pub trait ReadSeek: Read + Seek + Send {}
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
 #[derive(Debug, Clone)]
 pub struct FileDescriptor {
     pub path_name: String,
@@ -62,6 +94,17 @@ impl FileDescriptor {
         })
     }
 
+    pub fn from_data(file_name: String, data: Vec<u8>) -> Self {
+        Self {
+            path_name: String::new(),
+            file_name,
+            path: PathBuf::new(),
+            size: data.len(),
+            date: 0,
+            data: Some(data),
+        }
+    }
+
     #[cfg(test)]
     pub fn create_test(file_name: String, data: Vec<u8>) -> Self {
         Self {
@@ -74,6 +117,21 @@ impl FileDescriptor {
         }
     }
 
+    pub fn set_path(&mut self, path: PathBuf) {
+        self.path = path;
+    }
+
+    /// Opens the file contents for sending. Files on disk are read in chunks
+    /// so large uploads don't need to fit into memory.
+    pub fn open_reader(&self) -> TermComResult<Box<dyn ReadSeek>> {
+        if let Some(data) = &self.data {
+            Ok(Box::new(Cursor::new(data.clone())))
+        } else {
+            let file = File::open(&self.path)?;
+            Ok(Box::new(BufReader::with_capacity(READ_BUFFER_SIZE, file)))
+        }
+    }
+
     pub fn get_data(&self) -> std::vec::Vec<u8> {
         if let Some(data) = &self.data {
             data.clone()
@@ -91,18 +149,37 @@ impl FileDescriptor {
     }
 }
 
+/// A finished file of a batch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileStatistics {
+    pub file_name: String,
+    pub bytes: usize,
+    pub errors: usize,
+}
+
 #[derive(Debug, Clone)]
 pub struct TransferInformation {
     pub file_name: String,
     pub file_size: usize,
     pub bytes_transfered: usize,
 
+    /// Set by the protocol, some of them only count errors in a row.
     pub errors: usize,
-    pub files_finished: Vec<String>,
+    pub finished_files: Vec<FileStatistics>,
     pub check_size: String,
-    time: SystemTime,
-    bytes_transferred_timed: usize,
-    pub bps: u64,
+
+    rate: RateEstimator,
+    cur_file: String,
+    cur_file_bytes: usize,
+    cur_file_errors: usize,
+    last_errors: usize,
+    total_errors: usize,
+
+    /// Batch totals, 0 if the protocol doesn't know them.
+    pub total_files: usize,
+    pub total_bytes: usize,
+    pub batch_files_transfered: usize,
+    pub batch_bytes_transfered: usize,
 
     pub output_log: Vec<String>,
 }
@@ -114,39 +191,87 @@ impl TransferInformation {
             file_size: 0,
             bytes_transfered: 0,
             errors: 0,
-            files_finished: Vec::new(),
+            finished_files: Vec::new(),
             check_size: String::new(),
-            time: SystemTime::now(),
             output_log: Vec::new(),
-            bytes_transferred_timed: 0,
-            bps: 0,
+            rate: RateEstimator::new(Instant::now(), RATE_WINDOW),
+            cur_file: String::new(),
+            cur_file_bytes: 0,
+            cur_file_errors: 0,
+            last_errors: 0,
+            total_errors: 0,
+            total_files: 0,
+            total_bytes: 0,
+            batch_files_transfered: 0,
+            batch_bytes_transfered: 0,
         }
     }
 
-    pub fn update_bps(&mut self) {
-        let bytes = self
-            .bytes_transfered
-            .saturating_sub(self.bytes_transferred_timed);
-        let length = SystemTime::now().duration_since(self.time).unwrap();
+    /// Protocols call this after they've updated the fields.
+    pub fn update_statistics(&mut self) {
+        self.update_statistics_at(Instant::now());
+    }
 
-        if length > Duration::from_secs(10) {
-            self.bytes_transferred_timed = self.bytes_transfered;
+    fn update_statistics_at(&mut self, now: Instant) {
+        if self.file_name != self.cur_file {
+            if !self.cur_file.is_empty() {
+                self.finished_files.push(FileStatistics {
+                    file_name: std::mem::take(&mut self.cur_file),
+                    bytes: self.cur_file_bytes,
+                    errors: self.cur_file_errors,
+                });
+            }
+            self.cur_file = self.file_name.clone();
+            self.cur_file_bytes = 0;
+            self.cur_file_errors = 0;
         }
 
-        let length = length.as_secs();
-        if length > 0 {
-            self.bps = self.bps / 2 + bytes as u64 / length;
+        if self.errors > self.last_errors {
+            let new_errors = self.errors - self.last_errors;
+            self.cur_file_errors += new_errors;
+            self.total_errors += new_errors;
         }
+        self.last_errors = self.errors;
 
-        let length = SystemTime::now().duration_since(self.time).unwrap();
-        if length > Duration::from_secs(5) {
-            self.bytes_transferred_timed = self.bytes_transfered;
-            self.time = SystemTime::now();
-        }
+        // Rewinds after errors send the data again, that's still throughput.
+        let new_bytes = self.bytes_transfered.saturating_sub(self.cur_file_bytes);
+        self.cur_file_bytes = self.bytes_transfered;
+        self.rate.add_sample(now, self.rate.get_total() + new_bytes);
     }
 
-    pub fn get_bps(&self) -> u64 {
-        self.bps
+    /// Bytes per second over the last seconds.
+    pub fn get_cps(&self) -> u64 {
+        self.rate.get_rate()
+    }
+
+    pub fn get_average_cps(&self) -> u64 {
+        self.rate.get_average()
+    }
+
+    pub fn get_peak_cps(&self) -> u64 {
+        self.rate.get_peak()
+    }
+
+    pub fn get_total_errors(&self) -> usize {
+        self.total_errors
+    }
+
+    pub fn get_file_errors(&self) -> usize {
+        self.cur_file_errors
+    }
+
+    /// Of the whole batch if the protocol knows it, otherwise of the current file.
+    pub fn get_time_left(&self) -> Option<Duration> {
+        let cps = self.get_cps();
+        if cps == 0 {
+            return None;
+        }
+        let bytes_left = if self.total_bytes > 0 {
+            self.total_bytes.saturating_sub(self.batch_bytes_transfered)
+        } else {
+            self.file_size.saturating_sub(self.bytes_transfered)
+        };
+        Some(Duration::from_secs(bytes_left as u64 / cps))
     }
 
     pub fn _write(&mut self, txt: String) {
@@ -162,6 +287,23 @@ pub struct TransferState {
     pub start_time: SystemTime,
     pub send_state: TransferInformation,
     pub recieve_state: TransferInformation,
+
+    pub download_settings: DownloadSettings,
+    pub collision: Option<FileCollision>,
+    /// Bytes a ZModem sender sends before it waits for an acknowledge, 0 streams nonstop.
+    pub zmodem_window_size: usize,
+    /// A ZModem sender lets the receiver continue existing files.
+    pub zmodem_resume: bool,
+    pub zmodem_escaping: ZdleEscaping,
+    /// Received bytes that belong to the terminal, like the echo during a text upload.
+    pub terminal_data: Vec<u8>,
+
+    /// Written to the DSZLOG, see `transfer_log`.
+    pub board_name: String,
+    pub log_file: Option<PathBuf>,
+    pub download_hook: String,
+    /// The files of a finished download, for the download browser.
+    pub received_files: Vec<FileDescriptor>,
 }
 
 impl TransferState {
@@ -173,45 +315,62 @@ impl TransferState {
             start_time: SystemTime::now(),
             send_state: TransferInformation::new(),
             recieve_state: TransferInformation::new(),
+            download_settings: DownloadSettings::default(),
+            collision: None,
+            zmodem_window_size: 0,
+            zmodem_resume: false,
+            zmodem_escaping: ZdleEscaping::default(),
+            terminal_data: Vec::new(),
+            board_name: String::new(),
+            log_file: None,
+            download_hook: String::new(),
+            received_files: Vec::new(),
         }
     }
 }
 
-#[async_trait]
+/// A transfer protocol as a state machine. It doesn't touch the connection,
+/// it only turns received bytes into bytes to send - see `TransferDriver`.
 pub trait Protocol: Send {
-    async fn update(
+    /// Takes what it can use from `input` and tells what it needs next.
+    fn update(
         &mut self,
-        com: &mut Box<dyn Com>,
+        input: &mut InputBuffer,
+        output: &mut OutputBuffer,
         transfer_state: Arc<Mutex<TransferState>>,
-    ) -> TermComResult<bool>;
+    ) -> TermComResult<Step>;
 
-    async fn initiate_send(
+    fn initiate_send(
         &mut self,
-        com: &mut Box<dyn Com>,
+        output: &mut OutputBuffer,
         files: Vec<FileDescriptor>,
         transfer_state: Arc<Mutex<TransferState>>,
     ) -> TermComResult<()>;
 
-    async fn initiate_recv(
+    fn initiate_recv(
         &mut self,
-        com: &mut Box<dyn Com>,
+        output: &mut OutputBuffer,
         transfer_state: Arc<Mutex<TransferState>>,
     ) -> TermComResult<()>;
 
     fn get_received_files(&mut self) -> Vec<FileDescriptor>;
 
-    async fn cancel(&mut self, com: &mut Box<dyn Com>) -> TermComResult<()>;
+    fn cancel(&mut self, output: &mut OutputBuffer) -> TermComResult<()>;
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum TransferType {
     ZModem,
     ZedZap,
+    DirectZap,
     XModem,
     XModem1k,
     XModem1kG,
     YModem,
     YModemG,
+    Kermit,
+    Punter,
+    Text(TextUploadConfig),
 }
 
 impl TransferType {
@@ -219,11 +378,48 @@ impl TransferType {
         match self {
             TransferType::ZModem => Box::new(Zmodem::new(1024)),
             TransferType::ZedZap => Box::new(Zmodem::new(8 * 1024)),
+            TransferType::DirectZap => Box::new(Zmodem::new_direct_zap()),
             TransferType::XModem => Box::new(XYmodem::new(XYModemVariant::XModem)),
             TransferType::XModem1k => Box::new(XYmodem::new(XYModemVariant::XModem1k)),
             TransferType::XModem1kG => Box::new(XYmodem::new(XYModemVariant::XModem1kG)),
             TransferType::YModem => Box::new(XYmodem::new(XYModemVariant::YModem)),
             TransferType::YModemG => Box::new(XYmodem::new(XYModemVariant::YModemG)),
+            TransferType::Kermit => Box::new(Kermit::new()),
+            TransferType::Punter => Box::new(Punter::new()),
+            TransferType::Text(config) => Box::new(TextUpload::new(config)),
+        }
+    }
+    pub fn is_zmodem(&self) -> bool {
+        matches!(
+            self,
+            TransferType::ZModem | TransferType::ZedZap | TransferType::DirectZap
+        )
+    }
+
+    /// The code of the protocol in IEMSI packets.
+    pub fn get_iemsi_code(&self) -> Option<&'static str> {
+        match self {
+            TransferType::ZModem => Some("ZMO"),
+            TransferType::ZedZap => Some("ZAP"),
+            TransferType::DirectZap => Some("DZA"),
+            _ => None,
+        }
+    }
+
+    /// Parses the names `Debug` writes, text uploads have no name to store.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "zmodem" => Some(TransferType::ZModem),
+            "zedzap" => Some(TransferType::ZedZap),
+            "directzap" => Some(TransferType::DirectZap),
+            "xmodem" => Some(TransferType::XModem),
+            "xmodem1k" => Some(TransferType::XModem1k),
+            "xmodem1kg" => Some(TransferType::XModem1kG),
+            "ymodem" => Some(TransferType::YModem),
+            "ymodemg" => Some(TransferType::YModemG),
+            "kermit" => Some(TransferType::Kermit),
+            "punter" => Some(TransferType::Punter),
+            _ => None,
         }
     }
 }
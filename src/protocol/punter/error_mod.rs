@@ -0,0 +1,34 @@
+use std::error::Error;
+
+#[derive(Debug, Clone, Copy)]
+pub enum TransmissionError {
+    OneFile,
+    TooManyRetries,
+    BlockNumberMismatch(u16, u16),
+}
+
+impl std::fmt::Display for TransmissionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransmissionError::OneFile => write!(f, "Only 1 file can be send with punter"),
+            TransmissionError::TooManyRetries => write!(f, "too many retries"),
+            TransmissionError::BlockNumberMismatch(expected, got) => {
+                write!(f, "expected block {expected} but got {got}")
+            }
+        }
+    }
+}
+
+impl Error for TransmissionError {
+    fn description(&self) -> &str {
+        "use std::display"
+    }
+
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+
+    fn cause(&self) -> Option<&dyn Error> {
+        self.source()
+    }
+}
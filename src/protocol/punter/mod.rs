@@ -0,0 +1,183 @@
+//
+// Punter C1, the protocol of C64 boards. A transfer has two stages with the same
+// handshake: one block with the file type and then the file itself.
+//
+// Receiver  GOO                 S/B         GOO                 S/B ...      GOO        SYN
+// Sender          ACK                block0        ACK              block1       SYN        S/B
+//
+// A block is the additive and the cyclic checksum, the length of the next block,
+// the block number and up to 248 bytes of data. BAD instead of GOO asks for the
+// block again, a block number of $FFxx marks the last block.
+
+use std::sync::{Arc, Mutex};
+
+use crate::com::TermComResult;
+
+mod error_mod;
+mod rp;
+mod sp;
+mod tests;
+
+use self::error_mod::TransmissionError;
+
+use super::{FileDescriptor, InputBuffer, OutputBuffer, Protocol, Step, TransferState};
+
+pub const GOO: [u8; 3] = *b"GOO";
+pub const BAD: [u8; 3] = *b"BAD";
+pub const ACK: [u8; 3] = *b"ACK";
+pub const SEND_BLOCK: [u8; 3] = *b"S/B";
+pub const SYN: [u8; 3] = *b"SYN";
+
+pub const HEADER_LEN: usize = 7;
+pub const MAX_BLOCK_LEN: usize = 255;
+/// The high byte of the last block number.
+pub const LAST_BLOCK: u16 = 0xFF00;
+pub const FILE_TYPE_PRG: u8 = 1;
+pub const MAX_RETRIES: usize = 10;
+
+/// The additive and the cyclic checksum over the block from the length byte on.
+pub fn get_checksums(data: &[u8]) -> (u16, u16) {
+    let mut sum = 0u16;
+    let mut clc = 0u16;
+    for b in data {
+        sum = sum.wrapping_add(u16::from(*b));
+        clc = (clc ^ u16::from(*b)).rotate_left(1);
+    }
+    (sum, clc)
+}
+
+pub fn create_block(number: u16, next_len: usize, data: &[u8]) -> Vec<u8> {
+    let mut block = vec![0; 4];
+    block.push(next_len as u8);
+    block.extend_from_slice(&number.to_le_bytes());
+    block.extend_from_slice(data);
+    let (sum, clc) = get_checksums(&block[4..]);
+    block[0..2].copy_from_slice(&sum.to_le_bytes());
+    block[2..4].copy_from_slice(&clc.to_le_bytes());
+    block
+}
+
+/// Returns the length of the next block, the block number and the data, `None` if a checksum fails.
+pub fn parse_block(block: &[u8]) -> Option<(usize, u16, &[u8])> {
+    if block.len() < HEADER_LEN {
+        return None;
+    }
+    let (sum, clc) = get_checksums(&block[4..]);
+    if block[0..2] != sum.to_le_bytes() || block[2..4] != clc.to_le_bytes() {
+        return None;
+    }
+    let next_len = usize::from(block[4]);
+    let number = u16::from_le_bytes([block[5], block[6]]);
+    Some((next_len, number, &block[HEADER_LEN..]))
+}
+
+/// Takes the next handshake code from the input, other bytes are skipped.
+pub fn read_code(input: &mut InputBuffer) -> Option<[u8; 3]> {
+    let data = input.as_slice();
+    for (i, window) in data.windows(3).enumerate() {
+        let code = [window[0], window[1], window[2]];
+        if [GOO, BAD, ACK, SEND_BLOCK, SYN].contains(&code) {
+            input.consume(i + 3);
+            return Some(code);
+        }
+    }
+    // keep what may be the start of a code
+    let skipped = data.len().saturating_sub(2);
+    input.consume(skipped);
+    None
+}
+
+pub struct Punter {
+    sp: Option<sp::Sp>,
+    rp: Option<rp::Rp>,
+}
+
+impl Punter {
+    pub fn new() -> Self {
+        Self { sp: None, rp: None }
+    }
+}
+
+impl Protocol for Punter {
+    fn update(
+        &mut self,
+        input: &mut InputBuffer,
+        output: &mut OutputBuffer,
+        transfer_state: Arc<Mutex<TransferState>>,
+    ) -> TermComResult<Step> {
+        if let Some(rp) = &mut self.rp {
+            let step = rp.update(input, output, &transfer_state)?;
+            transfer_state.lock().unwrap().is_finished = rp.is_finished();
+            if rp.is_finished() {
+                return Ok(Step::Finished);
+            }
+            Ok(step)
+        } else if let Some(sp) = &mut self.sp {
+            let step = sp.update(input, output, &transfer_state)?;
+            transfer_state.lock().unwrap().is_finished = sp.is_finished();
+            if sp.is_finished() {
+                return Ok(Step::Finished);
+            }
+            Ok(step)
+        } else {
+            Ok(Step::Finished)
+        }
+    }
+
+    fn initiate_send(
+        &mut self,
+        _output: &mut OutputBuffer,
+        files: Vec<FileDescriptor>,
+        transfer_state: Arc<Mutex<TransferState>>,
+    ) -> TermComResult<()> {
+        if files.len() != 1 {
+            return Err(Box::new(TransmissionError::OneFile));
+        }
+        let mut sp = sp::Sp::new();
+        sp.send(files);
+        self.sp = Some(sp);
+        transfer_state.lock().unwrap().protocol_name = "Punter".to_string();
+        Ok(())
+    }
+
+    fn initiate_recv(
+        &mut self,
+        output: &mut OutputBuffer,
+        transfer_state: Arc<Mutex<TransferState>>,
+    ) -> TermComResult<()> {
+        let settings = {
+            let mut transfer_state = transfer_state.lock().unwrap();
+            transfer_state.protocol_name = "Punter".to_string();
+            transfer_state.download_settings.clone()
+        };
+        let mut rp = rp::Rp::new();
+        rp.download_dir = settings.download_dir;
+        rp.collision_policy = settings.collision_policy;
+        rp.recv(output);
+        // Punter doesn't transfer the file name, the user picks one before the download
+        let mut fd = FileDescriptor::new();
+        fd.file_name = settings.file_name.unwrap_or_default();
+        rp.files.push(fd);
+        self.rp = Some(rp);
+        Ok(())
+    }
+
+    fn get_received_files(&mut self) -> Vec<FileDescriptor> {
+        if let Some(rp) = &mut self.rp {
+            std::mem::take(&mut rp.files)
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn cancel(&mut self, _output: &mut OutputBuffer) -> TermComResult<()> {
+        // there's no cancel sequence, the other side times out
+        if let Some(rp) = &mut self.rp {
+            return rp.cancel();
+        }
+        if let Some(sp) = &mut self.sp {
+            sp.cancel();
+        }
+        Ok(())
+    }
+}
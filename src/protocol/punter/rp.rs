@@ -0,0 +1,227 @@
+use std::{
+    io::{self, ErrorKind},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use super::{
+    error_mod::TransmissionError, parse_block, read_code, ACK, BAD, GOO, HEADER_LEN, LAST_BLOCK,
+    MAX_BLOCK_LEN, MAX_RETRIES, SEND_BLOCK, SYN,
+};
+use crate::{
+    com::TermComResult,
+    protocol::{
+        get_default_download_dir, sanitize_file_name, CollisionPolicy, FileDescriptor,
+        IncomingFile, InputBuffer, OutputBuffer, Step, TransferState,
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RecvState {
+    None,
+    AwaitAck,
+    AwaitBlock,
+    AwaitSyn,
+    /// The S/B that ends a stage.
+    AwaitEnd,
+}
+
+pub struct Rp {
+    pub files: Vec<FileDescriptor>,
+    pub download_dir: Option<PathBuf>,
+    pub collision_policy: CollisionPolicy,
+    incoming: Option<IncomingFile>,
+    /// The file type is received first, then the file.
+    receiving_file: bool,
+
+    /// The number of the next block.
+    block_number: u16,
+    block_len: usize,
+    recv_state: RecvState,
+    retries: usize,
+    errors: usize,
+}
+
+impl Rp {
+    pub fn new() -> Self {
+        Self {
+            files: Vec::new(),
+            download_dir: get_default_download_dir(),
+            collision_policy: CollisionPolicy::default(),
+            incoming: None,
+            receiving_file: false,
+            block_number: 0,
+            block_len: HEADER_LEN,
+            recv_state: RecvState::None,
+            retries: 0,
+            errors: 0,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.recv_state == RecvState::None
+    }
+
+    pub fn recv(&mut self, output: &mut OutputBuffer) {
+        self.receiving_file = false;
+        self.start_stage(output);
+    }
+
+    pub fn update(
+        &mut self,
+        input: &mut InputBuffer,
+        output: &mut OutputBuffer,
+        state: &Arc<Mutex<TransferState>>,
+    ) -> TermComResult<Step> {
+        if let Ok(transfer_state) = &mut state.lock() {
+            transfer_state.current_state = if self.receiving_file {
+                "Receiving data…"
+            } else {
+                "Start receiving…"
+            };
+            let transfer_info = &mut transfer_state.recieve_state;
+            transfer_info.bytes_transfered = self.incoming.as_ref().map_or(0, IncomingFile::len);
+            transfer_info.errors = self.errors;
+            transfer_info.check_size = format!("Checksum/{MAX_BLOCK_LEN}");
+            transfer_info.update_statistics();
+        }
+
+        match self.recv_state {
+            RecvState::None => {}
+            RecvState::AwaitBlock => {
+                let Some(block) = input.read_exact(self.block_len) else {
+                    return Ok(Step::NeedInput);
+                };
+                self.check_block(&block, output)?;
+            }
+            _ => {
+                let Some(code) = read_code(input) else {
+                    return Ok(Step::NeedInput);
+                };
+                match (self.recv_state, code) {
+                    (RecvState::AwaitAck, ACK) => {
+                        // a block follows, nothing else may be in the input then
+                        input.clear();
+                        output.send(&SEND_BLOCK);
+                        self.recv_state = RecvState::AwaitBlock;
+                    }
+                    (RecvState::AwaitSyn, SYN) => {
+                        output.send(&SYN);
+                        self.recv_state = RecvState::AwaitEnd;
+                    }
+                    (RecvState::AwaitEnd, SEND_BLOCK) => {
+                        if self.receiving_file {
+                            self.recv_state = RecvState::None;
+                        } else {
+                            self.receiving_file = true;
+                            self.start_stage(output);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(Step::Continue)
+    }
+
+    fn start_stage(&mut self, output: &mut OutputBuffer) {
+        self.block_number = 0;
+        self.block_len = HEADER_LEN;
+        output.send(&GOO);
+        self.recv_state = RecvState::AwaitAck;
+    }
+
+    fn check_block(&mut self, block: &[u8], output: &mut OutputBuffer) -> TermComResult<()> {
+        let parsed = parse_block(block).filter(|(next_len, _, _)| *next_len >= HEADER_LEN);
+        let Some((next_len, number, data)) = parsed else {
+            self.errors += 1;
+            self.retries += 1;
+            if self.retries > MAX_RETRIES {
+                self.cancel()?;
+                return Err(Box::new(TransmissionError::TooManyRetries));
+            }
+            output.send(&BAD);
+            self.recv_state = RecvState::AwaitAck;
+            return Ok(());
+        };
+        self.retries = 0;
+        let low_byte = number & 0xFF;
+        if low_byte == self.block_number.wrapping_sub(1) & 0xFF {
+            // the sender missed the GOO and repeats the last block
+            output.send(&GOO);
+            self.recv_state = RecvState::AwaitAck;
+            return Ok(());
+        }
+        if low_byte != self.block_number & 0xFF {
+            let expected = self.block_number;
+            self.cancel()?;
+            return Err(Box::new(TransmissionError::BlockNumberMismatch(
+                expected, number,
+            )));
+        }
+        self.block_number = self.block_number.wrapping_add(1);
+        self.block_len = next_len;
+        let is_last = number & LAST_BLOCK == LAST_BLOCK;
+        if self.receiving_file {
+            self.get_incoming_file()?.write_all(data)?;
+            if is_last {
+                self.save_file()?;
+            }
+        }
+        output.send(&GOO);
+        self.recv_state = if is_last {
+            RecvState::AwaitSyn
+        } else {
+            RecvState::AwaitAck
+        };
+        Ok(())
+    }
+
+    fn get_incoming_file(&mut self) -> TermComResult<&mut IncomingFile> {
+        if self.incoming.is_none() {
+            let Some(dir) = &self.download_dir else {
+                return Err(Box::new(io::Error::new(
+                    ErrorKind::NotFound,
+                    "no download directory",
+                )));
+            };
+            let file_name = self.files.last().map_or("", |fd| fd.file_name.as_str());
+            let mut incoming = IncomingFile::open(dir, file_name)?;
+            // punter can't resume, remove data of an earlier attempt
+            incoming.set_len(0)?;
+            self.incoming = Some(incoming);
+        }
+        Ok(self.incoming.as_mut().unwrap())
+    }
+
+    fn save_file(&mut self) -> TermComResult<()> {
+        let (Some(dir), Some(incoming), Some(fd)) = (
+            &self.download_dir,
+            self.incoming.take(),
+            self.files.last_mut(),
+        ) else {
+            return Ok(());
+        };
+        // Punter can't wait for the user, asking falls back to renaming.
+        let exists = dir.join(sanitize_file_name(&fd.file_name)).exists();
+        if exists && self.collision_policy == CollisionPolicy::Skip {
+            incoming.discard()?;
+            self.files.pop();
+            return Ok(());
+        }
+        fd.size = incoming.len();
+        let overwrite = self.collision_policy == CollisionPolicy::Overwrite;
+        let path = incoming.finish(dir, &fd.file_name, overwrite)?;
+        fd.path_name = path.to_string_lossy().to_string();
+        fd.set_path(path);
+        Ok(())
+    }
+
+    pub fn cancel(&mut self) -> TermComResult<()> {
+        self.recv_state = RecvState::None;
+        if let Some(incoming) = self.incoming.take() {
+            incoming.suspend()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+use std::{
+    io::{Cursor, Read},
+    sync::{Arc, Mutex},
+};
+
+use super::{
+    create_block, error_mod::TransmissionError, read_code, ACK, BAD, FILE_TYPE_PRG, GOO,
+    HEADER_LEN, LAST_BLOCK, MAX_BLOCK_LEN, MAX_RETRIES, SEND_BLOCK, SYN,
+};
+use crate::{
+    com::TermComResult,
+    protocol::{FileDescriptor, InputBuffer, OutputBuffer, ReadSeek, Step, TransferState},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SendState {
+    None,
+    /// GOO for the block that was sent or BAD to send it again.
+    AwaitGood,
+    AwaitSendBlock,
+    AwaitSyn,
+}
+
+pub struct Sp {
+    pub files: Vec<FileDescriptor>,
+    /// The file type is sent first, then the file.
+    sending_file: bool,
+    reader: Option<Box<dyn ReadSeek>>,
+    bytes_send: usize,
+
+    block_number: u16,
+    /// The block that was sent last, empty before the first one.
+    block: Vec<u8>,
+    /// The data of the block after it, its length goes into the header.
+    next_data: Option<Vec<u8>>,
+    send_state: SendState,
+    retries: usize,
+    errors: usize,
+}
+
+impl Sp {
+    pub fn new() -> Self {
+        Self {
+            files: Vec::new(),
+            sending_file: false,
+            reader: None,
+            bytes_send: 0,
+            block_number: 0,
+            block: Vec::new(),
+            next_data: None,
+            send_state: SendState::None,
+            retries: 0,
+            errors: 0,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.send_state == SendState::None
+    }
+
+    pub fn send(&mut self, files: Vec<FileDescriptor>) {
+        self.files = files;
+        self.sending_file = false;
+        self.start_stage(Box::new(Cursor::new(vec![FILE_TYPE_PRG])));
+    }
+
+    pub fn update(
+        &mut self,
+        input: &mut InputBuffer,
+        output: &mut OutputBuffer,
+        state: &Arc<Mutex<TransferState>>,
+    ) -> TermComResult<Step> {
+        if let Ok(transfer_state) = &mut state.lock() {
+            transfer_state.current_state = if self.sending_file {
+                "Sending data…"
+            } else {
+                "Initiate send…"
+            };
+            let transfer_info = &mut transfer_state.send_state;
+            if let Some(f) = self.files.first() {
+                transfer_info.file_name = f.file_name.clone();
+                transfer_info.file_size = f.size;
+            }
+            transfer_info.bytes_transfered = if self.sending_file {
+                self.bytes_send
+            } else {
+                0
+            };
+            transfer_info.errors = self.errors;
+            transfer_info.check_size = format!("Checksum/{MAX_BLOCK_LEN}");
+            transfer_info.update_statistics();
+        }
+
+        if self.send_state == SendState::None {
+            return Ok(Step::Continue);
+        }
+        let Some(code) = read_code(input) else {
+            return Ok(Step::NeedInput);
+        };
+        match (self.send_state, code) {
+            (SendState::AwaitGood, GOO) => {
+                self.retries = 0;
+                if self.next_block()? {
+                    output.send(&ACK);
+                    self.send_state = SendState::AwaitSendBlock;
+                } else {
+                    output.send(&SYN);
+                    self.send_state = SendState::AwaitSyn;
+                }
+            }
+            (SendState::AwaitGood, BAD) if !self.block.is_empty() => {
+                self.errors += 1;
+                self.retries += 1;
+                if self.retries > MAX_RETRIES {
+                    self.cancel();
+                    return Err(Box::new(TransmissionError::TooManyRetries));
+                }
+                output.send(&ACK);
+                self.send_state = SendState::AwaitSendBlock;
+            }
+            (SendState::AwaitSendBlock, SEND_BLOCK) => {
+                // GOOs a receiver repeated while it waited for the start don't count
+                input.clear();
+                output.send(&self.block);
+                self.send_state = SendState::AwaitGood;
+            }
+            (SendState::AwaitSyn, SYN) => {
+                output.send(&SEND_BLOCK);
+                if self.sending_file {
+                    self.reader = None;
+                    self.send_state = SendState::None;
+                } else {
+                    let reader = self.files[0].open_reader()?;
+                    self.sending_file = true;
+                    self.start_stage(reader);
+                }
+            }
+            _ => {}
+        }
+        Ok(Step::Continue)
+    }
+
+    fn start_stage(&mut self, reader: Box<dyn ReadSeek>) {
+        self.reader = Some(reader);
+        self.block_number = 0;
+        self.block.clear();
+        self.next_data = None;
+        self.bytes_send = 0;
+        self.send_state = SendState::AwaitGood;
+    }
+
+    fn is_last_block(&self) -> bool {
+        self.block.len() >= HEADER_LEN
+            && u16::from_le_bytes([self.block[5], self.block[6]]) & LAST_BLOCK == LAST_BLOCK
+    }
+
+    /// Builds the block after the one that was sent, false if that was the last one.
+    /// The first block of a stage has no data, it only tells the length of the second.
+    fn next_block(&mut self) -> TermComResult<bool> {
+        if self.is_last_block() {
+            return Ok(false);
+        }
+        let data = self.next_data.take().unwrap_or_default();
+        let mut next = Vec::new();
+        if let Some(reader) = &mut self.reader {
+            reader
+                .by_ref()
+                .take((MAX_BLOCK_LEN - HEADER_LEN) as u64)
+                .read_to_end(&mut next)?;
+        }
+        let number = if next.is_empty() {
+            LAST_BLOCK | (self.block_number & 0xFF)
+        } else {
+            self.block_number
+        };
+        self.block = create_block(number, HEADER_LEN + next.len(), &data);
+        self.bytes_send += data.len();
+        self.block_number = (self.block_number + 1) % LAST_BLOCK;
+        self.next_data = Some(next);
+        Ok(true)
+    }
+
+    pub fn cancel(&mut self) {
+        self.send_state = SendState::None;
+        self.reader = None;
+    }
+}
@@ -0,0 +1,168 @@
+#[cfg(test)]
+mod punter_tests {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+        sync::{Arc, Mutex},
+    };
+
+    use crate::protocol::{
+        punter::{
+            create_block, parse_block, read_code, rp::Rp, sp::Sp, Punter, ACK, BAD, GOO,
+            HEADER_LEN, SEND_BLOCK,
+        },
+        DownloadSettings, FileDescriptor, InputBuffer, OutputBuffer, Protocol, Step, TransferState,
+    };
+
+    fn create_download_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("icy_term_{name}"));
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn create_test_data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    /// Passes the data between both sides until neither has anything to do,
+    /// `corrupt` may change what the sender sends.
+    fn transfer(dir: &Path, data: &[u8], corrupt: impl FnMut(&mut Vec<u8>)) -> (Sp, Rp) {
+        transfer_file(dir, "", data, corrupt)
+    }
+
+    /// The receiver saves the file as `file_name`, Punter doesn't transfer it.
+    fn transfer_file(
+        dir: &Path,
+        file_name: &str,
+        data: &[u8],
+        mut corrupt: impl FnMut(&mut Vec<u8>),
+    ) -> (Sp, Rp) {
+        let state = Arc::new(Mutex::new(TransferState::new()));
+        let mut sp = Sp::new();
+        sp.send(vec![FileDescriptor::create_test(
+            "foo.bar".to_string(),
+            data.to_vec(),
+        )]);
+        let mut rp = Rp::new();
+        rp.download_dir = Some(dir.to_path_buf());
+        let mut fd = FileDescriptor::new();
+        fd.file_name = file_name.to_string();
+        rp.files.push(fd);
+
+        let mut output = OutputBuffer::new();
+        rp.recv(&mut output);
+        let mut to_sender = output.take_data();
+        let mut send_input = InputBuffer::new();
+        let mut recv_input = InputBuffer::new();
+        for _ in 0..10_000 {
+            send_input.extend(&std::mem::take(&mut to_sender));
+            while !sp.is_finished() {
+                if sp.update(&mut send_input, &mut output, &state).unwrap() == Step::NeedInput {
+                    break;
+                }
+            }
+            let mut to_receiver = output.take_data();
+            corrupt(&mut to_receiver);
+
+            recv_input.extend(&to_receiver);
+            while !rp.is_finished() {
+                if rp.update(&mut recv_input, &mut output, &state).unwrap() == Step::NeedInput {
+                    break;
+                }
+            }
+            to_sender = output.take_data();
+            if to_sender.is_empty() {
+                break;
+            }
+        }
+        (sp, rp)
+    }
+
+    #[test]
+    fn test_block() {
+        let block = create_block(0xFF03, 7, b"Hello");
+        assert_eq!(HEADER_LEN + 5, block.len());
+        assert_eq!(Some((7, 0xFF03, &b"Hello"[..])), parse_block(&block));
+
+        for i in 0..block.len() {
+            let mut corrupted = block.clone();
+            corrupted[i] ^= 0x10;
+            assert_eq!(None, parse_block(&corrupted));
+        }
+    }
+
+    #[test]
+    fn test_read_code() {
+        let mut input = InputBuffer::new();
+        input.extend(b"xxGO");
+        assert_eq!(None, read_code(&mut input));
+        input.extend(b"OACKS/");
+        assert_eq!(Some(GOO), read_code(&mut input));
+        assert_eq!(Some(ACK), read_code(&mut input));
+        assert_eq!(None, read_code(&mut input));
+        input.extend(b"B");
+        assert_eq!(Some(SEND_BLOCK), read_code(&mut input));
+        input.extend(b"..BAD");
+        assert_eq!(Some(BAD), read_code(&mut input));
+    }
+
+    #[test]
+    fn test_punter() {
+        for len in [0, 1, 248, 10_000] {
+            let dir = create_download_dir(&format!("punter_{len}"));
+            let data = create_test_data(len);
+            let (sp, rp) = transfer(&dir, &data, |_| {});
+            assert!(sp.is_finished());
+            assert!(rp.is_finished());
+            assert_eq!(1, rp.files.len());
+            assert_eq!(len, rp.files[0].size);
+            assert_eq!(data, rp.files[0].get_data());
+        }
+    }
+
+    /// A broken block is answered with BAD and sent again.
+    #[test]
+    fn test_punter_bad_block() {
+        let dir = create_download_dir("punter_bad_block");
+        let data = create_test_data(2000);
+        let mut blocks = 0;
+        let (sp, rp) = transfer(&dir, &data, |sent| {
+            if sent.len() > HEADER_LEN {
+                blocks += 1;
+                if blocks % 3 == 0 {
+                    sent[HEADER_LEN] ^= 1;
+                }
+            }
+        });
+        assert!(sp.is_finished());
+        assert!(rp.is_finished());
+        assert_eq!(data, rp.files[0].get_data());
+    }
+
+    #[test]
+    fn test_punter_file_name() {
+        let dir = create_download_dir("punter_file_name");
+        let data = create_test_data(1000);
+        let (_, rp) = transfer_file(&dir, "game.prg", &data, |_| {});
+        assert!(rp.is_finished());
+        assert_eq!(data, fs::read(dir.join("game.prg")).unwrap());
+        assert!(!dir.join("download").exists());
+
+        // the name picked before the download reaches the receiver
+        let state = Arc::new(Mutex::new(TransferState::new()));
+        state.lock().unwrap().download_settings = DownloadSettings {
+            download_dir: Some(dir),
+            file_name: Some("game.prg".to_string()),
+            ..Default::default()
+        };
+        let mut punter = Punter::new();
+        punter
+            .initiate_recv(&mut OutputBuffer::new(), state)
+            .unwrap();
+        let files = punter.get_received_files();
+        assert_eq!("game.prg", files[0].file_name);
+    }
+}
@@ -0,0 +1,315 @@
+#[cfg(test)]
+mod protocol_tests {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use crate::{
+        com::{Com, Impairments, TermComResult, TestChannel},
+        protocol::{
+            FileDescriptor, Protocol, TextUploadConfig, TransferDriver, TransferState, TransferType,
+        },
+    };
+
+    /// Neither side waits longer for the other one.
+    const UPDATE_TIMEOUT: Duration = Duration::from_secs(3);
+    /// A transfer taking longer hangs, no matter how bad the line is.
+    const TRANSFER_TIMEOUT: Duration = Duration::from_secs(120);
+    const SEEDS: [u64; 3] = [1, 2, 3];
+    const FILE_NAME: &str = "foo.bar";
+
+    fn all_transfer_types() -> Vec<TransferType> {
+        vec![
+            TransferType::ZModem,
+            TransferType::ZedZap,
+            TransferType::DirectZap,
+            TransferType::XModem,
+            TransferType::XModem1k,
+            TransferType::XModem1kG,
+            TransferType::YModem,
+            TransferType::YModemG,
+            TransferType::Kermit,
+            TransferType::Punter,
+        ]
+    }
+
+    fn create_download_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("icy_term_faults_{name}"));
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Doesn't end with CPMEOF and isn't a multiple of any block size,
+    /// x-modem can't tell padding from data otherwise.
+    fn create_test_data() -> Vec<u8> {
+        (0..10_000).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    struct Outcome {
+        sender: TermComResult<()>,
+        receiver: TermComResult<()>,
+    }
+
+    /// Runs a protocol until it ends, a failing side cancels like the user would.
+    async fn run(
+        mut driver: TransferDriver,
+        mut protocol: Box<dyn Protocol>,
+        mut com: Box<dyn Com>,
+        state: Arc<Mutex<TransferState>>,
+    ) -> TermComResult<()> {
+        let result = driver.run(&mut protocol, &mut com, state).await;
+        if result.is_err() {
+            let _ = driver.cancel(&mut protocol, &mut com).await;
+        }
+        result
+    }
+
+    async fn transfer(
+        transfer_type: TransferType,
+        impairments: &Impairments,
+        data: &[u8],
+        download_dir: &Path,
+    ) -> Outcome {
+        let mut com = TestChannel::new_impaired(impairments);
+
+        let send_state = Arc::new(Mutex::new(TransferState::new()));
+        let mut sender = transfer_type.clone().create();
+        let files = vec![FileDescriptor::create_test(
+            FILE_NAME.to_string(),
+            data.to_vec(),
+        )];
+        let mut send_driver = TransferDriver::new(UPDATE_TIMEOUT);
+        let send_result = send_driver
+            .initiate_send(&mut sender, &mut com.sender, files, send_state.clone())
+            .await;
+
+        let mut recv_state = TransferState::new();
+        recv_state.download_settings.download_dir = Some(download_dir.to_path_buf());
+        let recv_state = Arc::new(Mutex::new(recv_state));
+        let mut receiver = transfer_type.create();
+        let mut recv_driver = TransferDriver::new(UPDATE_TIMEOUT);
+        let recv_result = recv_driver
+            .initiate_recv(&mut receiver, &mut com.receiver, recv_state.clone())
+            .await;
+
+        let (Ok(()), Ok(())) = (&send_result, &recv_result) else {
+            return Outcome {
+                sender: send_result,
+                receiver: recv_result,
+            };
+        };
+        let sender = tokio::spawn(run(send_driver, sender, com.sender, send_state));
+        let receiver = tokio::spawn(run(recv_driver, receiver, com.receiver, recv_state));
+        Outcome {
+            sender: sender.await.unwrap(),
+            receiver: receiver.await.unwrap(),
+        }
+    }
+
+    /// Files in the download directory, partial downloads don't count.
+    fn get_stored_files(dir: &Path) -> Vec<(String, Vec<u8>)> {
+        fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().map_or(true, |ext| ext != "part"))
+            .map(|path| {
+                let name = path.file_name().unwrap().to_string_lossy().to_string();
+                (name, fs::read(&path).unwrap())
+            })
+            .collect()
+    }
+
+    /// Runs every protocol over the impaired line. Every stored file must be
+    /// byte-identical to the sent one, if both sides succeed there has to be one.
+    /// Returns how many transfers succeeded.
+    async fn run_all(name: &str, impairments: &Impairments) -> usize {
+        let data = Arc::new(create_test_data());
+        let mut runs = Vec::new();
+        for transfer_type in all_transfer_types() {
+            for seed in SEEDS {
+                let run_name = format!("{transfer_type:?} seed {seed}");
+                let dir = create_download_dir(&format!("{name}_{transfer_type:?}_{seed}"));
+                let impairments = impairments.with_seed(seed);
+                let data = data.clone();
+                let task = tokio::spawn(async move {
+                    let run = transfer(transfer_type, &impairments, &data, &dir);
+                    let outcome = tokio::time::timeout(TRANSFER_TIMEOUT, run).await;
+                    (outcome, dir)
+                });
+                runs.push((run_name, task));
+            }
+        }
+
+        let mut succeeded = 0;
+        for (run_name, task) in runs {
+            let (outcome, dir) = task.await.unwrap();
+            let Ok(outcome) = outcome else {
+                panic!("{run_name} hangs");
+            };
+            let stored = get_stored_files(&dir);
+            for (file_name, content) in &stored {
+                assert!(
+                    data.as_slice() == content.as_slice(),
+                    "{run_name}: {file_name} differs from the sent file"
+                );
+            }
+            if outcome.sender.is_ok() && outcome.receiver.is_ok() {
+                assert_eq!(1, stored.len(), "{run_name}: file missing");
+                succeeded += 1;
+            }
+        }
+        succeeded
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_clean_line() {
+        let succeeded = run_all("clean", &Impairments::default()).await;
+        assert_eq!(all_transfer_types().len() * SEEDS.len(), succeeded);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_bit_flips() {
+        run_all(
+            "bit_flips",
+            &Impairments {
+                bit_flip_rate: 0.0002,
+                ..Default::default()
+            },
+        )
+        .await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_dropped_bytes() {
+        run_all(
+            "dropped",
+            &Impairments {
+                drop_rate: 0.0001,
+                ..Default::default()
+            },
+        )
+        .await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_duplicated_bytes() {
+        run_all(
+            "duplicated",
+            &Impairments {
+                duplicate_rate: 0.0001,
+                ..Default::default()
+            },
+        )
+        .await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_injected_flow_control() {
+        run_all(
+            "flow_control",
+            &Impairments {
+                flow_control_rate: 0.001,
+                ..Default::default()
+            },
+        )
+        .await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_can_bursts() {
+        run_all(
+            "can_bursts",
+            &Impairments {
+                can_burst_rate: 0.0002,
+                ..Default::default()
+            },
+        )
+        .await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_latency() {
+        let succeeded = run_all(
+            "latency",
+            &Impairments {
+                latency: Duration::from_millis(5),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert_eq!(all_transfer_types().len() * SEEDS.len(), succeeded);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_truncated_connection() {
+        let succeeded = run_all(
+            "truncated",
+            &Impairments {
+                truncate_after: Some(4000),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert_eq!(0, succeeded);
+    }
+
+    async fn upload_text(impairments: &Impairments, data: &[u8]) -> (TermComResult<()>, Vec<u8>) {
+        let mut com = TestChannel::new_impaired(impairments);
+        let state = Arc::new(Mutex::new(TransferState::new()));
+        let mut upload = TransferType::Text(TextUploadConfig::default()).create();
+        let files = vec![FileDescriptor::create_test(
+            "msg.txt".to_string(),
+            data.to_vec(),
+        )];
+        let mut driver = TransferDriver::new(UPDATE_TIMEOUT);
+        driver
+            .initiate_send(&mut upload, &mut com.sender, files, state.clone())
+            .await
+            .unwrap();
+        let result = tokio::time::timeout(TRANSFER_TIMEOUT, run(driver, upload, com.sender, state))
+            .await
+            .expect("text upload hangs");
+
+        let mut received = Vec::new();
+        while let Ok(buf) = com.receiver.read_data().await {
+            if buf.is_empty() {
+                break;
+            }
+            received.extend(buf);
+        }
+        (result, received)
+    }
+
+    /// Text has no error detection, only a clean line guarantees the content.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_text_upload_over_impaired_line() {
+        let data = b"Hello\rWorld\r".repeat(100);
+
+        let (result, received) = upload_text(&Impairments::default(), &data).await;
+        assert!(result.is_ok());
+        assert_eq!(data, received);
+
+        let noisy = Impairments {
+            seed: 1,
+            bit_flip_rate: 0.001,
+            drop_rate: 0.001,
+            ..Default::default()
+        };
+        let (result, _) = upload_text(&noisy, &data).await;
+        assert!(result.is_ok());
+
+        let truncated = Impairments {
+            truncate_after: Some(500),
+            ..Default::default()
+        };
+        let (result, received) = upload_text(&truncated, &data).await;
+        assert!(result.is_err());
+        assert_eq!(500, received.len());
+    }
+}
@@ -0,0 +1,414 @@
+use std::{
+    io::{self, ErrorKind},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use super::{FileDescriptor, InputBuffer, OutputBuffer, Protocol, Step, TransferState};
+use crate::{auto_file_transfer::PatternRecognizer, com::TermComResult};
+
+/// Time to wait for the remote echo of a single character.
+const ECHO_TIMEOUT: Duration = Duration::from_secs(2);
+/// Time to wait for the prompt after a line has been sent.
+const PROMPT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Runtime configuration of a text upload. All byte sequences are already
+/// translated to the character set of the remote terminal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextUploadConfig {
+    pub char_delay: Duration,
+    pub line_delay: Duration,
+    pub prompt: Vec<u8>,
+    pub wait_for_echo: bool,
+    pub line_end: Vec<u8>,
+}
+
+impl Default for TextUploadConfig {
+    fn default() -> Self {
+        Self {
+            char_delay: Duration::ZERO,
+            line_delay: Duration::ZERO,
+            prompt: Vec::new(),
+            wait_for_echo: false,
+            line_end: vec![b'\r'],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum UploadState {
+    NextLine,
+    /// Sending the current line char by char up to the given end.
+    SendChars(usize),
+    AwaitEcho(usize, Instant),
+    AwaitPrompt(Instant),
+}
+
+/// Sends text files line by line to line editors that can't cope with pasting at full speed.
+pub struct TextUpload {
+    config: TextUploadConfig,
+    files: Vec<FileDescriptor>,
+    cur_file: usize,
+    cur_pos: usize,
+    data: Vec<u8>,
+    errors: usize,
+    is_active: bool,
+    state: UploadState,
+    prompt: PatternRecognizer,
+}
+
+impl TextUpload {
+    pub fn new(config: TextUploadConfig) -> Self {
+        let prompt = PatternRecognizer::from(&config.prompt, false);
+        Self {
+            config,
+            files: Vec::new(),
+            cur_file: 0,
+            cur_pos: 0,
+            data: Vec::new(),
+            errors: 0,
+            is_active: false,
+            state: UploadState::NextLine,
+            prompt,
+        }
+    }
+
+    fn next_line_end(&self) -> usize {
+        let line_end = &self.config.line_end;
+        if line_end.is_empty() {
+            return self.data.len();
+        }
+        self.data[self.cur_pos..]
+            .windows(line_end.len())
+            .position(|w| w == line_end.as_slice())
+            .map_or(self.data.len(), |p| self.cur_pos + p + line_end.len())
+    }
+
+    fn delay(duration: Duration) -> Step {
+        if duration.is_zero() {
+            Step::Continue
+        } else {
+            Step::Wait(duration)
+        }
+    }
+
+    fn line_sent(&mut self) -> Step {
+        if self.config.prompt.is_empty() {
+            self.state = UploadState::NextLine;
+            return Self::delay(self.config.line_delay);
+        }
+        self.prompt.reset();
+        self.state = UploadState::AwaitPrompt(Instant::now() + PROMPT_TIMEOUT);
+        Step::Continue
+    }
+
+    fn next_file(&mut self) {
+        self.cur_file += 1;
+        self.cur_pos = 0;
+        if let Some(fd) = self.files.get(self.cur_file) {
+            self.data = fd.get_data();
+        } else {
+            self.is_active = false;
+        }
+    }
+
+    /// Everything read from `input` ends up in `received`, it's the remote echo and prompts
+    /// the user wants to see in the terminal.
+    fn step(
+        &mut self,
+        input: &mut InputBuffer,
+        output: &mut OutputBuffer,
+        received: &mut Vec<u8>,
+    ) -> Step {
+        match self.state {
+            UploadState::NextLine => {
+                if self.cur_pos >= self.data.len() {
+                    self.next_file();
+                    return Step::Continue;
+                }
+                let end = self.next_line_end();
+                if self.config.char_delay.is_zero() && !self.config.wait_for_echo {
+                    output.send(&self.data[self.cur_pos..end]);
+                    self.cur_pos = end;
+                    return self.line_sent();
+                }
+                self.state = UploadState::SendChars(end);
+                Step::Continue
+            }
+            UploadState::SendChars(end) => {
+                if self.cur_pos >= end {
+                    return self.line_sent();
+                }
+                output.send(&[self.data[self.cur_pos]]);
+                self.cur_pos += 1;
+                if self.config.wait_for_echo {
+                    self.state = UploadState::AwaitEcho(end, Instant::now() + ECHO_TIMEOUT);
+                    return Step::Continue;
+                }
+                Self::delay(self.config.char_delay)
+            }
+            UploadState::AwaitEcho(end, deadline) => {
+                // Line editors often translate the echoed character (CR -> CR LF, upper case…)
+                // so any received byte counts as echo.
+                if let Some(ch) = input.read_u8() {
+                    received.push(ch);
+                } else {
+                    if Instant::now() < deadline {
+                        return Step::NeedInput;
+                    }
+                    self.errors += 1;
+                }
+                self.state = UploadState::SendChars(end);
+                Self::delay(self.config.char_delay)
+            }
+            UploadState::AwaitPrompt(deadline) => {
+                while let Some(ch) = input.read_u8() {
+                    received.push(ch);
+                    if self.prompt.push_ch(ch) {
+                        self.state = UploadState::NextLine;
+                        return Self::delay(self.config.line_delay);
+                    }
+                }
+                if Instant::now() < deadline {
+                    return Step::NeedInput;
+                }
+                self.errors += 1;
+                self.state = UploadState::NextLine;
+                Self::delay(self.config.line_delay)
+            }
+        }
+    }
+}
+
+impl Protocol for TextUpload {
+    fn update(
+        &mut self,
+        input: &mut InputBuffer,
+        output: &mut OutputBuffer,
+        transfer_state: Arc<Mutex<TransferState>>,
+    ) -> TermComResult<Step> {
+        if !self.is_active {
+            let mut transfer_state = transfer_state.lock().unwrap();
+            // what arrived after the last line belongs to the terminal as well
+            transfer_state
+                .terminal_data
+                .extend_from_slice(input.as_slice());
+            input.clear();
+            transfer_state.is_finished = true;
+            return Ok(Step::Finished);
+        }
+
+        if let Ok(transfer_state) = &mut transfer_state.lock() {
+            transfer_state.current_state = if matches!(self.state, UploadState::AwaitPrompt(_)) {
+                "Waiting for prompt…"
+            } else {
+                "Sending text…"
+            };
+            let transfer_info = &mut transfer_state.send_state;
+            if let Some(fd) = self.files.get(self.cur_file) {
+                transfer_info.file_name = fd.file_name.clone();
+            }
+            transfer_info.file_size = self.data.len();
+            transfer_info.bytes_transfered = self.cur_pos;
+            transfer_info.errors = self.errors;
+            transfer_info.check_size = format!(
+                "{}ms/{}ms",
+                self.config.char_delay.as_millis(),
+                self.config.line_delay.as_millis()
+            );
+            transfer_info.update_statistics();
+        }
+
+        let mut received = Vec::new();
+        let step = self.step(input, output, &mut received);
+        if !received.is_empty() {
+            transfer_state
+                .lock()
+                .unwrap()
+                .terminal_data
+                .extend(received);
+        }
+        Ok(step)
+    }
+
+    fn initiate_send(
+        &mut self,
+        _output: &mut OutputBuffer,
+        files: Vec<FileDescriptor>,
+        transfer_state: Arc<Mutex<TransferState>>,
+    ) -> TermComResult<()> {
+        transfer_state.lock().unwrap().protocol_name = "ASCII".to_string();
+        self.files = files;
+        self.cur_file = 0;
+        self.cur_pos = 0;
+        self.errors = 0;
+        self.state = UploadState::NextLine;
+        if let Some(fd) = self.files.first() {
+            self.data = fd.get_data();
+            self.is_active = true;
+        }
+        Ok(())
+    }
+
+    fn initiate_recv(
+        &mut self,
+        _output: &mut OutputBuffer,
+        _transfer_state: Arc<Mutex<TransferState>>,
+    ) -> TermComResult<()> {
+        Err(Box::new(io::Error::new(
+            ErrorKind::Unsupported,
+            "text transfers can only be used for uploads",
+        )))
+    }
+
+    fn get_received_files(&mut self) -> Vec<FileDescriptor> {
+        Vec::new()
+    }
+
+    fn cancel(&mut self, _output: &mut OutputBuffer) -> TermComResult<()> {
+        self.is_active = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use crate::protocol::{
+        FileDescriptor, InputBuffer, OutputBuffer, Protocol, Step, TextUpload, TextUploadConfig,
+        TransferState,
+    };
+
+    #[test]
+    fn test_text_upload_lines() {
+        let mut input = InputBuffer::new();
+        let mut output = OutputBuffer::new();
+        let state = Arc::new(Mutex::new(TransferState::new()));
+        let mut upload = TextUpload::new(TextUploadConfig {
+            line_delay: Duration::from_millis(1),
+            ..Default::default()
+        });
+        let data = b"Hello\rWorld\r".to_vec();
+        upload
+            .initiate_send(
+                &mut output,
+                vec![FileDescriptor::create_test(
+                    "msg.txt".to_string(),
+                    data.clone(),
+                )],
+                state.clone(),
+            )
+            .unwrap();
+
+        let mut i = 0;
+        while upload
+            .update(&mut input, &mut output, state.clone())
+            .unwrap()
+            != Step::Finished
+        {
+            i += 1;
+            assert!(i < 10);
+        }
+        assert!(state.lock().unwrap().is_finished);
+        assert_eq!(data, output.take_data());
+    }
+
+    #[test]
+    fn test_text_upload_wait_for_prompt() {
+        let mut input = InputBuffer::new();
+        let mut output = OutputBuffer::new();
+        let state = Arc::new(Mutex::new(TransferState::new()));
+        let mut upload = TextUpload::new(TextUploadConfig {
+            prompt: b"> ".to_vec(),
+            ..Default::default()
+        });
+        upload
+            .initiate_send(
+                &mut output,
+                vec![FileDescriptor::create_test(
+                    "msg.txt".to_string(),
+                    b"a\rb\r".to_vec(),
+                )],
+                state.clone(),
+            )
+            .unwrap();
+
+        upload
+            .update(&mut input, &mut output, state.clone())
+            .unwrap();
+        assert_eq!(b"a\r".to_vec(), output.take_data());
+        assert_eq!(
+            Step::NeedInput,
+            upload
+                .update(&mut input, &mut output, state.clone())
+                .unwrap()
+        );
+
+        input.extend(b"1> ");
+        upload
+            .update(&mut input, &mut output, state.clone())
+            .unwrap();
+        upload
+            .update(&mut input, &mut output, state.clone())
+            .unwrap();
+        assert_eq!(b"b\r".to_vec(), output.take_data());
+
+        input.extend(b"2> ");
+        upload
+            .update(&mut input, &mut output, state.clone())
+            .unwrap();
+        assert!(input.is_empty());
+        assert_eq!(0, state.lock().unwrap().send_state.errors);
+
+        // the prompts are shown in the terminal, so is what comes after the last line
+        input.extend(b"bye");
+        for _ in 0..2 {
+            upload
+                .update(&mut input, &mut output, state.clone())
+                .unwrap();
+        }
+        assert_eq!(b"1> 2> bye".to_vec(), state.lock().unwrap().terminal_data);
+    }
+
+    #[test]
+    fn test_text_upload_forwards_echo() {
+        let mut input = InputBuffer::new();
+        let mut output = OutputBuffer::new();
+        let state = Arc::new(Mutex::new(TransferState::new()));
+        let mut upload = TextUpload::new(TextUploadConfig {
+            wait_for_echo: true,
+            ..Default::default()
+        });
+        upload
+            .initiate_send(
+                &mut output,
+                vec![FileDescriptor::create_test(
+                    "msg.txt".to_string(),
+                    b"ab".to_vec(),
+                )],
+                state.clone(),
+            )
+            .unwrap();
+
+        // the line editor echoes in upper case
+        let mut sent = Vec::new();
+        let mut i = 0;
+        while upload
+            .update(&mut input, &mut output, state.clone())
+            .unwrap()
+            != Step::Finished
+        {
+            let data = output.take_data();
+            input.extend(&data.to_ascii_uppercase());
+            sent.extend(data);
+            i += 1;
+            assert!(i < 20);
+        }
+        assert_eq!(b"ab".to_vec(), sent);
+        assert_eq!(b"AB".to_vec(), state.lock().unwrap().terminal_data);
+    }
+}
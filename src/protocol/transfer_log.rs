@@ -0,0 +1,427 @@
+//! The DSZLOG most BBS software reads after a transfer, one line per file:
+//! `Z  46532 31250 bps 3125 cps   0 errors     0 1024 C:\DL\FOO.ZIP Board`
+use std::{
+    fmt::{self, Display},
+    fs::{self, OpenOptions},
+    io::{self, ErrorKind, Write},
+    path::Path,
+    process::Stdio,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::com::TermComResult;
+
+use super::{FileDescriptor, TransferState, TransferType};
+
+/// A hook that runs longer gets killed, the connection waits for it.
+pub const DOWNLOAD_HOOK_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DszLogEntry {
+    pub code: char,
+    pub bytes: usize,
+    pub cps: u64,
+    pub errors: usize,
+    pub block_size: usize,
+    pub file_name: String,
+    pub board: String,
+}
+
+impl DszLogEntry {
+    /// 'Z'/'z' for a ZModem receive/send, 'R'/'S' for the other protocols and 'E' for a failed file.
+    pub fn get_code(transfer_type: &TransferType, download: bool, success: bool) -> char {
+        match (success, download, transfer_type.is_zmodem()) {
+            (false, _, _) => 'E',
+            (true, true, true) => 'Z',
+            (true, false, true) => 'z',
+            (true, true, false) => 'R',
+            (true, false, false) => 'S',
+        }
+    }
+}
+
+impl Display for DszLogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // DSZ logs the modem speed here, a telnet line has none - use what 8N1 would need.
+        write!(
+            f,
+            "{} {:>6} {:>5} bps {:>4} cps {:>3} errors {:>5} {:>4} {}",
+            self.code,
+            self.bytes,
+            self.cps * 10,
+            self.cps,
+            self.errors,
+            0,
+            self.block_size,
+            self.file_name
+        )?;
+        if !self.board.is_empty() {
+            write!(f, " {}", self.board)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn get_block_size(transfer_type: &TransferType) -> usize {
+    match transfer_type {
+        TransferType::ZModem
+        | TransferType::XModem1k
+        | TransferType::XModem1kG
+        | TransferType::YModem
+        | TransferType::YModemG => 1024,
+        TransferType::ZedZap | TransferType::DirectZap => 8 * 1024,
+        TransferType::XModem => 128,
+        TransferType::Kermit => 94,
+        TransferType::Punter => 255,
+        TransferType::Text(_) => 0,
+    }
+}
+
+/// One entry for every file that made it, and an 'E' entry for the file the transfer stopped at.
+pub fn create_log_entries(
+    transfer_type: &TransferType,
+    download: bool,
+    files: &[FileDescriptor],
+    state: &TransferState,
+) -> Vec<DszLogEntry> {
+    let info = if download {
+        &state.recieve_state
+    } else {
+        &state.send_state
+    };
+    let cps = info.get_average_cps();
+    let block_size = get_block_size(transfer_type);
+    // The file being transferred isn't in the finished files yet.
+    let get_errors = |file_name: &str| {
+        info.finished_files
+            .iter()
+            .find(|f| f.file_name == file_name)
+            .map_or(info.get_file_errors(), |f| f.errors)
+    };
+    let entry = |code, bytes, file_name: &str, log_name: &str| DszLogEntry {
+        code,
+        bytes,
+        cps,
+        errors: get_errors(file_name),
+        block_size,
+        file_name: log_name.to_string(),
+        board: state.board_name.clone(),
+    };
+
+    let transferred: Vec<&FileDescriptor> = if state.is_finished {
+        files.iter().collect()
+    } else if download {
+        // A received file only gets its path once it's complete.
+        files.iter().filter(|f| !f.path_name.is_empty()).collect()
+    } else {
+        files.iter().take(info.batch_files_transfered).collect()
+    };
+    let code = DszLogEntry::get_code(transfer_type, download, true);
+    let mut result: Vec<DszLogEntry> = transferred
+        .into_iter()
+        .map(|f| {
+            let name = if f.path_name.is_empty() {
+                &f.file_name
+            } else {
+                &f.path_name
+            };
+            entry(code, f.size, &f.file_name, name)
+        })
+        .collect();
+    if !state.is_finished && !info.file_name.is_empty() {
+        result.push(entry(
+            'E',
+            info.bytes_transfered,
+            &info.file_name,
+            &info.file_name,
+        ));
+    }
+    result
+}
+
+pub fn append_transfer_log(log_file: &Path, entries: &[DszLogEntry]) -> TermComResult<()> {
+    if let Some(dir) = log_file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_file)?;
+    for entry in entries {
+        writeln!(file, "{entry}")?;
+    }
+    Ok(())
+}
+
+/// The remote side picks the file name, so the path never becomes part of the
+/// command line. It's handed over as an argument of its own.
+#[cfg(not(windows))]
+fn create_hook_command(hook: &str, path: &str) -> io::Result<tokio::process::Command> {
+    // an empty script would run the downloaded file
+    if hook.trim().is_empty() {
+        return Err(get_empty_hook_error());
+    }
+    let script = if hook.contains("%f") {
+        hook.replace("%f", "\"$1\"")
+    } else {
+        format!("{hook} \"$1\"")
+    };
+    let mut command = tokio::process::Command::new("sh");
+    command.arg("-c").arg(script).arg("sh").arg(path);
+    Ok(command)
+}
+
+/// cmd expands variables before it parses the line, so the hook is split into
+/// program and arguments and runs without a shell.
+#[cfg(windows)]
+fn create_hook_command(hook: &str, path: &str) -> io::Result<tokio::process::Command> {
+    let mut args: Vec<String> = Vec::new();
+    let mut arg = String::new();
+    let mut in_quotes = false;
+    for ch in hook.chars() {
+        match ch {
+            '"' => in_quotes = !in_quotes,
+            ch if ch.is_whitespace() && !in_quotes => {
+                if !arg.is_empty() {
+                    args.push(std::mem::take(&mut arg));
+                }
+            }
+            ch => arg.push(ch),
+        }
+    }
+    if !arg.is_empty() {
+        args.push(arg);
+    }
+    if args.is_empty() {
+        return Err(get_empty_hook_error());
+    }
+    if hook.contains("%f") {
+        for arg in &mut args {
+            *arg = arg.replace("%f", path);
+        }
+    } else {
+        args.push(path.to_string());
+    }
+    let mut command = tokio::process::Command::new(args.remove(0));
+    command.args(args);
+    Ok(command)
+}
+
+fn get_empty_hook_error() -> io::Error {
+    io::Error::new(ErrorKind::InvalidInput, "the download hook has no command")
+}
+
+/// Runs `hook` for a downloaded file and returns what it printed,
+/// it's killed if it doesn't finish within `timeout`.
+pub async fn run_download_hook(
+    hook: &str,
+    path: &str,
+    timeout: Duration,
+) -> TermComResult<Vec<String>> {
+    let child = create_hook_command(hook, path)?
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    // on a timeout the child gets dropped and with it killed
+    let Ok(output) = tokio::time::timeout(timeout, child.wait_with_output()).await else {
+        return Err(Box::new(io::Error::new(
+            ErrorKind::TimedOut,
+            format!("killed after {} seconds", timeout.as_secs()),
+        )));
+    };
+    let output = output?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let mut result: Vec<String> = stdout
+        .lines()
+        .chain(stderr.lines())
+        .map(ToString::to_string)
+        .collect();
+    if !output.status.success() {
+        result.push(format!("{hook}: {}", output.status));
+    }
+    Ok(result)
+}
+
+/// Writes the log and runs the download hook once the protocol is done,
+/// everything worth telling the user ends up in the `output_log`.
+/// Received files are handed over in `received_files`.
+pub async fn finish_transfer(
+    transfer_type: &TransferType,
+    download: bool,
+    files: Vec<FileDescriptor>,
+    transfer_state: Arc<Mutex<TransferState>>,
+) {
+    let (entries, log_file, hook) = {
+        let state = transfer_state.lock().unwrap();
+        (
+            create_log_entries(transfer_type, download, &files, &state),
+            state.log_file.clone(),
+            state.download_hook.clone(),
+        )
+    };
+    let mut output_log = Vec::new();
+    if let Some(log_file) = log_file {
+        if let Err(err) = append_transfer_log(&log_file, &entries) {
+            output_log.push(format!("{}: {err}", log_file.display()));
+        }
+    }
+
+    if download && !hook.trim().is_empty() {
+        transfer_state.lock().unwrap().current_state = "Running download hook…";
+        for entry in entries.iter().filter(|e| e.code != 'E') {
+            output_log.push(entry.file_name.clone());
+            match run_download_hook(&hook, &entry.file_name, DOWNLOAD_HOOK_TIMEOUT).await {
+                Ok(lines) => output_log.extend(lines),
+                Err(err) => output_log.push(format!("{hook}: {err}")),
+            }
+        }
+        transfer_state.lock().unwrap().current_state = "Done";
+    }
+
+    let mut state = transfer_state.lock().unwrap();
+    if download {
+        state.recieve_state.output_log.extend(output_log);
+        state.received_files = files;
+    } else {
+        state.send_state.output_log.extend(output_log);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::{
+        append_transfer_log, create_log_entries, run_download_hook, DszLogEntry, FileDescriptor,
+        TransferState, TransferType, DOWNLOAD_HOOK_TIMEOUT,
+    };
+
+    #[test]
+    fn test_entry_format() {
+        let entry = DszLogEntry {
+            code: 'Z',
+            bytes: 46532,
+            cps: 3125,
+            errors: 0,
+            block_size: 1024,
+            file_name: "/tmp/FOO.ZIP".to_string(),
+            board: "Some BBS".to_string(),
+        };
+        assert_eq!(
+            "Z  46532 31250 bps 3125 cps   0 errors     0 1024 /tmp/FOO.ZIP Some BBS",
+            entry.to_string()
+        );
+    }
+
+    #[test]
+    fn test_codes() {
+        assert_eq!(
+            'Z',
+            DszLogEntry::get_code(&TransferType::ZedZap, true, true)
+        );
+        assert_eq!(
+            'z',
+            DszLogEntry::get_code(&TransferType::ZModem, false, true)
+        );
+        assert_eq!(
+            'R',
+            DszLogEntry::get_code(&TransferType::YModem, true, true)
+        );
+        assert_eq!(
+            'S',
+            DszLogEntry::get_code(&TransferType::XModem, false, true)
+        );
+        assert_eq!(
+            'E',
+            DszLogEntry::get_code(&TransferType::ZModem, true, false)
+        );
+    }
+
+    #[test]
+    fn test_failed_upload_entries() {
+        let files = vec![
+            FileDescriptor::create_test("foo.bar".to_string(), b"foo".to_vec()),
+            FileDescriptor::create_test("bar.foo".to_string(), b"barfoo".to_vec()),
+        ];
+        let mut state = TransferState::new();
+        state.send_state.batch_files_transfered = 1;
+        state.send_state.file_name = "bar.foo".to_string();
+        state.send_state.bytes_transfered = 2;
+
+        let entries = create_log_entries(&TransferType::ZModem, false, &files, &state);
+        assert_eq!(2, entries.len());
+        assert_eq!(('z', 3), (entries[0].code, entries[0].bytes));
+        assert_eq!("foo.bar", entries[0].file_name);
+        assert_eq!(('E', 2), (entries[1].code, entries[1].bytes));
+        assert_eq!("bar.foo", entries[1].file_name);
+
+        state.is_finished = true;
+        let entries = create_log_entries(&TransferType::ZModem, false, &files, &state);
+        assert_eq!(
+            vec!['z', 'z'],
+            entries.iter().map(|e| e.code).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_append_log() {
+        let log_file = std::env::temp_dir().join("icy_term_test_dszlog.txt");
+        if log_file.exists() {
+            std::fs::remove_file(&log_file).unwrap();
+        }
+        let entry = DszLogEntry {
+            code: 'R',
+            bytes: 128,
+            cps: 12,
+            errors: 1,
+            block_size: 128,
+            file_name: "foo.bar".to_string(),
+            board: String::new(),
+        };
+        append_transfer_log(&log_file, &[entry.clone()]).unwrap();
+        append_transfer_log(&log_file, &[entry]).unwrap();
+        let log = std::fs::read_to_string(&log_file).unwrap();
+        assert_eq!(2, log.lines().count());
+        assert_eq!(
+            "R    128   120 bps   12 cps   1 errors     0  128 foo.bar",
+            log.lines().next().unwrap()
+        );
+    }
+
+    #[cfg(not(windows))]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_hook_hostile_file_name() {
+        let path = "x$(echo pwned);`echo pwned` \"'.zip";
+        let lines = run_download_hook("echo", path, DOWNLOAD_HOOK_TIMEOUT)
+            .await
+            .unwrap();
+        assert_eq!(vec![path.to_string()], lines);
+        let lines = run_download_hook("echo [%f]", path, DOWNLOAD_HOOK_TIMEOUT)
+            .await
+            .unwrap();
+        assert_eq!(vec![format!("[{path}]")], lines);
+    }
+
+    #[cfg(not(windows))]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_hook_timeout() {
+        use std::time::{Duration, Instant};
+
+        let start = Instant::now();
+        let result =
+            run_download_hook("sleep 10; echo", "foo.bar", Duration::from_millis(200)).await;
+        assert!(result.is_err());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_empty_hook() {
+        for hook in ["", "  "] {
+            let result = run_download_hook(hook, "foo.bar", DOWNLOAD_HOOK_TIMEOUT).await;
+            assert!(result.is_err());
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// The span the current rate is measured over.
+pub const RATE_WINDOW: Duration = Duration::from_secs(5);
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+/// Shorter spans are too noisy to count as peak rate.
+const MIN_RATE_SPAN: Duration = Duration::from_secs(1);
+
+/// Bytes per second over a sliding window of (time, total bytes) samples.
+#[derive(Debug, Clone)]
+pub struct RateEstimator {
+    samples: VecDeque<(Instant, usize)>,
+    window: Duration,
+    start: Instant,
+    total: usize,
+    peak: u64,
+}
+
+impl RateEstimator {
+    pub fn new(start: Instant, window: Duration) -> Self {
+        let mut samples = VecDeque::new();
+        samples.push_back((start, 0));
+        Self {
+            samples,
+            window,
+            start,
+            total: 0,
+            peak: 0,
+        }
+    }
+
+    /// `total` is the number of bytes transferred since the start.
+    pub fn add_sample(&mut self, now: Instant, total: usize) {
+        self.total = total;
+        if let Some((time, _)) = self.samples.back() {
+            if now.saturating_duration_since(*time) < SAMPLE_INTERVAL {
+                return;
+            }
+        }
+        self.samples.push_back((now, total));
+        // Keep one sample at or before the window start so the span covers the whole window.
+        while self.samples.len() > 2
+            && now.saturating_duration_since(self.samples[1].0) >= self.window
+        {
+            self.samples.pop_front();
+        }
+        if let Some((first, _)) = self.samples.front() {
+            if now.saturating_duration_since(*first) >= MIN_RATE_SPAN {
+                self.peak = self.peak.max(self.get_rate_at(now));
+            }
+        }
+    }
+
+    /// The rate falls when no samples arrive, a stalled transfer shows as one.
+    pub fn get_rate_at(&self, now: Instant) -> u64 {
+        let Some((time, bytes)) = self.samples.front() else {
+            return 0;
+        };
+        let span = now.saturating_duration_since(*time);
+        if span < SAMPLE_INTERVAL {
+            return 0;
+        }
+        (self.total.saturating_sub(*bytes) as f64 / span.as_secs_f64()) as u64
+    }
+
+    pub fn get_rate(&self) -> u64 {
+        self.get_rate_at(Instant::now())
+    }
+
+    pub fn get_average_at(&self, now: Instant) -> u64 {
+        let span = now.saturating_duration_since(self.start);
+        if span < MIN_RATE_SPAN {
+            return self.get_rate_at(now);
+        }
+        (self.total as f64 / span.as_secs_f64()) as u64
+    }
+
+    pub fn get_average(&self) -> u64 {
+        self.get_average_at(Instant::now())
+    }
+
+    pub fn get_peak(&self) -> u64 {
+        self.peak
+    }
+
+    pub fn get_total(&self) -> usize {
+        self.total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::protocol::{FileStatistics, RateEstimator, TransferInformation};
+
+    fn ms(ms: usize) -> Duration {
+        Duration::from_millis(ms as u64)
+    }
+
+    #[test]
+    fn test_steady_rate() {
+        let start = Instant::now();
+        let mut rate = RateEstimator::new(start, Duration::from_secs(5));
+        for i in 1..=100 {
+            rate.add_sample(start + ms(i * 100), i * 100);
+        }
+        assert_eq!(1000, rate.get_rate_at(start + ms(10_000)));
+        assert_eq!(1000, rate.get_average_at(start + ms(10_000)));
+        assert_eq!(1000, rate.get_peak());
+    }
+
+    #[test]
+    fn test_rate_follows_speed_change() {
+        let start = Instant::now();
+        let mut rate = RateEstimator::new(start, Duration::from_secs(5));
+        let mut total = 0;
+        for i in 1..=200 {
+            total += if i <= 100 { 100 } else { 400 };
+            rate.add_sample(start + ms(i * 100), total);
+        }
+        // Only the last 5 seconds count, the average covers everything.
+        assert_eq!(4000, rate.get_rate_at(start + ms(20_000)));
+        assert_eq!(2500, rate.get_average_at(start + ms(20_000)));
+        assert_eq!(4000, rate.get_peak());
+    }
+
+    #[test]
+    fn test_stalled_transfer() {
+        let start = Instant::now();
+        let mut rate = RateEstimator::new(start, Duration::from_secs(5));
+        for i in 1..=50 {
+            rate.add_sample(start + ms(i * 100), i * 100);
+        }
+        assert_eq!(1000, rate.get_rate_at(start + ms(5_000)));
+        assert_eq!(500, rate.get_rate_at(start + ms(10_000)));
+    }
+
+    /// Protocols like ZModem reset their error count, the statistics keep counting per file.
+    #[test]
+    fn test_file_statistics() {
+        let start = Instant::now();
+        let mut info = TransferInformation::new();
+        info.file_name = "foo".to_string();
+        for (i, errors) in [0, 1, 2, 0, 1].iter().enumerate() {
+            info.errors = *errors;
+            info.bytes_transfered = i * 100;
+            info.update_statistics_at(start + ms(i * 100));
+        }
+        assert_eq!(3, info.get_file_errors());
+
+        info.file_name = "bar".to_string();
+        info.errors = 0;
+        info.bytes_transfered = 50;
+        info.update_statistics_at(start + ms(1_000));
+        assert_eq!(
+            vec![FileStatistics {
+                file_name: "foo".to_string(),
+                bytes: 400,
+                errors: 3
+            }],
+            info.finished_files
+        );
+        assert_eq!(0, info.get_file_errors());
+        assert_eq!(3, info.get_total_errors());
+    }
+}
@@ -6,6 +6,9 @@ pub enum TransmissionError {
     InvalidMode(u8),
     TooManyRetriesSendingHeader,
     XModem1File,
+    /// Streaming variants can't repeat a block, any error ends the transfer.
+    StreamingError,
+    BlockNumberMismatch(u8, u8),
 }
 
 impl std::fmt::Display for TransmissionError {
@@ -17,6 +20,12 @@ impl std::fmt::Display for TransmissionError {
                 write!(f, "too many retries sending ymodem header")
             }
             TransmissionError::XModem1File => write!(f, "Only 1 file can be send with x-modem"),
+            TransmissionError::StreamingError => {
+                write!(f, "transmission error in streaming mode")
+            }
+            TransmissionError::BlockNumberMismatch(expected, got) => {
+                write!(f, "expected block {expected} but got {got}")
+            }
         }
     }
 }
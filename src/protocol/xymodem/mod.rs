@@ -1,6 +1,4 @@
-use async_trait::async_trait;
-
-use crate::com::{Com, TermComResult};
+use crate::com::TermComResult;
 use std::sync::{Arc, Mutex};
 
 mod constants;
@@ -14,14 +12,14 @@ use self::{
     error_mod::TransmissionError,
 };
 
-use super::{FileDescriptor, TransferState};
+use super::{FileDescriptor, InputBuffer, OutputBuffer, Step, TransferState};
 #[derive(Debug, Clone, Copy)]
 pub enum Checksum {
     Default,
     CRC16,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum XYModemVariant {
     XModem,
     XModem1k,
@@ -48,32 +46,35 @@ impl XYmodem {
     }
 }
 
-#[async_trait]
 impl super::Protocol for XYmodem {
-    async fn update(
+    fn update(
         &mut self,
-        com: &mut Box<dyn Com>,
+        input: &mut InputBuffer,
+        output: &mut OutputBuffer,
         transfer_state: Arc<Mutex<TransferState>>,
-    ) -> TermComResult<bool> {
+    ) -> TermComResult<Step> {
         if let Some(ry) = &mut self.ry {
-            ry.update(com, &transfer_state).await?;
+            let step = ry.update(input, output, &transfer_state)?;
             transfer_state.lock().unwrap().is_finished = ry.is_finished();
             if ry.is_finished() {
-                return Ok(false);
+                return Ok(Step::Finished);
             }
+            Ok(step)
         } else if let Some(sy) = &mut self.sy {
-            sy.update(com, &transfer_state).await?;
+            let step = sy.update(input, output, &transfer_state)?;
             transfer_state.lock().unwrap().is_finished = sy.is_finished();
             if sy.is_finished() {
-                return Ok(false);
+                return Ok(Step::Finished);
             }
+            Ok(step)
+        } else {
+            Ok(Step::Finished)
         }
-        Ok(true)
     }
 
-    async fn initiate_send(
+    fn initiate_send(
         &mut self,
-        _com: &mut Box<dyn Com>,
+        _output: &mut OutputBuffer,
         files: Vec<FileDescriptor>,
         transfer_state: Arc<Mutex<TransferState>>,
     ) -> TermComResult<()> {
@@ -82,28 +83,32 @@ impl super::Protocol for XYmodem {
         }
 
         let mut sy = sy::Sy::new(self.config);
-        // read data for x-modem transfer
+        sy.send(files);
+        // y-modem opens the files after the header got accepted
         if !self.config.is_ymodem() {
-            sy.data = files[0].get_data();
+            sy.open_cur_file()?;
         }
-
-        sy.send(files);
         self.sy = Some(sy);
         transfer_state.lock().unwrap().protocol_name = self.config.get_protocol_name().to_string();
         Ok(())
     }
 
-    async fn initiate_recv(
+    fn initiate_recv(
         &mut self,
-        com: &mut Box<dyn Com>,
+        output: &mut OutputBuffer,
         transfer_state: Arc<Mutex<TransferState>>,
     ) -> TermComResult<()> {
+        let settings = {
+            let mut transfer_state = transfer_state.lock().unwrap();
+            transfer_state.protocol_name = self.config.get_protocol_name().to_string();
+            transfer_state.download_settings.clone()
+        };
         let mut ry = ry::Ry::new(self.config);
-        ry.recv(com).await?;
+        ry.download_dir = settings.download_dir;
+        ry.collision_policy = settings.collision_policy;
+        ry.recv(output);
         self.ry = Some(ry);
 
-        transfer_state.lock().unwrap().protocol_name = self.config.get_protocol_name().to_string();
-
         // Add ghost file with no name when receiving with x-modem because this protocol
         // doesn't transfer any file information. User needs to set a file name after download.
         if !self.config.is_ymodem() {
@@ -123,14 +128,17 @@ impl super::Protocol for XYmodem {
         }
     }
 
-    async fn cancel(&mut self, com: &mut Box<dyn Com>) -> TermComResult<()> {
-        cancel(com).await
+    fn cancel(&mut self, output: &mut OutputBuffer) -> TermComResult<()> {
+        if let Some(ry) = &mut self.ry {
+            return ry.cancel(output);
+        }
+        cancel(output);
+        Ok(())
     }
 }
 
-async fn cancel(com: &mut Box<dyn Com>) -> TermComResult<()> {
-    com.send(&[CAN, CAN, CAN, CAN, CAN, CAN]).await?;
-    Ok(())
+fn cancel(output: &mut OutputBuffer) {
+    output.send(&[CAN, CAN, CAN, CAN, CAN, CAN]);
 }
 
 fn get_checksum(block: &[u8]) -> u8 {
@@ -1,18 +1,21 @@
-use directories::UserDirs;
 use icy_engine::get_crc16;
 use std::{
-    fs::{self},
     io::{self, ErrorKind},
+    path::PathBuf,
     sync::{Arc, Mutex},
 };
 
-use super::{constants::DEFAULT_BLOCK_LENGTH, get_checksum, Checksum, XYModemConfiguration};
+use super::{
+    constants::DEFAULT_BLOCK_LENGTH, error_mod::TransmissionError, get_checksum, Checksum,
+    XYModemConfiguration,
+};
 use crate::{
-    com::{Com, TermComResult},
+    com::TermComResult,
     protocol::{
-        str_from_null_terminated_utf8_unchecked,
+        get_default_download_dir, sanitize_file_name,
         xymodem::constants::{ACK, CPMEOF, EOT, EXT_BLOCK_LENGTH, NAK, SOH, STX},
-        FileDescriptor, TransferState,
+        CollisionPolicy, FileDescriptor, IncomingFile, InputBuffer, OutputBuffer, Step,
+        TransferState, ZFileInfo,
     },
 };
 
@@ -32,10 +35,22 @@ pub struct Ry {
     pub bytes_send: usize,
 
     pub files: Vec<FileDescriptor>,
-    data: Vec<u8>,
+    pub download_dir: Option<PathBuf>,
+    pub collision_policy: CollisionPolicy,
+    incoming: Option<IncomingFile>,
+    /// The last block may be padded with CPMEOF so it's held back until the file ends.
+    last_block: Vec<u8>,
 
+    /// The number of the next data block.
+    block_number: u8,
     errors: usize,
     recv_state: RecvState,
+
+    /// Batch totals from the optional fields of the Y-Modem header, 0 if the sender doesn't send them.
+    batch_files_done: usize,
+    batch_bytes_done: usize,
+    batch_files_total: usize,
+    batch_bytes_total: usize,
 }
 
 impl Ry {
@@ -44,9 +59,17 @@ impl Ry {
             configuration,
             recv_state: RecvState::None,
             files: Vec::new(),
-            data: Vec::new(),
+            download_dir: get_default_download_dir(),
+            collision_policy: CollisionPolicy::default(),
+            incoming: None,
+            last_block: Vec::new(),
+            block_number: 1,
             errors: 0,
             bytes_send: 0,
+            batch_files_done: 0,
+            batch_bytes_done: 0,
+            batch_files_total: 0,
+            batch_bytes_total: 0,
         }
     }
 
@@ -54,11 +77,12 @@ impl Ry {
         matches!(self.recv_state, RecvState::None)
     }
 
-    pub async fn update(
+    pub fn update(
         &mut self,
-        com: &mut Box<dyn Com>,
+        input: &mut InputBuffer,
+        output: &mut OutputBuffer,
         state: &Arc<Mutex<TransferState>>,
-    ) -> TermComResult<()> {
+    ) -> TermComResult<Step> {
         if let Ok(transfer_state) = &mut state.lock() {
             let transfer_info = &mut transfer_state.recieve_state;
             if !self.files.is_empty() {
@@ -67,10 +91,18 @@ impl Ry {
                 transfer_info.file_name = f.file_name.clone();
                 transfer_info.file_size = f.size;
             }
-            transfer_info.bytes_transfered = self.bytes_send;
+            transfer_info.bytes_transfered =
+                self.incoming.as_ref().map_or(0, IncomingFile::len) + self.last_block.len();
+            if self.batch_files_total > 0 {
+                transfer_info.total_files = self.batch_files_total;
+                transfer_info.batch_files_transfered = self.batch_files_done;
+                transfer_info.total_bytes = self.batch_bytes_total;
+                transfer_info.batch_bytes_transfered = self.batch_bytes_done
+                    + transfer_info.bytes_transfered.min(transfer_info.file_size);
+            }
             transfer_info.errors = self.errors;
             transfer_info.check_size = self.configuration.get_check_and_size();
-            transfer_info.update_bps();
+            transfer_info.update_statistics();
         }
 
         // println!("\t\t\t\t\t\t{:?}", self.recv_state);
@@ -80,7 +112,9 @@ impl Ry {
             RecvState::StartReceive(retries) => {
                 state.lock().unwrap().current_state = "Start receiving...";
 
-                let start = com.read_u8().await?;
+                let Some(start) = input.read_u8() else {
+                    return Ok(Step::NeedInput);
+                };
                 // println!("{:02X} {}, {}", start, start, char::from_u32(start as u32).unwrap());
                 if start == SOH {
                     if self.configuration.is_ymodem() {
@@ -92,11 +126,11 @@ impl Ry {
                     self.recv_state = RecvState::ReadBlock(EXT_BLOCK_LENGTH, 0);
                 } else {
                     if retries < 3 {
-                        self.await_data(com).await?;
+                        self.await_data(output);
                     } else if retries == 4 {
-                        com.send(&[NAK]).await?;
+                        output.send(&[NAK]);
                     } else {
-                        self.cancel(com).await?;
+                        self.cancel(output)?;
                         return Err(Box::new(io::Error::new(
                             ErrorKind::ConnectionAborted,
                             "too many retries starting the communication",
@@ -117,109 +151,92 @@ impl Ry {
                     1
                 };
 
-                let block = com.read_exact(2 + len + chksum_size).await?;
+                let Some(block) = input.read_exact(2 + len + chksum_size) else {
+                    return Ok(Step::NeedInput);
+                };
 
                 if block[0] != block[1] ^ 0xFF {
-                    com.send(&[NAK]).await?;
-                    self.errors += 1;
+                    self.nak(output)?;
                     self.recv_state = RecvState::StartReceive(retries + 1);
-                    return Ok(());
+                    return Ok(Step::Continue);
                 }
                 let block = &block[2..];
                 if !self.check_crc(block) {
                     //println!("NAK CRC FAIL");
-                    self.errors += 1;
-                    com.send(&[NAK]).await?;
+                    self.nak(output)?;
                     self.recv_state = RecvState::ReadYModemHeader(retries + 1);
-                    return Ok(());
+                    return Ok(Step::Continue);
                 }
                 if block[0] == 0 {
                     // END transfer
                     //println!("END TRANSFER");
-                    com.send(&[ACK]).await?;
+                    output.send(&[ACK]);
                     self.recv_state = RecvState::None;
-                    return Ok(());
+                    return Ok(Step::Continue);
                 }
 
+                // Block 0 has the same fields as a ZFILE: "name\0length mtime mode serial files bytes".
+                let info = ZFileInfo::parse(block);
                 let mut fd = FileDescriptor::new();
-                fd.file_name = str_from_null_terminated_utf8_unchecked(block);
-                let num =
-                    str_from_null_terminated_utf8_unchecked(&block[(fd.file_name.len() + 1)..])
-                        .to_string();
-                if let Ok(file_size) = num.parse::<usize>() {
-                    fd.size = file_size;
+                fd.file_name = sanitize_file_name(&info.file_name);
+                fd.size = info.size;
+                if info.files_remaining > 0 {
+                    self.batch_files_total = self.batch_files_done + info.files_remaining;
+                    self.batch_bytes_total =
+                        self.batch_bytes_done + info.bytes_remaining.max(info.size);
                 }
                 self.files.push(fd);
+                self.block_number = 1;
                 if self.configuration.is_ymodem() {
-                    com.send(&[ACK, b'C']).await?;
+                    output.send(&[ACK, b'C']);
                 } else {
-                    com.send(&[ACK]).await?;
+                    output.send(&[ACK]);
                 }
                 self.recv_state = RecvState::ReadBlockStart(0, 0);
             }
 
             RecvState::ReadBlockStart(step, retries) => {
                 if step == 0 {
-                    let start = com.read_u8().await?;
+                    let Some(start) = input.read_u8() else {
+                        return Ok(Step::NeedInput);
+                    };
                     if start == SOH {
                         self.recv_state = RecvState::ReadBlock(DEFAULT_BLOCK_LENGTH, 0);
                     } else if start == STX {
                         self.recv_state = RecvState::ReadBlock(EXT_BLOCK_LENGTH, 0);
                     } else if start == EOT {
-                        while self.data.ends_with(&[CPMEOF]) {
-                            self.data.pop();
-                        }
-                        if self.files.is_empty() {
-                            self.files.push(FileDescriptor::new());
-                        }
-
-                        let cur_file = self.files.len() - 1;
-                        let fd = self.files.get_mut(cur_file).unwrap();
-                        if let Some(user_dirs) = UserDirs::new() {
-                            let dir = user_dirs.download_dir().unwrap();
-
-                            let mut file_name = dir.join(&fd.file_name);
-                            let mut i = 1;
-                            while file_name.exists() {
-                                file_name = dir.join(&format!("{}.{}", fd.file_name, i));
-                                i += 1;
-                            }
-                            fs::write(file_name, &self.data)?;
-                        }
-                        self.data = Vec::new();
-
-                        if self.configuration.is_ymodem() {
-                            com.send(&[NAK]).await?;
-                            self.recv_state = RecvState::ReadBlockStart(1, 0);
-                        } else {
-                            com.send(&[ACK]).await?;
-                            self.recv_state = RecvState::None;
-                        }
+                        // A single EOT may be a garbled byte, the sender repeats a real one.
+                        output.send(&[NAK]);
+                        self.recv_state = RecvState::ReadBlockStart(1, retries);
                     } else {
                         if retries < 5 {
-                            com.send(&[NAK]).await?;
+                            self.nak(output)?;
                         } else {
-                            self.cancel(com).await?;
+                            self.cancel(output)?;
                             return Err(Box::new(io::Error::new(
                                 ErrorKind::ConnectionAborted,
                                 "too many retries",
                             )));
                         }
-                        self.errors += 1;
                         self.recv_state = RecvState::ReadBlockStart(0, retries + 1);
                     }
                 } else if step == 1 {
-                    let eot = com.read_u8().await?;
+                    let Some(eot) = input.read_u8() else {
+                        return Ok(Step::NeedInput);
+                    };
                     if eot != EOT {
-                        self.recv_state = RecvState::None;
-                        return Ok(());
+                        self.nak(output)?;
+                        self.recv_state = RecvState::ReadBlockStart(0, retries + 1);
+                        return Ok(Step::Continue);
                     }
+                    self.save_file()?;
                     if self.configuration.is_ymodem() {
-                        com.send(&[ACK, b'C']).await?;
+                        output.send(&[ACK, b'C']);
+                        self.recv_state = RecvState::StartReceive(retries);
                     } else {
-                        com.send(&[ACK]).await?;
+                        output.send(&[ACK]);
+                        self.recv_state = RecvState::None;
                     }
-                    self.recv_state = RecvState::StartReceive(retries);
                 }
             }
 
@@ -230,66 +247,145 @@ impl Ry {
                 } else {
                     1
                 };
-                let block = com.read_exact(2 + len + chksum_size).await?;
+                let Some(block) = input.read_exact(2 + len + chksum_size) else {
+                    return Ok(Step::NeedInput);
+                };
                 if block[0] != block[1] ^ 0xFF {
-                    com.send(&[NAK]).await?;
-
-                    self.errors += 1;
-                    let start = com.read_u8().await?;
-                    if start == SOH {
-                        self.recv_state = RecvState::ReadBlock(DEFAULT_BLOCK_LENGTH, retries + 1);
-                    } else if start == STX {
-                        self.recv_state = RecvState::ReadBlock(EXT_BLOCK_LENGTH, retries + 1);
-                    } else {
-                        self.cancel(com).await?;
-                        return Err(Box::new(io::Error::new(
-                            ErrorKind::ConnectionAborted,
-                            "too many retries",
-                        )));
-                    }
-
-                    self.recv_state = RecvState::ReadBlock(EXT_BLOCK_LENGTH, retries + 1);
-                    return Ok(());
+                    self.nak(output)?;
+                    self.recv_state = RecvState::ReadBlockStart(0, retries + 1);
+                    return Ok(Step::Continue);
                 }
+                let block_number = block[0];
                 let block = &block[2..];
                 if !self.check_crc(block) {
                     //println!("\t\t\t\t\t\trecv crc mismatch");
-                    self.errors += 1;
-                    com.send(&[NAK]).await?;
+                    self.nak(output)?;
                     self.recv_state = RecvState::ReadBlockStart(0, retries + 1);
-                    return Ok(());
+                    return Ok(Step::Continue);
+                }
+                if block_number == self.block_number.wrapping_sub(1) {
+                    // The sender missed the ACK and repeats the last block.
+                    if !self.configuration.is_streaming() {
+                        output.send(&[ACK]);
+                    }
+                    self.recv_state = RecvState::ReadBlockStart(0, 0);
+                    return Ok(Step::Continue);
                 }
-                self.data.extend_from_slice(&block[0..len]);
+                if block_number != self.block_number {
+                    let expected = self.block_number;
+                    self.cancel(output)?;
+                    return Err(Box::new(TransmissionError::BlockNumberMismatch(
+                        expected,
+                        block_number,
+                    )));
+                }
+                self.block_number = self.block_number.wrapping_add(1);
+                let last_block = std::mem::replace(&mut self.last_block, block[0..len].to_vec());
+                self.get_incoming_file()?.write_all(&last_block)?;
                 if !self.configuration.is_streaming() {
-                    com.send(&[ACK]).await?;
+                    output.send(&[ACK]);
                 }
                 self.recv_state = RecvState::ReadBlockStart(0, 0);
             }
         }
+        Ok(Step::Continue)
+    }
+
+    fn get_incoming_file(&mut self) -> TermComResult<&mut IncomingFile> {
+        if self.incoming.is_none() {
+            let Some(dir) = &self.download_dir else {
+                return Err(Box::new(io::Error::new(
+                    ErrorKind::NotFound,
+                    "no download directory",
+                )));
+            };
+            let file_name = self.files.last().map_or("", |fd| fd.file_name.as_str());
+            let mut incoming = IncomingFile::open(dir, file_name)?;
+            // x/y-modem can't resume, remove data of an earlier attempt
+            incoming.set_len(0)?;
+            self.incoming = Some(incoming);
+        }
+        Ok(self.incoming.as_mut().unwrap())
+    }
+
+    fn save_file(&mut self) -> TermComResult<()> {
+        if self.files.is_empty() {
+            self.files.push(FileDescriptor::new());
+        }
+        let size = self.files.last().unwrap().size;
+        let mut last_block = std::mem::take(&mut self.last_block);
+        if size == 0 {
+            // x-modem doesn't know the file size, strip the padding
+            while last_block.ends_with(&[CPMEOF]) {
+                last_block.pop();
+            }
+        }
+        let incoming = self.get_incoming_file()?;
+        incoming.write_all(&last_block)?;
+        if size > 0 && incoming.len() > size {
+            incoming.set_len(size)?;
+        }
+        let len = incoming.len();
+        self.batch_files_done += 1;
+        self.batch_bytes_done += len;
+
+        let (Some(dir), Some(incoming), Some(fd)) = (
+            &self.download_dir,
+            self.incoming.take(),
+            self.files.last_mut(),
+        ) else {
+            return Ok(());
+        };
+        // X/Y-Modem can't wait for the user, asking falls back to renaming.
+        let exists = dir.join(sanitize_file_name(&fd.file_name)).exists();
+        if exists && self.collision_policy == CollisionPolicy::Skip {
+            incoming.discard()?;
+            self.files.pop();
+            return Ok(());
+        }
+        let overwrite = self.collision_policy == CollisionPolicy::Overwrite;
+        let path = incoming.finish(dir, &fd.file_name, overwrite)?;
+        fd.path_name = path.to_string_lossy().to_string();
+        fd.set_path(path);
         Ok(())
     }
 
-    pub async fn cancel(&mut self, com: &mut Box<dyn Com>) -> TermComResult<()> {
+    pub fn cancel(&mut self, output: &mut OutputBuffer) -> TermComResult<()> {
         self.recv_state = RecvState::None;
-        super::cancel(com).await
+        self.last_block.clear();
+        if let Some(incoming) = self.incoming.take() {
+            incoming.suspend()?;
+        }
+        super::cancel(output);
+        Ok(())
     }
 
-    pub async fn recv(&mut self, com: &mut Box<dyn Com>) -> TermComResult<()> {
-        self.await_data(com).await?;
-        self.data = Vec::new();
+    pub fn recv(&mut self, output: &mut OutputBuffer) {
+        self.await_data(output);
+        self.last_block.clear();
+        self.block_number = 1;
         self.recv_state = RecvState::StartReceive(0);
+    }
+
+    /// Requests the last block again, streaming variants can't do that and give up.
+    fn nak(&mut self, output: &mut OutputBuffer) -> TermComResult<()> {
+        self.errors += 1;
+        if self.configuration.is_streaming() {
+            self.cancel(output)?;
+            return Err(Box::new(TransmissionError::StreamingError));
+        }
+        output.send(&[NAK]);
         Ok(())
     }
 
-    async fn await_data(&mut self, com: &mut Box<dyn Com>) -> TermComResult<usize> {
+    fn await_data(&mut self, output: &mut OutputBuffer) {
         if self.configuration.is_streaming() {
-            com.send(&[b'G']).await?;
+            output.send(&[b'G']);
         } else if self.configuration.use_crc() {
-            com.send(&[b'C']).await?;
+            output.send(&[b'C']);
         } else {
-            com.send(&[NAK]).await?;
+            output.send(&[NAK]);
         }
-        Ok(1)
     }
 
     fn check_crc(&self, block: &[u8]) -> bool {
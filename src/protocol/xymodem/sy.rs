@@ -1,6 +1,7 @@
 use icy_engine::get_crc16;
 use std::{
     cmp::min,
+    io::{Read, Seek, SeekFrom},
     sync::{Arc, Mutex},
 };
 
@@ -10,10 +11,10 @@ use super::{
     get_checksum, Checksum, XYModemConfiguration, XYModemVariant,
 };
 use crate::{
-    com::{Com, TermComResult},
+    com::TermComResult,
     protocol::{
         xymodem::constants::{ACK, CPMEOF, EOT, EXT_BLOCK_LENGTH, NAK, SOH, STX},
-        FileDescriptor, TransferState,
+        FileDescriptor, InputBuffer, OutputBuffer, ReadSeek, Step, TransferState,
     },
 };
 
@@ -25,7 +26,7 @@ pub enum SendState {
     AckSendYmodemHeader(usize),
     SendData(usize, usize),
     AckSendData(usize, usize),
-    YModemEndHeader(u8),
+    EndOfFile(u8),
 }
 
 pub struct Sy {
@@ -39,7 +40,9 @@ pub struct Sy {
     errors: usize,
     send_state: SendState,
 
-    pub data: Vec<u8>,
+    reader: Option<Box<dyn ReadSeek>>,
+    reader_pos: usize,
+    data_len: usize,
 
     transfer_stopped: bool,
 }
@@ -51,7 +54,9 @@ impl Sy {
 
             send_state: SendState::None,
             files: Vec::new(),
-            data: Vec::new(),
+            reader: None,
+            reader_pos: 0,
+            data_len: 0,
             errors: 0,
             bytes_send: 0,
             block_number: 0,
@@ -64,11 +69,12 @@ impl Sy {
         matches!(self.send_state, SendState::None)
     }
 
-    pub async fn update(
+    pub fn update(
         &mut self,
-        com: &mut Box<dyn Com>,
+        input: &mut InputBuffer,
+        output: &mut OutputBuffer,
         state: &Arc<Mutex<TransferState>>,
-    ) -> TermComResult<()> {
+    ) -> TermComResult<Step> {
         if let Ok(transfer_state) = &mut state.lock() {
             let transfer_info = &mut transfer_state.send_state;
             if self.cur_file < self.files.len() {
@@ -77,9 +83,22 @@ impl Sy {
                 transfer_info.file_size = f.size;
             }
             transfer_info.bytes_transfered = self.bytes_send;
+            transfer_info.total_files = self.files.len();
+            transfer_info.batch_files_transfered = self.cur_file;
+            transfer_info.total_bytes = self.files.iter().map(|f| f.size).sum();
+            transfer_info.batch_bytes_transfered = self
+                .files
+                .iter()
+                .take(self.cur_file)
+                .map(|f| f.size)
+                .sum::<usize>()
+                + self
+                    .files
+                    .get(self.cur_file)
+                    .map_or(0, |f| self.bytes_send.min(f.size));
             transfer_info.errors = self.errors;
             transfer_info.check_size = self.configuration.get_check_and_size();
-            transfer_info.update_bps();
+            transfer_info.update_statistics();
         }
         // println!("send state: {:?} {:?}", self.send_state, self.configuration.variant);
 
@@ -87,7 +106,9 @@ impl Sy {
             SendState::None => {}
             SendState::InitiateSend => {
                 state.lock().unwrap().current_state = "Initiate send…";
-                self.get_mode(com).await?;
+                if !self.get_mode(input)? {
+                    return Ok(Step::NeedInput);
+                }
                 if self.configuration.is_ymodem() {
                     self.send_state = SendState::SendYModemHeader(0);
                 } else {
@@ -98,18 +119,25 @@ impl Sy {
             SendState::SendYModemHeader(retries) => {
                 if retries > 3 {
                     state.lock().unwrap().current_state = "Too many retries...aborting";
-                    self.cancel(com).await?;
-                    return Ok(());
+                    self.cancel(output);
+                    return Ok(Step::Continue);
                 }
                 self.block_number = 0;
                 //transfer_info.write("Send header...".to_string());
-                self.send_ymodem_header(com).await?;
+                self.send_ymodem_header(output);
                 self.send_state = SendState::AckSendYmodemHeader(retries);
             }
 
             SendState::AckSendYmodemHeader(retries) => {
                 //let now = SystemTime::now();
-                let ack = self.read_command(com).await?;
+                let Some(&ack) = input.as_slice().first() else {
+                    return Ok(Step::NeedInput);
+                };
+                // the header ACK is followed by the 'C' for the data
+                if ack == ACK && !self.transfer_stopped && input.len() < 2 {
+                    return Ok(Step::NeedInput);
+                }
+                input.consume(1);
                 if ack == NAK {
                     state.lock().unwrap().current_state = "Encountered error";
                     self.errors += 1;
@@ -118,16 +146,16 @@ impl Sy {
                         return Err(Box::new(TransmissionError::TooManyRetriesSendingHeader));
                     }
                     self.send_state = SendState::SendYModemHeader(retries + 1);
-                    return Ok(());
+                    return Ok(Step::Continue);
                 }
                 if ack == ACK {
                     if self.transfer_stopped {
                         self.send_state = SendState::None;
-                        return Ok(());
+                        return Ok(Step::Continue);
                     }
                     state.lock().unwrap().current_state = "Header accepted.";
-                    self.data = self.files[self.cur_file].get_data();
-                    let _ = self.read_command(com).await?;
+                    self.open_cur_file()?;
+                    input.consume(1);
                     // SKIP - not needed to check that
                     self.send_state = SendState::SendData(0, 0);
                 }
@@ -143,11 +171,11 @@ impl Sy {
             }
             SendState::SendData(cur_offset, retries) => {
                 state.lock().unwrap().current_state = "Send data...";
-                if self.send_data_block(com, cur_offset).await? {
+                if self.send_data_block(output, cur_offset)? {
                     if self.configuration.is_streaming() {
                         self.bytes_send = cur_offset + self.configuration.block_length;
                         self.send_state = SendState::SendData(self.bytes_send, 0);
-                        self.check_eof(com).await?;
+                        self.check_eof(output);
                     } else {
                         self.send_state = SendState::AckSendData(cur_offset, retries);
                     }
@@ -156,84 +184,107 @@ impl Sy {
                 };
             }
             SendState::AckSendData(cur_offset, retries) => {
-                let ack = self.read_command(com).await?;
+                let Some(&ack) = input.as_slice().first() else {
+                    return Ok(Step::NeedInput);
+                };
                 if ack == CAN {
                     // need 2 CAN
-                    let can2 = self.read_command(com).await?;
+                    let Some(can2) = input.read_exact(2).map(|b| b[1]) else {
+                        return Ok(Step::NeedInput);
+                    };
                     if can2 == CAN {
                         self.send_state = SendState::None;
                         //transfer_info.write("Got cancel ...".to_string());
                         return Err(Box::new(TransmissionError::Cancel));
                     }
+                } else {
+                    input.consume(1);
                 }
 
                 if ack != ACK {
                     self.errors += 1;
+                    // the block gets repeated with the same number
+                    self.block_number = self.block_number.wrapping_sub(1);
 
                     // fall back to short block length after too many errors
                     if retries > 3 && self.configuration.block_length == EXT_BLOCK_LENGTH {
                         self.configuration.block_length = DEFAULT_BLOCK_LENGTH;
                         self.send_state = SendState::SendData(cur_offset, retries + 2);
-                        return Ok(());
+                        return Ok(Step::Continue);
                     }
 
                     if retries > 5 {
-                        self.eot(com).await?;
+                        self.eot(output);
                         return Err(Box::new(TransmissionError::TooManyRetriesSendingHeader));
                     }
                     self.send_state = SendState::SendData(cur_offset, retries + 1);
-                    return Ok(());
+                    return Ok(Step::Continue);
                 }
                 self.bytes_send = cur_offset + self.configuration.block_length;
                 self.send_state = SendState::SendData(self.bytes_send, 0);
-                self.check_eof(com).await?;
+                self.check_eof(output);
             }
-            SendState::YModemEndHeader(step) => match step {
-                0 => {
-                    if self.read_command(com).await? == NAK {
-                        com.send(&[EOT]).await?;
-                        self.send_state = SendState::YModemEndHeader(1);
-                        return Ok(());
-                    }
-                    self.cancel(com).await?;
+            SendState::EndOfFile(step) => {
+                if step > 2 {
+                    self.send_state = SendState::None;
+                    return Ok(Step::Continue);
                 }
-                1 => {
-                    if self.read_command(com).await? == ACK {
-                        self.send_state = SendState::YModemEndHeader(2);
-                        return Ok(());
+                let Some(ack) = self.read_command(input) else {
+                    return Ok(Step::NeedInput);
+                };
+                match step {
+                    0 => {
+                        // The receiver NAKs the first EOT to make sure it's not a garbled byte.
+                        if ack == NAK {
+                            output.send(&[EOT]);
+                            self.send_state = SendState::EndOfFile(1);
+                            return Ok(Step::Continue);
+                        }
+                        if ack == ACK {
+                            self.end_of_file();
+                            return Ok(Step::Continue);
+                        }
+                        self.cancel(output);
                     }
-                    self.cancel(com).await?;
-                }
-                2 => {
-                    if self.read_command(com).await? == b'C' {
-                        self.send_state = SendState::SendYModemHeader(0);
-                        self.cur_file += 1;
-                        return Ok(());
+                    1 => {
+                        if ack == ACK {
+                            self.end_of_file();
+                            return Ok(Step::Continue);
+                        }
+                        self.cancel(output);
+                    }
+                    _ => {
+                        if ack == b'C' {
+                            self.send_state = SendState::SendYModemHeader(0);
+                            self.cur_file += 1;
+                            self.bytes_send = 0;
+                            return Ok(Step::Continue);
+                        }
+                        self.cancel(output);
                     }
-                    self.cancel(com).await?;
-                }
-                _ => {
-                    self.send_state = SendState::None;
                 }
-            },
+            }
         }
-        Ok(())
+        Ok(Step::Continue)
     }
 
-    async fn check_eof(&mut self, com: &mut Box<dyn Com>) -> TermComResult<()> {
+    fn check_eof(&mut self, output: &mut OutputBuffer) {
         if self.bytes_send >= self.files[self.cur_file].size {
-            self.eot(com).await?;
-            if self.configuration.is_ymodem() {
-                self.send_state = SendState::YModemEndHeader(0);
-            } else {
-                self.send_state = SendState::None;
-            }
+            self.eot(output);
+            self.send_state = SendState::EndOfFile(0);
         }
-        Ok(())
     }
 
-    async fn read_command(&self, com: &mut Box<dyn Com>) -> TermComResult<u8> {
-        let ch = com.read_u8().await?;
+    fn end_of_file(&mut self) {
+        self.send_state = if self.configuration.is_ymodem() {
+            SendState::EndOfFile(2)
+        } else {
+            SendState::None
+        };
+    }
+
+    fn read_command(&self, input: &mut InputBuffer) -> Option<u8> {
+        let ch = input.read_u8()?;
         /* let cmd = match ch {
             b'C' => "[C]",
             EOT => "[EOT]",
@@ -249,24 +300,27 @@ impl Sy {
             println!("GOT CMD: #{} (0x{:X})", ch, ch);
         }*/
 
-        Ok(ch)
+        Some(ch)
     }
 
-    async fn eot(&self, com: &mut Box<dyn Com>) -> TermComResult<usize> {
+    fn eot(&self, output: &mut OutputBuffer) {
         // println!("[EOT]");
-        com.send(&[EOT]).await
+        output.send(&[EOT]);
     }
 
-    pub async fn get_mode(&mut self, com: &mut Box<dyn Com>) -> TermComResult<()> {
-        let ch = self.read_command(com).await?;
+    /// Reads the checksum mode the receiver asks for, false if it didn't ask yet.
+    pub fn get_mode(&mut self, input: &mut InputBuffer) -> TermComResult<bool> {
+        let Some(ch) = self.read_command(input) else {
+            return Ok(false);
+        };
         match ch {
             NAK => {
                 self.configuration.checksum_mode = Checksum::Default;
-                Ok(())
+                Ok(true)
             }
             b'C' => {
                 self.configuration.checksum_mode = Checksum::CRC16;
-                Ok(())
+                Ok(true)
             }
             b'G' => {
                 self.configuration = if self.configuration.is_ymodem() {
@@ -274,19 +328,14 @@ impl Sy {
                 } else {
                     XYModemConfiguration::new(XYModemVariant::XModem1kG)
                 };
-                Ok(())
+                Ok(true)
             }
             CAN => Err(Box::new(TransmissionError::Cancel)),
             _ => Err(Box::new(TransmissionError::InvalidMode(ch))),
         }
     }
 
-    async fn send_block(
-        &mut self,
-        com: &mut Box<dyn Com>,
-        data: &[u8],
-        pad_byte: u8,
-    ) -> TermComResult<()> {
+    fn send_block(&mut self, output: &mut OutputBuffer, data: &[u8], pad_byte: u8) {
         let block_len = if data.len() <= DEFAULT_BLOCK_LENGTH {
             SOH
         } else {
@@ -317,12 +366,11 @@ impl Sy {
             }
         }
         // println!("Send block {:X?}", block);
-        com.send(&block).await?;
+        output.send(&block);
         self.block_number = self.block_number.wrapping_add(1);
-        Ok(())
     }
 
-    async fn send_ymodem_header(&mut self, com: &mut Box<dyn Com>) -> TermComResult<()> {
+    fn send_ymodem_header(&mut self, output: &mut OutputBuffer) {
         if self.cur_file < self.files.len() {
             // restart from 0
             let mut block = Vec::new();
@@ -331,20 +379,14 @@ impl Sy {
             block.extend_from_slice(name);
             block.push(0);
             block.extend_from_slice(format!("{}", fd.size).as_bytes());
-            self.send_block(com, &block, 0).await?;
-            Ok(())
+            self.send_block(output, &block, 0);
         } else {
-            self.end_ymodem(com).await?;
-            Ok(())
+            self.end_ymodem(output);
         }
     }
 
-    async fn send_data_block(
-        &mut self,
-        com: &mut Box<dyn Com>,
-        offset: usize,
-    ) -> TermComResult<bool> {
-        let data_len = self.data.len();
+    fn send_data_block(&mut self, output: &mut OutputBuffer, offset: usize) -> TermComResult<bool> {
+        let data_len = self.data_len;
         if offset >= data_len {
             return Ok(false);
         }
@@ -354,26 +396,48 @@ impl Sy {
             self.configuration.block_length = DEFAULT_BLOCK_LENGTH;
             block_end = min(offset + self.configuration.block_length, data_len);
         }
-        let d = self.data[offset..block_end].to_vec();
-        self.send_block(com, &d, CPMEOF).await?;
+        let d = self.read_block(offset, block_end)?;
+        self.send_block(output, &d, CPMEOF);
         Ok(true)
     }
 
-    pub async fn cancel(&mut self, com: &mut Box<dyn Com>) -> TermComResult<()> {
+    /// Opens the current file, the data is read block by block while sending.
+    pub fn open_cur_file(&mut self) -> TermComResult<()> {
+        let fd = &self.files[self.cur_file];
+        self.reader = Some(fd.open_reader()?);
+        self.reader_pos = 0;
+        self.data_len = fd.size;
+        Ok(())
+    }
+
+    fn read_block(&mut self, from: usize, to: usize) -> TermComResult<Vec<u8>> {
+        let mut block = vec![0; to - from];
+        if let Some(reader) = &mut self.reader {
+            if self.reader_pos != from {
+                reader.seek(SeekFrom::Start(from as u64))?;
+            }
+            reader.read_exact(&mut block)?;
+            self.reader_pos = to;
+        }
+        Ok(block)
+    }
+
+    pub fn cancel(&mut self, output: &mut OutputBuffer) {
         self.send_state = SendState::None;
-        super::cancel(com).await
+        super::cancel(output);
     }
 
     pub fn send(&mut self, files: Vec<FileDescriptor>) {
         self.send_state = SendState::InitiateSend;
+        // x-modem data starts with block 1, y-modem starts every file with header block 0
+        self.block_number = 1;
         self.files = files;
         self.cur_file = 0;
         self.bytes_send = 0;
     }
 
-    pub async fn end_ymodem(&mut self, com: &mut Box<dyn Com>) -> TermComResult<()> {
-        self.send_block(com, &[0], 0).await?;
+    pub fn end_ymodem(&mut self, output: &mut OutputBuffer) {
+        self.send_block(output, &[0], 0);
         self.transfer_stopped = true;
-        Ok(())
     }
 }
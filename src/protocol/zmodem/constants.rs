@@ -26,6 +26,11 @@ pub const ZMAXHLEN: u8 = 16; // Max header information length  NEVER CHANGE
 pub const ZMAXSPLEN: usize = 1024; // Max subpacket length  NEVER CHANGE
 pub const XON: u8 = 0x11;
 
+/* Attention string of ZSINIT */
+pub const ZATTNLEN: usize = 32; // Max length of attention string
+pub const ATTNBRK: u8 = 0xDD; // Send a break signal
+pub const ATTNPSE: u8 = 0xDE; // Pause one second
+
 /* ZDLE sequences */
 pub const ZCRCE: u8 = b'h'; /* CRC next, frame ends, header packet follows */
 pub const ZCRCG: u8 = b'i'; /* CRC next, frame continues nonstop */
@@ -56,7 +61,7 @@ pub mod zfile_flag {
     /* Management include options, one of these ored in ZF1 */
     // #define ZMSKNOLOC	0200	/* Skip file if not present at rx */
     /* Management options, one of these ored in ZF1 */
-    // #define ZMMASK	037	/* Mask for the choices below */
+    pub const ZMMASK: u8 = 0x1F; /* Mask for the choices below */
     pub const ZMNEWL: u8 = 1; /* Transfer if source newer or longer */
     pub const ZMCRC: u8 = 2; /* Transfer if different file CRC or length */
     pub const ZMAPND: u8 = 3; /* Append contents to existing file (if any) */
@@ -64,8 +69,8 @@ pub mod zfile_flag {
     pub const ZMNEW: u8 = 5; /* Transfer if source newer */
 
     /* Number 5 is alive ... */
-    // #define ZMDIFF	6	/* Transfer if dates or lengths different */
-    // #define ZMPROT	7	/* Protect destination file */
+    pub const ZMDIFF: u8 = 6; /* Transfer if dates or lengths different */
+    pub const ZMPROT: u8 = 7; /* Protect destination file */
     /* Transport options, one of these in ZF2 */
     // #define ZTLZW	1	/* Lempel-Ziv compression */
     // #define ZTCRYPT	2	/* Encryption */
@@ -79,6 +84,9 @@ pub mod zfile_flag {
 pub mod zsinit_flag {
     pub const TESCCTL: u8 = 0x40;
     pub const TESC8: u8 = 0x80;
+    // icy_term extension: the transmitter only escapes ZDLE from now on (DirectZAP),
+    // only sent to receivers with CANDZAP.
+    pub const TDZAP: u8 = 0x01;
 }
 
 pub mod zrinit_flag {
@@ -96,6 +104,7 @@ pub mod zrinit_flag {
     pub const TSYNC: u8 = 0xae;
 
     // Bit Masks for ZRINIT flags byte ZF1
+    pub const CANDZAP: u8 = 0x80; // icy_term extension: Rx reads data that only has ZDLE escaped
     const CANVHDR: u8 = 0x01; // Variable headers OK
                               /*
                               // Parameters for ZSINIT frame
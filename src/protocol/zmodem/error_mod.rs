@@ -6,6 +6,8 @@ pub enum TransmissionError {
     // Cancel,
     //InvalidMode(u8),
     InvalidSubpacket(u8),
+    /// No subpacket end within that many bytes.
+    SubpacketTooLong(usize),
     InvalidFrameType(u8),
     ZPADExected(u8),
     ZLDEExected(u8),
@@ -15,6 +17,8 @@ pub enum TransmissionError {
     ZDataBeforeZFILE,
     UnsupportedFrame(ZFrameType),
     HexNumberExpected,
+    /// ZModem positions are 32 bit.
+    FileTooLarge(usize),
 }
 
 impl std::fmt::Display for TransmissionError {
@@ -25,6 +29,9 @@ impl std::fmt::Display for TransmissionError {
             TransmissionError::InvalidSubpacket(m) => {
                 write!(f, "don't understand subpacket {m}/x{m:X}")
             }
+            TransmissionError::SubpacketTooLong(len) => {
+                write!(f, "subpacket didn't end within {len} bytes")
+            }
             TransmissionError::InvalidFrameType(ft) => write!(f, "invalid frame type {ft}"),
             TransmissionError::ZPADExected(b) => write!(
                 f,
@@ -48,6 +55,9 @@ impl std::fmt::Display for TransmissionError {
             TransmissionError::ZDataBeforeZFILE => write!(f, "Got ZDATA before ZFILE"),
             TransmissionError::UnsupportedFrame(ft) => write!(f, "unsupported frame {ft:?}"),
             TransmissionError::HexNumberExpected => write!(f, "hex number expected"),
+            TransmissionError::FileTooLarge(pos) => {
+                write!(f, "file too large for zmodem, got {pos} bytes")
+            }
         }
     }
 }
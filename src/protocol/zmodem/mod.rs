@@ -2,9 +2,12 @@
 // ZModem protocol specification http://cristal.inria.fr/~doligez/zmodem/zmodem.txt
 
 pub mod constants;
-use std::sync::{Arc, Mutex};
+use std::{
+    io::{self, Read},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
-use async_trait::async_trait;
 pub use constants::*;
 mod header_mod;
 pub use header_mod::*;
@@ -15,17 +18,61 @@ use sz::Sz;
 
 mod rz;
 use rz::Rz;
+pub use rz::ZFileInfo;
 
 mod error_mod;
 mod tests;
 
 use self::error_mod::TransmissionError;
 
-use super::{FileDescriptor, Protocol, TransferState};
-use crate::com::{Com, TermComResult};
+use super::{ByteReader, FileDescriptor, InputBuffer, OutputBuffer, Protocol, Step, TransferState};
+use crate::com::TermComResult;
+
+/// Which bytes a sender escapes with ZDLE.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ZdleEscaping {
+    /// Only ZDLE (CAN) itself, DirectZAP on clean 8 bit links. Standard ZModem has no
+    /// way to agree on that, it's only used if the other side is icy_term as well
+    /// (`zrinit_flag::CANDZAP`, `zsinit_flag::TDZAP`). Other peers get `Standard`.
+    Minimal,
+    /// ZDLE, DLE, XON/XOFF and CR after '@' - the classic ZModem set.
+    #[default]
+    Standard,
+    /// Every control character, for links that eat some of them (ESCCTL).
+    Control,
+}
+
+impl ZdleEscaping {
+    pub const ALL: [ZdleEscaping; 3] = [
+        ZdleEscaping::Minimal,
+        ZdleEscaping::Standard,
+        ZdleEscaping::Control,
+    ];
+
+    pub fn needs_escape(self, b: u8, last: u8) -> bool {
+        match self {
+            ZdleEscaping::Minimal => b == ZDLE,
+            ZdleEscaping::Standard => {
+                matches!(b, ZDLE | 0x10 | 0x90 | 0x11 | 0x91 | 0x13 | 0x93)
+                    || (b & 0x7F == 0x0D && last & 0x7F == 0x40)
+            }
+            ZdleEscaping::Control => b & 0x60 == 0,
+        }
+    }
+
+    /// Unescaped XON/XOFF are flow control characters of the link and get dropped,
+    /// on DirectZAP links they are data.
+    pub fn strips_flow_control(self) -> bool {
+        self != ZdleEscaping::Minimal
+    }
+}
 
 pub struct Zmodem {
     block_length: usize,
+    /// DirectZAP - ZedZap that only escapes ZDLE if the other side agrees.
+    direct_zap: bool,
+    /// Pause after every subpacket sent, see `Sz::block_delay`.
+    block_delay: Duration,
     rz: Option<rz::Rz>,
     sz: Option<sz::Sz>,
 }
@@ -34,116 +81,157 @@ impl Zmodem {
     pub fn new(block_length: usize) -> Self {
         Self {
             block_length,
+            direct_zap: false,
+            block_delay: sz::BLOCK_DELAY,
             sz: None,
             rz: None,
         }
     }
 
+    pub fn new_direct_zap() -> Self {
+        Self {
+            direct_zap: true,
+            ..Zmodem::new(8 * 1024)
+        }
+    }
+
+    pub fn with_block_delay(mut self, block_delay: Duration) -> Self {
+        self.block_delay = block_delay;
+        self
+    }
+
     fn get_name(&self) -> &str {
-        if self.block_length == 1024 {
+        if self.direct_zap {
+            "DirectZAP (Zmodem 8k)"
+        } else if self.block_length == 1024 {
             "Zmodem"
         } else {
             "ZedZap (Zmodem 8k)"
         }
     }
 
-    pub async fn cancel(com: &mut Box<dyn Com>) -> TermComResult<()> {
-        com.send(&ABORT_SEQ).await?;
-        Ok(())
+    /// The escaping to ask for, `Minimal` falls back to `Standard` unless the peer agrees.
+    fn get_escaping(&self, transfer_state: &TransferState) -> ZdleEscaping {
+        if self.direct_zap {
+            ZdleEscaping::Minimal
+        } else {
+            transfer_state.zmodem_escaping
+        }
     }
 
-    pub fn encode_subpacket_crc16(zcrc_byte: u8, data: &[u8]) -> Vec<u8> {
+    pub fn cancel(output: &mut OutputBuffer) {
+        output.send(&ABORT_SEQ);
+    }
+
+    pub fn encode_subpacket_crc16(zcrc_byte: u8, data: &[u8], escaping: ZdleEscaping) -> Vec<u8> {
         let mut v = Vec::new();
         let crc = icy_engine::get_crc16_buggy(data, zcrc_byte);
-        append_zdle_encoded(&mut v, data);
+        append_zdle_escaped(&mut v, data, escaping);
         v.extend_from_slice(&[ZDLE, zcrc_byte]);
-        append_zdle_encoded(&mut v, &u16::to_le_bytes(crc));
+        append_zdle_escaped(&mut v, &u16::to_le_bytes(crc), escaping);
         v
     }
 
-    pub fn encode_subpacket_crc32(zcrc_byte: u8, data: &[u8]) -> Vec<u8> {
+    pub fn encode_subpacket_crc32(zcrc_byte: u8, data: &[u8], escaping: ZdleEscaping) -> Vec<u8> {
         let mut v = Vec::new();
         let mut crc = get_crc32(data);
         crc = !update_crc32(!crc, zcrc_byte);
 
-        append_zdle_encoded(&mut v, data);
+        append_zdle_escaped(&mut v, data, escaping);
         v.extend_from_slice(&[ZDLE, zcrc_byte]);
-        append_zdle_encoded(&mut v, &u32::to_le_bytes(crc));
+        append_zdle_escaped(&mut v, &u32::to_le_bytes(crc), escaping);
         v
     }
 }
 
+/// CRC-32 of the first `len` bytes, what ZCRC asks for and answers with.
+pub fn get_file_crc32(reader: &mut impl Read, len: usize) -> io::Result<u32> {
+    let mut crc = 0xFFFF_FFFF;
+    let mut buf = vec![0; 64 * 1024];
+    let mut left = len;
+    while left > 0 {
+        let n = reader.read(&mut buf[..left.min(buf.len())])?;
+        if n == 0 {
+            break;
+        }
+        for b in &buf[..n] {
+            crc = update_crc32(crc, *b);
+        }
+        left -= n;
+    }
+    Ok(!crc)
+}
+
+/// Sends the attention string the remote sender set with ZSINIT to interrupt it.
+/// `ATTNBRK` sends a break signal, `ATTNPSE` pauses for a second.
+pub fn append_attn(output: &mut OutputBuffer, attn: &[u8]) {
+    for b in attn {
+        match *b {
+            0 => break,
+            ATTNBRK => output.send_break(),
+            ATTNPSE => output.pause(Duration::from_secs(1)),
+            b => output.send(&[b]),
+        }
+    }
+}
+
 pub fn append_zdle_encoded(v: &mut Vec<u8>, data: &[u8]) {
+    append_zdle_escaped(v, data, ZdleEscaping::Standard);
+}
+
+pub fn append_zdle_escaped(v: &mut Vec<u8>, data: &[u8], escaping: ZdleEscaping) {
     let mut last = 0u8;
     for b in data {
-        match *b {
-            ZDLE => v.extend_from_slice(&[ZDLE, ZDLEE]),
-            0x10 => v.extend_from_slice(&[ZDLE, ESC_0X10]),
-            0x90 => v.extend_from_slice(&[ZDLE, ESC_0X90]),
-            0x11 => v.extend_from_slice(&[ZDLE, ESC_0X11]),
-            0x91 => v.extend_from_slice(&[ZDLE, ESC_0X91]),
-            0x13 => v.extend_from_slice(&[ZDLE, ESC_0X13]),
-            0x93 => v.extend_from_slice(&[ZDLE, ESC_0X93]),
-            0x0D => {
-                if last == 0x40 || last == 0xc0 {
-                    v.extend_from_slice(&[ZDLE, ESC_0X0D]);
-                } else {
-                    v.push(0x0D);
-                }
-            }
-            0x8D => {
-                if last == 0x40 || last == 0xc0 {
-                    v.extend_from_slice(&[ZDLE, ESC_0X8D]);
-                } else {
-                    v.push(0x8D);
-                }
-            }
-
-            b => v.push(b),
+        if escaping.needs_escape(*b, last) {
+            v.extend_from_slice(&[ZDLE, b ^ 0x40]);
+        } else {
+            v.push(*b);
         }
         last = *b;
     }
 }
 
-pub async fn read_zdle_bytes(com: &mut Box<dyn Com>, length: usize) -> TermComResult<Vec<u8>> {
-    let mut data = Vec::new();
-    loop {
-        let c = com.read_u8().await?;
+/// Decodes the byte following a ZDLE. Senders may escape any control character
+/// (ZDLEE and the `ESC_` constants are just the most common ones).
+pub fn decode_zdle_escape(c: u8) -> Option<u8> {
+    match c {
+        ZRUB0 => Some(0x7F),
+        ZRUB1 => Some(0xFF),
+        c if c & 0x60 == 0x40 => Some(c ^ 0x40),
+        _ => None,
+    }
+}
+
+/// Decodes `length` bytes, `None` if they didn't arrive yet.
+pub fn read_zdle_bytes(
+    reader: &mut ByteReader,
+    length: usize,
+    escaping: ZdleEscaping,
+) -> TermComResult<Option<Vec<u8>>> {
+    let mut data = Vec::with_capacity(length);
+    while data.len() < length {
+        let Some(c) = reader.read_u8() else {
+            return Ok(None);
+        };
         match c {
             ZDLE => {
-                let c2 = com.read_u8().await?;
-                match c2 {
-                    ZDLEE => data.push(ZDLE),
-                    ESC_0X10 => data.push(0x10),
-                    ESC_0X90 => data.push(0x90),
-                    ESC_0X11 => data.push(0x11),
-                    ESC_0X91 => data.push(0x91),
-                    ESC_0X13 => data.push(0x13),
-                    ESC_0X93 => data.push(0x93),
-                    ESC_0X0D => data.push(0x0D),
-                    ESC_0X8D => data.push(0x8D),
-                    ZRUB0 => data.push(0x7F),
-                    ZRUB1 => data.push(0xFF),
-
-                    _ => {
-                        Header::empty(HeaderType::Bin32, ZFrameType::Nak)
-                            .write(com)
-                            .await?;
-                        return Err(Box::new(TransmissionError::InvalidSubpacket(c2)));
-                    }
-                }
+                let Some(c2) = reader.read_u8() else {
+                    return Ok(None);
+                };
+                let Some(b) = decode_zdle_escape(c2) else {
+                    return Err(Box::new(TransmissionError::InvalidSubpacket(c2)));
+                };
+                data.push(b);
             }
-            0x11 | 0x91 | 0x13 | 0x93 => {
+            0x11 | 0x91 | 0x13 | 0x93 if escaping.strips_flow_control() => {
                 // println!("ignored byte");
             }
             _ => {
                 data.push(c);
             }
         }
-        if data.len() >= length {
-            return Ok(data);
-        }
     }
+    Ok(Some(data))
 }
 
 fn get_hex(n: u8) -> u8 {
@@ -167,50 +255,84 @@ fn from_hex(n: u8) -> TermComResult<u8> {
     Err(Box::new(TransmissionError::HexNumberExpected))
 }
 
-#[async_trait]
 impl Protocol for Zmodem {
-    async fn update(
+    fn update(
         &mut self,
-        com: &mut Box<dyn Com>,
+        input: &mut InputBuffer,
+        output: &mut OutputBuffer,
         transfer_state: Arc<Mutex<TransferState>>,
-    ) -> TermComResult<bool> {
+    ) -> TermComResult<Step> {
         if let Some(rz) = &mut self.rz {
-            rz.update(com, transfer_state.clone()).await?;
+            let step = match rz.update(input, output, transfer_state.clone()) {
+                Ok(step) => step,
+                Err(err) => {
+                    rz.save_partial_file()?;
+                    return Err(err);
+                }
+            };
             if !rz.is_active() {
                 transfer_state.lock().unwrap().is_finished = true;
-                return Ok(false);
+                return Ok(Step::Finished);
             }
+            Ok(step)
         } else if let Some(sz) = &mut self.sz {
-            sz.update(com, transfer_state.clone()).await?;
+            let step = sz.update(input, output, transfer_state.clone())?;
             if !sz.is_active() {
                 transfer_state.lock().unwrap().is_finished = true;
-                return Ok(false);
+                return Ok(Step::Finished);
             }
+            Ok(step)
+        } else {
+            Ok(Step::Finished)
         }
-        Ok(true)
     }
 
-    async fn initiate_send(
+    fn initiate_send(
         &mut self,
-        com: &mut Box<dyn Com>,
+        _output: &mut OutputBuffer,
         files: Vec<FileDescriptor>,
         transfer_state: Arc<Mutex<TransferState>>,
     ) -> TermComResult<()> {
-        transfer_state.lock().unwrap().protocol_name = self.get_name().to_string();
+        let (settings, window_size, resume, escaping) = {
+            let mut transfer_state = transfer_state.lock().unwrap();
+            transfer_state.protocol_name = self.get_name().to_string();
+            (
+                transfer_state.download_settings.clone(),
+                transfer_state.zmodem_window_size,
+                transfer_state.zmodem_resume,
+                self.get_escaping(&transfer_state),
+            )
+        };
         let mut sz = Sz::new(self.block_length);
-        sz.send(com, files);
+        sz.escaping = escaping;
+        // the remote receiver handles existing files the way we do
+        sz.management_option = settings.collision_policy.get_management_option();
+        sz.window_size = window_size;
+        sz.resume = resume;
+        sz.block_delay = self.block_delay;
+        sz.send(files);
         self.sz = Some(sz);
         Ok(())
     }
 
-    async fn initiate_recv(
+    fn initiate_recv(
         &mut self,
-        com: &mut Box<dyn Com>,
+        output: &mut OutputBuffer,
         transfer_state: Arc<Mutex<TransferState>>,
     ) -> TermComResult<()> {
-        transfer_state.lock().unwrap().protocol_name = self.get_name().to_string();
+        let (settings, escaping) = {
+            let mut transfer_state = transfer_state.lock().unwrap();
+            transfer_state.protocol_name = self.get_name().to_string();
+            (
+                transfer_state.download_settings.clone(),
+                self.get_escaping(&transfer_state),
+            )
+        };
         let mut rz = Rz::new(self.block_length);
-        rz.recv(com).await?;
+        rz.escaping = escaping;
+        rz.download_dir = settings.download_dir;
+        rz.collision_policy = settings.collision_policy;
+        rz.recv(output);
         self.rz = Some(rz);
         Ok(())
     }
@@ -225,8 +347,11 @@ impl Protocol for Zmodem {
         }
     }
 
-    async fn cancel(&mut self, com: &mut Box<dyn Com>) -> TermComResult<()> {
-        com.send(&ABORT_SEQ).await?;
+    fn cancel(&mut self, output: &mut OutputBuffer) -> TermComResult<()> {
+        if let Some(rz) = &mut self.rz {
+            rz.save_partial_file()?;
+        }
+        output.send(&ABORT_SEQ);
         Ok(())
     }
 }
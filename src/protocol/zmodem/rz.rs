@@ -1,22 +1,74 @@
 #![allow(clippy::unused_self, clippy::wildcard_imports)]
 use std::{
     cmp::Ordering,
-    fs,
+    fs, io,
+    path::PathBuf,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
-use directories::UserDirs;
 use icy_engine::{get_crc32, update_crc32};
 
 use crate::{
-    com::{Com, TermComResult},
+    com::TermComResult,
     protocol::{
-        str_from_null_terminated_utf8_unchecked, FileDescriptor, Header, HeaderType, TransferState,
-        ZFrameType, Zmodem, ZCRCE, ZCRCG, ZCRCW,
+        convert_to_local_newlines, get_default_download_dir, sanitize_file_name, set_file_metadata,
+        str_from_null_terminated_utf8_unchecked, ByteReader, CollisionPolicy, FileCollision,
+        FileDescriptor, Header, HeaderType, IncomingFile, InputBuffer, OutputBuffer, Step,
+        TransferState, ZFrameType, ZdleEscaping, Zmodem, ZCRCE, ZCRCG, ZCRCW,
     },
 };
 
-use super::{constants::*, error_mod::TransmissionError, read_zdle_bytes};
+use super::{
+    append_attn, constants::*, decode_zdle_escape, error_mod::TransmissionError, read_zdle_bytes,
+};
+
+/// Contents of the ZFILE subpacket:
+/// `name\0length mtime mode serial files_remaining bytes_remaining\0`.
+/// The length is decimal, modification time and mode are octal, all fields after the name are optional.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ZFileInfo {
+    pub file_name: String,
+    pub size: usize,
+    /// Seconds since 1970-01-01 UTC, 0 if unknown.
+    pub mtime: u64,
+    /// Unix file mode, 0 if unknown.
+    pub mode: u32,
+    pub files_remaining: usize,
+    pub bytes_remaining: usize,
+}
+
+impl ZFileInfo {
+    pub fn parse(block: &[u8]) -> Self {
+        let name_end = block.iter().position(|b| *b == 0).unwrap_or(block.len());
+        let info = block
+            .get(name_end + 1..)
+            .map(str_from_null_terminated_utf8_unchecked)
+            .unwrap_or_default();
+        let mut fields = info.split_ascii_whitespace();
+        let mut next_field = |radix| {
+            fields
+                .next()
+                .and_then(|f| u64::from_str_radix(f, radix).ok())
+                .unwrap_or(0)
+        };
+        let size = next_field(10) as usize;
+        let mtime = next_field(8);
+        let mode = next_field(8) as u32;
+        let _serial = next_field(8);
+        let files_remaining = next_field(10) as usize;
+        let bytes_remaining = next_field(10) as usize;
+
+        Self {
+            file_name: str_from_null_terminated_utf8_unchecked(&block[..name_end]),
+            size,
+            mtime,
+            mode,
+            files_remaining,
+            bytes_remaining,
+        }
+    }
+}
 
 #[derive(Debug)]
 pub enum RevcState {
@@ -26,6 +78,9 @@ pub enum RevcState {
     AwaitFileData,
     AwaitEOF,
     SendZRINIT,
+    AwaitCollisionAnswer,
+    /// Waits for the sender's CRC of the data that's already there (ZCRC).
+    AwaitCrc,
 }
 
 pub struct Rz {
@@ -35,8 +90,29 @@ pub struct Rz {
     retries: usize,
     can_count: usize,
     block_length: usize,
-    sender_flags: u8,
     use_crc32: bool,
+    /// Sent to interrupt the sender, set with ZSINIT.
+    attn: Vec<u8>,
+    /// How the sender escapes the data. `Control` is requested with ESCCTL,
+    /// the sender may ask for it with ZSINIT as well. `Minimal` is offered with
+    /// CANDZAP and only used once the sender announced it with ZSINIT.
+    pub escaping: ZdleEscaping,
+    direct_zap: bool,
+
+    pub download_dir: Option<PathBuf>,
+    pub collision_policy: CollisionPolicy,
+    /// Data of the current file goes straight to disk.
+    incoming: Option<IncomingFile>,
+    overwrite: bool,
+    skipped_file: Option<String>,
+    /// ZCNL - the sender wants text line endings converted.
+    convert_newlines: bool,
+    cur_file_mode: u32,
+
+    batch_files_done: usize,
+    batch_bytes_done: usize,
+    batch_files_total: usize,
+    batch_bytes_total: usize,
 }
 
 impl Rz {
@@ -48,8 +124,21 @@ impl Rz {
             retries: 0,
             can_count: 0,
             errors: 0,
-            sender_flags: 0,
             use_crc32: false,
+            attn: Vec::new(),
+            escaping: ZdleEscaping::default(),
+            direct_zap: false,
+            download_dir: get_default_download_dir(),
+            collision_policy: CollisionPolicy::default(),
+            incoming: None,
+            overwrite: false,
+            skipped_file: None,
+            convert_newlines: false,
+            cur_file_mode: 0,
+            batch_files_done: 0,
+            batch_bytes_done: 0,
+            batch_files_total: 0,
+            batch_bytes_total: 0,
         }
     }
 
@@ -57,29 +146,40 @@ impl Rz {
         !matches!(self.state, RevcState::Idle)
     }
 
+    pub fn get_escaping(&self) -> ZdleEscaping {
+        if self.escaping == ZdleEscaping::Minimal && !self.direct_zap {
+            ZdleEscaping::Standard
+        } else {
+            self.escaping
+        }
+    }
+
     fn get_header_type(&self) -> HeaderType {
-        // does it make sense to value the flags from ZSINIT here?
         // Hex seems to be understood by all implementations and can be read by a human.
         // The receiver doesn't send large files so binary headers don't make much sense for the subpackets.
+        // Hex headers are 7 bit and don't contain control characters so they're fine for TESCCTL/TESC8 links.
         HeaderType::Hex
     }
 
-    async fn cancel(&mut self, com: &mut Box<dyn Com>) -> TermComResult<()> {
+    fn cancel(&mut self, output: &mut OutputBuffer) -> TermComResult<()> {
         self.state = RevcState::Idle;
-        Zmodem::cancel(com).await
+        self.save_partial_file()?;
+        Zmodem::cancel(output);
+        Ok(())
     }
 
-    pub async fn update(
+    pub fn update(
         &mut self,
-        com: &mut Box<dyn Com>,
+        input: &mut InputBuffer,
+        output: &mut OutputBuffer,
         transfer_state: Arc<Mutex<TransferState>>,
-    ) -> TermComResult<()> {
+    ) -> TermComResult<Step> {
         if let RevcState::Idle = self.state {
-            return Ok(());
+            return Ok(Step::Finished);
         }
         if self.retries > 5 {
-            self.cancel(com).await?;
-            return Ok(());
+            self.cancel(output)?;
+            return Ok(Step::Finished);
         }
         if let Ok(transfer_state) = &mut transfer_state.lock() {
             let transfer_info = &mut transfer_state.recieve_state;
@@ -89,340 +189,588 @@ impl Rz {
                 let fd = &self.files[cur_file];
                 transfer_info.file_name = fd.file_name.clone();
                 transfer_info.file_size = fd.size;
-                transfer_info.bytes_transfered = fd.data.as_ref().unwrap().len();
+                transfer_info.bytes_transfered = self.get_cur_file_len();
+                transfer_info.batch_bytes_transfered =
+                    self.batch_bytes_done + self.get_cur_file_len().min(fd.size);
             }
+            transfer_info.total_files = self.batch_files_total;
+            transfer_info.batch_files_transfered = self.batch_files_done;
+            transfer_info.total_bytes = self.batch_bytes_total;
             transfer_info.errors = self.errors;
             transfer_info.check_size = "Crc32".to_string();
-            transfer_info.update_bps();
+            transfer_info.update_statistics();
         }
 
         match self.state {
-            RevcState::SendZRINIT => {
-                if self.read_header(com).await? {
-                    return Ok(());
+            RevcState::AwaitCollisionAnswer => {
+                let answer = {
+                    let mut transfer_state = transfer_state.lock().unwrap();
+                    if transfer_state.collision.is_none() {
+                        transfer_state.collision = Some(FileCollision {
+                            file_name: self
+                                .files
+                                .last()
+                                .map_or_else(String::new, |fd| fd.file_name.clone()),
+                            answer: None,
+                        });
+                    }
+                    let answer = transfer_state.collision.as_ref().and_then(|c| c.answer);
+                    if answer.is_some() {
+                        transfer_state.collision = None;
+                    }
+                    answer
+                };
+                match answer {
+                    Some(CollisionPolicy::Rename) => self.start_file(output, false)?,
+                    Some(CollisionPolicy::Overwrite) => self.start_file(output, true)?,
+                    Some(CollisionPolicy::Skip | CollisionPolicy::Ask) => {
+                        self.skip_file(output);
+                    }
+                    None => return Ok(Step::Wait(Duration::from_millis(50))),
                 }
-                /*  let now = SystemTime::now();
-                 if now.duration_since(self.last_send).unwrap().as_millis() > 3000 {
-                    self.send_zrinit(com).await?;
-                    self.retries += 1;
-                    self.last_send = SystemTime::now();
-                }*/
-            }
-            RevcState::AwaitZDATA => {
-                /*  let now = SystemTime::now();
-                if now.duration_since(self.last_send).unwrap().as_millis() > 3000 {
-                    self.request_zpos(com).await?;
-                    self.retries += 1;
-                    self.last_send = SystemTime::now();
-                }*/
-                self.read_header(com).await?;
+                Ok(Step::Continue)
             }
             RevcState::AwaitFileData => {
-                let pck = read_subpacket(com, self.block_length, self.use_crc32).await;
-                let last = self.files.len() - 1;
-                if let Ok((block, is_last, expect_ack)) = pck {
-                    if expect_ack {
-                        Header::empty(self.get_header_type(), ZFrameType::Ack)
-                            .write(com)
-                            .await?;
-                    }
-                    if let Some(fd) = self.files.get_mut(last) {
-                        if let Some(data) = &mut fd.data {
-                            data.extend_from_slice(&block);
+                let mut reader = ByteReader::new(input.as_slice());
+                let pck = read_subpacket(
+                    &mut reader,
+                    self.block_length,
+                    self.use_crc32,
+                    self.get_escaping(),
+                );
+                let consumed = reader.pos();
+                match pck {
+                    Ok(None) => return Ok(Step::NeedInput),
+                    Ok(Some((block, is_last, expect_ack))) => {
+                        input.consume(consumed);
+                        if let Some(incoming) = &mut self.incoming {
+                            incoming.write_all(&block)?;
+                        }
+                        if expect_ack {
+                            let pos = self.get_zmodem_pos(output, self.get_cur_file_len())?;
+                            Header::from_number(self.get_header_type(), ZFrameType::Ack, pos)
+                                .write(output);
+                        }
+                        if is_last {
+                            self.state = RevcState::AwaitEOF;
                         }
                     }
-                    if is_last {
-                        self.state = RevcState::AwaitEOF;
-                    }
-                } else {
-                    self.errors += 1;
-                    //transfer_info.write(err.to_string());
-
-                    if let Some(fd) = self.files.get(last) {
-                        Header::from_number(
-                            self.get_header_type(),
-                            ZFrameType::RPos,
-                            u32::try_from(fd.data.as_ref().unwrap().len()).unwrap(),
-                        )
-                        .write(com)
-                        .await?;
+                    Err(_) => {
+                        input.consume(consumed);
+                        self.errors += 1;
+                        //transfer_info.write(err.to_string());
+
+                        // the sender is still streaming, interrupt it before asking for the data again
+                        append_attn(output, &self.attn);
+                        let pos = self.get_cur_file_len();
+                        self.request_zpos(output, pos)?;
                         self.state = RevcState::AwaitZDATA;
                     }
-                    return Ok(());
                 }
+                Ok(Step::Continue)
             }
-            _ => {
-                self.read_header(com).await?;
-            }
+            _ => self.read_header(input, output),
         }
-        Ok(())
     }
 
-    async fn request_zpos(&mut self, com: &mut Box<dyn Com>) -> TermComResult<usize> {
-        Header::from_number(self.get_header_type(), ZFrameType::RPos, 0)
-            .write(com)
-            .await
+    fn get_cur_file_len(&self) -> usize {
+        self.incoming.as_ref().map_or(0, IncomingFile::len)
     }
 
-    async fn read_header(&mut self, com: &mut Box<dyn Com>) -> TermComResult<bool> {
-        let result = Header::read(com, &mut self.can_count).await;
-        if result.is_err() {
-            if self.can_count >= 5 {
-                //transfer_state.write("Received cancel...".to_string());
-                self.cancel(com).await?;
-                self.cancel(com).await?;
-                self.cancel(com).await?;
-                self.state = RevcState::Idle;
-                return Ok(false);
-            }
-            //transfer_state.write(format!("{}", err));
-            self.errors += 1;
-            return Ok(false);
+    /// Positions beyond 4 GiB can't be sent, the transfer gets canceled.
+    fn get_zmodem_pos(&mut self, output: &mut OutputBuffer, pos: usize) -> TermComResult<u32> {
+        if let Ok(pos) = u32::try_from(pos) {
+            return Ok(pos);
         }
-        self.can_count = 0;
-        let res = result?;
-        if let Some(res) = res {
-            // println!("\t\t\t\t\t\tRECV header {}", res);
-            self.use_crc32 = res.header_type == HeaderType::Bin32;
-            match res.frame_type {
-                ZFrameType::Sinit => {
-                    let pck = read_subpacket(com, self.block_length, self.use_crc32).await;
-                    if pck.is_err() {
-                        Header::empty(self.get_header_type(), ZFrameType::Nak)
-                            .write(com)
-                            .await?;
-                        return Ok(false);
-                    }
-                    // TODO: Atn sequence
-                    self.sender_flags = res.f0();
-                    Header::empty(self.get_header_type(), ZFrameType::Ack)
-                        .write(com)
-                        .await?;
-                    return Ok(true);
-                }
+        self.cancel(output)?;
+        Err(Box::new(TransmissionError::FileTooLarge(pos)))
+    }
+
+    fn request_zpos(&mut self, output: &mut OutputBuffer, pos: usize) -> TermComResult<()> {
+        let pos = self.get_zmodem_pos(output, pos)?;
+        Header::from_number(self.get_header_type(), ZFrameType::RPos, pos).write(output);
+        Ok(())
+    }
 
-                ZFrameType::RQInit => {
-                    self.state = RevcState::SendZRINIT;
-                    return Ok(true);
+    fn read_header(
+        &mut self,
+        input: &mut InputBuffer,
+        output: &mut OutputBuffer,
+    ) -> TermComResult<Step> {
+        let mut reader = ByteReader::new(input.as_slice());
+        let res = match Header::read(&mut reader, &mut self.can_count) {
+            Ok(Some(res)) => res,
+            Ok(None) => return Ok(Step::NeedInput),
+            Err(_) => {
+                let consumed = reader.pos();
+                input.consume(consumed);
+                if self.can_count >= 5 {
+                    //transfer_state.write("Received cancel...".to_string());
+                    self.cancel(output)?;
+                    self.cancel(output)?;
+                    self.cancel(output)?;
+                    self.state = RevcState::Idle;
+                    return Ok(Step::Continue);
                 }
-                ZFrameType::File => {
-                    let pck = read_subpacket(com, self.block_length, self.use_crc32).await;
-
-                    if let Ok((block, _, _)) = pck {
-                        let file_name = str_from_null_terminated_utf8_unchecked(&block).to_string();
-                        if self.files.is_empty()
-                            || self.files.last().unwrap().file_name != file_name
-                        {
-                            let mut fd = FileDescriptor::new();
-                            fd.data = Some(Vec::new());
-                            fd.file_name = file_name;
-                            //transfer_state.write(format!("Got file header for '{}'", fd.file_name));
-                            let mut file_size = 0;
-                            for b in &block[(fd.file_name.len() + 1)..] {
-                                if *b < b'0' || *b > b'9' {
-                                    break;
-                                }
-                                file_size = file_size * 10 + (*b - b'0') as usize;
-                            }
-                            fd.size = file_size;
-                            self.files.push(fd);
-                        }
+                //transfer_state.write(format!("{}", err));
+                self.errors += 1;
+                return Ok(Step::Continue);
+            }
+        };
+        self.can_count = 0;
+        // println!("\t\t\t\t\t\tRECV header {}", res);
+        self.use_crc32 = res.header_type == HeaderType::Bin32;
 
-                        self.state = RevcState::AwaitZDATA;
-                        self.request_zpos(com).await?;
+        // the subpacket following the header has to be complete as well
+        let pck = if matches!(
+            res.frame_type,
+            ZFrameType::Sinit | ZFrameType::File | ZFrameType::Command
+        ) {
+            match read_subpacket(
+                &mut reader,
+                self.block_length,
+                self.use_crc32,
+                self.get_escaping(),
+            ) {
+                Ok(None) => return Ok(Step::NeedInput),
+                Ok(Some((block, _, _))) => Ok(block),
+                Err(err) => Err(err),
+            }
+        } else {
+            Ok(Vec::new())
+        };
+        let consumed = reader.pos();
+        input.consume(consumed);
 
-                        return Ok(true);
-                    }
+        match res.frame_type {
+            ZFrameType::Sinit => {
+                let Ok(block) = pck else {
+                    Header::empty(self.get_header_type(), ZFrameType::Nak).write(output);
+                    return Ok(Step::Continue);
+                };
+                self.attn = block
+                    .iter()
+                    .take_while(|b| **b != 0)
+                    .take(ZATTNLEN)
+                    .copied()
+                    .collect();
+                if res.f0() & zsinit_flag::TESCCTL != 0 {
+                    self.escaping = ZdleEscaping::Control;
+                } else if res.f0() & zsinit_flag::TDZAP != 0
+                    && self.escaping == ZdleEscaping::Minimal
+                {
+                    self.direct_zap = true;
+                }
+                Header::empty(self.get_header_type(), ZFrameType::Ack).write(output);
+            }
+
+            ZFrameType::RQInit => {
+                self.state = RevcState::SendZRINIT;
+            }
+            ZFrameType::File => {
+                let Ok(block) = pck else {
                     self.errors += 1;
                     //transfer_state.write(format!("Got no ZFILE subpacket: {}", err));
-                    return Ok(false);
+                    return Ok(Step::Continue);
+                };
+                let info = ZFileInfo::parse(&block);
+                let file_name = sanitize_file_name(&info.file_name);
+                if self.skipped_file.as_ref() == Some(&file_name) {
+                    // the sender didn't get the ZSKIP
+                    self.send_zskip(output);
+                    return Ok(Step::Continue);
                 }
-                ZFrameType::Data => {
-                    let offset = res.number();
-                    if self.files.is_empty() {
-                        self.cancel(com).await?;
-                        return Err(Box::new(TransmissionError::ZDataBeforeZFILE));
-                    }
-                    let header_type = self.get_header_type();
-                    let last = self.files.len() - 1;
-                    if let Some(fd) = self.files.get_mut(last) {
-                        if let Some(data) = &mut fd.data {
-                            match data.len().cmp(&(offset as usize)) {
-                                Ordering::Greater => data.resize(offset as usize, 0),
-                                Ordering::Less => {
-                                    Header::from_number(
-                                        header_type,
-                                        ZFrameType::RPos,
-                                        data.len() as u32,
-                                    )
-                                    .write(com)
-                                    .await?;
-                                    return Ok(false);
-                                }
-                                Ordering::Equal => {}
-                            }
-                            self.state = RevcState::AwaitFileData;
+                if self.incoming.is_none() || self.files.last().unwrap().file_name != file_name {
+                    self.state = RevcState::Await;
+                    let mut fd = FileDescriptor::new();
+                    fd.file_name = file_name;
+                    //transfer_state.write(format!("Got file header for '{}'", fd.file_name));
+                    fd.size = info.size;
+                    fd.date = info.mtime;
+                    self.cur_file_mode = info.mode;
+                    self.convert_newlines = res.f0() == zfile_flag::ZCNL;
+                    self.update_batch_totals(&info);
+                    self.save_partial_file()?;
+                    self.skipped_file = None;
+
+                    let policy = self.get_collision_policy(res.f1());
+                    self.overwrite = policy == CollisionPolicy::Overwrite;
+                    let incoming = self.open_incoming_file(&fd, res.f0(), policy)?;
+                    self.files.push(fd);
+                    match incoming {
+                        Some(incoming) => self.incoming = Some(incoming),
+                        None if policy == CollisionPolicy::Ask => {
+                            self.state = RevcState::AwaitCollisionAnswer;
+                            return Ok(Step::Continue);
+                        }
+                        None => {
+                            self.skip_file(output);
+                            return Ok(Step::Continue);
                         }
                     }
-                    return Ok(true);
                 }
-                ZFrameType::Eof => {
-                    self.send_zrinit(com).await?;
-                    self.save_last_file()?;
-                    //transfer_state.write("Got eof".to_string());
-                    self.state = RevcState::SendZRINIT;
-                    return Ok(true);
+
+                if matches!(self.state, RevcState::AwaitCrc) {
+                    // the sender didn't answer the ZCRC and sent ZFILE again
+                    self.restart_file()?;
+                } else if self
+                    .incoming
+                    .as_ref()
+                    .map_or(false, |incoming| !incoming.is_empty())
+                {
+                    if let Ok(len) = u32::try_from(self.get_cur_file_len()) {
+                        Header::from_number(self.get_header_type(), ZFrameType::Crc, len)
+                            .write(output);
+                        self.state = RevcState::AwaitCrc;
+                        return Ok(Step::Continue);
+                    }
+                    self.restart_file()?;
                 }
-                ZFrameType::Fin => {
-                    Header::empty(self.get_header_type(), ZFrameType::Fin)
-                        .write(com)
-                        .await?;
-                    //transfer_state.write("Transfer finished.".to_string());
-                    self.state = RevcState::Idle;
-                    return Ok(true);
+
+                self.state = RevcState::AwaitZDATA;
+                let pos = self.get_cur_file_len();
+                self.request_zpos(output, pos)?;
+            }
+            ZFrameType::Crc => {
+                if !matches!(self.state, RevcState::AwaitCrc) {
+                    return Ok(Step::Continue);
                 }
-                ZFrameType::Challenge => {
-                    // isn't specfied for receiver side.
-                    Header::from_number(self.get_header_type(), ZFrameType::Ack, res.number())
-                        .write(com)
-                        .await?;
+                let is_same_file = match &mut self.incoming {
+                    Some(incoming) => incoming.get_crc32()? == res.number(),
+                    None => false,
+                };
+                if !is_same_file {
+                    self.restart_file()?;
                 }
-                ZFrameType::FreeCnt => {
-                    // 0 means unlimited space but sending free hd space to an unknown source is a security issue
-                    Header::from_number(self.get_header_type(), ZFrameType::Ack, 0)
-                        .write(com)
-                        .await?;
+                self.state = RevcState::AwaitZDATA;
+                let pos = self.get_cur_file_len();
+                self.request_zpos(output, pos)?;
+            }
+            ZFrameType::Data => {
+                let offset = res.number();
+                if self.files.is_empty() {
+                    self.cancel(output)?;
+                    return Err(Box::new(TransmissionError::ZDataBeforeZFILE));
                 }
-                ZFrameType::Command => {
-                    // just protocol it.
-                    let package = read_subpacket(com, self.block_length, self.use_crc32).await;
-                    if let Ok((block, _, _)) = &package {
-                        let cmd = str_from_null_terminated_utf8_unchecked(block);
-                        eprintln!(
-                            "Remote wanted to execute {cmd} on the system. (did not execute)"
-                        );
+                if let Some(incoming) = &mut self.incoming {
+                    match incoming.len().cmp(&(offset as usize)) {
+                        Ordering::Greater => incoming.set_len(offset as usize)?,
+                        Ordering::Less => {
+                            let pos = incoming.len();
+                            self.request_zpos(output, pos)?;
+                            return Ok(Step::Continue);
+                        }
+                        Ordering::Equal => {}
                     }
-                    Header::from_number(self.get_header_type(), ZFrameType::Compl, 0)
-                        .write(com)
-                        .await?;
+                    self.state = RevcState::AwaitFileData;
                 }
-                ZFrameType::Abort | ZFrameType::FErr | ZFrameType::Can => {
-                    Header::empty(self.get_header_type(), ZFrameType::Fin)
-                        .write(com)
-                        .await?;
-                    self.state = RevcState::Idle;
+            }
+            ZFrameType::Eof => {
+                let pos = self.get_cur_file_len();
+                if self.incoming.is_some() && res.number() as usize != pos {
+                    // data got lost, the sender didn't notice yet
+                    self.request_zpos(output, pos)?;
+                    self.state = RevcState::AwaitZDATA;
+                    return Ok(Step::Continue);
                 }
-                unk_frame => {
-                    return Err(Box::new(TransmissionError::UnsupportedFrame(unk_frame)));
+                self.send_zrinit(output);
+                self.save_last_file()?;
+                //transfer_state.write("Got eof".to_string());
+                self.state = RevcState::SendZRINIT;
+            }
+            ZFrameType::Fin => {
+                Header::empty(self.get_header_type(), ZFrameType::Fin).write(output);
+                //transfer_state.write("Transfer finished.".to_string());
+                self.state = RevcState::Idle;
+            }
+            ZFrameType::Challenge => {
+                // isn't specfied for receiver side.
+                Header::from_number(self.get_header_type(), ZFrameType::Ack, res.number())
+                    .write(output);
+            }
+            ZFrameType::FreeCnt => {
+                // 0 means unlimited space but sending free hd space to an unknown source is a security issue
+                Header::from_number(self.get_header_type(), ZFrameType::Ack, 0).write(output);
+            }
+            ZFrameType::Command => {
+                // just protocol it.
+                if let Ok(block) = &pck {
+                    let cmd = str_from_null_terminated_utf8_unchecked(block);
+                    eprintln!("Remote wanted to execute {cmd} on the system. (did not execute)");
                 }
+                Header::from_number(self.get_header_type(), ZFrameType::Compl, 0).write(output);
+            }
+            ZFrameType::Abort | ZFrameType::FErr | ZFrameType::Can => {
+                Header::empty(self.get_header_type(), ZFrameType::Fin).write(output);
+                self.state = RevcState::Idle;
+            }
+            unk_frame => {
+                return Err(Box::new(TransmissionError::UnsupportedFrame(unk_frame)));
             }
         }
-        Ok(false)
+        Ok(Step::Continue)
     }
 
-    fn save_last_file(&mut self) -> TermComResult<()> {
-        if !self.files.is_empty() {
-            let fd = self.files.last().unwrap();
+    /// The remaining files/bytes in ZFILE include the current file.
+    fn update_batch_totals(&mut self, info: &ZFileInfo) {
+        self.batch_files_total = self.batch_files_done + info.files_remaining.max(1);
+        self.batch_bytes_total = self.batch_bytes_done + info.bytes_remaining.max(info.size);
+    }
+
+    fn finish_batch_file(&mut self, size: usize) {
+        self.batch_files_done += 1;
+        self.batch_bytes_done += size;
+    }
+
+    /// The sender may ask to protect existing files (ZMPROT) but it can't force overwriting them.
+    fn get_collision_policy(&self, f1: u8) -> CollisionPolicy {
+        if CollisionPolicy::from_management_option(f1) == Some(CollisionPolicy::Skip) {
+            CollisionPolicy::Skip
+        } else {
+            self.collision_policy
+        }
+    }
+
+    fn get_download_dir(&self) -> TermComResult<&PathBuf> {
+        match &self.download_dir {
+            Some(dir) => Ok(dir),
+            None => Err(Box::new(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no download directory",
+            ))),
+        }
+    }
 
-            if let Some(user_dirs) = UserDirs::new() {
-                let dir = user_dirs.download_dir().unwrap();
+    /// Opens the file the data of `fd` is written to. A partial file left by icy_term
+    /// or an existing file is only continued if the sender requests crash recovery and
+    /// the CRC of the data matches, see `RevcState::AwaitCrc`.
+    /// Returns `None` if the file already exists and must not be touched.
+    fn open_incoming_file(
+        &self,
+        fd: &FileDescriptor,
+        f0: u8,
+        policy: CollisionPolicy,
+    ) -> TermComResult<Option<IncomingFile>> {
+        let dir = self.get_download_dir()?;
+        if IncomingFile::get_partial_path(dir, &fd.file_name).exists() {
+            let mut incoming = IncomingFile::open(dir, &fd.file_name)?;
+            if f0 != zfile_flag::ZCRESUM || incoming.len() > fd.size {
+                incoming.set_len(0)?;
+            }
+            return Ok(Some(incoming));
+        }
 
-                let mut file_name = dir.join(&fd.file_name);
-                let mut i = 1;
-                while file_name.exists() {
-                    file_name = dir.join(&format!("{}.{}", fd.file_name, i));
-                    i += 1;
+        let file_name = dir.join(&fd.file_name);
+        if !file_name.exists() {
+            return Ok(Some(IncomingFile::open(dir, &fd.file_name)?));
+        }
+        match policy {
+            CollisionPolicy::Rename | CollisionPolicy::Overwrite => {
+                if f0 == zfile_flag::ZCRESUM && fs::metadata(&file_name)?.len() <= fd.size as u64 {
+                    return Ok(Some(IncomingFile::append_to(file_name)?));
                 }
-                fs::write(file_name, fd.data.as_ref().unwrap())?;
+                Ok(Some(IncomingFile::open(dir, &fd.file_name)?))
             }
+            CollisionPolicy::Skip | CollisionPolicy::Ask => Ok(None),
+        }
+    }
+
+    /// The data that's there isn't the start of the file that's sent. An existing file
+    /// is left alone and the collision policy decides, a partial file starts over.
+    fn restart_file(&mut self) -> TermComResult<()> {
+        let is_target = match &self.incoming {
+            Some(incoming) => incoming.is_target(),
+            None => return Ok(()),
+        };
+        if is_target {
+            let Some(fd) = self.files.last() else {
+                return Ok(());
+            };
+            let mut incoming = IncomingFile::open(self.get_download_dir()?, &fd.file_name)?;
+            incoming.set_len(0)?;
+            self.incoming = Some(incoming);
+        } else if let Some(incoming) = &mut self.incoming {
+            incoming.set_len(0)?;
         }
+        Ok(())
+    }
 
+    /// Starts receiving the current file after the user decided what to do with the existing one.
+    fn start_file(&mut self, output: &mut OutputBuffer, overwrite: bool) -> TermComResult<()> {
+        let Some(fd) = self.files.last() else {
+            return Ok(());
+        };
+        let incoming = IncomingFile::open(self.get_download_dir()?, &fd.file_name)?;
+        let pos = incoming.len();
+        self.incoming = Some(incoming);
+        self.overwrite = overwrite;
+        self.state = RevcState::AwaitZDATA;
+        self.request_zpos(output, pos)?;
         Ok(())
     }
 
-    pub async fn recv(&mut self, com: &mut Box<dyn Com>) -> TermComResult<()> {
+    /// Rejects the current file, it won't show up in the received files.
+    fn skip_file(&mut self, output: &mut OutputBuffer) {
+        if let Some(fd) = self.files.pop() {
+            self.finish_batch_file(fd.size);
+            self.skipped_file = Some(fd.file_name);
+        }
+        self.send_zskip(output);
+    }
+
+    fn send_zskip(&mut self, output: &mut OutputBuffer) {
+        Header::empty(self.get_header_type(), ZFrameType::Skip).write(output);
         self.state = RevcState::Await;
-        self.retries = 0;
-        self.send_zrinit(com).await?;
+    }
+
+    /// Keeps the data of an unfinished download so it can be resumed later.
+    pub fn save_partial_file(&mut self) -> TermComResult<()> {
+        if let Some(incoming) = self.incoming.take() {
+            incoming.suspend()?;
+        }
         Ok(())
     }
 
-    pub async fn send_zrinit(&mut self, com: &mut Box<dyn Com>) -> TermComResult<()> {
-        Header::from_flags(self.get_header_type(), ZFrameType::RIinit, 0, 0, 0, 0x23)
-            .write(com)
-            .await?;
+    fn save_last_file(&mut self) -> TermComResult<()> {
+        let (Some(dir), Some(incoming), Some(fd)) = (
+            &self.download_dir,
+            self.incoming.take(),
+            self.files.last_mut(),
+        ) else {
+            return Ok(());
+        };
+        let path = incoming.finish(dir, &fd.file_name, self.overwrite)?;
+        if self.convert_newlines {
+            convert_to_local_newlines(&path)?;
+        }
+        if let Err(err) = set_file_metadata(&path, fd.date, self.cur_file_mode) {
+            eprintln!("error setting file metadata of {}: {err}", path.display());
+        }
+        fd.path_name = path.to_string_lossy().to_string();
+        fd.set_path(path);
+        let size = fd.size;
+        self.finish_batch_file(size);
         Ok(())
     }
+
+    pub fn recv(&mut self, output: &mut OutputBuffer) {
+        self.state = RevcState::Await;
+        self.retries = 0;
+        self.send_zrinit(output);
+    }
+
+    pub fn send_zrinit(&mut self, output: &mut OutputBuffer) {
+        let mut flags = zrinit_flag::CANFDX | zrinit_flag::CANOVIO | zrinit_flag::CANFC32;
+        if self.escaping == ZdleEscaping::Control {
+            flags |= zrinit_flag::ESCCTL;
+        }
+        let f1 = if self.escaping == ZdleEscaping::Minimal {
+            zrinit_flag::CANDZAP
+        } else {
+            0
+        };
+        Header::from_flags(self.get_header_type(), ZFrameType::RIinit, 0, 0, f1, flags)
+            .write(output);
+    }
 }
 
-pub async fn read_subpacket(
-    com: &mut Box<dyn Com>,
+/// Reads a data subpacket: the data, whether the frame ends and whether the sender
+/// waits for a ZACK. `None` if the subpacket didn't arrive completely yet.
+pub fn read_subpacket(
+    reader: &mut ByteReader,
     block_length: usize,
     use_crc32: bool,
-) -> TermComResult<(Vec<u8>, bool, bool)> {
+    escaping: ZdleEscaping,
+) -> TermComResult<Option<(Vec<u8>, bool, bool)>> {
+    if !has_subpacket_end(reader.remaining()) {
+        let max_len = get_max_subpacket_len(block_length);
+        if reader.remaining().len() > max_len {
+            // the end got lost, drop what can't belong to this subpacket anymore
+            reader.skip(max_len);
+            return Err(Box::new(TransmissionError::SubpacketTooLong(max_len)));
+        }
+        // don't decode the same data again and again while it's coming in
+        return Ok(None);
+    }
     let mut data = Vec::with_capacity(block_length);
     loop {
-        let c = com.read_u8().await?;
+        if data.len() > block_length {
+            // the end got lost and the one of a following subpacket was found
+            return Err(Box::new(TransmissionError::SubpacketTooLong(reader.pos())));
+        }
+        let Some(c) = reader.read_u8() else {
+            return Ok(None);
+        };
         match c {
             ZDLE => {
-                let c2 = com.read_u8().await?;
-                match c2 {
-                    ZDLEE => data.push(ZDLE),
-                    ESC_0X10 => data.push(0x10),
-                    ESC_0X90 => data.push(0x90),
-                    ESC_0X11 => data.push(0x11),
-                    ESC_0X91 => data.push(0x91),
-                    ESC_0X13 => data.push(0x13),
-                    ESC_0X93 => data.push(0x93),
-                    ESC_0X0D => data.push(0x0D),
-                    ESC_0X8D => data.push(0x8D),
-                    ZRUB0 => data.push(0x7F),
-                    ZRUB1 => data.push(0xFF),
-
-                    ZCRCE => {
-                        // CRC next, frame ends, header packet follows
-                        check_crc(com, use_crc32, &data, c2).await?;
-                        return Ok((data, true, false));
-                    }
-                    ZCRCG => {
-                        // CRC next, frame continues nonstop
-                        check_crc(com, use_crc32, &data, c2).await?;
-                        return Ok((data, false, false));
-                    }
-                    ZCRCQ => {
-                        // CRC next, frame continues, ZACK expected
-                        check_crc(com, use_crc32, &data, c2).await?;
-                        return Ok((data, false, true));
-                    }
-                    ZCRCW => {
-                        // CRC next, ZACK expected, end of frame
-                        check_crc(com, use_crc32, &data, c2).await?;
-                        return Ok((data, true, true));
-                    }
+                let Some(c2) = reader.read_u8() else {
+                    return Ok(None);
+                };
+                let (is_last, expect_ack) = match c2 {
+                    // CRC next, frame ends, header packet follows
+                    ZCRCE => (true, false),
+                    // CRC next, frame continues nonstop
+                    ZCRCG => (false, false),
+                    // CRC next, frame continues, ZACK expected
+                    ZCRCQ => (false, true),
+                    // CRC next, ZACK expected, end of frame
+                    ZCRCW => (true, true),
                     _ => {
-                        return Err(Box::new(TransmissionError::InvalidSubpacket(c2)));
+                        let Some(b) = decode_zdle_escape(c2) else {
+                            return Err(Box::new(TransmissionError::InvalidSubpacket(c2)));
+                        };
+                        data.push(b);
+                        continue;
                     }
+                };
+                if !check_crc(reader, use_crc32, escaping, &data, c2)? {
+                    return Ok(None);
                 }
+                return Ok(Some((data, is_last, expect_ack)));
             }
-            0x11 | 0x91 | 0x13 | 0x93 => {
+            0x11 | 0x91 | 0x13 | 0x93 if escaping.strips_flow_control() => {
                 // they should be ignored, not errored according to spec
-                eprintln!("ignored byte");
             }
             _ => data.push(c),
         }
     }
 }
 
-async fn check_crc(
-    com: &mut Box<dyn Com>,
+/// Every byte may be escaped, the ZCRC byte and a CRC32 follow the data.
+fn get_max_subpacket_len(block_length: usize) -> usize {
+    2 * (block_length + 1 + 4)
+}
+
+/// A ZDLE followed by one of the ZCRC bytes ends a subpacket, an invalid escape sequence
+/// ends it as well. ZDLE itself is always escaped so a ZDLE always starts an escape sequence.
+fn has_subpacket_end(data: &[u8]) -> bool {
+    let mut i = 0;
+    while let Some(pos) = data[i..].iter().position(|b| *b == ZDLE) {
+        let Some(c) = data.get(i + pos + 1) else {
+            return false;
+        };
+        if matches!(*c, ZCRCE | ZCRCG | ZCRCQ | ZCRCW) || decode_zdle_escape(*c).is_none() {
+            return true;
+        }
+        i += pos + 2;
+    }
+    false
+}
+
+/// Ok(false) if the CRC didn't arrive yet.
+fn check_crc(
+    reader: &mut ByteReader,
     use_crc32: bool,
+    escaping: ZdleEscaping,
     data: &[u8],
     zcrc_byte: u8,
 ) -> TermComResult<bool> {
     if use_crc32 {
         let mut crc = get_crc32(data);
         crc = !update_crc32(!crc, zcrc_byte);
-        let crc_bytes = read_zdle_bytes(com, 4).await?;
+        let Some(crc_bytes) = read_zdle_bytes(reader, 4, escaping)? else {
+            return Ok(false);
+        };
         let check_crc = u32::from_le_bytes(crc_bytes.try_into().unwrap());
         if crc == check_crc {
             Ok(true)
@@ -431,7 +779,9 @@ async fn check_crc(
         }
     } else {
         let crc = icy_engine::get_crc16_buggy(data, zcrc_byte);
-        let crc_bytes = read_zdle_bytes(com, 2).await?;
+        let Some(crc_bytes) = read_zdle_bytes(reader, 2, escaping)? else {
+            return Ok(false);
+        };
         let check_crc = u16::from_le_bytes(crc_bytes.try_into().unwrap());
         if crc == check_crc {
             Ok(true)
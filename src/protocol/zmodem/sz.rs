@@ -2,19 +2,35 @@
 
 use std::{
     cmp::min,
+    io::{Read, Seek, SeekFrom},
     sync::{Arc, Mutex},
     time::Duration,
 };
 
 use crate::{
-    com::{Com, TermComResult},
+    com::TermComResult,
     protocol::{
-        zfile_flag, zmodem::error_mod::TransmissionError, FileDescriptor, Header, HeaderType,
-        TransferState, ZFrameType, Zmodem, ZCRCE, ZCRCG,
+        zfile_flag, zmodem::error_mod::TransmissionError, ByteReader, FileDescriptor, Header,
+        HeaderType, InputBuffer, OutputBuffer, ReadSeek, Step, TransferState, ZFrameType, Zmodem,
+        ZCRCE, ZCRCG,
     },
 };
 
-use super::{ZCRCQ, ZCRCW};
+use super::{get_file_crc32, zrinit_flag, zsinit_flag, ZdleEscaping, ZCRCQ, ZCRCW};
+
+/// Blocks don't shrink below this size after errors.
+const MIN_BLOCK_LEN: usize = 256;
+/// The ZedZap block size, plain ZModem only uses it if the receiver buffer is that large.
+const MAX_BLOCK_LEN: usize = 8 * 1024;
+/// Error free blocks in a row needed before the block size doubles again.
+const CLEAN_BLOCKS_TO_GROW: usize = 8;
+/// The window used after errors when no window size is configured, in blocks.
+const ERROR_WINDOW_BLOCKS: usize = 4;
+/// ZCRCQ subpackets per window, the ZACKs arrive before the window is full.
+const ACK_REQUESTS_PER_WINDOW: usize = 4;
+/// For some reason for some BBSes it's too fast - adding a little delay after each subpacket fixes that.
+/// Note that using ZCRCQ doesn't seem to fix that issue.
+pub const BLOCK_DELAY: Duration = Duration::from_millis(15);
 
 #[derive(Debug)]
 pub enum SendState {
@@ -23,6 +39,8 @@ pub enum SendState {
     SendZRQInit,
     SendZDATA,
     SendDataPackages,
+    /// Waits for the ZACK of a ZCRCW subpacket or for room in the window, whether the frame ended.
+    AwaitAck(bool),
     Finished,
 }
 
@@ -32,12 +50,44 @@ pub struct Sz {
     cur_file: i32,
     cur_file_pos: usize,
     pub errors: usize,
+    /// Current block size, shrinks on errors and grows back up to `max_package_len`.
     pub package_len: usize,
+    /// The block size of the protocol, receivers of plain ZModem may not take more.
+    max_package_len: usize,
+    /// Bytes sent before waiting for a ZACK (ZCRCQ), 0 streams nonstop.
+    pub window_size: usize,
+    /// Set after the receiver had to ask for a retransmit (ZRPOS).
+    had_errors: bool,
+    /// Bytes sent after the position of the last ZACK.
+    unacked_bytes: usize,
+    /// Bytes sent after the last ZCRCQ.
+    unrequested_bytes: usize,
+    clean_blocks: usize,
     pub transfered_file: bool,
-    data: Vec<u8>,
+    reader: Option<Box<dyn ReadSeek>>,
+    reader_pos: usize,
+    data_len: usize,
     retries: usize,
     can_count: usize,
     receiver_capabilities: u8,
+    /// Receive buffer size from ZRINIT, 0 means the receiver can take a whole file nonstop.
+    rx_buffer_len: usize,
+    /// ZF1 of ZFILE, tells the receiver what to do with existing files.
+    pub management_option: u8,
+    /// Lets the receiver append to a file of the same name (ZCRESUM), off by default as
+    /// some receivers don't check that it's the same file.
+    pub resume: bool,
+    /// Our escaping, the receiver may ask for more with ESCCTL.
+    pub escaping: ZdleEscaping,
+    /// The receiver acknowledged the ZSINIT that announced `Minimal` escaping.
+    direct_zap: bool,
+    zsinit_sent: bool,
+    zsinit_pending: bool,
+    /// ZF0 of the ZSINIT that waits for its ZACK.
+    zsinit_flags: u8,
+    zrqinit_sent: bool,
+    /// Pause after every subpacket.
+    pub block_delay: Duration,
 }
 
 impl Sz {
@@ -49,60 +99,163 @@ impl Sz {
             transfered_file: false,
             cur_file_pos: 0,
             errors: 0,
-            data: Vec::new(),
+            reader: None,
+            reader_pos: 0,
+            data_len: 0,
             retries: 0,
             receiver_capabilities: 0,
+            rx_buffer_len: 0,
             can_count: 0,
             package_len: block_length,
+            max_package_len: block_length,
+            window_size: 0,
+            had_errors: false,
+            unacked_bytes: 0,
+            unrequested_bytes: 0,
+            clean_blocks: 0,
+            management_option: 0,
+            resume: false,
+            escaping: ZdleEscaping::default(),
+            direct_zap: false,
+            zsinit_sent: false,
+            zsinit_pending: false,
+            zsinit_flags: 0,
+            zrqinit_sent: false,
+            block_delay: BLOCK_DELAY,
         }
     }
 
-    fn _can_fdx(&self) -> bool {
-        self.receiver_capabilities & super::zrinit_flag::CANFDX != 0
+    fn can_fdx(&self) -> bool {
+        self.receiver_capabilities & zrinit_flag::CANFDX != 0
     }
-    fn _can_receive_data_during_io(&self) -> bool {
-        self.receiver_capabilities & super::zrinit_flag::CANOVIO != 0
+    fn can_receive_data_during_io(&self) -> bool {
+        self.receiver_capabilities & zrinit_flag::CANOVIO != 0
     }
-    fn _can_send_break(&self) -> bool {
-        self.receiver_capabilities & super::zrinit_flag::CANBRK != 0
+    fn can_use_crc32(&self) -> bool {
+        self.receiver_capabilities & zrinit_flag::CANFC32 != 0
     }
-    fn _can_decrypt(&self) -> bool {
-        self.receiver_capabilities & super::zrinit_flag::CANCRY != 0
+    fn can_esc_control(&self) -> bool {
+        self.receiver_capabilities & zrinit_flag::ESCCTL != 0
     }
-    fn _can_lzw(&self) -> bool {
-        self.receiver_capabilities & super::zrinit_flag::CANLZW != 0
+    fn can_esc_8thbit(&self) -> bool {
+        self.receiver_capabilities & zrinit_flag::ESC8 != 0
     }
-    fn _can_use_crc32(&self) -> bool {
-        self.receiver_capabilities & super::zrinit_flag::CANFC32 != 0
-    }
-    fn _can_esc_control(&self) -> bool {
-        self.receiver_capabilities & super::zrinit_flag::ESCCTL != 0
+
+    /// Streaming (ZCRCG) needs a receiver that reads while it writes to disk and
+    /// that can interrupt us on a full duplex line. Otherwise every subpacket
+    /// ends the frame and waits for a ZACK (ZCRCW).
+    fn can_stream(&self) -> bool {
+        self.can_fdx() && self.can_receive_data_during_io() && self.rx_buffer_len == 0
     }
-    fn _can_esc_8thbit(&self) -> bool {
-        self.receiver_capabilities & super::zrinit_flag::ESC8 != 0
+
+    pub fn get_escaping(&self) -> ZdleEscaping {
+        // ZDLE can't get rid of the 8th bit, escaping the 8 bit control characters is the best we can do.
+        if self.can_esc_control() || self.can_esc_8thbit() {
+            ZdleEscaping::Control
+        } else if self.escaping == ZdleEscaping::Minimal && !self.direct_zap {
+            // a standard receiver drops unescaped XON/XOFF
+            ZdleEscaping::Standard
+        } else {
+            self.escaping
+        }
     }
 
     fn get_header_type(&self) -> HeaderType {
-        // Other headers fall back to crc16
-        // And the original crc16 implementation has a bug which isn't shared with only a few implementations these days crc32 is safe.
-        HeaderType::Bin32
-        /*
-        if self.can_esc_control() || self.can_esc_8thbit() {
+        // The original crc16 implementation has a bug which is shared with only a few implementations,
+        // these days crc32 is safe. Hex headers don't contain control characters.
+        if self.get_escaping() == ZdleEscaping::Control {
             HeaderType::Hex
+        } else if self.can_use_crc32() {
+            HeaderType::Bin32
         } else {
-            if self.can_use_crc32() {
-                HeaderType::Bin32
-            }  else {
-                HeaderType::Bin
-            }
-        }*/
+            HeaderType::Bin
+        }
+    }
+
+    /// Bytes that may be sent before waiting for an acknowledge, 0 means no limit.
+    fn get_window(&self) -> usize {
+        if self.window_size > 0 {
+            self.window_size
+        } else if self.had_errors {
+            ERROR_WINDOW_BLOCKS * self.package_len
+        } else {
+            0
+        }
+    }
+
+    /// The receiver lost data: use smaller blocks and wait for acknowledges from now on.
+    fn shrink_blocks(&mut self) {
+        self.errors += 1;
+        self.had_errors = true;
+        self.clean_blocks = 0;
+        self.unacked_bytes = 0;
+        self.unrequested_bytes = 0;
+        self.package_len = (self.package_len / 2).max(MIN_BLOCK_LEN.min(self.max_package_len));
+    }
+
+    /// The link is clean, grow the blocks to the maximum and stream again.
+    fn block_sent(&mut self) {
+        self.clean_blocks += 1;
+        if self.clean_blocks < CLEAN_BLOCKS_TO_GROW {
+            return;
+        }
+        self.clean_blocks = 0;
+        let max_block_len = self.get_max_block_len();
+        if self.package_len < max_block_len {
+            self.package_len = (self.package_len * 2).min(max_block_len);
+        } else {
+            self.had_errors = false;
+        }
+    }
+
+    /// A receiver buffer of 8k allows ZedZap blocks, a smaller one limits the blocks to its size.
+    fn get_max_block_len(&self) -> usize {
+        if self.rx_buffer_len >= MAX_BLOCK_LEN {
+            MAX_BLOCK_LEN
+        } else if self.rx_buffer_len > 0 {
+            self.max_package_len.min(self.rx_buffer_len)
+        } else {
+            self.max_package_len
+        }
+    }
+
+    fn get_block_len(&self) -> usize {
+        self.package_len.min(self.get_max_block_len())
     }
 
     fn encode_subpacket(&self, zcrc_byte: u8, data: &[u8]) -> Vec<u8> {
+        let escaping = self.get_escaping();
         match self.get_header_type() {
-            HeaderType::Bin | HeaderType::Hex => Zmodem::encode_subpacket_crc16(zcrc_byte, data),
-            HeaderType::Bin32 => Zmodem::encode_subpacket_crc32(zcrc_byte, data),
+            HeaderType::Bin | HeaderType::Hex => {
+                Zmodem::encode_subpacket_crc16(zcrc_byte, data, escaping)
+            }
+            HeaderType::Bin32 => Zmodem::encode_subpacket_crc32(zcrc_byte, data, escaping),
+        }
+    }
+
+    /// Reads the data between `from` and `to` of the current file, only seeks if
+    /// the receiver requested another position.
+    fn read_block(&mut self, from: usize, to: usize) -> TermComResult<Vec<u8>> {
+        let mut block = vec![0; to - from];
+        if let Some(reader) = &mut self.reader {
+            if self.reader_pos != from {
+                reader.seek(SeekFrom::Start(from as u64))?;
+            }
+            reader.read_exact(&mut block)?;
+            self.reader_pos = to;
         }
+        Ok(block)
+    }
+
+    /// CRC-32 of the start of the current file, for a receiver that wants to resume.
+    fn get_crc32(&mut self, len: usize) -> TermComResult<u32> {
+        let Some(reader) = &mut self.reader else {
+            return Ok(0);
+        };
+        reader.seek(SeekFrom::Start(0))?;
+        let crc = get_file_crc32(reader, len)?;
+        self.reader_pos = reader.stream_position()? as usize;
+        Ok(crc)
     }
 
     pub fn is_active(&self) -> bool {
@@ -113,18 +266,19 @@ impl Sz {
         self.cur_file += 1;
     }
 
-    pub async fn update(
+    pub fn update(
         &mut self,
-        com: &mut Box<dyn Com>,
+        input: &mut InputBuffer,
+        output: &mut OutputBuffer,
         transfer_state: Arc<Mutex<TransferState>>,
-    ) -> TermComResult<()> {
+    ) -> TermComResult<Step> {
         if let SendState::Finished = self.state {
-            return Ok(());
+            return Ok(Step::Finished);
         }
         if self.retries > 5 {
-            Zmodem::cancel(com).await?;
+            Zmodem::cancel(output);
             self.state = SendState::Finished;
-            return Ok(());
+            return Ok(Step::Finished);
         }
 
         if let Ok(transfer_state) = &mut transfer_state.lock() {
@@ -136,312 +290,437 @@ impl Sz {
                 }
             }
             transfer_info.bytes_transfered = self.cur_file_pos;
+            let cur_file = usize::try_from(self.cur_file.max(0)).unwrap();
+            transfer_info.total_files = self.files.len();
+            transfer_info.batch_files_transfered = cur_file;
+            transfer_info.total_bytes = self.files.iter().map(|f| f.size).sum();
+            transfer_info.batch_bytes_transfered = self
+                .files
+                .iter()
+                .take(cur_file)
+                .map(|f| f.size)
+                .sum::<usize>()
+                + self.cur_file_pos;
             transfer_info.errors = self.errors;
-            transfer_info.check_size = format!("Crc32/{}", self.package_len);
-            transfer_info.update_bps();
+            let crc = if self.get_header_type() == HeaderType::Bin32 {
+                "Crc32"
+            } else {
+                "Crc16"
+            };
+            transfer_info.check_size = format!("{crc}/{}", self.get_block_len());
+            transfer_info.update_statistics();
         }
 
         match self.state {
-            SendState::Await | SendState::AwaitZRPos => {
-                self.read_next_header(com).await?;
-            }
+            SendState::Await | SendState::AwaitZRPos => self.read_next_header(input, output),
             SendState::SendZRQInit => {
                 //                transfer_state.lock().unwrap().current_state = "Negotiating transfer";
-                //    let now = SystemTime::now();
-                //     if now.duration_since(self.last_send).unwrap().as_millis() > 3000 {
-                self.send_zrqinit(com).await?;
-                self.read_next_header(com).await?;
-
-                self.retries += 1;
-                //         self.last_send = SystemTime::now();
-                //     }
+                if !self.zrqinit_sent {
+                    self.send_zrqinit(output);
+                    self.zrqinit_sent = true;
+                    self.retries += 1;
+                }
+                let step = self.read_next_header(input, output)?;
+                if step != Step::NeedInput {
+                    // anything but a ZRINIT gets another ZRQINIT
+                    self.zrqinit_sent = false;
+                }
+                Ok(step)
             }
             SendState::SendZDATA => {
                 //              transfer_state.lock().unwrap().current_state = "Sending data";
                 if self.cur_file < 0 {
                     //println!("no file to send!");
-                    return Ok(());
+                    return Ok(Step::NeedInput);
                 }
                 Header::from_number(
                     self.get_header_type(),
                     ZFrameType::Data,
                     self.cur_file_pos as u32,
                 )
-                .write(com)
-                .await?;
+                .write(output);
                 self.state = SendState::SendDataPackages;
+                Ok(Step::Continue)
             }
             SendState::SendDataPackages => {
-                let mut p = Vec::new();
                 if self.cur_file < 0 {
-                    return Ok(());
+                    return Ok(Step::NeedInput);
                 }
-                let old_pos = self.cur_file_pos;
-                let end_pos = min(self.data.len(), self.cur_file_pos + self.package_len);
-                let nonstop = true; // self.package_len > 1024;
-
-                let crc_byte = if self.cur_file_pos + self.package_len < self.data.len() {
-                    if nonstop {
-                        ZCRCG
-                    } else {
-                        ZCRCQ
-                    }
-                } else {
-                    ZCRCE
-                };
-                p.extend_from_slice(
-                    &self.encode_subpacket(crc_byte, &self.data[self.cur_file_pos..end_pos]),
-                );
-                self.cur_file_pos = end_pos;
-                if end_pos >= self.data.len() {
-                    p.extend_from_slice(
-                        &Header::from_number(
-                            self.get_header_type(),
-                            ZFrameType::Eof,
-                            end_pos as u32,
-                        )
-                        .build(),
-                    );
-                    //transfer_info.write("Done sending file date.".to_string());
-                    // transfer_state.lock().unwrap().current_state = "Done data";
-                    self.transfered_file = true;
-                    self.state = SendState::Await;
-                }
-                com.send(&p).await?;
-                if !nonstop {
-                    let ack = Header::read(com, &mut self.can_count).await?;
-                    if let Some(header) = ack {
-                        match header.frame_type {
-                            ZFrameType::Ack => { /* ok */ }
-                            ZFrameType::Nak => {
-                                self.cur_file_pos = old_pos; /* resend */
-                            }
-                            ZFrameType::RPos => {
-                                self.cur_file_pos = header.number() as usize;
-                            }
-                            _ => {
-                                eprintln!("unexpected header {header:?}");
-                                // cancel
-                                self.state = SendState::Finished;
-                                Zmodem::cancel(com).await?;
-                            }
-                        }
-                    }
+                // ZACKs of ZCRCQ and ZRPOS arrive while the data flows
+                self.poll_ack(input, output);
+                if matches!(self.state, SendState::SendDataPackages) {
+                    self.send_data_package(output)?;
                 }
-                // for some reason for some BBSes it's too fast - adding a little delay here fixes that
-                // Note that using ZCRCQ doesn't seem to fix that issue.
-                std::thread::sleep(Duration::from_millis(15));
+                Ok(Step::Continue)
             }
-            SendState::Finished => {
-                //                transfer_state.lock().unwrap().current_state = "Finishing transfer…";
-                // let now = SystemTime::now();
-                //if now.duration_since(self.last_send).unwrap().as_millis() > 3000 {
-                self.send_zfin(com, 0).await?;
-                //}
-                return Ok(());
+            SendState::AwaitAck(frame_ended) => self.read_ack(input, output, frame_ended),
+            SendState::Finished => Ok(Step::Finished),
+        }
+    }
+
+    fn send_data_package(&mut self, output: &mut OutputBuffer) -> TermComResult<()> {
+        let mut p = Vec::new();
+        let old_pos = self.cur_file_pos;
+        let end_pos = min(self.data_len, self.cur_file_pos + self.get_block_len());
+
+        let len = end_pos - old_pos;
+        let window = self.get_window();
+        // the block that fills the window always asks for a ZACK, otherwise the wait never ends
+        let request_ack = window > 0
+            && (self.unacked_bytes + len >= window
+                || self.unrequested_bytes + len >= (window / ACK_REQUESTS_PER_WINDOW).max(1));
+
+        let crc_byte = if end_pos >= self.data_len {
+            ZCRCE
+        } else if !self.can_stream() {
+            ZCRCW
+        } else if request_ack {
+            ZCRCQ
+        } else {
+            ZCRCG
+        };
+        let block = self.read_block(self.cur_file_pos, end_pos)?;
+        p.extend_from_slice(&self.encode_subpacket(crc_byte, &block));
+        self.cur_file_pos = end_pos;
+        self.unacked_bytes += len;
+        self.unrequested_bytes = if crc_byte == ZCRCQ {
+            0
+        } else {
+            self.unrequested_bytes + len
+        };
+        if end_pos >= self.data_len {
+            p.extend_from_slice(
+                &Header::from_number(self.get_header_type(), ZFrameType::Eof, end_pos as u32)
+                    .build(),
+            );
+            //transfer_info.write("Done sending file date.".to_string());
+            // transfer_state.lock().unwrap().current_state = "Done data";
+            self.transfered_file = true;
+            self.state = SendState::Await;
+        }
+        output.send(&p);
+        if crc_byte == ZCRCW {
+            // a ZCRCW ends the frame, the next one needs a new ZDATA header
+            self.state = SendState::AwaitAck(true);
+        } else {
+            self.block_sent();
+            if crc_byte == ZCRCQ && self.unacked_bytes >= window {
+                // the window is full, the data flows on with the ZACK
+                self.state = SendState::AwaitAck(false);
             }
         }
+        if !self.block_delay.is_zero() {
+            output.pause(self.block_delay);
+        }
         Ok(())
     }
 
-    async fn read_next_header(&mut self, com: &mut Box<dyn Com>) -> TermComResult<()> {
-        let err = Header::read(com, &mut self.can_count).await;
+    /// Handles a header the receiver sent while the data flows, doesn't wait for one.
+    fn poll_ack(&mut self, input: &mut InputBuffer, output: &mut OutputBuffer) {
+        let mut reader = ByteReader::new(input.as_slice());
+        let ack = Header::read(&mut reader, &mut self.can_count);
+        if let Ok(None) = ack {
+            return;
+        }
+        let consumed = reader.pos();
+        input.consume(consumed);
+        // line noise is skipped, the receiver asks again for anything that got lost
+        if let Ok(Some(header)) = ack {
+            if matches!(header.frame_type, ZFrameType::Ack | ZFrameType::RPos) {
+                self.handle_ack(&header, output);
+            }
+        }
+    }
+
+    fn read_ack(
+        &mut self,
+        input: &mut InputBuffer,
+        output: &mut OutputBuffer,
+        frame_ended: bool,
+    ) -> TermComResult<Step> {
+        let mut reader = ByteReader::new(input.as_slice());
+        let ack = Header::read(&mut reader, &mut self.can_count);
+        if let Ok(None) = ack {
+            return Ok(Step::NeedInput);
+        }
+        let consumed = reader.pos();
+        input.consume(consumed);
+
+        self.state = if frame_ended {
+            SendState::SendZDATA
+        } else {
+            SendState::SendDataPackages
+        };
+        let Ok(Some(header)) = ack else {
+            // the next block asks for a ZACK again
+            self.errors += 1;
+            return Ok(Step::Continue);
+        };
+        if frame_ended && header.frame_type == ZFrameType::Ack {
+            self.block_sent();
+        }
+        self.handle_ack(&header, output);
+        Ok(Step::Continue)
+    }
+
+    fn handle_ack(&mut self, header: &Header, output: &mut OutputBuffer) {
+        match header.frame_type {
+            ZFrameType::Ack => {
+                // the receiver has everything up to its position
+                self.unacked_bytes = self.cur_file_pos.saturating_sub(header.number() as usize);
+            }
+            ZFrameType::Nak => {
+                // resend everything after the last acknowledged position
+                self.cur_file_pos = self.cur_file_pos.saturating_sub(self.unacked_bytes);
+                self.unacked_bytes = 0;
+                self.unrequested_bytes = 0;
+                self.state = SendState::SendZDATA;
+            }
+            ZFrameType::RPos => {
+                self.shrink_blocks();
+                self.cur_file_pos = min(header.number() as usize, self.data_len);
+                self.state = SendState::SendZDATA;
+            }
+            _ => {
+                eprintln!("unexpected header {header:?}");
+                // cancel
+                self.state = SendState::Finished;
+                Zmodem::cancel(output);
+            }
+        }
+    }
+
+    fn read_next_header(
+        &mut self,
+        input: &mut InputBuffer,
+        output: &mut OutputBuffer,
+    ) -> TermComResult<Step> {
+        let mut reader = ByteReader::new(input.as_slice());
+        let err = Header::read(&mut reader, &mut self.can_count);
+        if let Ok(None) = err {
+            return Ok(Step::NeedInput);
+        }
+        let consumed = reader.pos();
+        input.consume(consumed);
         if self.can_count >= 5 {
             // transfer_info.write("Received cancel...".to_string());
             self.state = SendState::Finished;
-            return Ok(());
+            return Ok(Step::Continue);
         }
-        if let Err(err) = err {
-            println!("{err}");
-            if self.errors > 3 {
-                self.state = SendState::Finished;
-                Zmodem::cancel(com).await?;
-                return Err(err);
+        let res = match err {
+            Ok(Some(res)) => res,
+            Ok(None) => return Ok(Step::NeedInput),
+            Err(err) => {
+                println!("{err}");
+                if self.errors > 3 {
+                    self.state = SendState::Finished;
+                    Zmodem::cancel(output);
+                    return Err(err);
+                }
+                self.errors += 1;
+                return Ok(Step::Continue);
             }
-            self.errors += 1;
-            return Ok(());
-        }
+        };
         self.errors = 0;
-        let res = err.unwrap();
-        if let Some(res) = res {
-            println!("Recv header {} {:?}", res, self.state);
-            match res.frame_type {
-                ZFrameType::RIinit => {
-                    if self.transfered_file {
-                        self.next_file();
-                        self.transfered_file = false;
-                    }
+        println!("Recv header {} {:?}", res, self.state);
+        match res.frame_type {
+            ZFrameType::RIinit => {
+                if self.transfered_file {
+                    self.next_file();
+                    self.transfered_file = false;
+                }
 
-                    if self.cur_file as usize >= self.files.len() {
-                        self.state = SendState::Await;
-                        self.send_zfin(com, self.cur_file_pos as u32).await?;
-                        self.cur_file_pos = 0;
-                        return Ok(());
-                    }
+                // ZF0 are the capability flags, P0/P1 the receive buffer size
+                self.receiver_capabilities = res.f0();
+                self.rx_buffer_len = usize::from(u16::from_le_bytes([res.data[0], res.data[1]]));
+
+                if self.cur_file as usize >= self.files.len() {
+                    self.state = SendState::Await;
+                    self.send_zfin(output, self.cur_file_pos as u32);
                     self.cur_file_pos = 0;
-                    self.receiver_capabilities = res.f0();
+                    return Ok(Step::Continue);
+                }
+                self.cur_file_pos = 0;
 
-                    /*
-                    if self.can_decrypt() {
-                        println!("receiver can decrypt");
-                    }
-                    if self.can_fdx() {
-                        println!("receiver can send and receive true full duplex");
-                    }
-                    if self.can_receive_data_during_io() {
-                        println!("receiver can receive data during disk I/O");
+                if !self.zsinit_sent {
+                    if self.escaping == ZdleEscaping::Control {
+                        // our side of the link needs escaping as well
+                        self.send_zsinit(output, zsinit_flag::TESCCTL);
+                        return Ok(Step::Continue);
                     }
-                    if self.can_send_break() {
-                        println!("receiver can send a break signal");
+                    if self.escaping == ZdleEscaping::Minimal
+                        && res.f1() & zrinit_flag::CANDZAP != 0
+                        && self.get_escaping() != ZdleEscaping::Control
+                    {
+                        self.send_zsinit(output, zsinit_flag::TDZAP);
+                        return Ok(Step::Continue);
                     }
-                    if self.can_lzw() {
-                        println!("receiver can uncompress");
-                    }
-                    if self.can_use_crc32() {
-                        println!("receiver can use 32 bit Frame Check");
-                    }
-                    if self.can_esc_control() {
-                        println!("receiver expects ctl chars to be escaped");
-                    }
-                    if self.can_esc_8thbit() {
-                        println!("receiver expects 8th bit to be escaped");
-                    }*/
-                    //  transfer_state.lock().unwrap().current_state = "Sending header";
-                    self.send_zfile(com).await?;
-                    self.state = SendState::AwaitZRPos;
-                    return Ok(());
                 }
 
-                ZFrameType::Nak => {
-                    // transfer_info
-                    //     .write("Package error, resending file header...".to_string());
-                }
+                //  transfer_state.lock().unwrap().current_state = "Sending header";
+                self.send_zfile(output)?;
+                self.state = SendState::AwaitZRPos;
+            }
 
-                ZFrameType::RPos => {
-                    self.cur_file_pos = res.number() as usize;
-                    self.state = SendState::SendZDATA;
-
-                    if let SendState::SendDataPackages = self.state {
-                        if self.package_len > 512 {
-                            //reinit transfer.
-                            self.package_len /= 2;
-                            self.state = SendState::SendZRQInit;
-                            //        com.write(b"rz\r")?;
-                            self.send_zrqinit(com).await?;
-                            return Ok(());
-                        }
-                    }
+            ZFrameType::Nak => {
+                // transfer_info
+                //     .write("Package error, resending file header...".to_string());
+                if self.zsinit_pending {
+                    self.send_zsinit(output, self.zsinit_flags);
                 }
+            }
 
-                ZFrameType::Fin => {
-                    self.state = SendState::Finished;
-                    com.send(b"OO").await?;
-                    return Ok(());
+            ZFrameType::RPos => {
+                // The first ZRPOS after ZFILE is the start position, a non zero position
+                // is a resume request of an interrupted transfer. Later ones report lost data.
+                if !matches!(self.state, SendState::AwaitZRPos) {
+                    self.shrink_blocks();
+                    self.transfered_file = false;
                 }
+                self.cur_file_pos = min(res.number() as usize, self.data_len);
+                self.unacked_bytes = 0;
+                self.unrequested_bytes = 0;
+                self.state = SendState::SendZDATA;
+            }
 
-                ZFrameType::Skip => {
-                    // transfer_state.lock().unwrap().current_state = "Skipped… next file";
-                    //transfer_info.write("Skip file".to_string());
-                    self.next_file();
-                    self.send_zfile(com).await?;
-                    return Ok(());
-                }
+            ZFrameType::Fin => {
+                self.state = SendState::Finished;
+                output.send(b"OO");
+            }
 
-                ZFrameType::Ack => {
-                    self.state = SendState::SendDataPackages;
-                }
-                ZFrameType::Challenge => {
-                    Header::from_number(self.get_header_type(), ZFrameType::Ack, res.number())
-                        .write(com)
-                        .await?;
+            ZFrameType::Skip => {
+                // transfer_state.lock().unwrap().current_state = "Skipped… next file";
+                //transfer_info.write("Skip file".to_string());
+                self.next_file();
+                if self.cur_file as usize >= self.files.len() {
+                    self.send_zfin(output, 0);
+                    return Ok(Step::Continue);
                 }
-                ZFrameType::Abort | ZFrameType::FErr | ZFrameType::Can => {
-                    Header::empty(self.get_header_type(), ZFrameType::Fin)
-                        .write(com)
-                        .await?;
-                    self.state = SendState::Finished;
+                self.send_zfile(output)?;
+            }
+
+            ZFrameType::Ack => {
+                if self.zsinit_pending {
+                    self.zsinit_pending = false;
+                    self.direct_zap = self.zsinit_flags & zsinit_flag::TDZAP != 0;
+                    self.send_zfile(output)?;
+                    return Ok(Step::Continue);
                 }
-                unk_frame => {
-                    return Err(Box::new(TransmissionError::UnsupportedFrame(unk_frame)));
+                // acknowledges of ZCRCQ may still arrive after the last subpacket
+                if self.cur_file_pos < self.data_len {
+                    self.state = SendState::SendDataPackages;
                 }
             }
+            ZFrameType::Challenge => {
+                Header::from_number(self.get_header_type(), ZFrameType::Ack, res.number())
+                    .write(output);
+            }
+            ZFrameType::Crc => {
+                // the receiver checks that the data it has is the start of this file, 0 means all of it
+                let len = match res.number() as usize {
+                    0 => self.data_len,
+                    len => len.min(self.data_len),
+                };
+                let crc = self.get_crc32(len)?;
+                Header::from_number(self.get_header_type(), ZFrameType::Crc, crc).write(output);
+            }
+            ZFrameType::Abort | ZFrameType::FErr | ZFrameType::Can => {
+                Header::empty(self.get_header_type(), ZFrameType::Fin).write(output);
+                self.state = SendState::Finished;
+            }
+            unk_frame => {
+                return Err(Box::new(TransmissionError::UnsupportedFrame(unk_frame)));
+            }
         }
-        Ok(())
+        Ok(Step::Continue)
     }
 
-    async fn send_zfile(&mut self, com: &mut Box<dyn Com>) -> TermComResult<()> {
+    fn send_zfile(&mut self, output: &mut OutputBuffer) -> TermComResult<()> {
         if self.cur_file < 0 {
             return Ok(());
         }
         let mut b = Vec::new();
         //transfer_state.write("Send file header".to_string());
+        let conversion = if self.resume {
+            zfile_flag::ZCRESUM
+        } else {
+            zfile_flag::ZCBIN
+        };
         b.extend_from_slice(
             &Header::from_flags(
                 self.get_header_type(),
                 ZFrameType::File,
                 0,
                 0,
-                zfile_flag::ZMNEW,
-                zfile_flag::ZCRESUM,
+                self.management_option,
+                conversion,
             )
             .build(),
         );
         let cur_file_size = usize::try_from(self.cur_file).unwrap();
         let f = &self.files[cur_file_size];
-        self.data = f.get_data();
-        let data = if f.date > 0 {
-            let bytes_left = self
-                .files
-                .iter()
-                .skip(cur_file_size + 1)
-                .fold(0, |b, f| b + f.size);
-            format!(
-                "{}\0{} {} 0 0 {} {}\0",
-                f.file_name,
-                f.size,
-                f.date,
-                self.files.len() - cur_file_size,
-                bytes_left
-            )
-            .into_bytes()
-        } else {
-            format!("{}\0{}\0", f.file_name, f.size).into_bytes()
-        };
+        self.reader = Some(f.open_reader()?);
+        self.reader_pos = 0;
+        self.data_len = f.size;
+        // remaining files and bytes include the current file, the modification time is octal
+        let bytes_left = self
+            .files
+            .iter()
+            .skip(cur_file_size)
+            .fold(0, |b, f| b + f.size);
+        let data = format!(
+            "{}\0{} {:o} 0 0 {} {}\0",
+            f.file_name,
+            f.size,
+            f.date,
+            self.files.len() - cur_file_size,
+            bytes_left
+        )
+        .into_bytes();
 
         b.extend_from_slice(&self.encode_subpacket(ZCRCW, &data));
 
-        com.send(&b).await?;
+        output.send(&b);
 
         self.cur_file_pos = 0;
         self.state = SendState::AwaitZRPos;
         Ok(())
     }
 
-    pub fn send(&mut self, _com: &mut Box<dyn Com>, files: Vec<FileDescriptor>) {
+    pub fn send(&mut self, files: Vec<FileDescriptor>) {
         //println!("initiate zmodem send {}", files.len());
         self.state = SendState::SendZRQInit;
         self.files = files;
         self.cur_file = 0;
         self.cur_file_pos = 0;
         self.retries = 0;
+        self.zrqinit_sent = false;
         //        com.write(b"rz\r")?;
     }
 
-    pub async fn send_zrqinit(&mut self, com: &mut Box<dyn Com>) -> TermComResult<()> {
+    pub fn send_zrqinit(&mut self, output: &mut OutputBuffer) {
         self.cur_file = -1;
         self.transfered_file = true;
-        Header::empty(self.get_header_type(), ZFrameType::RQInit)
-            .write(com)
-            .await?;
-        Ok(())
+        // the receiver capabilities are unknown yet, hex is understood by every receiver
+        Header::empty(HeaderType::Hex, ZFrameType::RQInit).write(output);
     }
 
-    pub async fn send_zfin(&mut self, com: &mut Box<dyn Com>, size: u32) -> TermComResult<()> {
+    /// Asks the receiver to escape the control characters it sends (TESCCTL) or tells
+    /// it that only ZDLE gets escaped from now on (TDZAP), no Attn sequence.
+    fn send_zsinit(&mut self, output: &mut OutputBuffer, flags: u8) {
+        let mut b =
+            Header::from_flags(self.get_header_type(), ZFrameType::Sinit, 0, 0, 0, flags).build();
+        b.extend_from_slice(&self.encode_subpacket(ZCRCW, b"\0"));
+        output.send(&b);
+        self.zsinit_sent = true;
+        self.zsinit_pending = true;
+        self.zsinit_flags = flags;
+        self.state = SendState::Await;
+    }
+
+    pub fn send_zfin(&mut self, output: &mut OutputBuffer, size: u32) {
         println!("send zfin!");
-        Header::from_number(self.get_header_type(), ZFrameType::Fin, size)
-            .write(com)
-            .await?;
+        Header::from_number(self.get_header_type(), ZFrameType::Fin, size).write(output);
         self.state = SendState::Await;
-        Ok(())
     }
 }
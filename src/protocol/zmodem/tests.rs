@@ -1,3 +1,759 @@
+#[cfg(test)]
+mod zmodem_tests {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        com::{Com, TestChannel},
+        protocol::{
+            zmodem::{
+                append_zdle_escaped, read_zdle_bytes,
+                rz::{read_subpacket, Rz, ZFileInfo},
+                sz::Sz,
+                zrinit_flag, zsinit_flag, Header, HeaderType, ZFrameType, ZdleEscaping, Zmodem,
+                ZCRCG, ZCRCW, ZDLE,
+            },
+            ByteReader, CollisionPolicy, FileDescriptor, InputBuffer, Output, OutputBuffer, Step,
+            TransferState,
+        },
+    };
+
+    /// The sender gives up if the receiver doesn't answer within that time.
+    const SENDER_TIMEOUT: Duration = Duration::from_secs(10);
+    /// The receiver keeps what it got if the sender stops sending for that long.
+    const RECEIVER_TIMEOUT: Duration = Duration::from_secs(2);
+
+    /// Moves the bytes between a state machine and the test line.
+    struct Line {
+        com: Box<dyn Com>,
+        input: InputBuffer,
+        output: OutputBuffer,
+    }
+
+    impl Line {
+        fn new(com: Box<dyn Com>) -> Self {
+            Self {
+                com,
+                input: InputBuffer::new(),
+                output: OutputBuffer::new(),
+            }
+        }
+
+        async fn flush(&mut self) {
+            for output in self.output.take() {
+                match output {
+                    Output::Data(data) => {
+                        self.com.send(&data).await.unwrap();
+                    }
+                    Output::Pause(duration) => tokio::time::sleep(duration).await,
+                    Output::Break => {}
+                }
+            }
+        }
+
+        /// Waits for data, false if nothing arrived within `timeout`.
+        async fn receive(&mut self, timeout: Duration) -> bool {
+            let start = Instant::now();
+            while start.elapsed() < timeout {
+                let data = self.com.read_data().await.unwrap();
+                if !data.is_empty() {
+                    self.input.extend(&data);
+                    return true;
+                }
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+            false
+        }
+    }
+
+    fn create_download_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("icy_term_{name}"));
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn create_test_data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    async fn run_sender(com: Box<dyn Com>, files: Vec<FileDescriptor>, max_updates: usize) -> Sz {
+        run_sz(com, Sz::new(1024), files, max_updates).await
+    }
+
+    /// Waiting for data doesn't count as update.
+    async fn run_sz(
+        com: Box<dyn Com>,
+        mut sz: Sz,
+        files: Vec<FileDescriptor>,
+        max_updates: usize,
+    ) -> Sz {
+        let state = Arc::new(Mutex::new(TransferState::new()));
+        let mut line = Line::new(com);
+        sz.send(files);
+        let mut updates = 0;
+        while sz.is_active() && updates < max_updates {
+            let step = sz
+                .update(&mut line.input, &mut line.output, state.clone())
+                .unwrap();
+            line.flush().await;
+            if step == Step::NeedInput {
+                if !line.receive(SENDER_TIMEOUT).await {
+                    break;
+                }
+            } else {
+                updates += 1;
+            }
+        }
+        sz
+    }
+
+    /// Runs the receiver until the transfer ends or the sender doesn't send data anymore.
+    async fn run_receiver(
+        com: Box<dyn Com>,
+        download_dir: PathBuf,
+        collision_policy: CollisionPolicy,
+    ) -> Rz {
+        let mut rz = Rz::new(1024);
+        rz.download_dir = Some(download_dir);
+        rz.collision_policy = collision_policy;
+        receive(com, rz).await
+    }
+
+    async fn receive(com: Box<dyn Com>, mut rz: Rz) -> Rz {
+        let state = Arc::new(Mutex::new(TransferState::new()));
+        let mut line = Line::new(com);
+        rz.recv(&mut line.output);
+        line.flush().await;
+        while rz.is_active() {
+            let step = rz
+                .update(&mut line.input, &mut line.output, state.clone())
+                .unwrap();
+            line.flush().await;
+            match step {
+                Step::NeedInput => {
+                    if !line.receive(RECEIVER_TIMEOUT).await {
+                        rz.save_partial_file().unwrap();
+                        break;
+                    }
+                }
+                Step::Wait(duration) => tokio::time::sleep(duration).await,
+                Step::Continue | Step::Finished => {}
+            }
+        }
+        rz
+    }
+
+    async fn transfer(data: &[u8], download_dir: &Path, max_sender_updates: usize) -> Rz {
+        transfer_file(
+            "foo.bar",
+            data,
+            download_dir,
+            max_sender_updates,
+            CollisionPolicy::Rename,
+        )
+        .await
+    }
+
+    async fn transfer_file(
+        file_name: &str,
+        data: &[u8],
+        download_dir: &Path,
+        max_sender_updates: usize,
+        collision_policy: CollisionPolicy,
+    ) -> Rz {
+        transfer_file_with(
+            Sz::new(1024),
+            file_name,
+            data,
+            download_dir,
+            max_sender_updates,
+            collision_policy,
+        )
+        .await
+    }
+
+    /// A sender that lets the receiver continue an existing file (ZCRESUM).
+    fn create_resuming_sz() -> Sz {
+        let mut sz = Sz::new(1024);
+        sz.resume = true;
+        sz
+    }
+
+    async fn transfer_file_with(
+        sz: Sz,
+        file_name: &str,
+        data: &[u8],
+        download_dir: &Path,
+        max_sender_updates: usize,
+        collision_policy: CollisionPolicy,
+    ) -> Rz {
+        let com = TestChannel::new();
+        let files = vec![FileDescriptor::create_test(
+            file_name.to_string(),
+            data.to_vec(),
+        )];
+        let sender = tokio::spawn(run_sz(com.sender, sz, files, max_sender_updates));
+        let receiver = tokio::spawn(run_receiver(
+            com.receiver,
+            download_dir.to_path_buf(),
+            collision_policy,
+        ));
+        sender.await.unwrap();
+        receiver.await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_zmodem_simple() {
+        let dir = create_download_dir("zmodem_simple");
+        let data = create_test_data(5000);
+        let mut rz = transfer(&data, &dir, 1000).await;
+        assert!(!rz.is_active());
+        assert_eq!(1, rz.files.len());
+        assert_eq!(data, rz.files.remove(0).get_data());
+        assert_eq!(data, fs::read(dir.join("foo.bar")).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_zmodem_resume_interrupted_download() {
+        let dir = create_download_dir("zmodem_resume");
+        let data = create_test_data(16 * 1024);
+
+        // ZRQINIT, ZRPOS, ZDATA + 5 subpackets - then the line drops
+        transfer(&data, &dir, 8).await;
+        let partial = fs::read(dir.join("foo.bar.part")).unwrap();
+        assert!(!partial.is_empty() && partial.len() < data.len());
+        assert_eq!(&data[..partial.len()], partial.as_slice());
+        assert!(!dir.join("foo.bar").exists());
+
+        let rz = transfer_file_with(
+            create_resuming_sz(),
+            "foo.bar",
+            &data,
+            &dir,
+            1000,
+            CollisionPolicy::Rename,
+        )
+        .await;
+        assert!(!rz.is_active());
+        assert_eq!(data, fs::read(dir.join("foo.bar")).unwrap());
+        assert!(!dir.join("foo.bar.part").exists());
+    }
+
+    #[tokio::test]
+    async fn test_zmodem_stale_partial_file() {
+        let dir = create_download_dir("zmodem_stale_part");
+        let data = create_test_data(8 * 1024);
+        // left behind by a download of another file with the same name
+        fs::write(dir.join("foo.bar.part"), vec![b'x'; 3000]).unwrap();
+
+        let rz = transfer(&data, &dir, 1000).await;
+        assert!(!rz.is_active());
+        assert_eq!(data, fs::read(dir.join("foo.bar")).unwrap());
+        assert!(!dir.join("foo.bar.part").exists());
+    }
+
+    #[tokio::test]
+    async fn test_zmodem_crash_recovery_of_existing_file() {
+        let dir = create_download_dir("zmodem_crash_recovery");
+        let data = create_test_data(8 * 1024);
+        fs::write(dir.join("foo.bar"), &data[..3000]).unwrap();
+
+        // the sender allows resuming and the CRC matches, so the existing file gets completed
+        transfer_file_with(
+            create_resuming_sz(),
+            "foo.bar",
+            &data,
+            &dir,
+            1000,
+            CollisionPolicy::Rename,
+        )
+        .await;
+        assert_eq!(data, fs::read(dir.join("foo.bar")).unwrap());
+        assert!(!dir.join("foo.bar.1").exists());
+    }
+
+    #[tokio::test]
+    async fn test_zmodem_no_resume_by_default() {
+        let dir = create_download_dir("zmodem_no_resume");
+        let data = create_test_data(8 * 1024);
+        fs::write(dir.join("foo.bar"), &data[..3000]).unwrap();
+
+        // without ZCRESUM an existing file is never continued, even if it matches
+        transfer(&data, &dir, 1000).await;
+        assert_eq!(&data[..3000], fs::read(dir.join("foo.bar")).unwrap());
+        assert_eq!(data, fs::read(dir.join("foo.bar.1")).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_zmodem_no_recovery_of_other_file() {
+        let dir = create_download_dir("zmodem_no_recovery");
+        let data = create_test_data(8 * 1024);
+        let old_data = vec![b'x'; 3000];
+        fs::write(dir.join("foo.bar"), &old_data).unwrap();
+
+        // resuming is allowed but the existing data isn't the start of the file
+        let policy = CollisionPolicy::Rename;
+        transfer_file_with(create_resuming_sz(), "foo.bar", &data, &dir, 1000, policy).await;
+        assert_eq!(old_data, fs::read(dir.join("foo.bar")).unwrap());
+        assert_eq!(data, fs::read(dir.join("foo.bar.1")).unwrap());
+
+        let policy = CollisionPolicy::Overwrite;
+        transfer_file_with(create_resuming_sz(), "foo.bar", &data, &dir, 1000, policy).await;
+        assert_eq!(data, fs::read(dir.join("foo.bar")).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_zmodem_sanitizes_file_names() {
+        let dir = create_download_dir("zmodem_sanitize");
+        let data = create_test_data(2000);
+        let rz = transfer_file("../foo.bar", &data, &dir, 1000, CollisionPolicy::Rename).await;
+        assert_eq!("foo.bar", rz.files[0].file_name);
+        assert_eq!(data, fs::read(dir.join("foo.bar")).unwrap());
+        assert!(!dir.parent().unwrap().join("foo.bar").exists());
+    }
+
+    #[tokio::test]
+    async fn test_zmodem_collision_policies() {
+        let dir = create_download_dir("zmodem_collision");
+        let old_data = create_test_data(4000);
+        let data = vec![b'x'; 2000];
+
+        fs::write(dir.join("foo.bar"), &old_data).unwrap();
+        let rz = transfer_file("foo.bar", &data, &dir, 1000, CollisionPolicy::Skip).await;
+        assert!(rz.files.is_empty());
+        assert_eq!(old_data, fs::read(dir.join("foo.bar")).unwrap());
+
+        transfer_file("foo.bar", &data, &dir, 1000, CollisionPolicy::Rename).await;
+        assert_eq!(old_data, fs::read(dir.join("foo.bar")).unwrap());
+        assert_eq!(data, fs::read(dir.join("foo.bar.1")).unwrap());
+
+        transfer_file("foo.bar", &data, &dir, 1000, CollisionPolicy::Overwrite).await;
+        assert_eq!(data, fs::read(dir.join("foo.bar")).unwrap());
+    }
+
+    #[test]
+    fn test_parse_zfile_info() {
+        let info = ZFileInfo::parse(b"foo.bar\x00123 14567416263 100644 0 3 4000\x00");
+        assert_eq!(
+            ZFileInfo {
+                file_name: "foo.bar".to_string(),
+                size: 123,
+                mtime: 0o14567416263,
+                mode: 0o100644,
+                files_remaining: 3,
+                bytes_remaining: 4000,
+            },
+            info
+        );
+
+        let info = ZFileInfo::parse(b"foo.bar\x0042\x00");
+        assert_eq!(42, info.size);
+        assert_eq!(0, info.mtime);
+        assert_eq!(0, info.files_remaining);
+    }
+
+    #[tokio::test]
+    async fn test_zmodem_keeps_modification_time() {
+        let dir = create_download_dir("zmodem_mtime");
+        let data = create_test_data(3000);
+        let mut fd = FileDescriptor::create_test("foo.bar".to_string(), data.clone());
+        fd.date = 1_234_567_890;
+
+        let com = TestChannel::new();
+        let sender = tokio::spawn(run_sender(com.sender, vec![fd], 1000));
+        let receiver = tokio::spawn(run_receiver(
+            com.receiver,
+            dir.clone(),
+            CollisionPolicy::Rename,
+        ));
+        sender.await.unwrap();
+        let rz = receiver.await.unwrap();
+
+        assert_eq!(1_234_567_890, rz.files[0].date);
+        let modified = fs::metadata(dir.join("foo.bar"))
+            .unwrap()
+            .modified()
+            .unwrap()
+            .duration_since(std::time::SystemTime::UNIX_EPOCH)
+            .unwrap();
+        assert_eq!(1_234_567_890, modified.as_secs());
+    }
+
+    #[test]
+    fn test_zdle_escape_control() {
+        let data: Vec<u8> = (0..=255).collect();
+        let mut encoded = Vec::new();
+        append_zdle_escaped(&mut encoded, &data, ZdleEscaping::Control);
+        assert!(encoded.iter().all(|b| *b == ZDLE || b & 0x60 != 0));
+
+        let decoded = read_zdle_bytes(
+            &mut ByteReader::new(&encoded),
+            data.len(),
+            ZdleEscaping::Control,
+        )
+        .unwrap();
+        assert_eq!(Some(data), decoded);
+    }
+
+    #[tokio::test]
+    async fn test_zmodem_escape_control() {
+        let dir = create_download_dir("zmodem_escctl");
+        let data: Vec<u8> = (0..4000).map(|i| (i % 256) as u8).collect();
+        let com = TestChannel::new();
+        let files = vec![FileDescriptor::create_test(
+            "foo.bar".to_string(),
+            data.clone(),
+        )];
+        let mut rz = Rz::new(1024);
+        rz.download_dir = Some(dir.clone());
+        rz.escaping = ZdleEscaping::Control;
+
+        let sender = tokio::spawn(run_sender(com.sender, files, 1000));
+        let receiver = tokio::spawn(receive(com.receiver, rz));
+        sender.await.unwrap();
+        let rz = receiver.await.unwrap();
+        assert!(!rz.is_active());
+        assert_eq!(data, fs::read(dir.join("foo.bar")).unwrap());
+    }
+
+    fn read_header(data: &[u8]) -> Header {
+        let mut can_count = 0;
+        Header::read(&mut ByteReader::new(data), &mut can_count)
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_zmodem_sends_attn_on_error() {
+        let dir = create_download_dir("zmodem_attn");
+        let mut input = InputBuffer::new();
+        let mut output = OutputBuffer::new();
+        let state = Arc::new(Mutex::new(TransferState::new()));
+        let mut rz = Rz::new(1024);
+        rz.download_dir = Some(dir);
+        rz.recv(&mut output);
+
+        let header = read_header(&output.take_data());
+        assert_eq!(ZFrameType::RIinit, header.frame_type);
+
+        let mut zsinit = Header::from_flags(
+            HeaderType::Bin32,
+            ZFrameType::Sinit,
+            0,
+            0,
+            0,
+            zsinit_flag::TESCCTL,
+        )
+        .build();
+        zsinit.extend_from_slice(&Zmodem::encode_subpacket_crc32(
+            ZCRCW,
+            b"ab\0",
+            ZdleEscaping::Standard,
+        ));
+        input.extend(&zsinit);
+        rz.update(&mut input, &mut output, state.clone()).unwrap();
+        let header = read_header(&output.take_data());
+        assert_eq!(ZFrameType::Ack, header.frame_type);
+
+        let mut zfile = Header::empty(HeaderType::Bin32, ZFrameType::File).build();
+        zfile.extend_from_slice(&Zmodem::encode_subpacket_crc32(
+            ZCRCW,
+            b"foo.bar\x003000\0",
+            ZdleEscaping::Standard,
+        ));
+        input.extend(&zfile);
+        rz.update(&mut input, &mut output, state.clone()).unwrap();
+        let header = read_header(&output.take_data());
+        assert_eq!(ZFrameType::RPos, header.frame_type);
+
+        // a garbled subpacket interrupts the sender with the attention string
+        let mut zdata = Header::from_number(HeaderType::Bin32, ZFrameType::Data, 0).build();
+        let mut subpacket =
+            Zmodem::encode_subpacket_crc32(ZCRCG, &[b'x'; 1024], ZdleEscaping::Standard);
+        subpacket[0] = b'y';
+        zdata.extend_from_slice(&subpacket);
+        input.extend(&zdata);
+        rz.update(&mut input, &mut output, state.clone()).unwrap();
+        rz.update(&mut input, &mut output, state.clone()).unwrap();
+
+        let data = output.take_data();
+        assert_eq!(b"ab", &data[..2]);
+        let header = read_header(&data[2..]);
+        assert_eq!(ZFrameType::RPos, header.frame_type);
+        assert_eq!(0, header.number());
+
+        // the sender asked for escaped control characters
+        rz.send_zrinit(&mut output);
+        let header = read_header(&output.take_data());
+        assert_ne!(0, header.f0() & zrinit_flag::ESCCTL);
+    }
+
+    #[test]
+    fn test_zmodem_waits_for_complete_subpacket() {
+        let dir = create_download_dir("zmodem_partial_subpacket");
+        let mut input = InputBuffer::new();
+        let mut output = OutputBuffer::new();
+        let state = Arc::new(Mutex::new(TransferState::new()));
+        let mut rz = Rz::new(1024);
+        rz.download_dir = Some(dir);
+        rz.recv(&mut output);
+
+        let mut zfile = Header::empty(HeaderType::Bin32, ZFrameType::File).build();
+        zfile.extend_from_slice(&Zmodem::encode_subpacket_crc32(
+            ZCRCW,
+            b"foo.bar\x003000\0",
+            ZdleEscaping::Standard,
+        ));
+        // the data trickles in byte by byte, nothing is lost in between
+        output.take();
+        for b in &zfile {
+            input.extend(&[*b]);
+            let step = rz.update(&mut input, &mut output, state.clone()).unwrap();
+            if !output.is_empty() {
+                break;
+            }
+            assert_eq!(Step::NeedInput, step);
+        }
+        assert!(input.is_empty());
+        let header = read_header(&output.take_data());
+        assert_eq!(ZFrameType::RPos, header.frame_type);
+        assert_eq!(0, rz.errors);
+    }
+
+    #[test]
+    fn test_zmodem_rejects_unterminated_subpacket() {
+        let dir = create_download_dir("zmodem_unterminated_subpacket");
+        let mut input = InputBuffer::new();
+        let mut output = OutputBuffer::new();
+        let state = Arc::new(Mutex::new(TransferState::new()));
+        let mut rz = Rz::new(1024);
+        rz.download_dir = Some(dir);
+        rz.recv(&mut output);
+
+        let mut zfile = Header::empty(HeaderType::Bin32, ZFrameType::File).build();
+        zfile.extend_from_slice(&Zmodem::encode_subpacket_crc32(
+            ZCRCW,
+            b"foo.bar\x003000\0",
+            ZdleEscaping::Standard,
+        ));
+        input.extend(&zfile);
+        rz.update(&mut input, &mut output, state.clone()).unwrap();
+        output.take();
+
+        // the subpacket end got lost, the receiver doesn't wait for it forever
+        input.extend(&Header::from_number(HeaderType::Bin32, ZFrameType::Data, 0).build());
+        input.extend(&[b'x'; 4 * 1024]);
+        for _ in 0..2 {
+            rz.update(&mut input, &mut output, state.clone()).unwrap();
+        }
+        let header = read_header(&output.take_data());
+        assert_eq!(ZFrameType::RPos, header.frame_type);
+        assert_eq!(0, header.number());
+        assert_eq!(1, rz.errors);
+    }
+
+    #[tokio::test]
+    async fn test_zmodem_window() {
+        let dir = create_download_dir("zmodem_window");
+        let data = create_test_data(20 * 1024);
+        let com = TestChannel::new();
+        let files = vec![FileDescriptor::create_test(
+            "foo.bar".to_string(),
+            data.clone(),
+        )];
+        let mut sz = Sz::new(1024);
+        sz.window_size = 3000;
+
+        let sender = tokio::spawn(run_sz(com.sender, sz, files, 1000));
+        let receiver = tokio::spawn(run_receiver(
+            com.receiver,
+            dir.clone(),
+            CollisionPolicy::Rename,
+        ));
+        sender.await.unwrap();
+        let rz = receiver.await.unwrap();
+        assert!(!rz.is_active());
+        assert_eq!(data, fs::read(dir.join("foo.bar")).unwrap());
+    }
+
+    /// Sends a file to a streaming receiver with the given buffer size, returns the subpacket sizes.
+    fn get_subpacket_lengths(mut sz: Sz, rx_buffer_len: u16, data: &[u8]) -> Vec<usize> {
+        let mut input = InputBuffer::new();
+        let mut output = OutputBuffer::new();
+        let state = Arc::new(Mutex::new(TransferState::new()));
+        sz.block_delay = Duration::ZERO;
+        sz.send(vec![FileDescriptor::create_test(
+            "foo.bar".to_string(),
+            data.to_vec(),
+        )]);
+        let [p0, p1] = rx_buffer_len.to_le_bytes();
+        let flags = zrinit_flag::CANFDX | zrinit_flag::CANOVIO | zrinit_flag::CANFC32;
+        for header in [
+            Header::from_flags(HeaderType::Hex, ZFrameType::RIinit, p0, p1, 0, flags),
+            Header::from_number(HeaderType::Hex, ZFrameType::RPos, 0),
+        ] {
+            sz.update(&mut input, &mut output, state.clone()).unwrap();
+            output.take();
+            input.extend(&header.build());
+        }
+        loop {
+            while sz.update(&mut input, &mut output, state.clone()).unwrap() != Step::NeedInput {}
+            if sz.transfered_file {
+                break;
+            }
+            // a receiver with a buffer acknowledges every subpacket
+            input.extend(&Header::from_number(HeaderType::Hex, ZFrameType::Ack, 0).build());
+        }
+
+        let data = output.take_data();
+        let mut reader = ByteReader::new(&data);
+        let mut result = Vec::new();
+        loop {
+            let header = Header::read(&mut reader, &mut 0).unwrap().unwrap();
+            if header.frame_type == ZFrameType::Eof {
+                return result;
+            }
+            assert_eq!(ZFrameType::Data, header.frame_type);
+            loop {
+                let (block, is_last, _) =
+                    read_subpacket(&mut reader, 16 * 1024, true, sz.get_escaping())
+                        .unwrap()
+                        .unwrap();
+                result.push(block.len());
+                if is_last {
+                    break;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_zmodem_block_len() {
+        let data = create_test_data(128 * 1024);
+        let lengths = get_subpacket_lengths(Sz::new(1024), 0, &data);
+        assert_eq!(128, lengths.len());
+        assert!(lengths.iter().all(|len| *len == 1024));
+
+        // a receiver with a large buffer can take ZedZap blocks
+        let lengths = get_subpacket_lengths(Sz::new(1024), 8 * 1024, &data);
+        assert_eq!(Some(&(8 * 1024)), lengths.iter().max());
+
+        let lengths = get_subpacket_lengths(Sz::new(8 * 1024), 0, &data);
+        assert!(lengths.iter().all(|len| *len == 8 * 1024));
+    }
+
+    #[tokio::test]
+    async fn test_zmodem_lossy_link() {
+        let dir = create_download_dir("zmodem_lossy");
+        let data = create_test_data(32 * 1024);
+        // the first error hits the initial streaming, the second one the retransmit
+        let com = TestChannel::new_lossy(vec![3000, 40_000]);
+        let files = vec![FileDescriptor::create_test(
+            "foo.bar".to_string(),
+            data.clone(),
+        )];
+        let sz = Sz::new(8 * 1024);
+
+        let sender = tokio::spawn(run_sz(com.sender, sz, files, 1000));
+        let receiver = tokio::spawn(run_receiver(
+            com.receiver,
+            dir.clone(),
+            CollisionPolicy::Rename,
+        ));
+        tokio::time::timeout(Duration::from_secs(60), sender)
+            .await
+            .expect("sender hangs")
+            .unwrap();
+        let rz = receiver.await.unwrap();
+        assert!(!rz.is_active());
+        assert!(rz.errors > 0);
+        assert_eq!(data, fs::read(dir.join("foo.bar")).unwrap());
+    }
+
+    async fn transfer_with(sz: Sz, rz: Rz, data: &[u8]) -> Rz {
+        let com = TestChannel::new();
+        let files = vec![FileDescriptor::create_test(
+            "foo.bar".to_string(),
+            data.to_vec(),
+        )];
+        let sender = tokio::spawn(run_sz(com.sender, sz, files, 1000));
+        let receiver = tokio::spawn(receive(com.receiver, rz));
+        sender.await.unwrap();
+        receiver.await.unwrap()
+    }
+
+    #[test]
+    fn test_zdle_escape_minimal() {
+        let data: Vec<u8> = (0..=255).collect();
+        let mut encoded = Vec::new();
+        append_zdle_escaped(&mut encoded, &data, ZdleEscaping::Minimal);
+        // only ZDLE gets escaped
+        assert_eq!(data.len() + 1, encoded.len());
+
+        let decoded = read_zdle_bytes(
+            &mut ByteReader::new(&encoded),
+            data.len(),
+            ZdleEscaping::Minimal,
+        )
+        .unwrap();
+        assert_eq!(Some(data), decoded);
+    }
+
+    #[tokio::test]
+    async fn test_direct_zap() {
+        let dir = create_download_dir("zmodem_direct_zap");
+        let data: Vec<u8> = (0..20_000).map(|i| (i % 256) as u8).collect();
+        let mut sz = Sz::new(8 * 1024);
+        sz.escaping = ZdleEscaping::Minimal;
+        let mut rz = Rz::new(8 * 1024);
+        rz.download_dir = Some(dir.clone());
+        rz.escaping = ZdleEscaping::Minimal;
+
+        let rz = transfer_with(sz, rz, &data).await;
+        assert!(!rz.is_active());
+        assert_eq!(ZdleEscaping::Minimal, rz.get_escaping());
+        assert_eq!(data, fs::read(dir.join("foo.bar")).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_direct_zap_standard_receiver() {
+        // a receiver that didn't offer DirectZAP drops unescaped XON/XOFF
+        let dir = create_download_dir("zmodem_direct_zap_fallback");
+        let data: Vec<u8> = (0..20_000).map(|i| (i % 256) as u8).collect();
+        let mut sz = Sz::new(8 * 1024);
+        sz.escaping = ZdleEscaping::Minimal;
+        let mut rz = Rz::new(8 * 1024);
+        rz.download_dir = Some(dir.clone());
+
+        let rz = transfer_with(sz, rz, &data).await;
+        assert!(!rz.is_active());
+        assert_eq!(ZdleEscaping::Standard, rz.get_escaping());
+        assert_eq!(data, fs::read(dir.join("foo.bar")).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_zmodem_sender_requests_escaping() {
+        let dir = create_download_dir("zmodem_zsinit");
+        let data: Vec<u8> = (0..5000).map(|i| (i % 256) as u8).collect();
+        let mut sz = Sz::new(1024);
+        sz.escaping = ZdleEscaping::Control;
+        let mut rz = Rz::new(1024);
+        rz.download_dir = Some(dir.clone());
+
+        let rz = transfer_with(sz, rz, &data).await;
+        assert!(!rz.is_active());
+        assert_eq!(ZdleEscaping::Control, rz.escaping);
+        assert_eq!(data, fs::read(dir.join("foo.bar")).unwrap());
+    }
+}
+
 /*
 #[cfg(test)]
 mod tests {
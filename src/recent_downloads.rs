@@ -0,0 +1,307 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, Read, Seek},
+    path::{Path, PathBuf},
+};
+
+use crate::{com::TermComResult, protocol::FileDescriptor};
+
+const MAX_RECENT_DOWNLOADS: usize = 50;
+
+/// The descriptions BBS software puts into archives, in the order they're looked for.
+const DESCRIPTION_FILES: [&str; 2] = ["FILE_ID.DIZ", "DESC.SDI"];
+/// Descriptions are a few lines, anything after that is cut off.
+const MAX_DESCRIPTION_LEN: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveType {
+    Zip,
+    Lha,
+    Arj,
+    Rar,
+}
+
+impl ArchiveType {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        match ext.as_str() {
+            "zip" => Some(ArchiveType::Zip),
+            "lha" | "lzh" => Some(ArchiveType::Lha),
+            "arj" => Some(ArchiveType::Arj),
+            "rar" => Some(ArchiveType::Rar),
+            _ => None,
+        }
+    }
+
+    /// ARJ and RAR are recognized but can't be looked into.
+    pub fn can_list(self) -> bool {
+        matches!(self, ArchiveType::Zip | ArchiveType::Lha)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    pub name: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ArchiveInfo {
+    pub entries: Vec<ArchiveEntry>,
+    /// The raw FILE_ID.DIZ/DESC.SDI, it's in the encoding of the board.
+    pub description: Option<Vec<u8>>,
+}
+
+fn is_description_file(name: &str) -> bool {
+    DESCRIPTION_FILES
+        .iter()
+        .any(|desc| name.eq_ignore_ascii_case(desc))
+}
+
+pub fn read_archive(path: &Path) -> TermComResult<ArchiveInfo> {
+    match ArchiveType::from_path(path) {
+        Some(ArchiveType::Zip) => read_zip(BufReader::new(File::open(path)?)),
+        Some(ArchiveType::Lha) => read_lha(BufReader::new(File::open(path)?)),
+        _ => Ok(ArchiveInfo::default()),
+    }
+}
+
+pub fn read_zip<R: Read + Seek>(reader: R) -> TermComResult<ArchiveInfo> {
+    let mut archive = zip::ZipArchive::new(reader)?;
+    let mut result = ArchiveInfo::default();
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        if file.is_dir() {
+            continue;
+        }
+        let name = file.name().to_string();
+        if result.description.is_none() && is_description_file(&name) {
+            let mut data = Vec::new();
+            file.by_ref()
+                .take(MAX_DESCRIPTION_LEN)
+                .read_to_end(&mut data)?;
+            result.description = Some(data);
+        }
+        result.entries.push(ArchiveEntry {
+            name,
+            size: file.size(),
+        });
+    }
+    Ok(result)
+}
+
+pub fn read_lha<R: Read>(reader: R) -> TermComResult<ArchiveInfo> {
+    let mut lha_reader = delharc::LhaDecodeReader::new(reader).map_err(|err| err.to_string())?;
+    let mut result = ArchiveInfo::default();
+    loop {
+        let header = lha_reader.header();
+        if !header.is_directory() {
+            let name = header.parse_pathname().to_string_lossy().to_string();
+            let size = header.original_size;
+            if result.description.is_none()
+                && is_description_file(&name)
+                && lha_reader.is_decoder_supported()
+            {
+                let mut data = Vec::new();
+                lha_reader
+                    .by_ref()
+                    .take(MAX_DESCRIPTION_LEN)
+                    .read_to_end(&mut data)?;
+                // only a complete file can be checked
+                if size <= MAX_DESCRIPTION_LEN {
+                    lha_reader.crc_check().map_err(|err| err.to_string())?;
+                }
+                result.description = Some(data);
+            }
+            result.entries.push(ArchiveEntry { name, size });
+        }
+        if !lha_reader.next_file().map_err(|err| err.to_string())? {
+            break;
+        }
+    }
+    Ok(result)
+}
+
+pub struct RecentDownload {
+    pub file_name: String,
+    pub path: PathBuf,
+    pub size: usize,
+    pub archive_type: Option<ArchiveType>,
+    /// Read when the download gets selected.
+    pub info: Option<Result<ArchiveInfo, String>>,
+}
+
+impl RecentDownload {
+    pub fn get_info(&mut self) -> &Result<ArchiveInfo, String> {
+        let path = &self.path;
+        self.info
+            .get_or_insert_with(|| read_archive(path).map_err(|err| err.to_string()))
+    }
+}
+
+/// The files received in this session, newest first.
+#[derive(Default)]
+pub struct RecentDownloads {
+    pub downloads: Vec<RecentDownload>,
+}
+
+impl RecentDownloads {
+    pub fn add(&mut self, files: &[FileDescriptor]) {
+        for fd in files {
+            // Only finished files have a path.
+            if fd.path_name.is_empty() {
+                continue;
+            }
+            let path = PathBuf::from(&fd.path_name);
+            self.downloads.retain(|d| d.path != path);
+            self.downloads.insert(
+                0,
+                RecentDownload {
+                    file_name: fd.file_name.clone(),
+                    archive_type: ArchiveType::from_path(&path),
+                    path,
+                    size: fd.size,
+                    info: None,
+                },
+            );
+        }
+        self.downloads.truncate(MAX_RECENT_DOWNLOADS);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.downloads.is_empty()
+    }
+
+    pub fn delete(&mut self, index: usize) -> io::Result<()> {
+        if let Some(download) = self.downloads.get(index) {
+            if download.path.exists() {
+                fs::remove_file(&download.path)?;
+            }
+            self.downloads.remove(index);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use crate::{
+        protocol::FileDescriptor,
+        recent_downloads::{read_lha, read_zip, ArchiveType, RecentDownloads},
+    };
+
+    #[test]
+    fn test_archive_type() {
+        assert_eq!(
+            Some(ArchiveType::Zip),
+            ArchiveType::from_path("FOO.ZIP".as_ref())
+        );
+        assert_eq!(
+            Some(ArchiveType::Lha),
+            ArchiveType::from_path("foo.lzh".as_ref())
+        );
+        assert_eq!(None, ArchiveType::from_path("foo.txt".as_ref()));
+    }
+
+    #[test]
+    fn test_read_zip() {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("README.TXT", zip::write::FileOptions::default())
+            .unwrap();
+        zip.write_all(b"Hello World").unwrap();
+        zip.start_file("file_id.diz", zip::write::FileOptions::default())
+            .unwrap();
+        zip.write_all(b"\x1B[1;33mFOO\x1B[0m v1.0").unwrap();
+        let data = zip.finish().unwrap().into_inner();
+
+        let info = read_zip(Cursor::new(data)).unwrap();
+        assert_eq!(2, info.entries.len());
+        assert_eq!("README.TXT", info.entries[0].name);
+        assert_eq!(11, info.entries[0].size);
+        assert_eq!(
+            Some(b"\x1B[1;33mFOO\x1B[0m v1.0".to_vec()),
+            info.description
+        );
+    }
+
+    #[test]
+    fn test_read_zip_cuts_description() {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("FILE_ID.DIZ", zip::write::FileOptions::default())
+            .unwrap();
+        zip.write_all(&vec![b'x'; 100 * 1024]).unwrap();
+        let data = zip.finish().unwrap().into_inner();
+
+        let info = read_zip(Cursor::new(data)).unwrap();
+        assert_eq!(100 * 1024, info.entries[0].size);
+        assert_eq!(Some(64 * 1024), info.description.map(|d| d.len()));
+    }
+
+    /// CRC-16/ARC, LHA uses it for the headers and the data.
+    fn crc16(data: &[u8]) -> u16 {
+        let mut crc = 0u16;
+        for b in data {
+            crc ^= u16::from(*b);
+            for _ in 0..8 {
+                crc = if crc & 1 == 1 {
+                    (crc >> 1) ^ 0xA001
+                } else {
+                    crc >> 1
+                };
+            }
+        }
+        crc
+    }
+
+    /// A level 0 header with a stored (-lh0-) file.
+    fn create_lha_file(name: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend_from_slice(b"-lh0-");
+        header.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        header.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        header.extend_from_slice(&0x5721_0000u32.to_le_bytes());
+        header.push(0x20);
+        header.push(0);
+        header.push(name.len() as u8);
+        header.extend_from_slice(name);
+        header.extend_from_slice(&crc16(data).to_le_bytes());
+
+        let checksum = header.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        let mut result = vec![header.len() as u8, checksum];
+        result.extend(header);
+        result.extend_from_slice(data);
+        result
+    }
+
+    #[test]
+    fn test_read_lha() {
+        let mut data = create_lha_file(b"FOO.EXE", b"MZ");
+        data.extend(create_lha_file(b"DESC.SDI", b"Foo for you"));
+        data.push(0);
+
+        let info = read_lha(Cursor::new(data)).unwrap();
+        assert_eq!(2, info.entries.len());
+        assert_eq!("FOO.EXE", info.entries[0].name);
+        assert_eq!(2, info.entries[0].size);
+        assert_eq!(Some(b"Foo for you".to_vec()), info.description);
+    }
+
+    #[test]
+    fn test_recent_downloads_order() {
+        let mut foo = FileDescriptor::new();
+        foo.file_name = "foo.zip".to_string();
+        foo.path_name = "/tmp/foo.zip".to_string();
+        let mut bar = FileDescriptor::new();
+        bar.file_name = "bar.lzh".to_string();
+        bar.path_name = "/tmp/bar.lzh".to_string();
+
+        let mut downloads = RecentDownloads::default();
+        downloads.add(&[foo.clone(), bar, FileDescriptor::new()]);
+        downloads.add(&[foo]);
+        assert_eq!(2, downloads.downloads.len());
+        assert_eq!("foo.zip", downloads.downloads[0].file_name);
+        assert_eq!(Some(ArchiveType::Lha), downloads.downloads[1].archive_type);
+    }
+}
@@ -15,6 +15,14 @@ impl Rng {
         }
     }
 
+    /// Always produces the same sequence for a seed.
+    #[cfg(test)]
+    pub fn from_seed(seed: u64) -> Rng {
+        Rng {
+            state: (seed % 0x7fff_fffe) as i32 + 1,
+        }
+    }
+
     // Lehmer random number generator
     pub fn next(&mut self) -> i32 {
         self.state = ((self.state as u64).wrapping_mul(48271) % 0x7fff_ffff) as i32;
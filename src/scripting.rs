@@ -0,0 +1,395 @@
+//! Rhai scripts that automate a session. A script runs once when it's loaded, after that
+//! these functions get called if the script defines them:
+//!
+//! `on_connect()`, `on_disconnect()`, `on_text(text)`, `on_screen_change()`, `on_key(key)`
+//! and `on_transfer_finished(download, files)`.
+//!
+//! Functions can't see the variables of the script, `this` is a map that keeps its values
+//! between the calls:
+//!
+//! ```text
+//! fn on_connect() { this.pages = 0; }
+//! fn on_text(text) {
+//!     if text.contains("More?") { this.pages += 1; send("\r"); }
+//! }
+//! ```
+use std::{
+    cell::RefCell,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::Arc,
+};
+
+use icy_engine::{ansi, BufferParser, Position};
+use rhai::{Array, CallFnOptions, Dynamic, Engine, EvalAltResult, FuncArgs, Map, Scope, AST};
+
+use crate::{
+    protocol::TransferType,
+    ui::{screen_modes::ScreenMode, BufferView},
+};
+
+/// Keeps a looping script from freezing the terminal.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// What a script asked the terminal to do, executed after the script returned.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptCommand {
+    Send(String),
+    SendBytes(Vec<u8>),
+    Download(TransferType),
+    Upload(TransferType, Vec<PathBuf>),
+    SetScreenMode(ScreenMode),
+    Message(String),
+}
+
+struct ScriptContext {
+    buffer_view: Option<Arc<eframe::epaint::mutex::Mutex<BufferView>>>,
+    /// Converts the screen cells to unicode.
+    parser: Box<dyn BufferParser>,
+    commands: Vec<ScriptCommand>,
+}
+
+impl ScriptContext {
+    /// Runs `f` on the visible screen, (0, 0) is its top left corner.
+    fn with_screen<T: Default>(&self, f: impl FnOnce(&BufferView, i32) -> T) -> T {
+        let Some(buffer_view) = &self.buffer_view else {
+            return T::default();
+        };
+        let view = buffer_view.lock();
+        let first_line =
+            0.max(view.buf.layers[0].lines.len() as i32 - view.buf.get_buffer_height());
+        f(&view, first_line)
+    }
+
+    fn get_char(&self, x: i64, y: i64) -> Option<icy_engine::AttributedChar> {
+        self.with_screen(|view, first_line| {
+            view.buf
+                .get_char(Position::new(x as i32, first_line + y as i32))
+        })
+    }
+}
+
+pub struct LoadedScript {
+    pub name: String,
+    /// Scripts of the address go away when it hangs up, the others stay.
+    pub from_address: bool,
+    ast: AST,
+    scope: Scope<'static>,
+    this: Dynamic,
+}
+
+pub struct ScriptHost {
+    engine: Engine,
+    context: Rc<RefCell<ScriptContext>>,
+    pub scripts: Vec<LoadedScript>,
+}
+
+fn get_protocol(name: &str) -> Result<TransferType, Box<EvalAltResult>> {
+    TransferType::from_name(name).ok_or_else(|| format!("unknown protocol '{name}'").into())
+}
+
+impl ScriptHost {
+    pub fn new(buffer_view: Option<Arc<eframe::epaint::mutex::Mutex<BufferView>>>) -> Self {
+        let context = Rc::new(RefCell::new(ScriptContext {
+            buffer_view,
+            parser: Box::<ansi::Parser>::default(),
+            commands: Vec::new(),
+        }));
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+
+        let ctx = context.clone();
+        engine.register_fn("send", move |text: &str| {
+            ctx.borrow_mut()
+                .commands
+                .push(ScriptCommand::Send(text.to_string()));
+        });
+        let ctx = context.clone();
+        engine.register_fn("send_bytes", move |bytes: Array| {
+            let bytes = bytes
+                .iter()
+                .filter_map(|b| b.as_int().ok())
+                .map(|b| b as u8);
+            ctx.borrow_mut()
+                .commands
+                .push(ScriptCommand::SendBytes(bytes.collect()));
+        });
+        let ctx = context.clone();
+        engine.register_fn("message", move |text: &str| {
+            ctx.borrow_mut()
+                .commands
+                .push(ScriptCommand::Message(text.to_string()));
+        });
+        let ctx = context.clone();
+        engine.register_fn(
+            "download",
+            move |protocol: &str| -> Result<(), Box<EvalAltResult>> {
+                let protocol = get_protocol(protocol)?;
+                ctx.borrow_mut()
+                    .commands
+                    .push(ScriptCommand::Download(protocol));
+                Ok(())
+            },
+        );
+        let ctx = context.clone();
+        engine.register_fn(
+            "upload",
+            move |protocol: &str, files: Array| -> Result<(), Box<EvalAltResult>> {
+                let protocol = get_protocol(protocol)?;
+                let files = files.into_iter().map(|f| PathBuf::from(f.to_string()));
+                ctx.borrow_mut()
+                    .commands
+                    .push(ScriptCommand::Upload(protocol, files.collect()));
+                Ok(())
+            },
+        );
+        let ctx = context.clone();
+        engine.register_fn(
+            "set_screen_mode",
+            move |name: &str| -> Result<(), Box<EvalAltResult>> {
+                let mode = ScreenMode::from_name(name)
+                    .ok_or_else(|| format!("unknown screen mode '{name}'"))?;
+                ctx.borrow_mut()
+                    .commands
+                    .push(ScriptCommand::SetScreenMode(mode));
+                Ok(())
+            },
+        );
+
+        let ctx = context.clone();
+        engine.register_fn("screen_width", move || {
+            i64::from(
+                ctx.borrow()
+                    .with_screen(|view, _| view.buf.get_buffer_width()),
+            )
+        });
+        let ctx = context.clone();
+        engine.register_fn("screen_height", move || {
+            i64::from(
+                ctx.borrow()
+                    .with_screen(|view, _| view.buf.get_buffer_height()),
+            )
+        });
+        let ctx = context.clone();
+        engine.register_fn("caret_x", move || {
+            i64::from(
+                ctx.borrow()
+                    .with_screen(|view, _| view.caret.get_position().x),
+            )
+        });
+        let ctx = context.clone();
+        engine.register_fn("caret_y", move || {
+            // relative to the screen like the rows of get_char
+            i64::from(
+                ctx.borrow()
+                    .with_screen(|view, first_line| view.caret.get_position().y - first_line),
+            )
+        });
+        let ctx = context.clone();
+        engine.register_fn("get_char", move |x: i64, y: i64| {
+            let ctx = ctx.borrow();
+            ctx.get_char(x, y)
+                .map(|ch| ctx.parser.convert_to_unicode(ch.ch).to_string())
+                .unwrap_or_default()
+        });
+        let ctx = context.clone();
+        engine.register_fn("get_foreground", move |x: i64, y: i64| {
+            ctx.borrow().get_char(x, y).map_or(0, |ch| {
+                let fg = ch.attribute.get_foreground();
+                i64::from(if ch.attribute.is_bold() { fg + 8 } else { fg })
+            })
+        });
+        let ctx = context.clone();
+        engine.register_fn("get_background", move |x: i64, y: i64| {
+            ctx.borrow()
+                .get_char(x, y)
+                .map_or(0, |ch| i64::from(ch.attribute.get_background()))
+        });
+        let ctx = context.clone();
+        engine.register_fn("get_line", move |y: i64| {
+            let ctx = ctx.borrow();
+            let width = ctx.with_screen(|view, _| view.buf.get_buffer_width());
+            let line: String = (0..width)
+                .map(|x| {
+                    ctx.get_char(i64::from(x), y)
+                        .map_or(' ', |ch| ctx.parser.convert_to_unicode(ch.ch))
+                })
+                .collect();
+            line.trim_end().to_string()
+        });
+
+        Self {
+            engine,
+            context,
+            scripts: Vec::new(),
+        }
+    }
+
+    /// The parser of the board, screen cells are in its encoding.
+    pub fn set_parser(&mut self, parser: Box<dyn BufferParser>) {
+        self.context.borrow_mut().parser = parser;
+    }
+
+    /// Compiles the script and runs its top level code.
+    pub fn load(&mut self, path: &Path, from_address: bool) -> Result<(), Box<dyn Error>> {
+        let source = fs::read_to_string(path)?;
+        let name = path.file_name().map_or_else(
+            || path.display().to_string(),
+            |n| n.to_string_lossy().to_string(),
+        );
+        self.load_source(&name, &source, from_address)
+    }
+
+    pub fn load_source(
+        &mut self,
+        name: &str,
+        source: &str,
+        from_address: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        let ast = self.engine.compile(source)?;
+        let mut scope = Scope::new();
+        self.engine.run_ast_with_scope(&mut scope, &ast)?;
+        self.scripts.retain(|s| s.name != name);
+        self.scripts.push(LoadedScript {
+            name: name.to_string(),
+            from_address,
+            ast,
+            scope,
+            this: Dynamic::from_map(Map::new()),
+        });
+        Ok(())
+    }
+
+    pub fn unload_address_scripts(&mut self) {
+        self.scripts.retain(|s| !s.from_address);
+    }
+
+    pub fn has_hook(&self, hook: &str) -> bool {
+        self.scripts
+            .iter()
+            .any(|s| s.ast.iter_functions().any(|f| f.name == hook))
+    }
+
+    /// Calls `hook` in every script that has it. A script that fails gets unloaded,
+    /// the errors are returned.
+    pub fn call_hook(&mut self, hook: &str, args: impl FuncArgs + Clone) -> Vec<String> {
+        let mut errors = Vec::new();
+        let engine = &self.engine;
+        self.scripts.retain_mut(|script| {
+            if !script.ast.iter_functions().any(|f| f.name == hook) {
+                return true;
+            }
+            let options = CallFnOptions::new()
+                .eval_ast(false)
+                .bind_this_ptr(&mut script.this);
+            match engine.call_fn_with_options::<Dynamic>(
+                options,
+                &mut script.scope,
+                &script.ast,
+                hook,
+                args.clone(),
+            ) {
+                Ok(_) => true,
+                Err(err) => {
+                    errors.push(format!("{}: {err}", script.name));
+                    false
+                }
+            }
+        });
+        errors
+    }
+
+    pub fn take_commands(&mut self) -> Vec<ScriptCommand> {
+        std::mem::take(&mut self.context.borrow_mut().commands)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rhai::Array;
+
+    use crate::{
+        protocol::TransferType,
+        scripting::{ScriptCommand, ScriptHost},
+        ui::screen_modes::ScreenMode,
+    };
+
+    #[test]
+    fn test_hooks() {
+        let mut host = ScriptHost::new(None);
+        host.load_source(
+            "more.rhai",
+            r#"
+            message("loaded");
+            fn on_connect() { this.pages = 0; }
+            fn on_text(text) {
+                if text.contains("More?") {
+                    this.pages += 1;
+                    send("\r");
+                    if this.pages == 2 { set_screen_mode("vga 80x50"); }
+                }
+            }
+            "#,
+            true,
+        )
+        .unwrap();
+        assert_eq!(
+            vec![ScriptCommand::Message("loaded".to_string())],
+            host.take_commands()
+        );
+        assert!(host.has_hook("on_text"));
+        assert!(!host.has_hook("on_key"));
+
+        assert!(host.call_hook("on_connect", ()).is_empty());
+        assert!(host
+            .call_hook("on_text", ("foo More? ".to_string(),))
+            .is_empty());
+        assert!(host.call_hook("on_text", ("bar".to_string(),)).is_empty());
+        assert!(host.call_hook("on_text", ("More?".to_string(),)).is_empty());
+        assert_eq!(
+            vec![
+                ScriptCommand::Send("\r".to_string()),
+                ScriptCommand::Send("\r".to_string()),
+                ScriptCommand::SetScreenMode(ScreenMode::Vga(80, 50)),
+            ],
+            host.take_commands()
+        );
+
+        host.unload_address_scripts();
+        assert!(host.scripts.is_empty());
+    }
+
+    #[test]
+    fn test_failing_script_gets_unloaded() {
+        let mut host = ScriptHost::new(None);
+        host.load_source(
+            "transfer.rhai",
+            r#"
+            fn on_transfer_finished(is_download, files) {
+                if is_download { upload("zmodem", files); } else { download("kermit"); }
+            }
+            "#,
+            false,
+        )
+        .unwrap();
+        let files: Array = vec!["/tmp/foo.zip".into()];
+        assert!(host
+            .call_hook("on_transfer_finished", (true, files.clone()))
+            .is_empty());
+        assert_eq!(
+            vec![ScriptCommand::Upload(
+                TransferType::ZModem,
+                vec!["/tmp/foo.zip".into()]
+            )],
+            host.take_commands()
+        );
+        assert_eq!(
+            1,
+            host.call_hook("on_transfer_finished", (false, files)).len()
+        );
+        assert!(host.scripts.is_empty());
+        assert!(host.load_source("broken.rhai", "fn (", false).is_err());
+    }
+}
@@ -0,0 +1,176 @@
+use crate::{
+    auto_file_transfer::PatternRecognizer,
+    protocol::{FileDescriptor, TransferType},
+};
+
+/// Files waiting for an upload. They're sent as one batch once the board is ready to receive.
+pub struct TransferQueue {
+    pub files: Vec<FileDescriptor>,
+    /// The files of the running batch.
+    pub sending: Vec<FileDescriptor>,
+    pub protocol: Option<TransferType>,
+    /// The hex ZRINIT header start.
+    zrinit: PatternRecognizer,
+    /// Punter receivers repeat GOO until the sender starts.
+    punter_goo: PatternRecognizer,
+    /// Kermit receivers NAK the send-init packet they wait for.
+    kermit_nak: PatternRecognizer,
+    last_ch: u8,
+    /// A 'C' that doesn't belong to a word, the second one in a row starts Y-Modem.
+    got_c: bool,
+}
+
+impl TransferQueue {
+    pub fn new() -> Self {
+        Self {
+            files: Vec::new(),
+            sending: Vec::new(),
+            protocol: None,
+            zrinit: PatternRecognizer::from(b"\x18B01", false),
+            punter_goo: PatternRecognizer::from(b"GOO", false),
+            kermit_nak: PatternRecognizer::from(b"\x01# N3", false),
+            last_ch: 0,
+            got_c: false,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn add(&mut self, files: Vec<FileDescriptor>) {
+        for fd in files {
+            if fd.path_name.is_empty() || !self.files.iter().any(|f| f.path_name == fd.path_name) {
+                self.files.push(fd);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index < self.files.len() {
+            self.files.remove(index);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.files.clear();
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.zrinit.reset();
+        self.punter_goo.reset();
+        self.kermit_nak.reset();
+        self.last_ch = 0;
+        self.got_c = false;
+    }
+
+    /// Moves the queued files to the running batch.
+    pub fn take_files(&mut self) -> Vec<FileDescriptor> {
+        self.reset();
+        self.sending = std::mem::take(&mut self.files);
+        self.sending.clone()
+    }
+
+    /// The protocol to start if the remote side is ready for the queued files.
+    pub fn try_start(&mut self, ch: u8) -> Option<TransferType> {
+        if self.files.is_empty() {
+            return None;
+        }
+        let protocol = self.protocol.as_ref()?;
+        let ready = if protocol.is_zmodem() {
+            self.zrinit.push_ch(ch)
+        } else if matches!(protocol, TransferType::Text(_)) {
+            false
+        } else if *protocol == TransferType::Punter {
+            self.punter_goo.push_ch(ch)
+        } else if *protocol == TransferType::Kermit {
+            self.kermit_nak.push_ch(ch)
+        } else {
+            // Receivers repeat the 'C' until the sender starts, text like "ACCESS" doesn't count.
+            let ready = if ch == b'C' {
+                let ready = self.got_c;
+                self.got_c = !self.last_ch.is_ascii_alphanumeric() || self.got_c;
+                ready
+            } else {
+                self.got_c = false;
+                false
+            };
+            self.last_ch = ch;
+            ready
+        };
+        if ready {
+            self.protocol.clone()
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        protocol::{FileDescriptor, TransferType},
+        transfer_queue::TransferQueue,
+    };
+
+    fn create_queue(protocol: TransferType) -> TransferQueue {
+        let mut queue = TransferQueue::new();
+        queue.protocol = Some(protocol);
+        queue.add(vec![FileDescriptor::create_test(
+            "foo.bar".to_string(),
+            b"foo".to_vec(),
+        )]);
+        queue
+    }
+
+    fn feed(queue: &mut TransferQueue, data: &[u8]) -> Option<TransferType> {
+        let mut result = None;
+        for b in data {
+            if let Some(protocol) = queue.try_start(*b) {
+                result = Some(protocol);
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn test_start_on_zrinit() {
+        let mut queue = create_queue(TransferType::ZedZap);
+        assert_eq!(None, feed(&mut queue, b"Begin your upload\r\nC"));
+        assert_eq!(
+            Some(TransferType::ZedZap),
+            feed(&mut queue, b"**\x18B0100000023be50\r\n\x11")
+        );
+        assert_eq!(1, queue.take_files().len());
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_start_on_ymodem_c() {
+        let mut queue = create_queue(TransferType::YModem);
+        assert_eq!(None, feed(&mut queue, b"ACCESS GRANTED\r\nC"));
+        assert_eq!(Some(TransferType::YModem), feed(&mut queue, b"C"));
+    }
+
+    #[test]
+    fn test_start_on_punter_goo() {
+        let mut queue = create_queue(TransferType::Punter);
+        assert_eq!(None, feed(&mut queue, b"Ready to GO"));
+        assert_eq!(Some(TransferType::Punter), feed(&mut queue, b"O"));
+    }
+
+    #[test]
+    fn test_start_on_kermit_nak() {
+        let mut queue = create_queue(TransferType::Kermit);
+        assert_eq!(None, feed(&mut queue, b"Return to send\r\n\x01# N"));
+        assert_eq!(Some(TransferType::Kermit), feed(&mut queue, b"3\r"));
+    }
+
+    #[test]
+    fn test_empty_queue_never_starts() {
+        let mut queue = TransferQueue::new();
+        queue.protocol = Some(TransferType::ZModem);
+        assert_eq!(None, feed(&mut queue, b"**\x18B0100000023be50\r\n"));
+    }
+}
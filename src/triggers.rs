@@ -0,0 +1,268 @@
+//! User defined triggers on the received text. Plain text patterns ignore the case,
+//! regular expressions are taken as they are. Escape sequences don't count as text and
+//! a match can't span lines.
+use regex::Regex;
+use toml::Value;
+
+use crate::address_mod::escape;
+
+/// Longer lines only keep their end for matching.
+const MAX_LINE_LENGTH: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Trigger {
+    pub pattern: String,
+    pub is_regex: bool,
+    /// Sent when the pattern matches, same escapes and variables as `send` in login scripts.
+    pub send: String,
+    pub sound: bool,
+    pub highlight: bool,
+    pub notify: bool,
+    pub capture: bool,
+}
+
+impl Trigger {
+    pub fn create_regex(&self) -> Result<Regex, regex::Error> {
+        if self.is_regex {
+            Regex::new(&self.pattern)
+        } else {
+            Regex::new(&format!("(?i){}", regex::escape(&self.pattern)))
+        }
+    }
+
+    pub fn to_toml(&self) -> String {
+        let mut result = format!("{{ pattern = \"{}\"", escape(&self.pattern));
+        if self.is_regex {
+            result.push_str(", regex = true");
+        }
+        if !self.send.is_empty() {
+            result.push_str(&format!(", send = \"{}\"", escape(&self.send)));
+        }
+        for (name, set) in [
+            ("sound", self.sound),
+            ("highlight", self.highlight),
+            ("notify", self.notify),
+            ("capture", self.capture),
+        ] {
+            if set {
+                result.push_str(&format!(", {name} = true"));
+            }
+        }
+        result.push_str(" }");
+        result
+    }
+}
+
+pub fn parse_trigger(value: &Value) -> Option<Trigger> {
+    let Value::Table(table) = value else {
+        return None;
+    };
+    let Some(Value::String(pattern)) = table.get("pattern") else {
+        return None;
+    };
+    let get_bool = |key| matches!(table.get(key), Some(Value::Boolean(true)));
+    let send = match table.get("send") {
+        Some(Value::String(send)) => send.clone(),
+        _ => String::new(),
+    };
+    Some(Trigger {
+        pattern: pattern.clone(),
+        is_regex: get_bool("regex"),
+        send,
+        sound: get_bool("sound"),
+        highlight: get_bool("highlight"),
+        notify: get_bool("notify"),
+        capture: get_bool("capture"),
+    })
+}
+
+pub fn triggers_to_toml(triggers: &[Trigger]) -> String {
+    let triggers: Vec<String> = triggers
+        .iter()
+        .filter(|t| !t.pattern.is_empty())
+        .map(Trigger::to_toml)
+        .collect();
+    format!("[{}]", triggers.join(", "))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TriggerMatch {
+    pub trigger: Trigger,
+    pub text: String,
+    /// The number of screen cells the text takes, they end at the caret.
+    pub len: usize,
+}
+
+#[derive(Default)]
+enum EscapeState {
+    #[default]
+    Text,
+    Escape,
+    Csi,
+}
+
+#[derive(Default)]
+pub struct TriggerMatcher {
+    triggers: Vec<(Trigger, Regex)>,
+    line: String,
+    state: EscapeState,
+}
+
+impl TriggerMatcher {
+    /// Triggers with an empty or invalid pattern are left out.
+    pub fn new<'a>(triggers: impl IntoIterator<Item = &'a Trigger>) -> Self {
+        Self {
+            triggers: triggers
+                .into_iter()
+                .filter(|t| !t.pattern.is_empty())
+                .filter_map(|t| t.create_regex().ok().map(|r| (t.clone(), r)))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.triggers.is_empty()
+    }
+
+    /// Feeds one decoded char, returns the trigger the line now ends with.
+    pub fn push_char(&mut self, ch: char) -> Option<TriggerMatch> {
+        if self.triggers.is_empty() {
+            return None;
+        }
+        match self.state {
+            EscapeState::Escape => {
+                self.state = if ch == '[' {
+                    EscapeState::Csi
+                } else {
+                    EscapeState::Text
+                };
+                return None;
+            }
+            EscapeState::Csi => {
+                if ('@'..='~').contains(&ch) {
+                    self.state = EscapeState::Text;
+                }
+                return None;
+            }
+            EscapeState::Text => {}
+        }
+        match ch {
+            '\x1B' => {
+                self.state = EscapeState::Escape;
+                return None;
+            }
+            '\r' | '\n' => {
+                self.line.clear();
+                return None;
+            }
+            '\x08' => {
+                self.line.pop();
+                return None;
+            }
+            ch if ch.is_control() => return None,
+            ch => self.line.push(ch),
+        }
+        if self.line.len() > MAX_LINE_LENGTH * 2 {
+            let cut = self.line.len() - MAX_LINE_LENGTH;
+            let cut = (cut..self.line.len())
+                .find(|i| self.line.is_char_boundary(*i))
+                .unwrap_or(0);
+            self.line.drain(..cut);
+        }
+
+        for (trigger, regex) in &self.triggers {
+            let found = regex
+                .find_iter(&self.line)
+                .find(|m| m.end() == self.line.len() && !m.is_empty());
+            if let Some(m) = found {
+                let text = m.as_str().to_string();
+                self.line.clear();
+                return Some(TriggerMatch {
+                    trigger: trigger.clone(),
+                    len: text.chars().count(),
+                    text,
+                });
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::triggers::{parse_trigger, Trigger, TriggerMatcher};
+
+    fn push_str(matcher: &mut TriggerMatcher, text: &str) -> Vec<String> {
+        text.chars()
+            .filter_map(|ch| matcher.push_char(ch))
+            .map(|m| m.text)
+            .collect()
+    }
+
+    #[test]
+    fn test_plain_text() {
+        let trigger = Trigger {
+            pattern: "press any key".to_string(),
+            ..Default::default()
+        };
+        let mut matcher = TriggerMatcher::new([&trigger]);
+        assert_eq!(
+            vec!["Press any KEY".to_string()],
+            push_str(&mut matcher, "Press \x1B[1;33many\x1B[0m KEY to continue")
+        );
+        // Only once per appearance and not across lines.
+        assert!(push_str(&mut matcher, " press any\r\nkey").is_empty());
+    }
+
+    #[test]
+    fn test_regex() {
+        let triggers = [
+            Trigger {
+                pattern: r"More\? \[Y/n\]".to_string(),
+                is_regex: true,
+                send: "\\r".to_string(),
+                ..Default::default()
+            },
+            Trigger {
+                pattern: "(?i)sysop.*pag".to_string(),
+                is_regex: true,
+                notify: true,
+                ..Default::default()
+            },
+            Trigger {
+                pattern: "(".to_string(),
+                is_regex: true,
+                ..Default::default()
+            },
+        ];
+        let mut matcher = TriggerMatcher::new(&triggers);
+        let matches: Vec<_> = "The SysOp is paging you\r\nMore? [Y/n] "
+            .chars()
+            .filter_map(|ch| matcher.push_char(ch))
+            .collect();
+        assert_eq!(2, matches.len());
+        assert_eq!("SysOp is pag", matches[0].text);
+        assert!(matches[0].trigger.notify);
+        assert_eq!("More? [Y/n]", matches[1].text);
+        assert_eq!(11, matches[1].len);
+    }
+
+    #[test]
+    fn test_toml() {
+        let trigger = Trigger {
+            pattern: "\"More\"".to_string(),
+            send: "\\r".to_string(),
+            sound: true,
+            capture: true,
+            ..Default::default()
+        };
+        let value = format!("triggers = [{}]", trigger.to_toml())
+            .parse::<toml::Value>()
+            .unwrap();
+        let toml::Value::Array(values) = &value["triggers"] else {
+            panic!("no array");
+        };
+        assert_eq!(Some(trigger), parse_trigger(&values[0]));
+    }
+}
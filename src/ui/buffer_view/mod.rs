@@ -173,6 +173,32 @@ impl BufferView {
         Some(res)
     }
 
+    /// Inverts the `len` cells before the caret, where the text printed last went.
+    pub fn highlight_before_caret(&mut self, len: usize) {
+        let width = self.buf.get_buffer_width();
+        let mut pos = self.caret.get_position();
+        for _ in 0..len {
+            pos.x -= 1;
+            if pos.x < 0 {
+                pos.x = width - 1;
+                pos.y -= 1;
+                if pos.y < 0 {
+                    break;
+                }
+            }
+            let Some(line) = self.buf.layers[0].lines.get_mut(pos.y as usize) else {
+                continue;
+            };
+            if let Some(ch) = line.chars.get_mut(pos.x as usize) {
+                let fg = ch.attribute.get_foreground();
+                let bg = ch.attribute.get_background();
+                ch.attribute.set_foreground(bg);
+                ch.attribute.set_background(fg);
+            }
+        }
+        self.redraw_view();
+    }
+
     pub fn redraw_view(&mut self) {
         self.terminal_renderer.redraw_terminal();
     }
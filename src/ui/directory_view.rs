@@ -0,0 +1,234 @@
+use std::path::Path;
+
+use eframe::{
+    egui::{self, RichText, TextEdit},
+    epaint::Color32,
+};
+use egui_extras::{Column, TableBuilder};
+use i18n_embed_fl::fl;
+
+use crate::{
+    address_mod::Terminal,
+    bbs_directory::{get_values, load_directory, DirectoryEntry, DirectoryFilter},
+    phonebook_io::get_host_key,
+    TerminalResult,
+};
+
+use super::main_window_mod::{MainWindow, MainWindowMode};
+
+/// The terminals the listings tell apart.
+const FILTER_TERMINALS: [Terminal; 4] = [
+    Terminal::Ansi,
+    Terminal::PETscii,
+    Terminal::ATAscii,
+    Terminal::ViewData,
+];
+
+/// A BBS listing loaded from disk, to add systems to the phonebook.
+#[derive(Default)]
+pub struct BbsDirectory {
+    pub entries: Vec<DirectoryEntry>,
+    pub filter: DirectoryFilter,
+    software: Vec<String>,
+    countries: Vec<String>,
+    /// What happened to the last added entry.
+    message: Option<(String, bool)>,
+}
+
+impl BbsDirectory {
+    pub fn load(&mut self, path: &Path) -> TerminalResult<()> {
+        self.entries = load_directory(path)?;
+        self.software = get_values(&self.entries, |e| &e.software);
+        self.countries = get_values(&self.entries, |e| &e.country);
+        self.filter = DirectoryFilter::default();
+        self.message = None;
+        Ok(())
+    }
+}
+
+fn filter_combobox(ui: &mut egui::Ui, id: &str, value: &mut String, values: &[String]) {
+    let all = fl!(crate::LANGUAGE_LOADER, "directory-filter-all");
+    egui::ComboBox::from_id_source(id)
+        .selected_text(if value.is_empty() {
+            all.as_str()
+        } else {
+            value.as_str()
+        })
+        .width(140.)
+        .show_ui(ui, |ui| {
+            ui.selectable_value(value, String::new(), all.as_str());
+            for v in values {
+                ui.selectable_value(value, v.clone(), v.as_str());
+            }
+        });
+}
+
+pub fn view_directory(window: &mut MainWindow, ctx: &egui::Context) {
+    let mut open = true;
+    let mut add = None;
+    egui::Window::new(fl!(crate::LANGUAGE_LOADER, "directory-heading"))
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(true)
+        .default_width(760.)
+        .show(ctx, |ui| {
+            let directory = &mut window.directory;
+            ui.horizontal(|ui| {
+                if ui
+                    .button(fl!(crate::LANGUAGE_LOADER, "directory-open"))
+                    .clicked()
+                {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .add_filter("BBS list", &["csv", "xml"])
+                        .pick_file()
+                    {
+                        if let Err(err) = directory.load(&path) {
+                            directory.message = Some((err.to_string(), true));
+                        }
+                    }
+                }
+                ui.add(
+                    TextEdit::singleline(&mut directory.filter.text)
+                        .hint_text(fl!(crate::LANGUAGE_LOADER, "phonebook-filter-placeholder")),
+                );
+            });
+            ui.horizontal(|ui| {
+                ui.label(fl!(crate::LANGUAGE_LOADER, "directory-software"));
+                filter_combobox(
+                    ui,
+                    "directory_software",
+                    &mut directory.filter.software,
+                    &directory.software,
+                );
+                ui.label(fl!(crate::LANGUAGE_LOADER, "directory-terminal"));
+                let all = fl!(crate::LANGUAGE_LOADER, "directory-filter-all");
+                egui::ComboBox::from_id_source("directory_terminal")
+                    .selected_text(
+                        directory
+                            .filter
+                            .terminal_type
+                            .map_or(all.clone(), |t| t.to_string()),
+                    )
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(
+                            &mut directory.filter.terminal_type,
+                            None,
+                            all.as_str(),
+                        );
+                        for t in FILTER_TERMINALS {
+                            ui.selectable_value(
+                                &mut directory.filter.terminal_type,
+                                Some(t),
+                                t.to_string(),
+                            );
+                        }
+                    });
+                ui.label(fl!(crate::LANGUAGE_LOADER, "directory-country"));
+                filter_combobox(
+                    ui,
+                    "directory_country",
+                    &mut directory.filter.country,
+                    &directory.countries,
+                );
+            });
+            if let Some((message, is_error)) = &directory.message {
+                let color = if *is_error {
+                    Color32::RED
+                } else {
+                    Color32::LIGHT_GREEN
+                };
+                ui.colored_label(color, message.as_str());
+            }
+            ui.separator();
+            if directory.entries.is_empty() {
+                ui.label(RichText::new(fl!(crate::LANGUAGE_LOADER, "directory-empty")).italics());
+                return;
+            }
+
+            let entries: Vec<(usize, &DirectoryEntry)> = directory
+                .entries
+                .iter()
+                .enumerate()
+                .filter(|(_, e)| directory.filter.matches(e))
+                .collect();
+            TableBuilder::new(ui)
+                .striped(true)
+                .max_scroll_height(400.)
+                .column(Column::auto())
+                .column(Column::remainder().at_least(120.))
+                .column(Column::auto())
+                .column(Column::auto())
+                .column(Column::auto())
+                .column(Column::auto())
+                .header(20.0, |mut header| {
+                    header.col(|_| {});
+                    for label in [
+                        fl!(crate::LANGUAGE_LOADER, "directory-name"),
+                        fl!(crate::LANGUAGE_LOADER, "phonebook-address"),
+                        fl!(crate::LANGUAGE_LOADER, "directory-software"),
+                        fl!(crate::LANGUAGE_LOADER, "directory-country"),
+                        fl!(crate::LANGUAGE_LOADER, "directory-terminal"),
+                    ] {
+                        header.col(|ui| {
+                            ui.label(label);
+                        });
+                    }
+                })
+                .body(|body| {
+                    body.rows(18.0, entries.len(), |i, mut row| {
+                        let (index, entry) = entries[i];
+                        row.col(|ui| {
+                            if ui
+                                .small_button("➕")
+                                .on_hover_text(fl!(crate::LANGUAGE_LOADER, "directory-add"))
+                                .clicked()
+                            {
+                                add = Some(index);
+                            }
+                        });
+                        row.col(|ui| {
+                            ui.label(entry.name.as_str())
+                                .on_hover_text(entry.comment.as_str());
+                        });
+                        row.col(|ui| {
+                            ui.label(entry.address.as_str());
+                        });
+                        row.col(|ui| {
+                            ui.label(entry.software.as_str());
+                        });
+                        row.col(|ui| {
+                            ui.label(entry.country.as_str());
+                        });
+                        row.col(|ui| {
+                            ui.label(entry.terminal_type.to_string());
+                        });
+                    });
+                });
+        });
+
+    if let Some(i) = add {
+        let adr = window.directory.entries[i].to_address();
+        let key = get_host_key(&adr);
+        let message = if window.addresses.iter().any(|a| get_host_key(a) == key) {
+            let message = fl!(
+                crate::LANGUAGE_LOADER,
+                "directory-already-added",
+                name = adr.system_name.clone()
+            );
+            (message, true)
+        } else {
+            let message = fl!(
+                crate::LANGUAGE_LOADER,
+                "directory-added",
+                name = adr.system_name.clone()
+            );
+            window.addresses.push(adr);
+            super::store_phonebook(window);
+            (message, false)
+        };
+        window.directory.message = Some(message);
+    }
+    if !open {
+        window.mode = MainWindowMode::ShowPhonebook;
+    }
+}
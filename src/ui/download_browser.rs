@@ -0,0 +1,275 @@
+use std::path::PathBuf;
+
+use eframe::egui::{self, text::LayoutJob, RichText, TextFormat};
+use eframe::epaint::{Color32, FontId};
+use egui_extras::{Column, TableBuilder};
+use gabi::BytesConfig;
+use i18n_embed_fl::fl;
+use icy_engine::{Buffer, BufferParser, Caret, Palette, Position};
+
+use crate::recent_downloads::RecentDownloads;
+
+use super::main_window_mod::{MainWindow, MainWindowMode};
+
+#[derive(Default)]
+pub struct DownloadBrowser {
+    pub downloads: RecentDownloads,
+    selected: usize,
+    confirm_delete: bool,
+    /// The rendered description of the selected download.
+    preview: Option<(PathBuf, LayoutJob)>,
+}
+
+/// Runs the description through the parser of the board so ANSI and CP437 look like they would online.
+fn render_description(
+    parser: &mut Box<dyn BufferParser>,
+    palette: Palette,
+    data: &[u8],
+) -> LayoutJob {
+    let mut buf = Buffer::create(80, 25);
+    buf.palette = palette;
+    let mut caret = Caret::default();
+    for b in data {
+        // DOS end of file, a SAUCE record may follow.
+        if *b == 0x1A
+            || parser
+                .print_char(&mut buf, &mut caret, char::from(*b))
+                .is_err()
+        {
+            break;
+        }
+    }
+
+    let font_id = FontId::monospace(14.0);
+    let get_color = |c: u32| {
+        buf.palette
+            .colors
+            .get(c as usize)
+            .map_or(Color32::LIGHT_GRAY, |c| {
+                let (r, g, b) = c.get_rgb();
+                Color32::from_rgb(r, g, b)
+            })
+    };
+    let height = (0..buf.get_real_buffer_height())
+        .rev()
+        .find(|y| buf.get_line_length(*y) > 0)
+        .map_or(0, |y| y + 1);
+    let mut job = LayoutJob::default();
+    for y in 0..height {
+        for x in 0..buf.get_line_length(y) {
+            let ch = buf.get_char(Position::new(x, y)).unwrap_or_default();
+            let mut fg = ch.attribute.get_foreground();
+            if ch.attribute.is_bold() {
+                fg += 8;
+            }
+            job.append(
+                &parser.convert_to_unicode(ch.ch).to_string(),
+                0.0,
+                TextFormat {
+                    font_id: font_id.clone(),
+                    color: get_color(fg),
+                    background: get_color(ch.attribute.get_background()),
+                    ..Default::default()
+                },
+            );
+        }
+        job.append(
+            "\n",
+            0.0,
+            TextFormat::simple(font_id.clone(), Color32::BLACK),
+        );
+    }
+    job
+}
+
+pub fn view_download_browser(window: &mut MainWindow, ctx: &egui::Context) {
+    let bb = BytesConfig::default();
+    let mut open = true;
+    let mut select = None;
+    let mut open_folder = None;
+    let mut delete = false;
+
+    let mut new_preview = None;
+    if let Some(download) = window
+        .download_browser
+        .downloads
+        .downloads
+        .get_mut(window.download_browser.selected)
+    {
+        let is_current = window
+            .download_browser
+            .preview
+            .as_ref()
+            .map_or(false, |(path, _)| *path == download.path);
+        if !is_current {
+            let description = download
+                .get_info()
+                .as_ref()
+                .ok()
+                .and_then(|info| info.description.clone());
+            new_preview = Some((download.path.clone(), description));
+        }
+    }
+    if let Some((path, description)) = new_preview {
+        let job = description.map_or_else(LayoutJob::default, |data| {
+            let palette = Palette {
+                colors: window.buffer_view.lock().buf.palette.colors.clone(),
+            };
+            render_description(&mut window.create_terminal_parser(), palette, &data)
+        });
+        window.download_browser.preview = Some((path, job));
+    }
+
+    egui::Window::new(fl!(crate::LANGUAGE_LOADER, "downloads-heading"))
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(true)
+        .default_width(640.)
+        .show(ctx, |ui| {
+            let browser = &mut window.download_browser;
+            if browser.downloads.is_empty() {
+                ui.label(fl!(crate::LANGUAGE_LOADER, "downloads-empty"));
+                return;
+            }
+            ui.horizontal_top(|ui| {
+                egui::ScrollArea::vertical()
+                    .id_source("download_list")
+                    .max_height(400.)
+                    .max_width(200.)
+                    .show(ui, |ui| {
+                        ui.vertical(|ui| {
+                            for (i, download) in browser.downloads.downloads.iter().enumerate() {
+                                let label = RichText::new(&download.file_name);
+                                if ui
+                                    .selectable_label(i == browser.selected, label)
+                                    .on_hover_text(bb.bytes(download.size as u64).to_string())
+                                    .clicked()
+                                {
+                                    select = Some(i);
+                                }
+                            }
+                        });
+                    });
+                ui.separator();
+
+                let Some(download) = browser.downloads.downloads.get_mut(browser.selected) else {
+                    return;
+                };
+                ui.vertical(|ui| {
+                    ui.label(RichText::new(download.path.to_string_lossy()).color(Color32::WHITE));
+                    ui.horizontal(|ui| {
+                        if ui
+                            .button(fl!(crate::LANGUAGE_LOADER, "downloads-open-folder"))
+                            .clicked()
+                        {
+                            open_folder = download.path.parent().map(PathBuf::from);
+                        }
+                        if browser.confirm_delete {
+                            ui.label(fl!(
+                                crate::LANGUAGE_LOADER,
+                                "downloads-delete-question",
+                                file = download.file_name.clone()
+                            ));
+                            if ui
+                                .button(fl!(crate::LANGUAGE_LOADER, "downloads-delete"))
+                                .clicked()
+                            {
+                                delete = true;
+                                browser.confirm_delete = false;
+                            }
+                            if ui
+                                .button(fl!(crate::LANGUAGE_LOADER, "phonebook-cancel-button"))
+                                .clicked()
+                            {
+                                browser.confirm_delete = false;
+                            }
+                        } else if ui
+                            .button(fl!(crate::LANGUAGE_LOADER, "downloads-delete"))
+                            .clicked()
+                        {
+                            browser.confirm_delete = true;
+                        }
+                    });
+
+                    if let Some((_, job)) = &browser.preview {
+                        if !job.is_empty() {
+                            ui.separator();
+                            egui::Frame::none()
+                                .fill(Color32::BLACK)
+                                .inner_margin(egui::style::Margin::same(4.0))
+                                .show(ui, |ui| {
+                                    ui.label(job.clone());
+                                });
+                        }
+                    }
+
+                    match download.archive_type {
+                        Some(archive_type) if archive_type.can_list() => {}
+                        Some(_) => {
+                            ui.label(fl!(crate::LANGUAGE_LOADER, "downloads-unsupported"));
+                            return;
+                        }
+                        None => return,
+                    }
+                    ui.separator();
+                    match download.get_info() {
+                        Ok(info) => {
+                            TableBuilder::new(ui)
+                                .striped(true)
+                                .max_scroll_height(200.)
+                                .column(Column::remainder())
+                                .column(Column::auto())
+                                .header(20.0, |mut header| {
+                                    header.col(|ui| {
+                                        ui.label(fl!(crate::LANGUAGE_LOADER, "downloads-name"));
+                                    });
+                                    header.col(|ui| {
+                                        ui.label(fl!(crate::LANGUAGE_LOADER, "downloads-size"));
+                                    });
+                                })
+                                .body(|mut body| {
+                                    for entry in &info.entries {
+                                        body.row(18.0, |mut row| {
+                                            row.col(|ui| {
+                                                ui.label(entry.name.as_str());
+                                            });
+                                            row.col(|ui| {
+                                                ui.label(bb.bytes(entry.size).to_string());
+                                            });
+                                        });
+                                    }
+                                });
+                        }
+                        Err(err) => {
+                            ui.colored_label(Color32::RED, err.as_str());
+                        }
+                    }
+                });
+            });
+        });
+
+    if let Some(i) = select {
+        window.download_browser.selected = i;
+        window.download_browser.confirm_delete = false;
+    }
+    if let Some(dir) = open_folder {
+        window.handle_result(open::that(dir).map_err(Into::into), false);
+    }
+    if delete {
+        let selected = window.download_browser.selected;
+        let res = window.download_browser.downloads.delete(selected);
+        window.download_browser.selected = selected.min(
+            window
+                .download_browser
+                .downloads
+                .downloads
+                .len()
+                .saturating_sub(1),
+        );
+        window.download_browser.preview = None;
+        window.handle_result(res.map_err(Into::into), false);
+    }
+    if !open {
+        window.mode = MainWindowMode::ShowTerminal;
+    }
+}
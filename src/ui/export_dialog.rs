@@ -0,0 +1,151 @@
+use std::fs;
+
+use eframe::{
+    egui::{self, RichText, ScrollArea},
+    epaint::Color32,
+};
+use i18n_embed_fl::fl;
+
+use crate::{
+    address_mod::Address,
+    credentials::CredentialError,
+    phonebook_io::export::{strip_credentials, ExportFormat},
+};
+
+use super::{main_window_mod::MainWindow, UnlockDialog};
+
+/// Exports the checked addresses, by default without the credentials.
+pub struct ExportDialog {
+    /// Ids of the checked addresses.
+    selected: Vec<usize>,
+    format: ExportFormat,
+    strip_credentials: bool,
+    error: Option<String>,
+}
+
+impl ExportDialog {
+    pub fn new(selected: Option<usize>) -> Self {
+        Self {
+            selected: selected.into_iter().collect(),
+            format: ExportFormat::Bundle,
+            strip_credentials: true,
+            error: None,
+        }
+    }
+}
+
+fn get_format_label(format: ExportFormat) -> String {
+    match format {
+        ExportFormat::SyncTerm => fl!(crate::LANGUAGE_LOADER, "export-format-syncterm"),
+        ExportFormat::Bundle => fl!(crate::LANGUAGE_LOADER, "export-format-bundle"),
+        ExportFormat::Csv => fl!(crate::LANGUAGE_LOADER, "export-format-csv"),
+    }
+}
+
+pub fn show_export_dialog(window: &mut MainWindow, ctx: &egui::Context) {
+    let Some(dialog) = &mut window.export_dialog else {
+        return;
+    };
+    let mut open = true;
+    let mut export = false;
+    egui::Window::new(RichText::new(fl!(crate::LANGUAGE_LOADER, "export-heading")))
+        .open(&mut open)
+        .collapsible(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui
+                    .button(fl!(crate::LANGUAGE_LOADER, "export-select-all"))
+                    .clicked()
+                {
+                    dialog.selected = window.addresses.iter().skip(1).map(|a| a.id).collect();
+                }
+                if ui
+                    .button(fl!(crate::LANGUAGE_LOADER, "export-select-none"))
+                    .clicked()
+                {
+                    dialog.selected.clear();
+                }
+            });
+            ScrollArea::vertical().max_height(300.).show(ui, |ui| {
+                for adr in window.addresses.iter().skip(1) {
+                    let mut checked = dialog.selected.contains(&adr.id);
+                    if ui.checkbox(&mut checked, &adr.system_name).changed() {
+                        if checked {
+                            dialog.selected.push(adr.id);
+                        } else {
+                            dialog.selected.retain(|id| *id != adr.id);
+                        }
+                    }
+                }
+            });
+            ui.separator();
+            ui.horizontal(|ui| {
+                for format in ExportFormat::ALL {
+                    ui.radio_value(&mut dialog.format, format, get_format_label(format));
+                }
+            });
+            ui.checkbox(
+                &mut dialog.strip_credentials,
+                fl!(crate::LANGUAGE_LOADER, "export-strip-credentials"),
+            );
+            if let Some(err) = &dialog.error {
+                ui.colored_label(Color32::RED, err);
+            }
+            ui.separator();
+            export = ui
+                .add_enabled(
+                    !dialog.selected.is_empty(),
+                    egui::Button::new(fl!(crate::LANGUAGE_LOADER, "export-button")),
+                )
+                .clicked();
+        });
+    if !open {
+        window.export_dialog = None;
+        return;
+    }
+    if !export {
+        return;
+    }
+
+    let mut addresses: Vec<Address> = window
+        .addresses
+        .iter()
+        .skip(1)
+        .filter(|adr| dialog.selected.contains(&adr.id))
+        .cloned()
+        .collect();
+    for adr in &mut addresses {
+        if dialog.strip_credentials {
+            strip_credentials(adr);
+            continue;
+        }
+        match window.credentials.open(&adr.password) {
+            Ok(password) => adr.password = password,
+            Err(CredentialError::Locked) => {
+                window.unlock_dialog = Some(UnlockDialog::unlock(None));
+                return;
+            }
+            Err(err) => {
+                dialog.error = Some(err.to_string());
+                return;
+            }
+        }
+    }
+    let extension = dialog.format.extension();
+    let Some(path) = rfd::FileDialog::new()
+        .add_filter(&get_format_label(dialog.format), &[extension])
+        .set_file_name(&format!("phonebook.{extension}"))
+        .save_file()
+    else {
+        return;
+    };
+    match dialog
+        .format
+        .export(&addresses)
+        .and_then(|text| Ok(fs::write(path, text)?))
+    {
+        Ok(()) => window.export_dialog = None,
+        Err(err) => dialog.error = Some(err.to_string()),
+    }
+}
@@ -1,6 +1,6 @@
 use std::cmp::max;
 use std::sync::{Arc, Mutex};
-use std::time::{Duration, SystemTime};
+use std::time::SystemTime;
 
 use eframe::egui::{self, ProgressBar, RichText};
 use eframe::epaint::Color32;
@@ -8,13 +8,25 @@ use egui_extras::{Column, TableBuilder};
 use gabi::BytesConfig;
 use i18n_embed_fl::fl;
 
-use crate::protocol::TransferState;
+use crate::protocol::{CollisionPolicy, FileDescriptor, TransferInformation, TransferState};
+
+use super::main_window_mod::{MainWindow, MainWindowMode};
+
+pub fn get_collision_policy_label(policy: CollisionPolicy) -> String {
+    match policy {
+        CollisionPolicy::Rename => fl!(crate::LANGUAGE_LOADER, "collision-policy-rename"),
+        CollisionPolicy::Overwrite => fl!(crate::LANGUAGE_LOADER, "collision-policy-overwrite"),
+        CollisionPolicy::Skip => fl!(crate::LANGUAGE_LOADER, "collision-policy-skip"),
+        CollisionPolicy::Ask => fl!(crate::LANGUAGE_LOADER, "collision-policy-ask"),
+    }
+}
 
 pub fn view_filetransfer(
     ctx: &egui::Context,
     _frame: &mut eframe::Frame,
     transfer_state: &Arc<Mutex<TransferState>>,
     download: bool,
+    queue: &[FileDescriptor],
 ) -> bool {
     let mut open = true;
     let title = RichText::new(if download {
@@ -28,7 +40,33 @@ pub fn view_filetransfer(
         .collapsible(false)
         .resizable(false)
         .show(ctx, |ui| {
-            let state = transfer_state.lock().unwrap();
+            let mut state = transfer_state.lock().unwrap();
+            if let Some(collision) = &mut state.collision {
+                if collision.answer.is_none() {
+                    let file = collision.file_name.clone();
+                    ui.label(
+                        RichText::new(fl!(
+                            crate::LANGUAGE_LOADER,
+                            "transfer-file-exists",
+                            file = file
+                        ))
+                        .color(Color32::WHITE),
+                    );
+                    ui.horizontal(|ui| {
+                        for policy in [
+                            CollisionPolicy::Rename,
+                            CollisionPolicy::Overwrite,
+                            CollisionPolicy::Skip,
+                        ] {
+                            if ui.button(get_collision_policy_label(policy)).clicked() {
+                                collision.answer = Some(policy);
+                            }
+                        }
+                    });
+                    ui.separator();
+                }
+            }
+
             let transfer_info = if download {
                 &state.recieve_state
             } else {
@@ -39,11 +77,17 @@ pub fn view_filetransfer(
             let file_name = transfer_info.file_name.clone();
             let current_state = state.current_state.to_string();
 
-            let bps = transfer_info.get_bps();
-            let bytes_left = transfer_info
-                .file_size
-                .saturating_sub(transfer_info.bytes_transfered);
-            let time_left = Duration::from_secs(bytes_left as u64 / max(1, bps));
+            let bps = transfer_info.get_cps();
+            let time_left = transfer_info.get_time_left().map_or_else(
+                || "--:--".to_string(),
+                |time_left| {
+                    format!(
+                        "{:02}:{:02}",
+                        time_left.as_secs() / 60,
+                        time_left.as_secs() % 60
+                    )
+                },
+            );
 
             let bb = BytesConfig::default();
 
@@ -53,19 +97,6 @@ pub fn view_filetransfer(
                 elapsed_time.as_secs() / 60,
                 elapsed_time.as_secs() % 60
             );
-            /*
-            let log = column(
-                transfer_state
-                    .output_log
-                    .iter()
-                    .rev()
-                    .take(1)
-                    .rev()
-                    .map(|txt| row![text(txt)].align_items(Alignment::Center).into())
-                    .collect(),
-            )
-            .spacing(10);*/
-
             if state.is_finished {
                 ui.label("Completed");
             }
@@ -93,7 +124,13 @@ pub fn view_filetransfer(
                             "transfer-total-errors"
                         )));
                         ui.label(
-                            RichText::new(transfer_info.errors.to_string()).color(Color32::WHITE),
+                            RichText::new(fl!(
+                                crate::LANGUAGE_LOADER,
+                                "transfer-errors",
+                                total = transfer_info.get_total_errors(),
+                                file = transfer_info.get_file_errors()
+                            ))
+                            .color(Color32::WHITE),
                         );
                     });
                 });
@@ -127,14 +164,7 @@ pub fn view_filetransfer(
                             crate::LANGUAGE_LOADER,
                             "transfer-timeleft"
                         )));
-                        ui.label(
-                            RichText::new(format!(
-                                "{:02}:{:02}",
-                                time_left.as_secs() / 60,
-                                time_left.as_secs() % 60
-                            ))
-                            .color(Color32::WHITE),
-                        );
+                        ui.label(RichText::new(time_left).color(Color32::WHITE));
                     });
                 });
             });
@@ -154,6 +184,30 @@ pub fn view_filetransfer(
                     bb.bytes(transfer_info.file_size as u64)
                 ))),
             );
+            if transfer_info.total_files > 1 {
+                let cur_file =
+                    (transfer_info.batch_files_transfered + 1).min(transfer_info.total_files);
+                ui.add(
+                    ProgressBar::new(
+                        transfer_info.batch_bytes_transfered as f32
+                            / max(1, transfer_info.total_bytes) as f32,
+                    )
+                    .text(RichText::new(fl!(
+                        crate::LANGUAGE_LOADER,
+                        "transfer-batch",
+                        files = format!("{}/{}", cur_file, transfer_info.total_files),
+                        bytes = format!(
+                            "{}/{}",
+                            bb.bytes(transfer_info.batch_bytes_transfered as u64),
+                            bb.bytes(transfer_info.total_bytes as u64)
+                        )
+                    ))),
+                );
+            }
+            if queue.len() > 1 {
+                ui.separator();
+                show_queue_progress(ui, queue, transfer_info, &bb);
+            }
             ui.horizontal(|ui| {
                 ui.label(RichText::new(fl!(crate::LANGUAGE_LOADER, "transfer-rate")));
                 let bps = bb.bytes(bps).to_string();
@@ -161,7 +215,155 @@ pub fn view_filetransfer(
                     RichText::new(fl!(crate::LANGUAGE_LOADER, "transfer-bps", bps = bps))
                         .color(Color32::WHITE),
                 );
+                ui.label(fl!(
+                    crate::LANGUAGE_LOADER,
+                    "transfer-rate-details",
+                    average = bb.bytes(transfer_info.get_average_cps()).to_string(),
+                    peak = bb.bytes(transfer_info.get_peak_cps()).to_string()
+                ));
             });
+            if !transfer_info.finished_files.is_empty() {
+                egui::CollapsingHeader::new(fl!(crate::LANGUAGE_LOADER, "transfer-finished-files"))
+                    .show(ui, |ui| {
+                        show_finished_files(ui, transfer_info, &bb);
+                    });
+            }
+            if !transfer_info.output_log.is_empty() {
+                ui.separator();
+                egui::ScrollArea::vertical()
+                    .max_height(150.)
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        for line in &transfer_info.output_log {
+                            ui.label(RichText::new(line).monospace());
+                        }
+                    });
+            }
         });
     open
 }
+
+fn show_finished_files(ui: &mut egui::Ui, transfer_info: &TransferInformation, bb: &BytesConfig) {
+    egui::ScrollArea::vertical()
+        .id_source("finished_files")
+        .max_height(100.)
+        .show(ui, |ui| {
+            for file in &transfer_info.finished_files {
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new(&file.file_name).color(Color32::WHITE));
+                    ui.label(bb.bytes(file.bytes as u64).to_string());
+                    ui.label(fl!(
+                        crate::LANGUAGE_LOADER,
+                        "transfer-file-errors",
+                        errors = file.errors
+                    ));
+                });
+            }
+        });
+}
+
+/// Every file of the running batch with its own progress.
+fn show_queue_progress(
+    ui: &mut egui::Ui,
+    queue: &[FileDescriptor],
+    transfer_info: &TransferInformation,
+    bb: &BytesConfig,
+) {
+    egui::ScrollArea::vertical()
+        .max_height(150.)
+        .show(ui, |ui| {
+            for (i, fd) in queue.iter().enumerate() {
+                let progress = match i.cmp(&transfer_info.batch_files_transfered) {
+                    std::cmp::Ordering::Less => 1.0,
+                    std::cmp::Ordering::Equal => {
+                        transfer_info.bytes_transfered.min(fd.size) as f32 / max(1, fd.size) as f32
+                    }
+                    std::cmp::Ordering::Greater => 0.0,
+                };
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new(&fd.file_name).color(Color32::WHITE));
+                    ui.add(
+                        ProgressBar::new(progress)
+                            .desired_width(120.)
+                            .text(bb.bytes(fd.size as u64).to_string()),
+                    );
+                });
+            }
+        });
+}
+
+/// The files dropped on the terminal while they wait for the board.
+pub fn view_transfer_queue(window: &mut MainWindow, ctx: &egui::Context) {
+    let bb = BytesConfig::default();
+    let mut open = true;
+    let mut remove = None;
+    let mut select_protocol = false;
+    let mut start = false;
+
+    egui::Window::new(fl!(crate::LANGUAGE_LOADER, "transfer-queue"))
+        .open(&mut open)
+        .collapsible(true)
+        .resizable(false)
+        .anchor(egui::Align2::RIGHT_BOTTOM, [-10., -10.])
+        .show(ctx, |ui| {
+            let queue = &window.transfer_queue;
+            ui.horizontal(|ui| {
+                ui.label(RichText::new(fl!(
+                    crate::LANGUAGE_LOADER,
+                    "transfer-protocol"
+                )));
+                let protocol = queue
+                    .protocol
+                    .as_ref()
+                    .map_or_else(|| "-".to_string(), |p| format!("{p:?}"));
+                ui.label(RichText::new(protocol).color(Color32::WHITE));
+                select_protocol = ui
+                    .button(fl!(crate::LANGUAGE_LOADER, "transfer-queue-protocol"))
+                    .clicked();
+            });
+            ui.label(fl!(crate::LANGUAGE_LOADER, "transfer-queue-waiting"));
+            ui.separator();
+            egui::ScrollArea::vertical()
+                .max_height(200.)
+                .show(ui, |ui| {
+                    for (i, fd) in queue.files.iter().enumerate() {
+                        ui.horizontal(|ui| {
+                            if ui.small_button("🗙").clicked() {
+                                remove = Some(i);
+                            }
+                            ui.label(RichText::new(&fd.file_name).color(Color32::WHITE));
+                            ui.label(bb.bytes(fd.size as u64).to_string());
+                        });
+                    }
+                });
+            ui.separator();
+            ui.horizontal(|ui| {
+                let total: usize = queue.files.iter().map(|f| f.size).sum();
+                ui.label(fl!(
+                    crate::LANGUAGE_LOADER,
+                    "transfer-queue-total",
+                    files = queue.files.len(),
+                    bytes = bb.bytes(total as u64).to_string()
+                ));
+                start = ui
+                    .add_enabled(
+                        queue.protocol.is_some(),
+                        egui::Button::new(fl!(crate::LANGUAGE_LOADER, "transfer-queue-start")),
+                    )
+                    .clicked();
+            });
+        });
+
+    if let Some(i) = remove {
+        window.transfer_queue.remove(i);
+    }
+    if !open {
+        window.transfer_queue.clear();
+    } else if select_protocol {
+        window.mode = MainWindowMode::SelectProtocol(false);
+    } else if start {
+        if let Some(protocol) = window.transfer_queue.protocol.clone() {
+            window.initiate_file_transfer(protocol, false);
+        }
+    }
+}
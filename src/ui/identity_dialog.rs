@@ -0,0 +1,59 @@
+use eframe::egui::{self, RichText};
+use i18n_embed_fl::fl;
+
+use super::main_window_mod::MainWindow;
+
+/// Asks which identity to call with, the default one is preselected.
+pub struct IdentityDialog {
+    address: usize,
+    names: Vec<String>,
+    selected: usize,
+}
+
+impl IdentityDialog {
+    pub fn new(address: usize, names: Vec<String>) -> Self {
+        Self {
+            address,
+            names,
+            selected: 0,
+        }
+    }
+}
+
+pub fn show_identity_dialog(window: &mut MainWindow, ctx: &egui::Context) {
+    let Some(dialog) = &mut window.identity_dialog else {
+        return;
+    };
+    let mut open = true;
+    let mut ok = false;
+    egui::Window::new(RichText::new(fl!(
+        crate::LANGUAGE_LOADER,
+        "identity-dialog-heading"
+    )))
+    .open(&mut open)
+    .collapsible(false)
+    .resizable(false)
+    .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+    .show(ctx, |ui| {
+        for (i, name) in dialog.names.iter().enumerate() {
+            let r = ui.radio_value(&mut dialog.selected, i, name);
+            ok |= r.double_clicked();
+        }
+        ui.separator();
+        ok |= ui
+            .button(fl!(crate::LANGUAGE_LOADER, "identity-dialog-call"))
+            .clicked();
+        ok |= ui.input(|i| i.key_pressed(egui::Key::Enter));
+    });
+    if !open {
+        window.identity_dialog = None;
+        return;
+    }
+    if !ok {
+        return;
+    }
+    let address = dialog.address;
+    let name = dialog.names.get(dialog.selected).cloned();
+    window.identity_dialog = None;
+    window.call_bbs_as(address, name);
+}
@@ -0,0 +1,158 @@
+use eframe::{
+    egui::{self, RichText, TextEdit},
+    epaint::Color32,
+};
+use i18n_embed_fl::fl;
+
+use crate::{
+    credentials::{CredentialError, CredentialStore},
+    identities::{get_free_name, is_free_name, Identity},
+};
+
+fn edit_password(ui: &mut egui::Ui, identity: &mut Identity, credentials: &CredentialStore) {
+    let mut password = match credentials.open(&identity.password) {
+        Ok(password) => password,
+        Err(CredentialError::Locked) => {
+            ui.label(
+                RichText::new(fl!(crate::LANGUAGE_LOADER, "settings-passwords-locked")).italics(),
+            );
+            return;
+        }
+        Err(err) => {
+            ui.colored_label(Color32::RED, err.to_string());
+            String::new()
+        }
+    };
+    if ui
+        .add(TextEdit::singleline(&mut password).password(true))
+        .changed()
+    {
+        match credentials.seal(&password) {
+            Ok(password) => identity.password = password,
+            Err(err) => eprintln!("{err}"),
+        }
+    }
+}
+
+/// Returns true if an identity was changed, added or removed.
+/// Passwords are sealed with `credentials` and can't be edited while it's locked.
+pub fn edit_identities(
+    ui: &mut egui::Ui,
+    id: &str,
+    identities: &mut Vec<Identity>,
+    credentials: &CredentialStore,
+) -> bool {
+    let old_identities = identities.clone();
+    let mut remove = None;
+    let mut rename = None;
+    for (i, identity) in identities.iter_mut().enumerate() {
+        egui::Grid::new(format!("{id}_{i}"))
+            .num_columns(2)
+            .spacing([4.0, 4.0])
+            .show(ui, |ui| {
+                ui.label(fl!(crate::LANGUAGE_LOADER, "identities-name"));
+                ui.horizontal(|ui| {
+                    // the name is edited on the side, the addresses follow once it's done
+                    let name_id = ui.make_persistent_id((id, i, "name"));
+                    let mut name = ui
+                        .data(|d| d.get_temp::<String>(name_id))
+                        .unwrap_or_else(|| identity.name.clone());
+                    let response = ui.add(TextEdit::singleline(&mut name));
+                    if response.lost_focus() {
+                        ui.data_mut(|d| d.remove::<String>(name_id));
+                        rename = Some((i, name.trim().to_string()));
+                    } else if response.has_focus() {
+                        ui.data_mut(|d| d.insert_temp(name_id, name));
+                    }
+                    if ui.button("🗑").clicked() {
+                        remove = Some(i);
+                    }
+                });
+                ui.end_row();
+
+                for (label, value) in [
+                    (
+                        fl!(crate::LANGUAGE_LOADER, "identities-handle"),
+                        &mut identity.handle,
+                    ),
+                    (
+                        fl!(crate::LANGUAGE_LOADER, "identities-first-name"),
+                        &mut identity.first_name,
+                    ),
+                    (
+                        fl!(crate::LANGUAGE_LOADER, "identities-last-name"),
+                        &mut identity.last_name,
+                    ),
+                    (
+                        fl!(crate::LANGUAGE_LOADER, "identities-location"),
+                        &mut identity.location,
+                    ),
+                ] {
+                    ui.label(label);
+                    ui.add(TextEdit::singleline(value));
+                    ui.end_row();
+                }
+
+                ui.label(fl!(crate::LANGUAGE_LOADER, "phonebook-password"));
+                edit_password(ui, identity, credentials);
+                ui.end_row();
+
+                ui.label(fl!(crate::LANGUAGE_LOADER, "identities-fields"));
+                ui.vertical(|ui| {
+                    let mut remove_field = None;
+                    for (j, (name, value)) in identity.fields.iter_mut().enumerate() {
+                        ui.horizontal(|ui| {
+                            ui.add(TextEdit::singleline(name).desired_width(100.));
+                            ui.add(TextEdit::singleline(value));
+                            if ui.button("🗑").clicked() {
+                                remove_field = Some(j);
+                            }
+                        });
+                    }
+                    if let Some(j) = remove_field {
+                        identity.fields.remove(j);
+                    }
+                    if ui
+                        .button(fl!(crate::LANGUAGE_LOADER, "phonebook-login-variable-add"))
+                        .clicked()
+                    {
+                        identity.fields.push((String::new(), String::new()));
+                    }
+                });
+                ui.end_row();
+            });
+        ui.separator();
+    }
+    let error_id = ui.make_persistent_id((id, "name_error"));
+    if let Some((i, name)) = rename {
+        if name != identities[i].name {
+            if is_free_name(identities, i, &name) {
+                identities[i].name = name;
+                ui.data_mut(|d| d.remove::<String>(error_id));
+            } else {
+                let error = fl!(crate::LANGUAGE_LOADER, "identities-name-taken");
+                ui.data_mut(|d| d.insert_temp(error_id, error));
+            }
+        }
+    }
+    if let Some(error) = ui.data(|d| d.get_temp::<String>(error_id)) {
+        ui.colored_label(Color32::RED, error);
+    }
+    if let Some(i) = remove {
+        identities.remove(i);
+    }
+    if identities.is_empty() {
+        ui.label(RichText::new(fl!(crate::LANGUAGE_LOADER, "identities-none")).italics());
+    }
+    if ui
+        .button(fl!(crate::LANGUAGE_LOADER, "identities-add"))
+        .clicked()
+    {
+        let name = get_free_name(identities, &fl!(crate::LANGUAGE_LOADER, "identities-new"));
+        identities.push(Identity {
+            name,
+            ..Default::default()
+        });
+    }
+    old_identities != *identities
+}
@@ -0,0 +1,53 @@
+use eframe::egui::{self, TextEdit};
+use i18n_embed_fl::fl;
+
+use crate::iemsi_mod::{EmsiICI, IEmsiProfile};
+
+/// Empty fields show what they fall back to, `defaults` or the built in values.
+pub fn edit_iemsi_profile(
+    ui: &mut egui::Ui,
+    id: &str,
+    profile: &mut IEmsiProfile,
+    defaults: &IEmsiProfile,
+) -> bool {
+    let old_profile = profile.clone();
+    let mut fallback = EmsiICI::new();
+    fallback.apply_profile(defaults);
+    egui::Grid::new(id)
+        .num_columns(2)
+        .spacing([4.0, 8.0])
+        .show(ui, |ui| {
+            for (label, value, hint) in [
+                (
+                    fl!(crate::LANGUAGE_LOADER, "iemsi-alias"),
+                    &mut profile.alias,
+                    &fallback.alias,
+                ),
+                (
+                    fl!(crate::LANGUAGE_LOADER, "iemsi-location"),
+                    &mut profile.location,
+                    &fallback.location,
+                ),
+                (
+                    fl!(crate::LANGUAGE_LOADER, "iemsi-data-phone"),
+                    &mut profile.data_phone,
+                    &fallback.data_telephone,
+                ),
+                (
+                    fl!(crate::LANGUAGE_LOADER, "iemsi-voice-phone"),
+                    &mut profile.voice_phone,
+                    &fallback.voice_telephone,
+                ),
+                (
+                    fl!(crate::LANGUAGE_LOADER, "iemsi-requests"),
+                    &mut profile.requests,
+                    &fallback.requests,
+                ),
+            ] {
+                ui.label(label);
+                ui.add(TextEdit::singleline(value).hint_text(hint.as_str()));
+                ui.end_row();
+            }
+        });
+    old_profile != *profile
+}
@@ -6,9 +6,10 @@ use i18n_embed_fl::fl;
 use icy_engine::{ansi, BufferParser};
 use poll_promise::Promise;
 use rfd::FileDialog;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 use std::{
     env,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
 };
 
@@ -16,21 +17,55 @@ use eframe::egui::{self, Key};
 
 use crate::auto_file_transfer::AutoFileTransfer;
 use crate::auto_login::AutoLogin;
+use crate::capture::Capture;
 use crate::com::{Com, TermComResult};
-use crate::protocol::TransferState;
+use crate::iemsi_mod::{get_crtdef, EmsiEvent, EmsiEvents, EmsiISI, IsmCache, EMSI_ACK};
+use crate::login_script::{expand_send, get_variables, parse_send};
+use crate::protocol::{
+    finish_transfer, get_default_download_dir, CollisionPolicy, DownloadSettings, TextUploadConfig,
+    TransferDriver, TransferState, TransferType, INACTIVITY_TIMEOUT,
+};
 use crate::rng::Rng;
+use crate::scripting::{ScriptCommand, ScriptHost};
+use crate::transfer_queue::TransferQueue;
+use crate::triggers::{TriggerMatch, TriggerMatcher};
 use crate::{
     address_mod::{start_read_book, store_phone_book, Address},
     com::{ComRawImpl, ComTelnetImpl, SendData},
+    credentials::{CredentialError, CredentialStore, Credentials},
+    identities::{get_identities, Identity},
     protocol::FileDescriptor,
     TerminalResult,
 };
 
-use super::{screen_modes::ScreenMode, BufferView};
+use super::{
+    screen_modes::ScreenMode, BbsDirectory, BufferView, DownloadBrowser, ExportDialog,
+    IdentityDialog, SysopChat, UnlockDialog,
+};
 use super::{Options, PhonebookFilter};
 use crate::com::Connection;
 use tokio::sync::mpsc;
 
+/// The bytes a trigger sends back, errors are printed.
+fn get_trigger_response(
+    adr: &Address,
+    credentials: &Credentials,
+    m: &TriggerMatch,
+) -> Option<Vec<u8>> {
+    if m.trigger.send.is_empty() {
+        return None;
+    }
+    let variables = get_variables(adr, credentials);
+    let res = parse_send(0, &m.trigger.send).and_then(|parts| expand_send(&parts, &variables));
+    match res {
+        Ok(data) => Some(data),
+        Err(err) => {
+            eprintln!("{err}");
+            None
+        }
+    }
+}
+
 #[derive(PartialEq, Eq)]
 pub enum MainWindowMode {
     ShowTerminal,
@@ -38,6 +73,8 @@ pub enum MainWindowMode {
     ShowSettings(bool),
     SelectProtocol(bool),
     FileTransfer(bool),
+    ShowDownloads,
+    ShowDirectory,
     //   AskDeleteEntry
 }
 
@@ -54,6 +91,8 @@ pub struct MainWindow {
     pub selected_bbs: Option<usize>,
     pub phonebook_filter: PhonebookFilter,
     pub phonebook_filter_string: String,
+    /// Why the last phonebook import failed.
+    pub phonebook_error: Option<String>,
 
     pub options: Options,
     pub screen_mode: ScreenMode,
@@ -61,6 +100,22 @@ pub struct MainWindow {
 
     pub rng: Rng,
     auto_file_transfer: AutoFileTransfer,
+    pub transfer_queue: TransferQueue,
+    pub download_browser: DownloadBrowser,
+    pub directory: BbsDirectory,
+    pub scripts: ScriptHost,
+    trigger_matcher: TriggerMatcher,
+    pub capture: Capture,
+    /// Trigger notifications with the time they were raised.
+    pub notifications: Vec<(Instant, String)>,
+    emsi_events: EmsiEvents,
+    /// Set while the server has an IEMSI chat open.
+    pub chat: Option<SysopChat>,
+    pub credentials: CredentialStore,
+    pub unlock_dialog: Option<UnlockDialog>,
+    pub identities: Vec<Identity>,
+    pub identity_dialog: Option<IdentityDialog>,
+    pub export_dialog: Option<ExportDialog>,
     // protocols
     current_transfer: Option<Arc<Mutex<TransferState>>>,
     is_alt_pressed: bool,
@@ -76,8 +131,10 @@ impl MainWindow {
             .expect("You need to run eframe with the glow backend");
         let options = Options::load_options();
         let view = BufferView::new(gl, &options);
+        let buffer_view = Arc::new(eframe::epaint::mutex::Mutex::new(view));
         let mut view = MainWindow {
-            buffer_view: Arc::new(eframe::epaint::mutex::Mutex::new(view)),
+            scripts: ScriptHost::new(Some(buffer_view.clone())),
+            buffer_view,
             //address_list: HoverList::new(),
             mode: MainWindowMode::ShowPhonebook,
             addresses: start_read_book(),
@@ -85,8 +142,21 @@ impl MainWindow {
             selected_bbs: None,
             connection_opt: None,
             options,
-            auto_login: AutoLogin::new(""),
+            auto_login: AutoLogin::default(),
             auto_file_transfer: AutoFileTransfer::new(),
+            transfer_queue: TransferQueue::new(),
+            download_browser: DownloadBrowser::default(),
+            directory: BbsDirectory::default(),
+            trigger_matcher: TriggerMatcher::default(),
+            capture: Capture::default(),
+            notifications: Vec::new(),
+            emsi_events: EmsiEvents::default(),
+            chat: None,
+            credentials: CredentialStore::new(Address::read_vault()),
+            unlock_dialog: None,
+            identities: Address::read_identities(),
+            identity_dialog: None,
+            export_dialog: None,
             screen_mode: ScreenMode::Vga(80, 25),
             current_transfer: None,
             handled_char: false,
@@ -95,6 +165,7 @@ impl MainWindow {
             buffer_parser: Box::<ansi::Parser>::default(),
             open_connection_promise: None,
             phonebook_filter_string: String::new(),
+            phonebook_error: None,
             rng: Rng::new(),
         };
         let args: Vec<String> = env::args().collect();
@@ -214,9 +285,34 @@ impl MainWindow {
         protocol_type: crate::protocol::TransferType,
         download: bool,
         files_opt: Option<Vec<FileDescriptor>>,
+    ) {
+        let download_settings = self.get_download_settings();
+        self.start_transfer_thread_with(protocol_type, download, files_opt, download_settings);
+    }
+
+    fn start_transfer_thread_with(
+        &mut self,
+        protocol_type: crate::protocol::TransferType,
+        download: bool,
+        files_opt: Option<Vec<FileDescriptor>>,
+        download_settings: DownloadSettings,
     ) {
         self.mode = MainWindowMode::FileTransfer(download);
-        let state = Arc::new(Mutex::new(TransferState::new()));
+        let mut state = TransferState::new();
+        state.download_settings = download_settings;
+        state.zmodem_window_size = self.options.zmodem_window_size;
+        state.zmodem_resume = self.options.zmodem_resume;
+        state.zmodem_escaping = self.options.zmodem_escaping;
+        state.log_file = self.options.get_transfer_log_file();
+        state.download_hook = self.options.download_hook.clone();
+        if let Some(adr) = self.addresses.get(self.cur_addr) {
+            state.board_name = if adr.system_name.is_empty() {
+                adr.address.clone()
+            } else {
+                adr.system_name.clone()
+            };
+        }
+        let state = Arc::new(Mutex::new(state));
         self.current_transfer = Some(state.clone());
         let res = self.connection_opt.as_mut().unwrap().start_file_transfer(
             protocol_type,
@@ -227,6 +323,44 @@ impl MainWindow {
         self.handle_result(res, true);
     }
 
+    /// The download directory of the current address wins over the one in the options.
+    fn get_download_settings(&self) -> DownloadSettings {
+        let address_dir = self
+            .addresses
+            .get(self.cur_addr)
+            .map(|adr| adr.download_dir.as_str())
+            .unwrap_or_default();
+        let dir = if address_dir.is_empty() {
+            self.options.download_dir.as_str()
+        } else {
+            address_dir
+        };
+        DownloadSettings {
+            download_dir: if dir.is_empty() {
+                get_default_download_dir()
+            } else {
+                Some(PathBuf::from(dir))
+            },
+            collision_policy: self.options.collision_policy,
+            file_name: None,
+        }
+    }
+
+    /// Punter doesn't transfer the file name, the user picks the file before the download.
+    fn ask_download_file(&self) -> Option<DownloadSettings> {
+        let mut settings = self.get_download_settings();
+        let mut dialog = FileDialog::new();
+        if let Some(dir) = &settings.download_dir {
+            dialog = dialog.set_directory(dir);
+        }
+        let path = dialog.save_file()?;
+        settings.download_dir = path.parent().map(Path::to_path_buf);
+        settings.file_name = Some(path.file_name()?.to_string_lossy().to_string());
+        // the file dialog already asked about replacing an existing file
+        settings.collision_policy = CollisionPolicy::Overwrite;
+        Some(settings)
+    }
+
     /*
 
                                     let mut protocol = protocol_type.create();
@@ -251,8 +385,15 @@ impl MainWindow {
         self.mode = MainWindowMode::ShowTerminal;
         match self.connection_opt.as_mut() {
             Some(_) => {
-                if download {
+                if download && protocol_type == TransferType::Punter {
+                    if let Some(settings) = self.ask_download_file() {
+                        self.start_transfer_thread_with(protocol_type, download, None, settings);
+                    }
+                } else if download {
                     self.start_transfer_thread(protocol_type, download, None);
+                } else if !self.transfer_queue.is_empty() {
+                    let files = self.transfer_queue.take_files();
+                    self.start_transfer_thread(protocol_type, download, Some(files));
                 } else {
                     let files = FileDialog::new().pick_files();
                     if let Some(path) = files {
@@ -269,6 +410,107 @@ impl MainWindow {
         }
     }
 
+    /// Remembers the protocol for the board. Queued files wait until the board is ready.
+    pub(crate) fn select_upload_protocol(&mut self, protocol_type: TransferType) {
+        if self.cur_addr > 0 {
+            if let Some(adr) = self.addresses.get_mut(self.cur_addr) {
+                adr.upload_protocol = Some(protocol_type.clone());
+                let res = self.store_phone_book();
+                self.handle_result(res, false);
+            }
+        }
+        self.transfer_queue.protocol = Some(protocol_type.clone());
+        if self.transfer_queue.is_empty() {
+            self.initiate_file_transfer(protocol_type, false);
+        } else {
+            self.mode = MainWindowMode::ShowTerminal;
+        }
+    }
+
+    /// Files dropped on the terminal, the upload starts once the board asks for them.
+    pub(crate) fn queue_files(&mut self, paths: &Vec<PathBuf>) {
+        if self.connection_opt.is_none() {
+            return;
+        }
+        match FileDescriptor::from_paths(paths) {
+            Ok(files) => self.transfer_queue.add(files),
+            Err(err) => {
+                self.handle_result::<()>(Err(err), false);
+                return;
+            }
+        }
+        if self.transfer_queue.protocol.is_none() {
+            self.mode = MainWindowMode::SelectProtocol(false);
+        }
+    }
+
+    pub(crate) fn initiate_text_upload(&mut self) {
+        self.mode = MainWindowMode::ShowTerminal;
+        if self.connection_opt.is_none() {
+            eprintln!("Communication error.");
+            return;
+        }
+        let Some(path) = FileDialog::new().pick_file() else {
+            return;
+        };
+        let data = match std::fs::read(&path) {
+            Ok(data) => data,
+            Err(err) => {
+                self.handle_result::<()>(Err(Box::new(err)), false);
+                return;
+            }
+        };
+        // Text that isn't valid utf8 is most likely already in the BBS character set
+        // and is sent as it is, only the line endings change.
+        let lines: Vec<Vec<u8>> = match String::from_utf8(data) {
+            Ok(text) => text
+                .lines()
+                .map(|line| {
+                    line.chars()
+                        .map(|ch| self.buffer_parser.convert_from_unicode(ch) as u8)
+                        .collect()
+                })
+                .collect(),
+            Err(err) => {
+                let data = err.into_bytes();
+                data.strip_suffix(b"\n")
+                    .unwrap_or(data.as_slice())
+                    .split(|b| *b == b'\n')
+                    .map(|line| line.strip_suffix(b"\r").unwrap_or(line).to_vec())
+                    .collect()
+            }
+        };
+
+        let line_end = self.get_enter_sequence();
+        let mut converted = Vec::new();
+        for line in lines {
+            converted.extend_from_slice(&line);
+            converted.extend_from_slice(&line_end);
+        }
+
+        let settings = &self.options.text_upload;
+        let config = TextUploadConfig {
+            char_delay: Duration::from_millis(settings.char_delay_ms),
+            line_delay: Duration::from_millis(settings.line_delay_ms),
+            prompt: settings
+                .prompt
+                .chars()
+                .map(|ch| self.buffer_parser.convert_from_unicode(ch) as u8)
+                .collect(),
+            wait_for_echo: settings.wait_for_echo,
+            line_end,
+        };
+        let file_name = path
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or_default();
+        self.start_transfer_thread(
+            TransferType::Text(config),
+            false,
+            Some(vec![FileDescriptor::from_data(file_name, converted)]),
+        );
+    }
+
     pub fn set_screen_mode(&mut self, mode: ScreenMode) {
         self.screen_mode = mode;
         mode.set_mode(self);
@@ -282,6 +524,14 @@ impl MainWindow {
         self.mode = MainWindowMode::ShowPhonebook;
     }
 
+    /// A parser for the terminal type of the current board, the one in use keeps its state.
+    pub fn create_terminal_parser(&self) -> Box<dyn BufferParser> {
+        match self.addresses.get(self.cur_addr) {
+            Some(adr) => adr.get_terminal_parser(adr),
+            None => Box::<ansi::Parser>::default(),
+        }
+    }
+
     pub fn get_address_mut(&mut self, uuid: Option<usize>) -> &mut Address {
         if uuid.is_none() {
             return &mut self.addresses[0];
@@ -312,7 +562,43 @@ impl MainWindow {
         }
     }
 
+    /// Asks for the identity first if the address has more than one.
     pub fn call_bbs(&mut self, i: usize) {
+        let names: Vec<String> = get_identities(&self.identities, &self.addresses[i])
+            .iter()
+            .map(|identity| identity.name.clone())
+            .collect();
+        if names.len() > 1 {
+            self.identity_dialog = Some(IdentityDialog::new(i, names));
+            return;
+        }
+        self.call_bbs_as(i, names.into_iter().next());
+    }
+
+    /// Calls with the named identity, or the login of the address.
+    pub fn call_bbs_as(&mut self, i: usize, identity_name: Option<String>) {
+        let identity = identity_name
+            .as_ref()
+            .and_then(|name| self.identities.iter().find(|i| i.name == *name));
+        let (credentials, credential_error) = match self
+            .credentials
+            .get_credentials(&self.addresses[i], identity)
+        {
+            Ok(credentials) => (credentials, None),
+            Err(CredentialError::Locked) => {
+                self.unlock_dialog = Some(UnlockDialog::unlock(Some((i, identity_name))));
+                return;
+            }
+            Err(err) => (
+                Credentials {
+                    user_name: identity
+                        .map_or(&self.addresses[i].user_name, |i| &i.handle)
+                        .clone(),
+                    ..Default::default()
+                },
+                Some(err),
+            ),
+        };
         self.mode = MainWindowMode::ShowTerminal;
         let mut adr = self.addresses[i].address.clone();
         if !adr.contains(':') {
@@ -320,15 +606,38 @@ impl MainWindow {
         }
         self.addresses[i].number_of_calls += 1;
         self.addresses[i].last_call = Some(Utc::now());
-        store_phone_book(&self.addresses).unwrap_or_default();
+        self.store_phone_book().unwrap_or_default();
 
         let call_adr = self.addresses[i].clone();
-        self.auto_login = AutoLogin::new(&call_adr.auto_login);
+        let login_error = match AutoLogin::new(&call_adr, credentials) {
+            Ok(auto_login) => {
+                self.auto_login = auto_login;
+                None
+            }
+            Err(err) => {
+                self.auto_login = AutoLogin::default();
+                Some(err)
+            }
+        };
+        if let Some(iemsi) = &mut self.auto_login.iemsi {
+            iemsi.profile = call_adr.iemsi.or(&self.options.iemsi);
+            iemsi.crtdef = get_crtdef(call_adr.terminal_type, call_adr.screen_mode);
+        }
+        self.auto_file_transfer
+            .set_prompts(&call_adr.transfer_prompts);
+        self.transfer_queue.clear();
+        self.transfer_queue.protocol = call_adr.upload_protocol.clone();
+        self.trigger_matcher =
+            TriggerMatcher::new(self.options.triggers.iter().chain(call_adr.triggers.iter()));
+        self.emsi_events = EmsiEvents::default();
+        self.chat = None;
         self.auto_login.disabled = self.is_alt_pressed;
         self.buffer_view.lock().buf.clear();
         self.cur_addr = i;
         self.set_screen_mode(call_adr.screen_mode);
         self.buffer_parser = self.addresses[i].get_terminal_parser(&call_adr);
+        self.scripts.unload_address_scripts();
+        self.scripts.set_parser(self.create_terminal_parser());
 
         self.buffer_view.lock().redraw_font();
         self.buffer_view.lock().redraw_palette();
@@ -341,9 +650,18 @@ impl MainWindow {
             address = call_adr.address.clone()
         ))
         .unwrap_or_default();
+        if let Some(err) = credential_error {
+            self.print_script_message(&err.to_string());
+        }
+        if let Some(err) = login_error {
+            self.print_login_script_error(&err.to_string());
+        }
 
         let timeout = self.options.connect_timeout;
         let ct = call_adr.protocol;
+        // The connection may log in itself (SSH), it gets the opened password.
+        let mut call_adr = call_adr;
+        call_adr.password = self.auto_login.credentials.password.clone();
         let window_size = self.screen_mode.get_window_size();
 
         self.open_connection_promise = Some(Promise::spawn_async(async move {
@@ -376,23 +694,54 @@ impl MainWindow {
                 }
             }
         }
-        let res = store_phone_book(&self.addresses);
+        let res = self.store_phone_book();
         self.handle_result(res, true);
     }
 
+    /// Seals plain text passwords first if the credential store is unlocked.
+    pub fn store_phone_book(&mut self) -> TerminalResult<()> {
+        if self.credentials.is_unlocked() {
+            self.credentials
+                .seal_all(&mut self.addresses, &mut self.identities)?;
+        }
+        store_phone_book(
+            &self.addresses,
+            &self.identities,
+            self.credentials.vault.as_ref(),
+        )
+    }
+
+    /// What a transfer passed on to the terminal, like the echo of a text upload.
+    fn print_transfer_data(&mut self) {
+        let Some(state) = &self.current_transfer else {
+            return;
+        };
+        let data = std::mem::take(&mut state.lock().unwrap().terminal_data);
+        for ch in data {
+            let res = self.print_char(ch);
+            self.handle_result(res, false);
+        }
+    }
+
     pub fn update_state(&mut self) -> TerminalResult<()> {
         //        unsafe { super::simulate::run_sim(self); }
         let Some(con) = &mut self.connection_opt else {
             return Ok(());
         };
         let mut send_data = Vec::new();
+        let mut received_text = String::new();
+        let mut triggered = Vec::new();
+        let mut recalled_images = Vec::new();
 
         if con.is_data_available()? {
             for ch in con.read_buffer() {
-                if let Some(adr) = self.addresses.get(self.cur_addr) {
-                    if let Err(err) = self.auto_login.try_login(con, adr, ch) {
-                        eprintln!("{err}");
-                    }
+                let decoded = self.buffer_parser.convert_to_unicode(char::from(ch));
+                received_text.push(decoded);
+                if let Err(err) = self.capture.write(&[ch]) {
+                    eprintln!("{err}");
+                }
+                if let Err(err) = self.auto_login.try_login(con, ch) {
+                    eprintln!("{err}");
                 }
                 /*
                 match ch {
@@ -412,12 +761,36 @@ impl MainWindow {
                 }*/
                 // print!("{}", char::from_u32(ch as u32).unwrap());
 
-                let result = self
-                    .buffer_view
-                    .lock()
-                    .print_char(&mut self.buffer_parser, unsafe {
-                        char::from_u32_unchecked(ch as u32)
-                    });
+                match self.emsi_events.push_char(ch) {
+                    Some(EmsiEvent::StartChat) => {
+                        send_data.extend_from_slice(EMSI_ACK);
+                        self.chat.get_or_insert_with(SysopChat::default);
+                    }
+                    Some(EmsiEvent::EndChat) => {
+                        send_data.extend_from_slice(EMSI_ACK);
+                        self.chat = None;
+                    }
+                    Some(EmsiEvent::Image(data)) => {
+                        if let Some(dir) = Options::get_ism_cache_dir() {
+                            if let Err(err) = IsmCache::new(dir).store(&data) {
+                                eprintln!("{err}");
+                            }
+                        }
+                    }
+                    Some(EmsiEvent::RecallImage(id)) => recalled_images.push(id),
+                    None => {}
+                }
+
+                let result = if let Some(chat) = &mut self.chat {
+                    chat.push_remote(decoded);
+                    Ok(icy_engine::CallbackAction::None)
+                } else {
+                    self.buffer_view
+                        .lock()
+                        .print_char(&mut self.buffer_parser, unsafe {
+                            char::from_u32_unchecked(ch as u32)
+                        })
+                };
 
                 match result {
                     Ok(icy_engine::CallbackAction::None) => {}
@@ -434,6 +807,24 @@ impl MainWindow {
                         eprintln!("{err}");
                     }
                 }
+                if let Some(m) = self.trigger_matcher.push_char(decoded) {
+                    if m.trigger.highlight {
+                        self.buffer_view.lock().highlight_before_caret(m.len);
+                    }
+                    let adr = self.addresses.get(self.cur_addr);
+                    let credentials = &self.auto_login.credentials;
+                    if let Some(data) =
+                        adr.and_then(|adr| get_trigger_response(adr, credentials, &m))
+                    {
+                        send_data.extend(data);
+                    }
+                    triggered.push(m);
+                }
+                if let Some(protocol_type) = self.transfer_queue.try_start(ch) {
+                    let files = self.transfer_queue.take_files();
+                    self.start_transfer_thread(protocol_type, false, Some(files));
+                    return Ok(());
+                }
                 if let Some((protocol_type, download)) = self.auto_file_transfer.try_transfer(ch) {
                     self.initiate_file_transfer(protocol_type, download);
                     return Ok(());
@@ -445,43 +836,225 @@ impl MainWindow {
             con.send(send_data)?;
         }
 
-        if con.is_disconnected() {
+        let disconnected = con.is_disconnected();
+        if disconnected {
             self.connection_opt = None;
         }
+        if !received_text.is_empty() {
+            self.call_script_hook("on_text", (received_text,));
+            self.call_script_hook("on_screen_change", ());
+        }
+        for m in triggered {
+            self.run_trigger_actions(&m);
+        }
+        for id in recalled_images {
+            let image = Options::get_ism_cache_dir().and_then(|dir| IsmCache::new(dir).load(&id));
+            for ch in image.unwrap_or_default() {
+                if let Err(err) = self.print_char(ch) {
+                    eprintln!("{err}");
+                }
+            }
+        }
+        if disconnected {
+            self.chat = None;
+            self.call_script_hook("on_disconnect", ());
+            self.scripts.unload_address_scripts();
+            self.stop_capture();
+        }
         self.auto_login.disabled |= self.is_alt_pressed;
+        let mut login_error = None;
         if let Some(adr) = self.addresses.get(self.cur_addr) {
             if let Some(con) = &mut self.connection_opt {
                 if let Err(err) = self.auto_login.run_autologin(con, adr) {
-                    eprintln!("{err}");
+                    login_error = Some(err.to_string());
                 }
             }
         }
+        if let Some(err) = login_error {
+            self.print_login_script_error(&err);
+        }
 
         Ok(())
     }
 
+    pub fn call_script_hook(&mut self, hook: &str, args: impl rhai::FuncArgs + Clone) {
+        if !self.scripts.has_hook(hook) {
+            return;
+        }
+        for err in self.scripts.call_hook(hook, args) {
+            self.print_script_message(&fl!(
+                crate::LANGUAGE_LOADER,
+                "terminal-script-failed",
+                error = err
+            ));
+        }
+        self.run_script_commands();
+    }
+
+    /// Loads a script and runs what its top level code asked for.
+    pub fn run_script(&mut self, path: &std::path::Path, from_address: bool) {
+        if let Err(err) = self.scripts.load(path, from_address) {
+            self.print_script_message(&fl!(
+                crate::LANGUAGE_LOADER,
+                "terminal-script-failed",
+                error = format!("{}: {err}", path.display())
+            ));
+        }
+        self.run_script_commands();
+    }
+
+    fn run_script_commands(&mut self) {
+        for command in self.scripts.take_commands() {
+            match command {
+                ScriptCommand::Send(text) => self.output_string(&text),
+                ScriptCommand::SendBytes(data) => {
+                    if let Some(con) = &mut self.connection_opt {
+                        let res = con.send(data);
+                        self.handle_result(res, false);
+                    }
+                }
+                ScriptCommand::Download(protocol_type) => {
+                    if self.connection_opt.is_some() {
+                        self.start_transfer_thread(protocol_type, true, None);
+                    }
+                }
+                ScriptCommand::Upload(protocol_type, paths) => {
+                    if self.connection_opt.is_some() {
+                        match FileDescriptor::from_paths(&paths) {
+                            Ok(files) => {
+                                self.start_transfer_thread(protocol_type, false, Some(files));
+                            }
+                            Err(err) => self.print_script_message(&err.to_string()),
+                        }
+                    }
+                }
+                ScriptCommand::SetScreenMode(mode) => {
+                    self.set_screen_mode(mode);
+                    self.buffer_view.lock().redraw_font();
+                    self.buffer_view.lock().redraw_palette();
+                    self.buffer_view.lock().redraw_view();
+                }
+                ScriptCommand::Message(msg) => self.print_script_message(&msg),
+            }
+        }
+    }
+
+    fn run_trigger_actions(&mut self, m: &TriggerMatch) {
+        if m.trigger.sound {
+            crate::sound::beep();
+        }
+        if m.trigger.notify {
+            self.notifications.push((
+                Instant::now(),
+                fl!(
+                    crate::LANGUAGE_LOADER,
+                    "terminal-trigger-matched",
+                    text = m.text.clone()
+                ),
+            ));
+        }
+        if m.trigger.capture && !self.capture.is_capturing() {
+            self.start_capture();
+        }
+    }
+
+    pub fn start_capture(&mut self) {
+        let board = self
+            .addresses
+            .get(self.cur_addr)
+            .map(|adr| {
+                if adr.system_name.is_empty() {
+                    adr.address.clone()
+                } else {
+                    adr.system_name.clone()
+                }
+            })
+            .unwrap_or_default();
+        let Some(dir) = Options::get_capture_dir() else {
+            return;
+        };
+        match self.capture.start(&dir, &board) {
+            Ok(path) => self.print_script_message(&fl!(
+                crate::LANGUAGE_LOADER,
+                "terminal-capture-started",
+                file = path.display().to_string()
+            )),
+            Err(err) => self.print_script_message(&fl!(
+                crate::LANGUAGE_LOADER,
+                "terminal-capture-failed",
+                error = err.to_string()
+            )),
+        }
+    }
+
+    pub fn stop_capture(&mut self) {
+        match self.capture.stop() {
+            Ok(Some(path)) => {
+                self.notifications.push((
+                    Instant::now(),
+                    fl!(
+                        crate::LANGUAGE_LOADER,
+                        "terminal-capture-stopped",
+                        file = path.display().to_string()
+                    ),
+                ));
+            }
+            Ok(None) => {}
+            Err(err) => self.print_script_message(&fl!(
+                crate::LANGUAGE_LOADER,
+                "terminal-capture-failed",
+                error = err.to_string()
+            )),
+        }
+    }
+
+    fn print_script_message(&mut self, msg: &str) {
+        self.println(&format!("\n\r{msg}\n\r")).unwrap_or_default();
+    }
+
+    fn print_login_script_error(&mut self, err: &str) {
+        let msg = fl!(
+            crate::LANGUAGE_LOADER,
+            "terminal-login-script-failed",
+            error = err
+        );
+        self.println(&format!("\n\r{msg}\n\r")).unwrap_or_default();
+    }
+
     pub fn hangup(&mut self) {
         self.open_connection_promise = None;
         if let Some(con) = &mut self.connection_opt {
             con.disconnect().unwrap_or_default();
+            self.call_script_hook("on_disconnect", ());
         }
+        self.scripts.unload_address_scripts();
+        self.stop_capture();
+        self.chat = None;
         self.connection_opt = None;
         self.mode = MainWindowMode::ShowPhonebook;
     }
 
-    pub fn send_login(&mut self) {
-        let adr = self.addresses.get(self.cur_addr).unwrap();
-        let mut cr = [self.buffer_parser.convert_from_unicode('\r') as u8].to_vec();
+    /// What the server sent about itself in the IEMSI login.
+    pub fn get_server_info(&self) -> Option<&EmsiISI> {
+        self.auto_login.iemsi.as_ref()?.isi.as_ref()
+    }
+
+    fn get_enter_sequence(&self) -> Vec<u8> {
         for (k, v) in self.screen_mode.get_input_mode().cur_map() {
             if *k == Key::Enter as u32 {
-                cr = v.to_vec();
-                break;
+                return v.to_vec();
             }
         }
+        vec![self.buffer_parser.convert_from_unicode('\r') as u8]
+    }
+
+    pub fn send_login(&mut self) {
+        let cr = self.get_enter_sequence();
+        let credentials = &self.auto_login.credentials;
         let mut data = Vec::new();
-        data.extend_from_slice(adr.user_name.as_bytes());
+        data.extend_from_slice(credentials.user_name.as_bytes());
         data.extend(&cr);
-        data.extend_from_slice(adr.password.as_bytes());
+        data.extend_from_slice(credentials.password.as_bytes());
         data.extend(cr);
         if let Some(con) = &mut self.connection_opt {
             let res = con.send(data);
@@ -503,10 +1076,10 @@ impl MainWindow {
                 let hours = minutes / 60;
                 let cur = &self.addresses[self.cur_addr];
                 let t = format!("{:02}:{:02}:{:02}", hours, minutes % 60, sec % 60);
-                let s = if cur.system_name.is_empty() {
-                    cur.address.clone()
-                } else {
-                    cur.system_name.clone()
+                let s = match self.get_server_info() {
+                    Some(isi) if !isi.name.is_empty() => isi.name.clone(),
+                    _ if cur.system_name.is_empty() => cur.address.clone(),
+                    _ => cur.system_name.clone(),
                 };
 
                 fl!(
@@ -566,6 +1139,13 @@ impl eframe::App for MainWindow {
                         let (tx, rx) = mpsc::channel::<SendData>(32);
                         let (tx2, mut rx2) = mpsc::channel::<SendData>(32);
                         self.connection_opt = Some(Connection::new(rx, tx2));
+                        if let Some(adr) = self.addresses.get(self.cur_addr) {
+                            if !adr.script_file.is_empty() {
+                                let path = PathBuf::from(&adr.script_file);
+                                self.run_script(&path, true);
+                            }
+                        }
+                        self.call_script_hook("on_connect", ());
 
                         let mut handle = handle;
 
@@ -590,18 +1170,20 @@ impl eframe::App for MainWindow {
                                                 }
                                             },
                                             Some(SendData::StartTransfer(protocol_type, download, transfer_state, files_opt)) => {
-                                            let mut protocol = protocol_type.create();
+                                            let mut protocol = protocol_type.clone().create();
+                                            let mut driver = TransferDriver::new(INACTIVITY_TIMEOUT);
+                                            let send_files = files_opt.unwrap_or_default();
                                             if let Err(err) = if download {
-                                                    protocol.initiate_recv(&mut handle, transfer_state.clone()).await
+                                                    driver.initiate_recv(&mut protocol, &mut handle, transfer_state.clone()).await
                                                 } else {
-                                                    protocol.initiate_send(&mut handle, files_opt.unwrap(), transfer_state.clone()).await
+                                                    driver.initiate_send(&mut protocol, &mut handle, send_files.clone(), transfer_state.clone()).await
                                                 } {
                                                     eprintln!("{err}");
                                                     break;
                                                 }
                                                 loop {
                                                     tokio::select! {
-                                                        v = protocol.update(&mut handle, transfer_state.clone()) => {
+                                                        v = driver.step(&mut protocol, &mut handle, transfer_state.clone()) => {
                                                             match v {
                                                                 Ok(running) => {
                                                                     if !running {
@@ -616,13 +1198,15 @@ impl eframe::App for MainWindow {
                                                         }
                                                         result = rx2.recv() => {
                                                             if let Some(SendData::CancelTransfer) = result {
-                                                                protocol.cancel(&mut handle).await.unwrap_or_default();
+                                                                driver.cancel(&mut protocol, &mut handle).await.unwrap_or_default();
                                                                 eprintln!("Cancel");
                                                                 break;
                                                             }
                                                         }
                                                     }
                                                 }
+                                                let files = if download { protocol.get_received_files() } else { send_files };
+                                                finish_transfer(&protocol_type, download, files, transfer_state).await;
                                                 tx.send(SendData::EndTransfer).await.unwrap_or_default();
                                             }
                                             Some(SendData::Disconnect) => {
@@ -648,6 +1232,11 @@ impl eframe::App for MainWindow {
                 let res = self.update_state();
                 self.update_terminal_window(ctx, frame);
                 self.handle_result(res, false);
+                if matches!(self.mode, MainWindowMode::ShowTerminal)
+                    && !self.transfer_queue.is_empty()
+                {
+                    super::view_transfer_queue(self, ctx);
+                }
                 ctx.request_repaint_after(Duration::from_millis(150));
             }
             MainWindowMode::ShowSettings(in_phonebook) => {
@@ -661,31 +1250,71 @@ impl eframe::App for MainWindow {
                 }
                 super::show_settings(self, ctx, frame);
             }
+            MainWindowMode::ShowDownloads => {
+                let res = self.update_state();
+                self.update_terminal_window(ctx, frame);
+                self.handle_result(res, false);
+                super::view_download_browser(self, ctx);
+                ctx.request_repaint_after(Duration::from_millis(150));
+            }
+            MainWindowMode::ShowDirectory => {
+                super::view_phonebook(self, ctx);
+                super::view_directory(self, ctx);
+            }
             MainWindowMode::SelectProtocol(download) => {
                 self.update_terminal_window(ctx, frame);
                 super::view_selector(self, ctx, frame, download);
             }
             MainWindowMode::FileTransfer(download) => {
-                if self.connection_opt.as_mut().unwrap().should_end_transfer() {
-                    /*  if guard.1.is_finished {
-                        for f in guard.0.get_received_files() {
-                            f.save_file_in_downloads(
-                                guard.1.recieve_state.as_mut().unwrap(),
-                            )
-                            .expect("error saving file.");
-                        }
-                    } else */
-                    self.mode = MainWindowMode::ShowTerminal;
+                self.print_transfer_data();
+                let transfer_ended = self.connection_opt.as_mut().unwrap().should_end_transfer();
+                if transfer_ended {
+                    // Keep the window open until the user has seen what the hook printed.
+                    let has_output = self.current_transfer.as_ref().map_or(false, |state| {
+                        let state = state.lock().unwrap();
+                        let info = if download {
+                            &state.recieve_state
+                        } else {
+                            &state.send_state
+                        };
+                        !info.output_log.is_empty()
+                    });
+                    if !has_output {
+                        self.mode = MainWindowMode::ShowTerminal;
+                    }
+                    let mut files = Vec::new();
+                    if let Some(state) = &self.current_transfer {
+                        files = std::mem::take(&mut state.lock().unwrap().received_files);
+                        self.download_browser.downloads.add(&files);
+                    }
+                    if !download {
+                        files = std::mem::take(&mut self.transfer_queue.sending);
+                    }
                     self.auto_file_transfer.reset();
+                    self.transfer_queue.sending.clear();
+                    let paths: rhai::Array = files
+                        .iter()
+                        .filter(|f| !f.path_name.is_empty())
+                        .map(|f| f.path_name.clone().into())
+                        .collect();
+                    self.call_script_hook("on_transfer_finished", (download, paths));
                 }
 
                 self.update_terminal_window(ctx, frame);
                 if let Some(a) = &mut self.current_transfer {
                     // self.print_result(&r);
-                    if !super::view_filetransfer(ctx, frame, a, download) {
+                    if !super::view_filetransfer(
+                        ctx,
+                        frame,
+                        a,
+                        download,
+                        &self.transfer_queue.sending,
+                    ) {
                         self.mode = MainWindowMode::ShowTerminal;
-                        let res = self.connection_opt.as_mut().unwrap().cancel_transfer();
-                        self.handle_result(res, true);
+                        if !transfer_ended {
+                            let res = self.connection_opt.as_mut().unwrap().cancel_transfer();
+                            self.handle_result(res, true);
+                        }
                     }
                 } else {
                     eprintln!("error - in file transfer but no current protocol.");
@@ -694,6 +1323,9 @@ impl eframe::App for MainWindow {
                 ctx.request_repaint_after(Duration::from_millis(150));
             } // MainWindowMode::AskDeleteEntry => todo!(),
         }
+        super::show_export_dialog(self, ctx);
+        super::show_identity_dialog(self, ctx);
+        super::show_unlock_dialog(self, ctx);
     }
 
     fn on_exit(&mut self, gl: Option<&glow::Context>) {
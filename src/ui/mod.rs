@@ -23,9 +23,36 @@ pub use protocol_selector::*;
 pub mod file_transfer;
 pub use file_transfer::*;
 
+pub mod download_browser;
+pub use download_browser::*;
+
+pub mod directory_view;
+pub use directory_view::*;
+
 pub mod keymaps;
 pub use keymaps::*;
 
+pub mod trigger_editor;
+pub use trigger_editor::*;
+
+pub mod iemsi_editor;
+pub use iemsi_editor::*;
+
+pub mod sysop_chat;
+pub use sysop_chat::*;
+
+pub mod identity_editor;
+pub use identity_editor::*;
+
+pub mod identity_dialog;
+pub use identity_dialog::*;
+
+pub mod export_dialog;
+pub use export_dialog::*;
+
+pub mod unlock_dialog;
+pub use unlock_dialog::*;
+
 pub mod settings_dialog;
 pub use settings_dialog::*;
 
@@ -1,13 +1,20 @@
 use std::{
     fs::{self, File},
     io::Write,
+    path::PathBuf,
     time::Duration,
 };
 
 use directories::ProjectDirs;
 use toml::Value;
 
-use crate::TerminalResult;
+use crate::{
+    address_mod::escape,
+    iemsi_mod::{parse_iemsi_profile, IEmsiProfile},
+    protocol::{CollisionPolicy, ZdleEscaping},
+    triggers::{parse_trigger, triggers_to_toml, Trigger},
+    TerminalResult,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Scaling {
@@ -60,11 +67,39 @@ impl Default for MonitorSettings {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TextUploadSettings {
+    pub char_delay_ms: u64,
+    pub line_delay_ms: u64,
+    pub prompt: String,
+    pub wait_for_echo: bool,
+}
+
 #[derive(Debug, Clone)]
 pub struct Options {
     pub scaling: Scaling,
     pub connect_timeout: Duration,
     pub monitor_settings: MonitorSettings,
+    pub text_upload: TextUploadSettings,
+
+    /// Empty means the download directory of the system.
+    pub download_dir: String,
+    pub collision_policy: CollisionPolicy,
+    /// DSZLOG of all transfers, empty means `dszlog.txt` in the config directory.
+    pub transfer_log: String,
+    /// Runs on every downloaded file, `%f` is replaced by the path.
+    pub download_hook: String,
+
+    /// ZModem send window in bytes, 0 streams nonstop.
+    pub zmodem_window_size: usize,
+    /// Uploads ask the receiver to continue a file of the same name.
+    pub zmodem_resume: bool,
+    pub zmodem_escaping: ZdleEscaping,
+
+    /// Triggers for all boards, the ones of the address come on top.
+    pub triggers: Vec<Trigger>,
+    /// IEMSI defaults for addresses that leave fields empty.
+    pub iemsi: IEmsiProfile,
 }
 
 impl Options {
@@ -73,6 +108,16 @@ impl Options {
             connect_timeout: Duration::from_secs(10),
             scaling: Scaling::Linear,
             monitor_settings: MonitorSettings::default(),
+            text_upload: TextUploadSettings::default(),
+            download_dir: String::new(),
+            collision_policy: CollisionPolicy::default(),
+            transfer_log: String::new(),
+            download_hook: String::new(),
+            zmodem_window_size: 0,
+            zmodem_resume: false,
+            zmodem_escaping: ZdleEscaping::default(),
+            triggers: Vec::new(),
+            iemsi: IEmsiProfile::default(),
         }
     }
 
@@ -87,6 +132,29 @@ impl Options {
         Options::new()
     }
 
+    pub fn get_default_transfer_log() -> Option<PathBuf> {
+        ProjectDirs::from("com", "GitHub", "icy_term")
+            .map(|proj_dirs| proj_dirs.config_dir().join("dszlog.txt"))
+    }
+
+    pub fn get_capture_dir() -> Option<PathBuf> {
+        ProjectDirs::from("com", "GitHub", "icy_term")
+            .map(|proj_dirs| proj_dirs.config_dir().join("captures"))
+    }
+
+    pub fn get_ism_cache_dir() -> Option<PathBuf> {
+        ProjectDirs::from("com", "GitHub", "icy_term")
+            .map(|proj_dirs| proj_dirs.cache_dir().join("ism"))
+    }
+
+    pub fn get_transfer_log_file(&self) -> Option<PathBuf> {
+        if self.transfer_log.is_empty() {
+            Options::get_default_transfer_log()
+        } else {
+            Some(PathBuf::from(&self.transfer_log))
+        }
+    }
+
     pub fn store_options(&self) -> TerminalResult<()> {
         if let Some(proj_dirs) = ProjectDirs::from("com", "GitHub", "icy_term") {
             let options_file = proj_dirs.config_dir().join("options.toml");
@@ -138,6 +206,69 @@ impl Options {
                 )
                 .as_bytes(),
             )?;
+            file.write_all(
+                format!(
+                    "text_upload_char_delay = {}\n",
+                    self.text_upload.char_delay_ms
+                )
+                .as_bytes(),
+            )?;
+            file.write_all(
+                format!(
+                    "text_upload_line_delay = {}\n",
+                    self.text_upload.line_delay_ms
+                )
+                .as_bytes(),
+            )?;
+            if !self.text_upload.prompt.is_empty() {
+                file.write_all(
+                    format!(
+                        "text_upload_prompt = \"{}\"\n",
+                        escape(&self.text_upload.prompt)
+                    )
+                    .as_bytes(),
+                )?;
+            }
+            file.write_all(
+                format!(
+                    "text_upload_wait_for_echo = {}\n",
+                    self.text_upload.wait_for_echo
+                )
+                .as_bytes(),
+            )?;
+            if !self.download_dir.is_empty() {
+                file.write_all(
+                    format!("download_dir = \"{}\"\n", escape(&self.download_dir)).as_bytes(),
+                )?;
+            }
+            file.write_all(
+                format!("collision_policy = \"{:?}\"\n", self.collision_policy).as_bytes(),
+            )?;
+            if !self.transfer_log.is_empty() {
+                file.write_all(
+                    format!("transfer_log = \"{}\"\n", escape(&self.transfer_log)).as_bytes(),
+                )?;
+            }
+            if !self.download_hook.is_empty() {
+                file.write_all(
+                    format!("download_hook = \"{}\"\n", escape(&self.download_hook)).as_bytes(),
+                )?;
+            }
+            file.write_all(
+                format!("zmodem_window_size = {}\n", self.zmodem_window_size).as_bytes(),
+            )?;
+            file.write_all(format!("zmodem_resume = {}\n", self.zmodem_resume).as_bytes())?;
+            file.write_all(
+                format!("zmodem_escaping = \"{:?}\"\n", self.zmodem_escaping).as_bytes(),
+            )?;
+            if !self.triggers.is_empty() {
+                file.write_all(
+                    format!("triggers = {}\n", triggers_to_toml(&self.triggers)).as_bytes(),
+                )?;
+            }
+            if !self.iemsi.is_empty() {
+                file.write_all(format!("iemsi = {}\n", self.iemsi.to_toml()).as_bytes())?;
+            }
             file.flush()?;
         }
         Ok(())
@@ -230,6 +361,80 @@ fn parse_value(options: &mut Options, value: &Value) {
                             options.monitor_settings.scanlines = *f as f32;
                         }
                     }
+                    "text_upload_char_delay" => {
+                        if let Value::Integer(i) = v {
+                            options.text_upload.char_delay_ms = (*i).max(0) as u64;
+                        }
+                    }
+                    "text_upload_line_delay" => {
+                        if let Value::Integer(i) = v {
+                            options.text_upload.line_delay_ms = (*i).max(0) as u64;
+                        }
+                    }
+                    "text_upload_prompt" => {
+                        if let Value::String(str) = v {
+                            options.text_upload.prompt = str.clone();
+                        }
+                    }
+                    "text_upload_wait_for_echo" => {
+                        if let Value::Boolean(b) = v {
+                            options.text_upload.wait_for_echo = *b;
+                        }
+                    }
+                    "download_dir" => {
+                        if let Value::String(str) = v {
+                            options.download_dir = str.clone();
+                        }
+                    }
+                    "collision_policy" => {
+                        if let Value::String(str) = v {
+                            match str.as_str() {
+                                "Rename" => options.collision_policy = CollisionPolicy::Rename,
+                                "Overwrite" => {
+                                    options.collision_policy = CollisionPolicy::Overwrite
+                                }
+                                "Skip" => options.collision_policy = CollisionPolicy::Skip,
+                                "Ask" => options.collision_policy = CollisionPolicy::Ask,
+                                _ => {}
+                            }
+                        }
+                    }
+                    "transfer_log" => {
+                        if let Value::String(str) = v {
+                            options.transfer_log = str.clone();
+                        }
+                    }
+                    "download_hook" => {
+                        if let Value::String(str) = v {
+                            options.download_hook = str.clone();
+                        }
+                    }
+                    "zmodem_window_size" => {
+                        if let Value::Integer(i) = v {
+                            options.zmodem_window_size = (*i).max(0) as usize;
+                        }
+                    }
+                    "zmodem_resume" => {
+                        if let Value::Boolean(b) = v {
+                            options.zmodem_resume = *b;
+                        }
+                    }
+                    "zmodem_escaping" => {
+                        if let Value::String(str) = v {
+                            match str.as_str() {
+                                "Minimal" => options.zmodem_escaping = ZdleEscaping::Minimal,
+                                "Standard" => options.zmodem_escaping = ZdleEscaping::Standard,
+                                "Control" => options.zmodem_escaping = ZdleEscaping::Control,
+                                _ => {}
+                            }
+                        }
+                    }
+                    "triggers" => {
+                        if let Value::Array(values) = v {
+                            options.triggers = values.iter().filter_map(parse_trigger).collect();
+                        }
+                    }
+                    "iemsi" => options.iemsi = parse_iemsi_profile(v),
                     _ => {}
                 }
             }
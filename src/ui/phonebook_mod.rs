@@ -8,9 +8,14 @@ use egui::{Id, Rect};
 use i18n_embed_fl::fl;
 use icy_engine::ansi::MusicOption;
 
-use crate::address_mod::{self, store_phone_book, Address, Terminal};
+use crate::address_mod::{self, Address, Terminal};
+use crate::credentials::CredentialError;
+use crate::login_script::parse_script;
 
-use super::{main_window_mod::MainWindow, DEFAULT_MODES};
+use super::{
+    main_window_mod::{MainWindow, MainWindowMode},
+    ExportDialog, UnlockDialog, DEFAULT_MODES,
+};
 
 pub enum PhonebookFilter {
     All,
@@ -22,6 +27,8 @@ pub enum AdressCategory {
     Server,
     Login,
     Terminal,
+    Triggers,
+    Iemsi,
     Notes,
 }
 
@@ -113,6 +120,9 @@ pub fn view_phonebook(window: &mut MainWindow, ctx: &egui::Context) {
                 ui.add_space(8.);
                 render_list(window, ui);
                 ui.add_space(8.);
+                if let Some(err) = &window.phonebook_error {
+                    ui.colored_label(Color32::RED, err);
+                }
 
                 ui.with_layout(Layout::left_to_right(egui::Align::BOTTOM), |ui| {
                     let r: egui::Response = ui
@@ -132,6 +142,48 @@ pub fn view_phonebook(window: &mut MainWindow, ctx: &egui::Context) {
                         )));
                         window.selected_bbs = None;
                     }
+
+                    let r: egui::Response = ui
+                        .button(
+                            RichText::new("📥").font(FontId::new(20.0, FontFamily::Proportional)),
+                        )
+                        .on_hover_ui(|ui| {
+                            ui.label(
+                                RichText::new(fl!(crate::LANGUAGE_LOADER, "phonebook-import"))
+                                    .small(),
+                            );
+                        });
+                    if r.clicked() {
+                        import_phonebook(window);
+                    }
+
+                    let r: egui::Response = ui
+                        .button(
+                            RichText::new("📤").font(FontId::new(20.0, FontFamily::Proportional)),
+                        )
+                        .on_hover_ui(|ui| {
+                            ui.label(
+                                RichText::new(fl!(crate::LANGUAGE_LOADER, "phonebook-export"))
+                                    .small(),
+                            );
+                        });
+                    if r.clicked() {
+                        window.export_dialog = Some(ExportDialog::new(window.selected_bbs));
+                    }
+
+                    let r: egui::Response = ui
+                        .button(
+                            RichText::new("📖").font(FontId::new(20.0, FontFamily::Proportional)),
+                        )
+                        .on_hover_ui(|ui| {
+                            ui.label(
+                                RichText::new(fl!(crate::LANGUAGE_LOADER, "phonebook-directory"))
+                                    .small(),
+                            );
+                        });
+                    if r.clicked() {
+                        window.mode = MainWindowMode::ShowDirectory;
+                    }
                 });
             });
 
@@ -219,8 +271,27 @@ fn show_content(window: &mut MainWindow, ui: &mut egui::Ui) {
     }
 }
 
-pub fn store_phonebook(window: &MainWindow) {
-    if let Err(err) = store_phone_book(&window.addresses) {
+/// Merges another terminal's dialing directory or a bundle, known systems are skipped.
+fn import_phonebook(window: &mut MainWindow) {
+    let Some(path) = rfd::FileDialog::new()
+        .add_filter("Phonebook", &["lst", "phn", "dir", "fon", "FON", "toml"])
+        .pick_file()
+    else {
+        return;
+    };
+    match crate::phonebook_io::import_phonebook(&path) {
+        Ok(addresses) => {
+            window.phonebook_error = None;
+            if crate::phonebook_io::merge_addresses(&mut window.addresses, addresses) > 0 {
+                store_phonebook(window);
+            }
+        }
+        Err(err) => window.phonebook_error = Some(err.to_string()),
+    }
+}
+
+pub fn store_phonebook(window: &mut MainWindow) {
+    if let Err(err) = window.store_phone_book() {
         eprintln!("{err}");
     }
 }
@@ -460,6 +531,16 @@ fn view_edit_bbs(window: &mut MainWindow, ui: &mut egui::Ui) {
         );
         ui.add_space(8.);
 
+        ui.selectable_value(
+            &mut adr.adress_category,
+            AdressCategory::Triggers,
+            fl!(crate::LANGUAGE_LOADER, "phonebook-triggers"),
+        );
+        ui.add_space(8.);
+
+        ui.selectable_value(&mut adr.adress_category, AdressCategory::Iemsi, "IEMSI");
+        ui.add_space(8.);
+
         ui.selectable_value(&mut adr.adress_category, AdressCategory::Notes, "Comment");
     });
     ui.separator();
@@ -475,6 +556,15 @@ fn view_edit_bbs(window: &mut MainWindow, ui: &mut egui::Ui) {
         AdressCategory::Terminal => {
             render_terminal_category(window, ui);
         }
+        AdressCategory::Triggers => {
+            let adr = window.get_address_mut(window.selected_bbs);
+            super::edit_triggers(ui, "address_triggers_grid", &mut adr.triggers);
+        }
+        AdressCategory::Iemsi => {
+            let defaults = window.options.iemsi.clone();
+            let adr = window.get_address_mut(window.selected_bbs);
+            super::edit_iemsi_profile(ui, "address_iemsi_grid", &mut adr.iemsi, &defaults);
+        }
 
         AdressCategory::Notes => {
             ui.add(
@@ -591,9 +681,24 @@ fn render_login_category(window: &mut MainWindow, ui: &mut egui::Ui) {
                 )));
             });
             ui.with_layout(Layout::left_to_right(egui::Align::Center), |ui| {
-                ui.add(TextEdit::singleline(
-                    &mut window.get_address_mut(window.selected_bbs).password,
-                ));
+                let stored = window.get_address_mut(window.selected_bbs).password.clone();
+                let mut password = match window.credentials.open(&stored) {
+                    Ok(password) => password,
+                    Err(CredentialError::Locked) => {
+                        if ui
+                            .button(fl!(crate::LANGUAGE_LOADER, "phonebook-unlock"))
+                            .clicked()
+                        {
+                            window.unlock_dialog = Some(UnlockDialog::unlock(None));
+                        }
+                        return;
+                    }
+                    Err(err) => {
+                        ui.colored_label(Color32::RED, err.to_string());
+                        String::new()
+                    }
+                };
+                let mut changed = ui.add(TextEdit::singleline(&mut password)).changed();
                 if ui
                     .button(RichText::new(fl!(
                         crate::LANGUAGE_LOADER,
@@ -601,13 +706,59 @@ fn render_login_category(window: &mut MainWindow, ui: &mut egui::Ui) {
                     )))
                     .clicked()
                 {
-                    let mut pw = String::new();
+                    password.clear();
                     for _ in 0..16 {
-                        pw.push(unsafe {
+                        password.push(unsafe {
                             char::from_u32_unchecked(window.rng.gen_range(b'0'..=b'z'))
                         });
                     }
-                    window.get_address_mut(window.selected_bbs).password = pw;
+                    changed = true;
+                }
+                if changed {
+                    match window.credentials.seal(&password) {
+                        Ok(password) => {
+                            window.get_address_mut(window.selected_bbs).password = password
+                        }
+                        Err(err) => eprintln!("{err}"),
+                    }
+                }
+            });
+            ui.end_row();
+
+            // Identities row
+            ui.with_layout(Layout::right_to_left(egui::Align::TOP), |ui| {
+                ui.label(RichText::new(fl!(
+                    crate::LANGUAGE_LOADER,
+                    "phonebook-identities"
+                )));
+            });
+            let names: Vec<String> = window.identities.iter().map(|i| i.name.clone()).collect();
+            let adr = window.get_address_mut(window.selected_bbs);
+            ui.vertical(|ui| {
+                if names.is_empty() {
+                    ui.label(
+                        RichText::new(fl!(crate::LANGUAGE_LOADER, "phonebook-identities-hint"))
+                            .italics(),
+                    );
+                }
+                for name in &names {
+                    ui.horizontal(|ui| {
+                        let mut attached = adr.identities.contains(name);
+                        if ui.checkbox(&mut attached, name.as_str()).changed() {
+                            if attached {
+                                adr.identities.push(name.clone());
+                            } else {
+                                adr.identities.retain(|n| n != name);
+                            }
+                        }
+                        if attached {
+                            ui.radio_value(
+                                &mut adr.default_identity,
+                                name.clone(),
+                                fl!(crate::LANGUAGE_LOADER, "phonebook-identity-default"),
+                            );
+                        }
+                    });
                 }
             });
             ui.end_row();
@@ -624,6 +775,81 @@ fn render_login_category(window: &mut MainWindow, ui: &mut egui::Ui) {
                     .desired_width(f32::INFINITY),
             );
             ui.end_row();
+
+            let adr = window.get_address_mut(window.selected_bbs);
+            // Login script row
+            ui.with_layout(Layout::right_to_left(egui::Align::TOP), |ui| {
+                ui.label(RichText::new(fl!(
+                    crate::LANGUAGE_LOADER,
+                    "phonebook-login-script"
+                )));
+            });
+            ui.vertical(|ui| {
+                ui.add(
+                    TextEdit::multiline(&mut adr.login_script)
+                        .font(egui::TextStyle::Monospace)
+                        .hint_text(fl!(crate::LANGUAGE_LOADER, "phonebook-login-script-hint"))
+                        .desired_rows(6)
+                        .desired_width(f32::INFINITY),
+                );
+                if let Err(err) = parse_script(&adr.login_script) {
+                    ui.colored_label(Color32::RED, err.to_string());
+                }
+            });
+            ui.end_row();
+
+            // Login variables row
+            ui.with_layout(Layout::right_to_left(egui::Align::TOP), |ui| {
+                ui.label(RichText::new(fl!(
+                    crate::LANGUAGE_LOADER,
+                    "phonebook-login-variables"
+                )));
+            });
+            ui.vertical(|ui| {
+                let mut remove = None;
+                for (i, (name, value)) in adr.login_variables.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.add(TextEdit::singleline(name).desired_width(100.));
+                        ui.add(TextEdit::singleline(value));
+                        if ui.button("🗑").clicked() {
+                            remove = Some(i);
+                        }
+                    });
+                }
+                if let Some(i) = remove {
+                    adr.login_variables.remove(i);
+                }
+                if ui
+                    .button(fl!(crate::LANGUAGE_LOADER, "phonebook-login-variable-add"))
+                    .clicked()
+                {
+                    adr.login_variables.push((String::new(), String::new()));
+                }
+            });
+            ui.end_row();
+
+            // Script row
+            ui.with_layout(Layout::right_to_left(egui::Align::Center), |ui| {
+                ui.label(RichText::new(fl!(
+                    crate::LANGUAGE_LOADER,
+                    "phonebook-script-file"
+                )));
+            });
+            ui.horizontal(|ui| {
+                ui.add(
+                    TextEdit::singleline(&mut adr.script_file)
+                        .hint_text(fl!(crate::LANGUAGE_LOADER, "phonebook-script-file-hint")),
+                );
+                if ui.button("…").clicked() {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .add_filter("Rhai", &["rhai"])
+                        .pick_file()
+                    {
+                        adr.script_file = path.to_string_lossy().to_string();
+                    }
+                }
+            });
+            ui.end_row();
         });
 }
 
@@ -661,6 +887,19 @@ fn render_server_catogery(window: &mut MainWindow, ui: &mut egui::Ui) {
                     }
                 });
             ui.end_row();
+
+            // Download directory row
+            ui.with_layout(Layout::right_to_left(egui::Align::Center), |ui| {
+                ui.label(RichText::new(fl!(
+                    crate::LANGUAGE_LOADER,
+                    "phonebook-download-dir"
+                )));
+            });
+            ui.add(TextEdit::singleline(&mut adr.download_dir).hint_text(fl!(
+                crate::LANGUAGE_LOADER,
+                "phonebook-download-dir-default"
+            )));
+            ui.end_row();
         });
 }
 
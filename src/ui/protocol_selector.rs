@@ -17,7 +17,11 @@ fn create_button_row(
     body.row(30., |mut row| {
         row.col(|ui| {
             if ui.button(RichText::new(title)).clicked() {
-                window.initiate_file_transfer(protocol, download);
+                if download {
+                    window.initiate_file_transfer(protocol, download);
+                } else {
+                    window.select_upload_protocol(protocol);
+                }
             }
         });
         row.col(|ui| {
@@ -67,6 +71,14 @@ pub fn view_selector(
                     "ZedZap",
                     fl!(crate::LANGUAGE_LOADER, "protocol-zmodem8k-description"),
                 );
+                create_button_row(
+                    window,
+                    &mut body,
+                    TransferType::DirectZap,
+                    download,
+                    "DirectZAP",
+                    fl!(crate::LANGUAGE_LOADER, "protocol-directzap-description"),
+                );
                 create_button_row(
                     window,
                     &mut body,
@@ -107,6 +119,37 @@ pub fn view_selector(
                     "Ymodem-G",
                     fl!(crate::LANGUAGE_LOADER, "protocol-ymodemg-description"),
                 );
+                create_button_row(
+                    window,
+                    &mut body,
+                    TransferType::Kermit,
+                    download,
+                    "Kermit",
+                    fl!(crate::LANGUAGE_LOADER, "protocol-kermit-description"),
+                );
+                create_button_row(
+                    window,
+                    &mut body,
+                    TransferType::Punter,
+                    download,
+                    "Punter",
+                    fl!(crate::LANGUAGE_LOADER, "protocol-punter-description"),
+                );
+                if !download {
+                    body.row(30., |mut row| {
+                        row.col(|ui| {
+                            if ui.button(RichText::new("ASCII")).clicked() {
+                                window.initiate_text_upload();
+                            }
+                        });
+                        row.col(|ui| {
+                            ui.label(RichText::new(fl!(
+                                crate::LANGUAGE_LOADER,
+                                "protocol-text-description"
+                            )));
+                        });
+                    });
+                }
             });
         });
 
@@ -45,6 +45,17 @@ impl Display for ScreenMode {
 }
 
 impl ScreenMode {
+    /// Parses the displayed name, like "VGA 80x50" or "VIC-II".
+    pub fn from_name(name: &str) -> Option<Self> {
+        if name.eq_ignore_ascii_case("vic") {
+            return Some(ScreenMode::Vic);
+        }
+        DEFAULT_MODES
+            .iter()
+            .find(|mode| mode.to_string().eq_ignore_ascii_case(name.trim()))
+            .copied()
+    }
+
     pub fn get_input_mode(&self) -> BufferInputMode {
         match self {
             //ScreenMode::Cga(_, _) | ScreenMode::Ega(_, _) |
@@ -2,9 +2,13 @@ use eframe::egui::{self, RichText};
 use i18n_embed_fl::fl;
 
 use super::{
+    get_collision_policy_label,
     main_window_mod::{MainWindow, MainWindowMode},
-    Scaling,
+    Options, Scaling,
 };
+use crate::address_mod::Address;
+use crate::iemsi_mod::IEmsiProfile;
+use crate::protocol::{CollisionPolicy, ZdleEscaping};
 const MONITOR_NAMES: [&str; 6] = [
     "Color",
     "Grayscale",
@@ -14,6 +18,14 @@ const MONITOR_NAMES: [&str; 6] = [
     "Futuristic",
 ];
 
+fn get_zmodem_escaping_label(escaping: ZdleEscaping) -> String {
+    match escaping {
+        ZdleEscaping::Minimal => fl!(crate::LANGUAGE_LOADER, "zmodem-escaping-minimal"),
+        ZdleEscaping::Standard => fl!(crate::LANGUAGE_LOADER, "zmodem-escaping-standard"),
+        ZdleEscaping::Control => fl!(crate::LANGUAGE_LOADER, "zmodem-escaping-control"),
+    }
+}
+
 pub fn show_settings(window: &mut MainWindow, ctx: &egui::Context, _frame: &mut eframe::Frame) {
     let mut open = true;
     let mut close_dialog = false;
@@ -140,6 +152,236 @@ pub fn show_settings(window: &mut MainWindow, ctx: &egui::Context, _frame: &mut
             );
             ui.add_space(8.0);
 
+            ui.separator();
+            ui.add_space(8.0);
+            let old_text_upload = window.options.text_upload.clone();
+            ui.label(fl!(crate::LANGUAGE_LOADER, "settings-text-upload"));
+            egui::Grid::new("text_upload_grid")
+                .num_columns(2)
+                .spacing([4.0, 8.0])
+                .show(ui, |ui| {
+                    ui.label(fl!(
+                        crate::LANGUAGE_LOADER,
+                        "settings-text-upload-char-delay"
+                    ));
+                    ui.add(
+                        egui::DragValue::new(&mut window.options.text_upload.char_delay_ms)
+                            .clamp_range(0..=1000)
+                            .suffix(" ms"),
+                    );
+                    ui.end_row();
+
+                    ui.label(fl!(
+                        crate::LANGUAGE_LOADER,
+                        "settings-text-upload-line-delay"
+                    ));
+                    ui.add(
+                        egui::DragValue::new(&mut window.options.text_upload.line_delay_ms)
+                            .clamp_range(0..=10000)
+                            .suffix(" ms"),
+                    );
+                    ui.end_row();
+
+                    ui.label(fl!(crate::LANGUAGE_LOADER, "settings-text-upload-prompt"));
+                    ui.text_edit_singleline(&mut window.options.text_upload.prompt);
+                    ui.end_row();
+                });
+            ui.checkbox(
+                &mut window.options.text_upload.wait_for_echo,
+                fl!(crate::LANGUAGE_LOADER, "settings-text-upload-echo"),
+            );
+            if old_text_upload != window.options.text_upload {
+                window.handle_result(window.options.store_options(), false);
+            }
+            ui.add_space(8.0);
+
+            ui.separator();
+            ui.add_space(8.0);
+            let old_download_dir = window.options.download_dir.clone();
+            let old_collision_policy = window.options.collision_policy;
+            let old_transfer_log = window.options.transfer_log.clone();
+            let old_download_hook = window.options.download_hook.clone();
+            ui.label(fl!(crate::LANGUAGE_LOADER, "settings-downloads"));
+            egui::Grid::new("downloads_grid")
+                .num_columns(2)
+                .spacing([4.0, 8.0])
+                .show(ui, |ui| {
+                    ui.label(fl!(crate::LANGUAGE_LOADER, "settings-download-dir"));
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::TextEdit::singleline(&mut window.options.download_dir).hint_text(
+                                fl!(crate::LANGUAGE_LOADER, "settings-download-dir-default"),
+                            ),
+                        );
+                        if ui.button("…").clicked() {
+                            if let Some(dir) = rfd::FileDialog::new().pick_folder() {
+                                window.options.download_dir = dir.to_string_lossy().to_string();
+                            }
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label(fl!(crate::LANGUAGE_LOADER, "settings-collision-policy"));
+                    egui::ComboBox::from_id_source("collision_policy_combobox")
+                        .selected_text(get_collision_policy_label(window.options.collision_policy))
+                        .show_ui(ui, |ui| {
+                            for policy in &CollisionPolicy::ALL {
+                                ui.selectable_value(
+                                    &mut window.options.collision_policy,
+                                    *policy,
+                                    get_collision_policy_label(*policy),
+                                );
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label(fl!(crate::LANGUAGE_LOADER, "settings-transfer-log"));
+                    let default_log = Options::get_default_transfer_log()
+                        .map(|f| f.to_string_lossy().to_string())
+                        .unwrap_or_default();
+                    ui.add(
+                        egui::TextEdit::singleline(&mut window.options.transfer_log)
+                            .hint_text(default_log),
+                    );
+                    ui.end_row();
+
+                    ui.label(fl!(crate::LANGUAGE_LOADER, "settings-download-hook"));
+                    ui.add(
+                        egui::TextEdit::singleline(&mut window.options.download_hook)
+                            .hint_text("clamscan \"%f\""),
+                    )
+                    .on_hover_text(fl!(crate::LANGUAGE_LOADER, "settings-download-hook-hint"));
+                    ui.end_row();
+                });
+            if old_download_dir != window.options.download_dir
+                || old_collision_policy != window.options.collision_policy
+                || old_transfer_log != window.options.transfer_log
+                || old_download_hook != window.options.download_hook
+            {
+                window.handle_result(window.options.store_options(), false);
+            }
+            ui.add_space(8.0);
+
+            ui.separator();
+            ui.add_space(8.0);
+            let old_window_size = window.options.zmodem_window_size;
+            let old_escaping = window.options.zmodem_escaping;
+            let old_resume = window.options.zmodem_resume;
+            ui.label(fl!(crate::LANGUAGE_LOADER, "settings-zmodem"));
+            egui::Grid::new("zmodem_grid")
+                .num_columns(2)
+                .spacing([4.0, 8.0])
+                .show(ui, |ui| {
+                    ui.label(fl!(crate::LANGUAGE_LOADER, "settings-zmodem-window"));
+                    ui.add(
+                        egui::DragValue::new(&mut window.options.zmodem_window_size)
+                            .clamp_range(0..=64 * 1024)
+                            .speed(256)
+                            .suffix(" bytes"),
+                    )
+                    .on_hover_text(fl!(crate::LANGUAGE_LOADER, "settings-zmodem-window-hint"));
+                    ui.end_row();
+
+                    ui.label(fl!(crate::LANGUAGE_LOADER, "settings-zmodem-escaping"));
+                    egui::ComboBox::from_id_source("zmodem_escaping_combobox")
+                        .selected_text(get_zmodem_escaping_label(window.options.zmodem_escaping))
+                        .show_ui(ui, |ui| {
+                            for escaping in &ZdleEscaping::ALL {
+                                ui.selectable_value(
+                                    &mut window.options.zmodem_escaping,
+                                    *escaping,
+                                    get_zmodem_escaping_label(*escaping),
+                                );
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("");
+                    ui.checkbox(
+                        &mut window.options.zmodem_resume,
+                        fl!(crate::LANGUAGE_LOADER, "settings-zmodem-resume"),
+                    )
+                    .on_hover_text(fl!(crate::LANGUAGE_LOADER, "settings-zmodem-resume-hint"));
+                    ui.end_row();
+                });
+            if old_window_size != window.options.zmodem_window_size
+                || old_escaping != window.options.zmodem_escaping
+                || old_resume != window.options.zmodem_resume
+            {
+                window.handle_result(window.options.store_options(), false);
+            }
+            ui.add_space(8.0);
+
+            ui.separator();
+            ui.label(fl!(crate::LANGUAGE_LOADER, "settings-passwords"));
+            ui.horizontal(|ui| {
+                if !window.credentials.is_enabled() {
+                    ui.label(fl!(crate::LANGUAGE_LOADER, "settings-passwords-plain"));
+                    if ui
+                        .button(fl!(crate::LANGUAGE_LOADER, "settings-passwords-encrypt"))
+                        .clicked()
+                    {
+                        window.unlock_dialog = Some(super::UnlockDialog::create());
+                    }
+                } else if window.credentials.is_unlocked() {
+                    ui.label(fl!(crate::LANGUAGE_LOADER, "settings-passwords-unlocked"));
+                    if ui
+                        .button(fl!(crate::LANGUAGE_LOADER, "settings-passwords-lock"))
+                        .clicked()
+                    {
+                        window.credentials.lock();
+                    }
+                } else {
+                    ui.label(fl!(crate::LANGUAGE_LOADER, "settings-passwords-locked"));
+                    if ui
+                        .button(fl!(crate::LANGUAGE_LOADER, "phonebook-unlock"))
+                        .clicked()
+                    {
+                        window.unlock_dialog = Some(super::UnlockDialog::unlock(None));
+                    }
+                }
+            });
+            ui.add_space(8.0);
+
+            ui.separator();
+            ui.collapsing(fl!(crate::LANGUAGE_LOADER, "settings-identities"), |ui| {
+                let old_names: Vec<String> =
+                    window.identities.iter().map(|i| i.name.clone()).collect();
+                if super::edit_identities(
+                    ui,
+                    "settings_identities_grid",
+                    &mut window.identities,
+                    &window.credentials,
+                ) {
+                    if old_names.len() == window.identities.len() {
+                        for (old_name, identity) in old_names.iter().zip(&window.identities) {
+                            if *old_name != identity.name {
+                                rename_identity(&mut window.addresses, old_name, &identity.name);
+                            }
+                        }
+                    }
+                    let res = window.store_phone_book();
+                    window.handle_result(res, false);
+                }
+            });
+            ui.collapsing(fl!(crate::LANGUAGE_LOADER, "settings-iemsi"), |ui| {
+                if super::edit_iemsi_profile(
+                    ui,
+                    "settings_iemsi_grid",
+                    &mut window.options.iemsi,
+                    &IEmsiProfile::default(),
+                ) {
+                    window.handle_result(window.options.store_options(), false);
+                }
+            });
+            ui.collapsing(fl!(crate::LANGUAGE_LOADER, "settings-triggers"), |ui| {
+                if super::edit_triggers(ui, "settings_triggers_grid", &mut window.options.triggers)
+                {
+                    window.handle_result(window.options.store_options(), false);
+                }
+            });
+            ui.add_space(8.0);
+
             ui.separator();
             ui.horizontal(|ui| {
                 if ui.button("Reset").clicked() {
@@ -171,3 +413,17 @@ pub fn show_settings(window: &mut MainWindow, ctx: &egui::Context, _frame: &mut
         }
     }
 }
+
+/// Keeps the addresses attached to a renamed identity.
+fn rename_identity(addresses: &mut [Address], old_name: &str, new_name: &str) {
+    for adr in addresses {
+        for name in &mut adr.identities {
+            if name == old_name {
+                *name = new_name.to_string();
+            }
+        }
+        if adr.default_identity == old_name {
+            adr.default_identity = new_name.to_string();
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use eframe::{
+    egui::{self, RichText, ScrollArea},
+    epaint::Color32,
+};
+use i18n_embed_fl::fl;
+
+use super::main_window_mod::MainWindow;
+
+/// Lines kept per side, older ones scroll away.
+const MAX_LINES: usize = 500;
+
+/// The split screen of an IEMSI chat, the sysop on top and the user below.
+/// The server doesn't echo during the chat, so the typed text is shown locally.
+#[derive(Default)]
+pub struct SysopChat {
+    pub remote: Vec<String>,
+    pub local: Vec<String>,
+}
+
+fn push_chat_char(lines: &mut Vec<String>, ch: char) {
+    if lines.is_empty() {
+        lines.push(String::new());
+    }
+    match ch {
+        '\r' | '\n' => {
+            // CR LF is one line break.
+            if ch == '\r' || !lines.last().unwrap().is_empty() {
+                lines.push(String::new());
+            }
+            if lines.len() > MAX_LINES {
+                lines.remove(0);
+            }
+        }
+        '\x08' | '\x7F' => {
+            lines.last_mut().unwrap().pop();
+        }
+        ch if ch.is_control() => {}
+        ch => lines.last_mut().unwrap().push(ch),
+    }
+}
+
+impl SysopChat {
+    pub fn push_remote(&mut self, ch: char) {
+        push_chat_char(&mut self.remote, ch);
+    }
+
+    pub fn push_local(&mut self, ch: char) {
+        push_chat_char(&mut self.local, ch);
+    }
+}
+
+fn show_lines(ui: &mut egui::Ui, id: &str, title: String, lines: &[String], color: Color32) {
+    ui.label(RichText::new(title).small());
+    ScrollArea::vertical()
+        .id_source(id)
+        .auto_shrink([false; 2])
+        .stick_to_bottom(true)
+        .max_height(ui.available_height() / 2. - 20.)
+        .show(ui, |ui| {
+            for line in lines {
+                ui.label(RichText::new(line).monospace().color(color));
+            }
+        });
+}
+
+/// Call before the central panel, the chat takes the lower half of the window.
+pub fn show_sysop_chat(window: &MainWindow, ctx: &egui::Context) {
+    let Some(chat) = &window.chat else {
+        return;
+    };
+    egui::TopBottomPanel::bottom("sysop_chat")
+        .exact_height(ctx.available_rect().height() / 2.)
+        .show(ctx, |ui| {
+            show_lines(
+                ui,
+                "sysop_chat_remote",
+                fl!(crate::LANGUAGE_LOADER, "chat-sysop"),
+                &chat.remote,
+                Color32::LIGHT_YELLOW,
+            );
+            ui.separator();
+            show_lines(
+                ui,
+                "sysop_chat_local",
+                fl!(crate::LANGUAGE_LOADER, "chat-you"),
+                &chat.local,
+                Color32::LIGHT_GREEN,
+            );
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ui::SysopChat;
+
+    #[test]
+    fn test_chat_lines() {
+        let mut chat = SysopChat::default();
+        for ch in "Hello\r\nHow arr\x08e you?\r".chars() {
+            chat.push_remote(ch);
+        }
+        assert_eq!(vec!["Hello", "How are you?", ""], chat.remote);
+        assert!(chat.local.is_empty());
+    }
+}
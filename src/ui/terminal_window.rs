@@ -1,4 +1,4 @@
-use std::cmp::max;
+use std::{cmp::max, time::Duration};
 
 use clipboard::{ClipboardContext, ClipboardProvider};
 use eframe::{
@@ -9,6 +9,8 @@ use i18n_embed_fl::fl;
 
 use super::main_window_mod::{MainWindow, MainWindowMode};
 
+const NOTIFICATION_TIMEOUT: Duration = Duration::from_secs(10);
+
 fn encode_mouse_button(button: i32) -> char {
     unsafe { char::from_u32_unchecked(b' '.saturating_add(button as u8) as u32) }
 }
@@ -63,6 +65,25 @@ impl MainWindow {
                         self.mode = MainWindowMode::SelectProtocol(true);
                     }
 
+                    let r = ui
+                        .button(
+                            RichText::new("🗁")
+                                .font(FontId::new(img_size, FontFamily::Proportional)),
+                        )
+                        .on_hover_ui(|ui| {
+                            ui.label(
+                                RichText::new(fl!(
+                                    crate::LANGUAGE_LOADER,
+                                    "terminal-recent-downloads"
+                                ))
+                                .small(),
+                            );
+                        });
+
+                    if r.clicked() {
+                        self.mode = MainWindowMode::ShowDownloads;
+                    }
+
                     if !self.auto_login.logged_in {
                         let r = ui
                             .button(
@@ -99,8 +120,20 @@ impl MainWindow {
                         self.show_phonebook();
                     }
 
+                    if let Some(isi) = self.get_server_info() {
+                        let info = fl!(
+                            crate::LANGUAGE_LOADER,
+                            "terminal-server-info",
+                            name = isi.name.clone(),
+                            operator = isi.operator.clone(),
+                            location = isi.location.clone()
+                        );
+                        ui.label(RichText::new(info).small())
+                            .on_hover_text(format!("{}\n{}", isi.id, isi.notice));
+                    }
+
                     let size = ui.available_size_before_wrap();
-                    ui.add_space(size.x - 70.0);
+                    ui.add_space((size.x - 70.0).max(0.0));
 
                     let r = ui
                         .button(
@@ -135,6 +168,31 @@ impl MainWindow {
                                 ui.close_menu();
                             }
                             ui.separator();
+                            if ui
+                                .button(fl!(crate::LANGUAGE_LOADER, "menu-item-run-script"))
+                                .clicked()
+                            {
+                                ui.close_menu();
+                                if let Some(path) = rfd::FileDialog::new()
+                                    .add_filter("Rhai", &["rhai"])
+                                    .pick_file()
+                                {
+                                    self.run_script(&path, false);
+                                }
+                            }
+                            let capture_label = if self.capture.is_capturing() {
+                                fl!(crate::LANGUAGE_LOADER, "menu-item-stop-capture")
+                            } else {
+                                fl!(crate::LANGUAGE_LOADER, "menu-item-start-capture")
+                            };
+                            if ui.button(capture_label).clicked() {
+                                ui.close_menu();
+                                if self.capture.is_capturing() {
+                                    self.stop_capture();
+                                } else {
+                                    self.start_capture();
+                                }
+                            }
                             if ui
                                 .button(fl!(crate::LANGUAGE_LOADER, "menu-item-settings"))
                                 .clicked()
@@ -150,17 +208,64 @@ impl MainWindow {
         let frame_no_margins = egui::containers::Frame::none()
             .inner_margin(egui::style::Margin::same(0.0))
             .fill(Color32::from_rgb(0x40, 0x44, 0x4b));
+        if !show_pb {
+            super::show_sysop_chat(self, ctx);
+        }
         egui::CentralPanel::default()
             .frame(frame_no_margins)
             .show(ctx, |ui| {
                 self.custom_painting(ui, top_margin_height);
             });
 
+        if !show_pb {
+            let dropped_files: Vec<_> = ctx.input(|i| {
+                i.raw
+                    .dropped_files
+                    .iter()
+                    .filter_map(|f| f.path.clone())
+                    .collect()
+            });
+            if !dropped_files.is_empty() {
+                self.queue_files(&dropped_files);
+            }
+        }
+
+        if !show_pb {
+            self.show_notifications(ctx);
+        }
+
         if show_pb {
             super::view_phonebook(self, ctx);
         }
     }
 
+    fn show_notifications(&mut self, ctx: &egui::Context) {
+        self.notifications
+            .retain(|(time, _)| time.elapsed() < NOTIFICATION_TIMEOUT);
+        if self.notifications.is_empty() {
+            return;
+        }
+        let mut close = None;
+        egui::Window::new("notifications")
+            .title_bar(false)
+            .resizable(false)
+            .anchor(egui::Align2::RIGHT_BOTTOM, Vec2::new(-10., -10.))
+            .show(ctx, |ui| {
+                for (i, (_, msg)) in self.notifications.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.label(msg);
+                        if ui.small_button("🗙").clicked() {
+                            close = Some(i);
+                        }
+                    });
+                }
+            });
+        if let Some(i) = close {
+            self.notifications.remove(i);
+        }
+        ctx.request_repaint_after(Duration::from_millis(500));
+    }
+
     fn custom_painting(&mut self, ui: &mut egui::Ui, top_margin_height: f32) -> egui::Response {
         let output = ScrollArea::vertical()
             .auto_shrink([false; 2])
@@ -264,7 +369,11 @@ impl MainWindow {
                             egui::Event::CompositionEnd(text) | egui::Event::Text(text) => {
                                 for c in text.chars() {
                                     self.output_char(c);
+                                    if let Some(chat) = &mut self.chat {
+                                        chat.push_local(c);
+                                    }
                                 }
+                                self.call_script_hook("on_key", (text,));
                                 response.mark_changed();
                             }
 
@@ -432,6 +541,9 @@ impl MainWindow {
                                 for (k, m) in key_map {
                                     if *k == key_code {
                                         self.handled_char = true;
+                                        if let (Some(chat), [c]) = (&mut self.chat, m) {
+                                            chat.push_local(char::from(*c));
+                                        }
                                         if let Some(con) = &mut self.connection_opt {
                                             let res = con.send(m.to_vec());
                                             self.handle_result(res, true);
@@ -444,6 +556,7 @@ impl MainWindow {
                                         }
                                         response.mark_changed();
                                         ui.input_mut(|i| i.consume_key(modifiers, key));
+                                        self.call_script_hook("on_key", (format!("{key:?}"),));
                                         break;
                                     }
                                 }
@@ -0,0 +1,74 @@
+use eframe::{
+    egui::{self, RichText, TextEdit},
+    epaint::Color32,
+};
+use i18n_embed_fl::fl;
+
+use crate::triggers::Trigger;
+
+/// Returns true if a trigger was changed, added or removed.
+pub fn edit_triggers(ui: &mut egui::Ui, id: &str, triggers: &mut Vec<Trigger>) -> bool {
+    let old_triggers = triggers.clone();
+    let mut remove = None;
+    egui::Grid::new(id)
+        .num_columns(3)
+        .spacing([4.0, 4.0])
+        .show(ui, |ui| {
+            for (i, trigger) in triggers.iter_mut().enumerate() {
+                let is_valid = trigger.pattern.is_empty() || trigger.create_regex().is_ok();
+                let mut pattern = TextEdit::singleline(&mut trigger.pattern)
+                    .hint_text(fl!(crate::LANGUAGE_LOADER, "triggers-pattern"));
+                if !is_valid {
+                    pattern = pattern.text_color(Color32::RED);
+                }
+                ui.add(pattern);
+                ui.add(
+                    TextEdit::singleline(&mut trigger.send)
+                        .hint_text(fl!(crate::LANGUAGE_LOADER, "triggers-send")),
+                );
+                if ui.button("🗑").clicked() {
+                    remove = Some(i);
+                }
+                ui.end_row();
+
+                ui.horizontal(|ui| {
+                    ui.checkbox(
+                        &mut trigger.is_regex,
+                        fl!(crate::LANGUAGE_LOADER, "triggers-regex"),
+                    );
+                    ui.checkbox(
+                        &mut trigger.highlight,
+                        fl!(crate::LANGUAGE_LOADER, "triggers-highlight"),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.checkbox(
+                        &mut trigger.sound,
+                        fl!(crate::LANGUAGE_LOADER, "triggers-sound"),
+                    );
+                    ui.checkbox(
+                        &mut trigger.notify,
+                        fl!(crate::LANGUAGE_LOADER, "triggers-notify"),
+                    );
+                    ui.checkbox(
+                        &mut trigger.capture,
+                        fl!(crate::LANGUAGE_LOADER, "triggers-capture"),
+                    );
+                });
+                ui.end_row();
+            }
+        });
+    if let Some(i) = remove {
+        triggers.remove(i);
+    }
+    if triggers.is_empty() {
+        ui.label(RichText::new(fl!(crate::LANGUAGE_LOADER, "triggers-none")).italics());
+    }
+    if ui
+        .button(fl!(crate::LANGUAGE_LOADER, "triggers-add"))
+        .clicked()
+    {
+        triggers.push(Trigger::default());
+    }
+    old_triggers != *triggers
+}
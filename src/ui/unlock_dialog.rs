@@ -0,0 +1,115 @@
+use eframe::{
+    egui::{self, RichText, TextEdit},
+    epaint::Color32,
+};
+use i18n_embed_fl::fl;
+
+use crate::credentials::CredentialStore;
+
+use super::main_window_mod::MainWindow;
+
+/// Asks for the master password, or a new one to set up the vault.
+pub struct UnlockDialog {
+    create: bool,
+    password: String,
+    confirm: String,
+    error: Option<String>,
+    /// The address and identity to call once unlocked.
+    call_after: Option<(usize, Option<String>)>,
+}
+
+impl UnlockDialog {
+    pub fn unlock(call_after: Option<(usize, Option<String>)>) -> Self {
+        Self {
+            create: false,
+            password: String::new(),
+            confirm: String::new(),
+            error: None,
+            call_after,
+        }
+    }
+
+    pub fn create() -> Self {
+        Self {
+            create: true,
+            ..UnlockDialog::unlock(None)
+        }
+    }
+}
+
+pub fn show_unlock_dialog(window: &mut MainWindow, ctx: &egui::Context) {
+    let Some(dialog) = &mut window.unlock_dialog else {
+        return;
+    };
+    let mut open = true;
+    let mut ok = false;
+    let title = if dialog.create {
+        fl!(crate::LANGUAGE_LOADER, "unlock-create-heading")
+    } else {
+        fl!(crate::LANGUAGE_LOADER, "unlock-heading")
+    };
+    egui::Window::new(RichText::new(title))
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .show(ctx, |ui| {
+            egui::Grid::new("unlock_grid")
+                .num_columns(2)
+                .spacing([4.0, 8.0])
+                .show(ui, |ui| {
+                    ui.label(fl!(crate::LANGUAGE_LOADER, "unlock-master-password"));
+                    let r = ui.add(TextEdit::singleline(&mut dialog.password).password(true));
+                    ok |= r.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    ui.end_row();
+
+                    if dialog.create {
+                        ui.label(fl!(crate::LANGUAGE_LOADER, "unlock-confirm-password"));
+                        ui.add(TextEdit::singleline(&mut dialog.confirm).password(true));
+                        ui.end_row();
+                    }
+                });
+            if dialog.create {
+                ui.label(RichText::new(fl!(crate::LANGUAGE_LOADER, "unlock-create-hint")).small());
+            }
+            if let Some(err) = &dialog.error {
+                ui.colored_label(Color32::RED, err);
+            }
+            ui.separator();
+            ok |= ui
+                .button(fl!(crate::LANGUAGE_LOADER, "phonebook-ok-button"))
+                .clicked();
+        });
+    if !open {
+        window.unlock_dialog = None;
+        return;
+    }
+    if !ok {
+        return;
+    }
+
+    if dialog.create {
+        if dialog.password.is_empty() {
+            dialog.error = Some(fl!(crate::LANGUAGE_LOADER, "unlock-empty-password"));
+            return;
+        }
+        if dialog.password != dialog.confirm {
+            dialog.error = Some(fl!(crate::LANGUAGE_LOADER, "unlock-passwords-differ"));
+            return;
+        }
+        window.credentials = CredentialStore::create(&dialog.password);
+    } else if let Err(err) = window.credentials.unlock(&dialog.password) {
+        dialog.error = Some(err.to_string());
+        dialog.password.clear();
+        return;
+    }
+    let call_after = dialog.call_after.take();
+    window.unlock_dialog = None;
+
+    // Seals the passwords that are still plain text.
+    let res = window.store_phone_book();
+    window.handle_result(res, true);
+    if let Some((i, identity)) = call_after {
+        window.call_bbs_as(i, identity);
+    }
+}